@@ -1,3 +1,4 @@
+use std::fmt::{Display, Formatter};
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
 use bigdecimal::{BigDecimal, Zero};
@@ -88,9 +89,9 @@ impl Amount {
 /// assert_eq!(Amount::new(BigDecimal::from(-100i32), "CNY").to_string(), "-100 CNY");
 /// assert_eq!(Amount::new(BigDecimal::from(100i32), "CNY").to_string(), "100 CNY");
 /// ```
-impl ToString for Amount {
-    fn to_string(&self) -> String {
-        format!("{} {}", self.number, self.currency)
+impl Display for Amount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.number, self.currency)
     }
 }
 
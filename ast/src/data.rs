@@ -7,7 +7,7 @@ use crate::models::*;
 use crate::utils::inventory::{AmountLotPair, Inventory, LotInfo};
 use crate::utils::multi_value_map::MultiValueMap;
 use crate::Account;
-use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
 use chrono_tz::Tz;
 use indexmap::IndexSet;
 use itertools::Itertools;
@@ -25,8 +25,30 @@ impl Date {
     pub fn now(timezone: &Tz) -> Date {
         Date::Datetime(Utc::now().with_timezone(timezone).naive_local())
     }
+    /// the earlier one is taken if local datetime is repeated by daylight saving,
+    /// and the first instant after the skip is taken if it is skipped, so that later local datetimes are never earlier instants
     pub fn to_timezone_datetime(&self, timezone: &Tz) -> DateTime<Tz> {
-        timezone.from_local_datetime(&self.naive_datetime()).unwrap()
+        let local = self.naive_datetime();
+        match timezone.from_local_datetime(&local) {
+            LocalResult::Single(datetime) => datetime,
+            LocalResult::Ambiguous(earliest, _) => earliest,
+            LocalResult::None => {
+                let offset_before = timezone.offset_from_utc_datetime(&(local - Duration::days(1))).fix();
+                let offset_after = timezone.offset_from_utc_datetime(&(local + Duration::days(1))).fix();
+                // the skip starts between the two instants, bisect to find it
+                let mut before = local - offset_after;
+                let mut after = local - offset_before;
+                while after - before > Duration::seconds(1) {
+                    let middle = before + (after - before) / 2;
+                    if timezone.offset_from_utc_datetime(&middle).fix() == offset_before {
+                        before = middle;
+                    } else {
+                        after = middle;
+                    }
+                }
+                timezone.from_utc_datetime(&after)
+            }
+        }
     }
     pub(crate) fn naive_datetime(&self) -> NaiveDateTime {
         match self {
@@ -44,7 +66,7 @@ impl Date {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Open {
     pub date: Date,
     pub account: Account,
@@ -52,7 +74,7 @@ pub struct Open {
     pub meta: Meta,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Close {
     pub date: Date,
     pub account: Account,
@@ -66,7 +88,7 @@ pub struct Commodity {
     pub meta: Meta,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Balance {
     BalanceCheck(BalanceCheck),
     BalancePad(BalancePad),
@@ -126,7 +148,7 @@ impl Transaction {
         Ok(inventory)
     }
 
-    pub fn txn_postings(&self) -> Vec<TxnPosting> {
+    pub fn txn_postings(&self) -> Vec<TxnPosting<'_>> {
         self.postings.iter().map(|posting| TxnPosting { txn: self, posting }).collect_vec()
     }
     pub fn has_account(&self, name: &String) -> bool {
@@ -225,7 +247,7 @@ impl<'a> TxnPosting<'a> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Note {
     pub date: Date,
     pub account: Account,
@@ -236,7 +258,7 @@ pub struct Note {
     pub meta: Meta,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Event {
     pub date: Date,
 
@@ -246,7 +268,7 @@ pub struct Event {
     pub meta: Meta,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Query {
     pub date: Date,
 
@@ -256,7 +278,7 @@ pub struct Query {
     pub meta: Meta,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Price {
     pub date: Date,
 
@@ -266,7 +288,7 @@ pub struct Price {
     pub meta: Meta,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Document {
     pub date: Date,
 
@@ -277,7 +299,7 @@ pub struct Document {
     pub meta: Meta,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Custom {
     pub date: Date,
 
@@ -286,30 +308,47 @@ pub struct Custom {
     pub meta: Meta,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Options {
     pub key: ZhangString,
     pub value: ZhangString,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Plugin {
     pub module: ZhangString,
     pub value: Vec<ZhangString>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Include {
     pub file: ZhangString,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Comment {
     pub content: String,
 }
 
 #[cfg(test)]
 mod test {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::Date;
+
+    #[test]
+    fn should_convert_local_datetime_skipped_or_repeated_by_daylight_saving() {
+        let timezone = chrono_tz::America::New_York;
+        let local = |hour| Date::Datetime(NaiveDate::from_ymd_opt(2023, 3, 12).unwrap().and_hms_opt(hour, 30, 0).unwrap());
+        // 02:00 to 03:00 is skipped, which starts at 07:00 UTC
+        assert_eq!(Utc.with_ymd_and_hms(2023, 3, 12, 6, 30, 0).unwrap(), local(1).to_timezone_datetime(&timezone));
+        assert_eq!(Utc.with_ymd_and_hms(2023, 3, 12, 7, 0, 0).unwrap(), local(2).to_timezone_datetime(&timezone));
+        assert_eq!(Utc.with_ymd_and_hms(2023, 3, 12, 7, 30, 0).unwrap(), local(3).to_timezone_datetime(&timezone));
+
+        // 01:00 to 02:00 is repeated
+        let repeated = Date::Datetime(NaiveDate::from_ymd_opt(2023, 11, 5).unwrap().and_hms_opt(1, 30, 0).unwrap());
+        assert_eq!(Utc.with_ymd_and_hms(2023, 11, 5, 5, 30, 0).unwrap(), repeated.to_timezone_datetime(&timezone));
+    }
     // mod transaction {
     //     use indoc::indoc;
     //
@@ -4,7 +4,7 @@ use strum::{Display, EnumString};
 
 use crate::account::Account;
 use crate::amount::Amount;
-use crate::data::{Balance, Close, Comment, Commodity, Custom, Document, Event, Include, Note, Open, Options, Plugin, Price, Transaction};
+use crate::data::{Balance, Close, Comment, Commodity, Custom, Document, Event, Include, Note, Open, Options, Plugin, Price, Query, Transaction};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum DirectiveType {
@@ -17,6 +17,7 @@ pub enum DirectiveType {
     Document,
     Price,
     Event,
+    Query,
     Custom,
     Option,
     Plugin,
@@ -24,7 +25,7 @@ pub enum DirectiveType {
     Comment,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Directive {
     Open(Open),
     Close(Close),
@@ -35,6 +36,7 @@ pub enum Directive {
     Document(Document),
     Price(Price),
     Event(Event),
+    Query(Query),
     Custom(Custom),
     Option(Options),
     Plugin(Plugin),
@@ -57,6 +59,7 @@ impl Directive {
             Directive::Document(document) => Some(document.date.naive_datetime()),
             Directive::Price(price) => Some(price.date.naive_datetime()),
             Directive::Event(event) => Some(event.date.naive_datetime()),
+            Directive::Query(query) => Some(query.date.naive_datetime()),
             Directive::Custom(custom) => Some(custom.date.naive_datetime()),
             Directive::Option(_) => None,
             Directive::Plugin(_) => None,
@@ -75,6 +78,7 @@ impl Directive {
             Directive::Document(_) => DirectiveType::Document,
             Directive::Price(_) => DirectiveType::Price,
             Directive::Event(_) => DirectiveType::Event,
+            Directive::Query(_) => DirectiveType::Query,
             Directive::Custom(_) => DirectiveType::Custom,
             Directive::Option(_) => DirectiveType::Option,
             Directive::Plugin(_) => DirectiveType::Plugin,
@@ -84,7 +88,7 @@ impl Directive {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum StringOrAccount {
     String(ZhangString),
     Account(Account),
@@ -18,7 +18,7 @@ impl<Key: Eq + Hash, Value> Default for MultiValueMap<Key, Value> {
 
 impl<Key: Hash + Eq, Value> MultiValueMap<Key, Value> {
     pub fn insert(&mut self, key: Key, value: Value) {
-        let key_store = self.inner.entry(key).or_insert_with(Vec::new);
+        let key_store = self.inner.entry(key).or_default();
         key_store.push(value);
     }
 
@@ -27,7 +27,7 @@ impl<Key: Hash + Eq, Value> MultiValueMap<Key, Value> {
         Key: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.inner.get(key.borrow()).and_then(|store| store.get(0))
+        self.inner.get(key.borrow()).and_then(|store| store.first())
     }
 
     pub fn pop_one<Q>(&mut self, key: &Q) -> Option<Value>
@@ -4,14 +4,16 @@ use std::ops::Deref;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct SpanInfo {
     pub start: usize,
     pub end: usize,
     pub content: String,
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>))]
     pub filename: Option<PathBuf>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Spanned<T: Debug + PartialEq> {
     pub data: T,
     pub span: SpanInfo,
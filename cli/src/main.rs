@@ -1,18 +1,29 @@
 use std::fmt::Debug;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use clap::{Args, Parser};
+use clap::builder::BoolishValueParser;
+use clap::{Args, Parser, Subcommand};
 use env_logger::Env;
 use log::{error, info, LevelFilter};
 use self_update::Status;
 use tokio::task::spawn_blocking;
 
 use beancount::Beancount;
-use zhang_core::exporter::{AppendableExporter, TextExporter};
+use json::{JsonExporter, JsonTransformer};
+use zhang_ast::Directive;
+use zhang_core::exporter::{AppendableExporter, Exporter as _, TextExporter};
 use zhang_core::ledger::Ledger;
 use zhang_core::transform::{TextTransformer, Transformer};
-use zhang_server::ServeConfig;
+use zhang_server::auth::AuthConfig;
+use zhang_server::config::{FileConfig, LedgerEntry};
+use zhang_server::error::ServerError;
+use zhang_server::update::GithubReleaseSource;
+use zhang_server::{LedgerConfig, ServeConfig, ServerResult};
+
+/// config file used by `serve` if present in the working directory and `--config` is not given
+const DEFAULT_CONFIG_FILE: &str = "zhang.toml";
 
 #[derive(Parser, Debug)]
 #[clap(about, version, author)]
@@ -26,6 +37,16 @@ pub enum Opts {
     /// start an internal server with frontend ui
     Serve(ServerOpts),
 
+    /// import ledger from other accounting tools into a fresh zhang project
+    Import(ImportOpts),
+
+    /// export openapi document of the rest api
+    Openapi(OpenapiOpts),
+
+    /// manage the config of server
+    #[clap(subcommand)]
+    Config(ConfigOpts),
+
     /// self update
     Update {
         #[clap(short, long)]
@@ -52,45 +73,225 @@ pub struct ExportOpts {
     pub path: PathBuf,
 
     /// the endpoint of main zhang file.
-    #[clap(short, long, default_value = "main.zhang")]
+    #[clap(long, default_value = "main.zhang")]
     pub endpoint: String,
 
-    /// the endpoint of main zhang file.
+    /// the format of exported content
     #[clap(short, long, default_value = "Text")]
     pub exporter: Exporter,
+
+    /// target file of exported content, print to stdout if not present
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, clap::ValueEnum)]
 pub enum Exporter {
+    #[clap(name = "Text")]
     Text,
+    #[clap(name = "Beancount")]
     Beancount,
+    #[clap(name = "Json")]
+    Json,
 }
 
 #[derive(Args, Debug)]
-pub struct ServerOpts {
-    /// base path of zhang project
+pub struct ImportOpts {
+    /// source file of the ledger to import
+    pub source: PathBuf,
+
+    /// base path of the new zhang project
     pub path: PathBuf,
 
     /// the endpoint of main zhang file.
     #[clap(short, long, default_value = "main.zhang")]
     pub endpoint: String,
 
-    /// serve port
-    #[clap(short, long, default_value_t = 8000)]
-    pub port: u16,
+    /// the format of source file
+    #[clap(short, long, default_value = "GnuCash")]
+    pub importer: Importer,
+}
+
+#[derive(Args, Debug)]
+pub struct OpenapiOpts {
+    /// target file of openapi document, print to stdout if not present
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// check whether the target file is up to date instead of writing it
+    #[clap(long, requires = "output")]
+    pub check: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigOpts {
+    /// validate the config file merged with arguments and `ZHANG_*` environment variables of `serve`
+    Check(ServerOpts),
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum Importer {
+    #[clap(name = "GnuCash")]
+    GnuCash,
+}
+
+#[derive(Args, Debug)]
+pub struct ServerOpts {
+    /// base path of zhang project, required unless ledgers are listed in the config file
+    pub path: Option<PathBuf>,
+
+    /// config file of server, `zhang.toml` in the working directory is used if present
+    #[clap(long, env = "ZHANG_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// the endpoint of main zhang file.
+    #[clap(short, long, default_value = "main.zhang")]
+    pub endpoint: String,
+
+    /// address to listen on, `0.0.0.0` if not present
+    #[clap(long, env = "ZHANG_BIND")]
+    pub bind: Option<IpAddr>,
+
+    /// serve port, 8000 if not present
+    #[clap(short, long, env = "ZHANG_PORT")]
+    pub port: Option<u16>,
 
     /// indicate cache database file path, use memory database if not present
-    #[clap(long)]
+    #[clap(long, env = "ZHANG_DATABASE")]
     pub database: Option<PathBuf>,
 
-    /// whether the server report version info for anonymous statistics
-    #[clap(long)]
+    /// report version info for anonymous statistics, disabled if not present
+    #[clap(long, env = "ZHANG_REPORT", value_parser = BoolishValueParser::new(), num_args = 0..=1, default_missing_value = "true")]
+    pub report: Option<bool>,
+
+    /// check new releases of zhang on github, disabled if not present
+    #[clap(long, env = "ZHANG_UPDATE_CHECK", value_parser = BoolishValueParser::new(), num_args = 0..=1, default_missing_value = "true")]
+    pub update_check: Option<bool>,
+
+    /// version report is disabled by default, kept for existing scripts
+    #[clap(long, hide = true)]
     pub no_report: bool,
+
+    /// file of `username:hash` lines for http basic authentication, hash is argon2 in PHC string format
+    #[clap(long, env = "ZHANG_AUTH_FILE")]
+    pub auth_file: Option<PathBuf>,
+
+    /// whitespace separated `username:hash` credentials for http basic authentication
+    #[clap(long, env = "ZHANG_AUTH_CREDENTIALS", hide_env_values = true)]
+    pub auth_credentials: Option<String>,
+
+    /// bearer token for api access, can be given multiple times
+    #[clap(long = "auth-token", env = "ZHANG_AUTH_TOKENS", value_delimiter = ',', hide_env_values = true)]
+    pub auth_tokens: Vec<String>,
+
+    /// origin allowed by cors, can be given multiple times, all origins are allowed if not present
+    #[clap(long = "cors-origin", env = "ZHANG_CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Vec<String>,
+
+    /// milliseconds to wait for more file changes before reloading the ledger, 500 if not present
+    #[clap(long, env = "ZHANG_WATCHER_DEBOUNCE_MS")]
+    pub watcher_debounce_ms: Option<u64>,
+
+    /// milliseconds to wait after each following file change, 100 if not present
+    #[clap(long, env = "ZHANG_WATCHER_INTERVAL_MS")]
+    pub watcher_interval_ms: Option<u64>,
+
+    /// disable all routes that modify the ledger
+    #[clap(long, env = "ZHANG_READONLY", value_parser = BoolishValueParser::new())]
+    pub readonly: bool,
+}
+
+impl ServerOpts {
+    /// merge arguments and environment variables into the config file, the former take precedence
+    fn serve_config(self) -> ServerResult<ServeConfig> {
+        let file = self.config.or_else(|| Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|it| it.exists()));
+        let mut config = match &file {
+            Some(file) => {
+                info!("using config file {}", file.display());
+                FileConfig::load(file)?
+            }
+            None => FileConfig::default(),
+        };
+        if !self.cors_origins.is_empty() {
+            config.cors_origins = self.cors_origins;
+        }
+        if !self.auth_tokens.is_empty() {
+            config.auth.tokens = self.auth_tokens;
+        }
+        if let Some(debounce_ms) = self.watcher_debounce_ms {
+            config.watcher.debounce_ms = debounce_ms;
+        }
+        if let Some(interval_ms) = self.watcher_interval_ms {
+            config.watcher.interval_ms = interval_ms;
+        }
+        config.validate()?;
+
+        let ledgers = match (self.path, config.ledgers.is_empty()) {
+            (Some(path), true) => vec![LedgerEntry {
+                id: "default".to_owned(),
+                path,
+                endpoint: self.endpoint,
+                database: self.database.or(config.database),
+            }],
+            (None, false) => config.ledgers,
+            (Some(_), false) => {
+                return Err(ServerError::InvalidConfig(
+                    "ledgers are given by both the path argument and the config file, use only one of them".to_owned(),
+                ))
+            }
+            (None, true) => {
+                return Err(ServerError::InvalidConfig(
+                    "no ledger to serve, give the path of ledger or list ledgers in the config file".to_owned(),
+                ))
+            }
+        };
+        let ledgers = ledgers
+            .into_iter()
+            .map(|entry| {
+                let format = SupportedFormat::from_path(&entry.endpoint)
+                    .ok_or_else(|| ServerError::InvalidConfig(format!("unsupported file type of endpoint {} of ledger {}", entry.endpoint, entry.id)))?;
+                let entry_file = entry.path.join(&entry.endpoint);
+                if !entry_file.is_file() {
+                    return Err(ServerError::InvalidConfig(format!(
+                        "entry file {} of ledger {} does not exist",
+                        entry_file.display(),
+                        entry.id
+                    )));
+                }
+                Ok(LedgerConfig {
+                    id: entry.id,
+                    path: entry.path,
+                    endpoint: entry.endpoint,
+                    database: entry.database,
+                    exporter: format.exporter(),
+                    transformer: format.transformer(),
+                })
+            })
+            .collect::<ServerResult<Vec<_>>>()?;
+        let auth = AuthConfig::load(
+            self.auth_file.or(config.auth.file).as_deref(),
+            self.auth_credentials.or(config.auth.credentials).as_deref(),
+            config.auth.tokens,
+        )?;
+        Ok(ServeConfig {
+            ledgers,
+            bind: self.bind.or(config.bind).unwrap_or(IpAddr::from([0, 0, 0, 0])),
+            port: self.port.or(config.port).unwrap_or(8000),
+            cors_origins: config.cors_origins,
+            watcher: config.watcher,
+            update_check: self.update_check.or(config.update.check).unwrap_or(false),
+            report: !self.no_report && self.report.or(config.update.report).unwrap_or(false),
+            release_source: Arc::new(GithubReleaseSource),
+            auth,
+            readonly: self.readonly || config.readonly.unwrap_or(false),
+        })
+    }
 }
 
 enum SupportedFormat {
     Zhang,
     Beancount,
+    Json,
 }
 
 impl SupportedFormat {
@@ -98,6 +299,7 @@ impl SupportedFormat {
         path.as_ref().extension().and_then(|it| it.to_str()).and_then(|ext| match ext {
             "bc" | "bean" => Some(SupportedFormat::Beancount),
             "zhang" => Some(SupportedFormat::Zhang),
+            "json" => Some(SupportedFormat::Json),
             _ => None,
         })
     }
@@ -105,12 +307,14 @@ impl SupportedFormat {
         match self {
             SupportedFormat::Zhang => Arc::new(TextTransformer::default()),
             SupportedFormat::Beancount => Arc::new(Beancount::default()),
+            SupportedFormat::Json => Arc::new(JsonTransformer::default()),
         }
     }
     fn exporter(&self) -> Arc<dyn AppendableExporter> {
         match self {
             SupportedFormat::Zhang => Arc::new(TextExporter {}),
-            SupportedFormat::Beancount => Arc::new(Beancount {}),
+            SupportedFormat::Beancount => Arc::new(Beancount::default()),
+            SupportedFormat::Json => Arc::new(JsonExporter {}),
         }
     }
 }
@@ -124,21 +328,67 @@ impl Opts {
                     .await
                     .expect("Cannot load ledger");
             }
-            Opts::Export(_) => todo!(),
-            Opts::Serve(opts) => {
+            Opts::Export(opts) => {
                 let format = SupportedFormat::from_path(&opts.endpoint).expect("unsupported file type");
-                zhang_server::serve(ServeConfig {
-                    path: opts.path,
-                    endpoint: opts.endpoint,
-                    port: opts.port,
-                    database: opts.database,
-                    no_report: opts.no_report,
-                    exporter: format.exporter(),
-                    transformer: format.transformer(),
-                })
-                .await
-                .expect("cannot serve")
+                let transform_result = format.transformer().load(opts.path, opts.endpoint).expect("Cannot load ledger");
+                // all directives are exported into one single target, includes are meaningless there
+                let directives = transform_result.directives.into_iter().filter(|it| !matches!(it.data, Directive::Include(_)));
+                let content = match opts.exporter {
+                    Exporter::Text => directives.map(|it| TextExporter {}.export_directive(it.data)).collect::<Vec<_>>().join("\n\n"),
+                    Exporter::Beancount => directives
+                        .map(|it| Beancount::default().export_directive(it.data))
+                        .collect::<Vec<_>>()
+                        .join("\n\n"),
+                    Exporter::Json => JsonExporter {}.export_directives(directives.collect()),
+                };
+                match opts.output {
+                    Some(output) => std::fs::write(output, content).expect("cannot write exported content"),
+                    None => println!("{}", content),
+                }
+            }
+            Opts::Serve(opts) => {
+                let config = opts.serve_config().unwrap_or_else(|e| {
+                    error!("{}", e);
+                    std::process::exit(1);
+                });
+                zhang_server::serve(config).await.expect("cannot serve")
+            }
+            Opts::Import(opts) => match opts.importer {
+                Importer::GnuCash => gnucash::import(&opts.source, &opts.path, &opts.endpoint)
+                    .await
+                    .expect("cannot import gnucash book"),
+            },
+            Opts::Openapi(opts) => {
+                let content = format!("{}\n", zhang_server::openapi::openapi_json());
+                match opts.output {
+                    Some(output) if opts.check => {
+                        if std::fs::read_to_string(&output).ok().as_deref() != Some(content.as_str()) {
+                            error!(
+                                "{} is out of date, run `zhang openapi --output {}` to update it",
+                                output.display(),
+                                output.display()
+                            );
+                            std::process::exit(1);
+                        }
+                    }
+                    Some(output) => std::fs::write(output, content).expect("cannot write openapi document"),
+                    None => print!("{}", content),
+                }
             }
+            Opts::Config(ConfigOpts::Check(opts)) => match opts.serve_config() {
+                Ok(config) => {
+                    for ledger in &config.ledgers {
+                        info!("ledger {}: {}", ledger.id, ledger.path.join(&ledger.endpoint).display());
+                    }
+                    info!("listen on {}:{}", config.bind, config.port);
+                    info!("update checking: {}, version report: {}", config.update_check, config.report);
+                    info!("config is valid");
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    std::process::exit(1);
+                }
+            },
             Opts::Update { verbose } => {
                 info!("performing self update");
                 info!("current version is {}", env!("CARGO_PKG_VERSION"));
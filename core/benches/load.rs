@@ -0,0 +1,73 @@
+//! time of loading a generated ledger, run by `cargo bench -p zhang-core --bench load`
+use std::fmt::Write;
+use std::time::Instant;
+
+use chrono::{Duration, NaiveDate};
+use zhang_core::ledger::Ledger;
+use zhang_core::transform::TextTransformer;
+
+const TRANSACTIONS: usize = 100_000;
+const ACCOUNTS: [&str; 6] = [
+    "Expenses:Food",
+    "Expenses:Travel",
+    "Expenses:Rent",
+    "Income:Salary",
+    "Income:Bonus",
+    "Liabilities:CreditCard",
+];
+
+fn generate(transactions: usize) -> String {
+    let mut content = String::new();
+    writeln!(content, "option \"operating_currency\" \"CNY\"").unwrap();
+    writeln!(content, "1970-01-01 commodity CNY").unwrap();
+    writeln!(content, "1970-01-01 open Assets:Bank CNY").unwrap();
+    for account in ACCOUNTS {
+        writeln!(content, "1970-01-01 open {account} CNY").unwrap();
+    }
+    let start = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+    for idx in 0..transactions {
+        let date = start + Duration::hours(idx as i64);
+        let account = ACCOUNTS[idx % ACCOUNTS.len()];
+        writeln!(
+            content,
+            "{} \"Payee {}\" \"transaction {idx}\" #tag{}",
+            date.format("%Y-%m-%d"),
+            idx % 100,
+            idx % 10
+        )
+        .unwrap();
+        writeln!(content, "  {account} {}.{:02} CNY", idx % 1000, idx % 100).unwrap();
+        writeln!(content, "  Assets:Bank").unwrap();
+        if idx % 1000 == 999 {
+            writeln!(
+                content,
+                "{} balance Assets:Bank 0 CNY with pad Income:Bonus",
+                (date + Duration::days(1)).format("%Y-%m-%d")
+            )
+            .unwrap();
+        }
+    }
+    content
+}
+
+fn main() {
+    // `cargo test` runs benches once without `--bench` to make sure they work, a small ledger is enough there
+    let transactions = if std::env::args().any(|arg| arg == "--bench") { TRANSACTIONS } else { 100 };
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(temp_dir.path().join("main.zhang"), generate(transactions)).unwrap();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let start = Instant::now();
+    let ledger = runtime
+        .block_on(Ledger::load::<TextTransformer>(temp_dir.path().to_path_buf(), "main.zhang".to_string()))
+        .unwrap();
+    let duration = start.elapsed();
+    println!(
+        "load {} directives of {} transactions in {:?}, {:.0} transactions/s",
+        ledger.directives.len(),
+        transactions,
+        duration,
+        transactions as f64 / duration.as_secs_f64()
+    );
+}
@@ -0,0 +1,71 @@
+use glob::{glob, Pattern};
+use itertools::Itertools;
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::error::IoErrorIntoZhangError;
+use crate::ZhangResult;
+
+/// version of the data written by processing directives, cache databases of other versions are rebuilt.
+///
+/// it should be increased once the way of processing directives is changed, otherwise cache databases are kept across upgrades.
+/// schema changes are handled by migrations instead.
+pub const CACHE_VERSION: i64 = 1;
+
+#[derive(Debug, PartialEq, Eq, FromRow)]
+pub struct Fingerprint {
+    pub path: String,
+    pub hash: String,
+}
+
+impl Fingerprint {
+    /// sha256 of every file matched by visited patterns
+    pub fn of_files(visited_files: &[Pattern]) -> ZhangResult<Vec<Fingerprint>> {
+        let mut fingerprints = vec![];
+        for pattern in visited_files {
+            for path in glob(pattern.as_str()).unwrap().flatten() {
+                let hash = sha256::try_digest(path.as_path()).with_path(&path)?;
+                fingerprints.push(Fingerprint {
+                    path: path.to_string_lossy().to_string(),
+                    hash,
+                });
+            }
+        }
+        Ok(fingerprints
+            .into_iter()
+            .sorted_by(|a, b| a.path.cmp(&b.path))
+            .dedup_by(|a, b| a.path == b.path)
+            .collect_vec())
+    }
+}
+
+/// fingerprints of the files which the cache database is built from, `None` if it's not built by current cache version
+///
+/// it only reads the database, which may not be migrated yet.
+pub async fn read_fingerprints(conn: &mut SqliteConnection) -> ZhangResult<Option<Vec<Fingerprint>>> {
+    let version: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(&mut *conn).await?;
+    if version != CACHE_VERSION {
+        return Ok(None);
+    }
+    let existed: Option<String> = sqlx::query_scalar("select name from sqlite_master where type = 'table' and name = 'fingerprints'")
+        .fetch_optional(&mut *conn)
+        .await?;
+    if existed.is_none() {
+        return Ok(None);
+    }
+    let fingerprints = sqlx::query_as("select path, hash from fingerprints order by path")
+        .fetch_all(&mut *conn)
+        .await?;
+    Ok(Some(fingerprints))
+}
+
+pub async fn write_fingerprints(conn: &mut SqliteConnection, fingerprints: &[Fingerprint]) -> ZhangResult<()> {
+    for fingerprint in fingerprints {
+        sqlx::query("INSERT OR REPLACE INTO fingerprints (path, hash) VALUES ($1, $2)")
+            .bind(&fingerprint.path)
+            .bind(&fingerprint.hash)
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query(&format!("PRAGMA user_version = {CACHE_VERSION}")).execute(&mut *conn).await?;
+    Ok(())
+}
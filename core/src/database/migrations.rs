@@ -1,3 +1,4 @@
+use log::info;
 use sqlx::sqlite::SqliteConnection;
 use sqlx::Acquire;
 
@@ -5,12 +6,13 @@ use crate::ZhangResult;
 
 pub struct Migration;
 
-static TABLES: [&str; 12] = [
+static TABLES: [&str; 13] = [
     "options",
     "accounts",
     "metas",
     "commodities",
     "documents",
+    "transactions_fts",
     "transactions",
     "transaction_links",
     "transaction_tags",
@@ -19,48 +21,213 @@ static TABLES: [&str; 12] = [
     "commodity_lots",
     "errors",
 ];
-static VIEWS: [&str; 2] = ["account_balance", "account_daily_balance"];
-
-static TABLES_SQL: [&str; 14] = [
-    include_str!("./schemas/options.sql"),
-    include_str!("./schemas/prices.sql"),
-    include_str!("./schemas/accounts.sql"),
-    include_str!("./schemas/metas.sql"),
-    include_str!("./schemas/commodities.sql"),
-    include_str!("./schemas/commodity_lots.sql"),
-    include_str!("./schemas/documents.sql"),
-    include_str!("./schemas/transactions.sql"),
-    include_str!("./schemas/transaction_links.sql"),
-    include_str!("./schemas/transaction_tags.sql"),
-    include_str!("./schemas/transaction_postings.sql"),
-    include_str!("./schemas/account_balance.sql"),
-    include_str!("./schemas/account_daily_balance.sql"),
-    include_str!("./schemas/errors.sql"),
+
+/// numbered schema migrations, which are forward only.
+///
+/// a released migration must never be changed, schema changes are appended as a new migration instead,
+/// so that databases created by previous versions can be upgraded.
+static MIGRATIONS: [(i64, &str, &[&str]); 3] = [
+    (
+        1,
+        "init",
+        &[
+            include_str!("./schemas/options.sql"),
+            include_str!("./schemas/prices.sql"),
+            include_str!("./schemas/accounts.sql"),
+            include_str!("./schemas/metas.sql"),
+            include_str!("./schemas/commodities.sql"),
+            include_str!("./schemas/commodity_lots.sql"),
+            include_str!("./schemas/documents.sql"),
+            include_str!("./schemas/transactions.sql"),
+            include_str!("./schemas/transaction_links.sql"),
+            include_str!("./schemas/transaction_tags.sql"),
+            include_str!("./schemas/transaction_postings.sql"),
+            include_str!("./schemas/account_balance.sql"),
+            include_str!("./schemas/account_daily_balance.sql"),
+            include_str!("./schemas/errors.sql"),
+        ],
+    ),
+    (
+        2,
+        "transactions_fts",
+        &[
+            include_str!("./schemas/transactions_fts.sql"),
+            "insert into transactions_fts(transactions_fts) values ('rebuild')",
+        ],
+    ),
+    (3, "fingerprints", &[include_str!("./schemas/fingerprints.sql")]),
 ];
 
+/// tables created by each migration that was released before migrations are versioned
+static LEGACY_TABLES: [(i64, &str); 3] = [(1, "transactions"), (2, "transactions_fts"), (3, "fingerprints")];
+
 impl Migration {
-    pub async fn init_database_if_missing(conn: &mut SqliteConnection) -> ZhangResult<()> {
-        Migration::clear_tables(conn).await?;
+    /// the version of the latest migration
+    pub fn latest_version() -> i64 {
+        MIGRATIONS.last().map(|(version, _, _)| *version).unwrap_or_default()
+    }
 
-        let mut trx = conn.begin().await?;
-        for sql in TABLES_SQL {
-            sqlx::query(sql).execute(&mut trx).await?;
+    /// upgrade the database to the latest version by applying every pending migration
+    pub async fn migrate(conn: &mut SqliteConnection) -> ZhangResult<()> {
+        Migration::migrate_to(conn, Migration::latest_version()).await
+    }
+
+    /// apply pending migrations up to the given version, each one runs in its own transaction along with its record
+    pub async fn migrate_to(conn: &mut SqliteConnection, version: i64) -> ZhangResult<()> {
+        if !Migration::is_versioned(conn).await? {
+            Migration::version_legacy_database(conn).await?;
         }
-        trx.commit().await?;
+        let current = Migration::current_version(conn).await?;
 
+        for (migration_version, name, sqls) in MIGRATIONS.iter().filter(|(it, _, _)| *it > current && *it <= version) {
+            let mut trx = conn.begin().await?;
+            for sql in sqls.iter() {
+                sqlx::query(sql).execute(&mut trx).await?;
+            }
+            Migration::record(&mut trx, *migration_version, name).await?;
+            trx.commit().await?;
+            if current > 0 {
+                info!("database is migrated to version {}: {}", migration_version, name);
+            }
+        }
         Ok(())
     }
-    pub async fn clear_tables(conn: &mut SqliteConnection) -> ZhangResult<()> {
-        let mut trx = conn.begin().await?;
 
-        for table_name in TABLES {
-            sqlx::query(&format!("DROP TABLE IF EXISTS {table_name}")).execute(&mut trx).await?;
+    /// the version of the latest applied migration, 0 for an empty database
+    pub async fn current_version(conn: &mut SqliteConnection) -> ZhangResult<i64> {
+        if !Migration::is_versioned(conn).await? {
+            return Ok(0);
         }
+        let version: Option<i64> = sqlx::query_scalar("select max(version) from schema_migrations").fetch_one(&mut *conn).await?;
+        Ok(version.unwrap_or_default())
+    }
 
-        for view_name in VIEWS {
-            sqlx::query(&format!("DROP VIEW IF EXISTS {view_name}")).execute(&mut trx).await?;
+    async fn is_versioned(conn: &mut SqliteConnection) -> ZhangResult<bool> {
+        let existed: Option<String> = sqlx::query_scalar("select name from sqlite_master where type = 'table' and name = 'schema_migrations'")
+            .fetch_optional(&mut *conn)
+            .await?;
+        Ok(existed.is_some())
+    }
+
+    /// record the migrations which databases created before migrations are versioned are built by already.
+    ///
+    /// those schemas are never changed but only appended, so the applied ones are told by the tables they create.
+    async fn version_legacy_database(conn: &mut SqliteConnection) -> ZhangResult<()> {
+        let tables: Vec<String> = sqlx::query_scalar("select name from sqlite_master where type = 'table'")
+            .fetch_all(&mut *conn)
+            .await?;
+        let mut trx = conn.begin().await?;
+        sqlx::query(include_str!("./schemas/schema_migrations.sql")).execute(&mut trx).await?;
+        for (version, _) in LEGACY_TABLES.iter().take_while(|(_, table)| tables.iter().any(|it| it == table)) {
+            let (_, name, _) = MIGRATIONS.iter().find(|(it, _, _)| it == version).expect("legacy migration should exist");
+            Migration::record(&mut trx, *version, name).await?;
+            info!("database created before versioning is at version {}: {}", version, name);
         }
         trx.commit().await?;
         Ok(())
     }
+
+    async fn record(conn: &mut SqliteConnection, version: i64, name: &str) -> ZhangResult<()> {
+        sqlx::query("INSERT INTO schema_migrations (version, name) VALUES ($1, $2)")
+            .bind(version)
+            .bind(name)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// copy all rows of given in memory database into the current one, which is initialized already
+    pub async fn copy_from(conn: &mut SqliteConnection, database: &str) -> ZhangResult<()> {
+        sqlx::query("ATTACH DATABASE $1 AS previous").bind(database).execute(&mut *conn).await?;
+        let mut trx = conn.begin().await?;
+        // the full text index is filled by the insert trigger of transactions
+        for table_name in TABLES.iter().filter(|it| **it != "transactions_fts") {
+            sqlx::query(&format!("INSERT INTO main.{table_name} SELECT * FROM previous.{table_name}"))
+                .execute(&mut trx)
+                .await?;
+        }
+        trx.commit().await?;
+        sqlx::query("DETACH DATABASE previous").execute(&mut *conn).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+    use sqlx::{ConnectOptions, Connection};
+    use std::str::FromStr;
+    use tempfile::tempdir;
+
+    use crate::database::migrations::Migration;
+
+    async fn insert_transaction(conn: &mut SqliteConnection) {
+        sqlx::query(
+            r#"INSERT INTO transactions (id, datetime, type, payee, narration, source_file, span_start, span_end)
+               VALUES ('trx', '2022-01-01 00:00:00', '*', 'KFC', 'crazy thursday', 'main.zhang', 0, 10)"#,
+        )
+        .execute(conn)
+        .await
+        .unwrap();
+    }
+
+    async fn applied_versions(conn: &mut SqliteConnection) -> Vec<i64> {
+        sqlx::query_scalar("select version from schema_migrations order by version")
+            .fetch_all(conn)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_migrate_database_of_previous_schema() {
+        let temp_dir = tempdir().unwrap();
+        let database = temp_dir.path().join("cache.db");
+
+        let mut conn = SqliteConnectOptions::default()
+            .filename(&database)
+            .create_if_missing(true)
+            .connect()
+            .await
+            .unwrap();
+        sqlx::query(include_str!("../../tests/fixtures/baseline.sql")).execute(&mut conn).await.unwrap();
+        insert_transaction(&mut conn).await;
+        sqlx::query("PRAGMA user_version = 1").execute(&mut conn).await.unwrap();
+        conn.close().await.unwrap();
+
+        let mut conn = SqliteConnectOptions::default().filename(&database).connect().await.unwrap();
+        assert_eq!(0, Migration::current_version(&mut conn).await.unwrap());
+        Migration::migrate(&mut conn).await.unwrap();
+        assert_eq!(Migration::latest_version(), Migration::current_version(&mut conn).await.unwrap());
+        assert_eq!(vec![1, 2, 3], applied_versions(&mut conn).await);
+
+        let matched: Vec<i64> = sqlx::query_scalar("select rowid from transactions_fts where transactions_fts match 'crazy'")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(1, matched.len(), "existing transactions should be indexed by new full text table");
+        let fingerprints: Vec<String> = sqlx::query_scalar("select path from fingerprints").fetch_all(&mut conn).await.unwrap();
+        assert!(fingerprints.is_empty());
+        let user_version: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(&mut conn).await.unwrap();
+        assert_eq!(1, user_version, "cache version is kept by migrations");
+    }
+
+    #[tokio::test]
+    async fn should_record_applied_migrations_of_database_created_before_versioning() {
+        let mut conn = SqliteConnectOptions::from_str("sqlite::memory:").unwrap().connect().await.unwrap();
+        Migration::migrate_to(&mut conn, 2).await.unwrap();
+        insert_transaction(&mut conn).await;
+        sqlx::query("drop table schema_migrations").execute(&mut conn).await.unwrap();
+
+        Migration::migrate(&mut conn).await.unwrap();
+        assert_eq!(vec![1, 2, 3], applied_versions(&mut conn).await);
+        let transactions: i64 = sqlx::query_scalar("select count(1) from transactions").fetch_one(&mut conn).await.unwrap();
+        assert_eq!(1, transactions);
+    }
+
+    #[tokio::test]
+    async fn should_apply_every_migration_to_empty_database() {
+        let mut conn = SqliteConnectOptions::from_str("sqlite::memory:").unwrap().connect().await.unwrap();
+        Migration::migrate(&mut conn).await.unwrap();
+        assert_eq!(vec![1, 2, 3], applied_versions(&mut conn).await);
+    }
 }
@@ -1,2 +1,5 @@
+#[cfg(feature = "sqlite")]
+pub mod cache;
+#[cfg(feature = "sqlite")]
 pub mod migrations;
 pub mod type_ext;
@@ -0,0 +1,6 @@
+create table if not exists fingerprints
+(
+    path varchar not null
+        primary key,
+    hash varchar not null
+);
@@ -0,0 +1,7 @@
+create table if not exists schema_migrations
+(
+    version    integer  not null
+        primary key,
+    name       varchar  not null,
+    applied_at datetime not null default current_timestamp
+);
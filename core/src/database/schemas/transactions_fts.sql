@@ -0,0 +1,29 @@
+create virtual table if not exists transactions_fts using fts5
+(
+    payee,
+    narration,
+    content = 'transactions',
+    content_rowid = 'sequence'
+);
+
+create trigger if not exists transactions_fts_insert
+    after insert
+    on transactions
+begin
+    insert into transactions_fts(rowid, payee, narration) values (new.sequence, new.payee, new.narration);
+end;
+
+create trigger if not exists transactions_fts_delete
+    after delete
+    on transactions
+begin
+    insert into transactions_fts(transactions_fts, rowid, payee, narration) values ('delete', old.sequence, old.payee, old.narration);
+end;
+
+create trigger if not exists transactions_fts_update
+    after update
+    on transactions
+begin
+    insert into transactions_fts(transactions_fts, rowid, payee, narration) values ('delete', old.sequence, old.payee, old.narration);
+    insert into transactions_fts(rowid, payee, narration) values (new.sequence, new.payee, new.narration);
+end;
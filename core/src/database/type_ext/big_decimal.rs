@@ -1,15 +1,22 @@
 use std::ops::Deref;
+#[cfg(feature = "sqlite")]
 use std::str::FromStr;
 
 use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "sqlite")]
 use sqlx::database::{HasArguments, HasValueRef};
+#[cfg(feature = "sqlite")]
 use sqlx::encode::IsNull;
+#[cfg(feature = "sqlite")]
 use sqlx::error::BoxDynError;
+#[cfg(feature = "sqlite")]
 use sqlx::sqlite::SqliteTypeInfo;
+#[cfg(feature = "sqlite")]
 use sqlx::{Database, Decode, Encode, Sqlite};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema), schema(value_type = String, example = "1.23"))]
 pub struct ZhangBigDecimal(pub BigDecimal);
 
 impl Deref for ZhangBigDecimal {
@@ -20,6 +27,7 @@ impl Deref for ZhangBigDecimal {
     }
 }
 
+#[cfg(feature = "sqlite")]
 impl<'r, DB: Database> Decode<'r, DB> for ZhangBigDecimal
 where
     String: Decode<'r, DB>,
@@ -29,6 +37,7 @@ where
         Ok(ZhangBigDecimal(BigDecimal::from_str(&value).unwrap()))
     }
 }
+#[cfg(feature = "sqlite")]
 impl<'q, DB: Database> Encode<'q, DB> for ZhangBigDecimal
 where
     String: Encode<'q, DB>,
@@ -39,6 +48,7 @@ where
     }
 }
 
+#[cfg(feature = "sqlite")]
 impl sqlx::Type<Sqlite> for ZhangBigDecimal {
     fn type_info() -> SqliteTypeInfo {
         <f64 as sqlx::Type<Sqlite>>::type_info()
@@ -1,368 +1,57 @@
-use crate::domains::schemas::{
-    AccountBalanceDomain, AccountDailyBalanceDomain, AccountDomain, AccountJournalDomain, CommodityDomain, ErrorDomain, ErrorType, MetaDomain, MetaType,
-    OptionDomain, PriceDomain, TransactionInfoDomain,
-};
+use crate::domains::schemas::{MetaDomain, MetaType, PriceDomain};
+use crate::store::LedgerStore;
 use crate::ZhangResult;
 use chrono::{NaiveDateTime, TimeZone};
 use chrono_tz::Tz;
-use itertools::Itertools;
-use sqlx::pool::PoolConnection;
-use sqlx::{Acquire, FromRow, Sqlite};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use uuid::Uuid;
-use zhang_ast::{Meta, SpanInfo};
+use std::ops::{Deref, DerefMut};
+use zhang_ast::Meta;
 
 pub mod schemas;
 
-#[derive(FromRow)]
-struct ValueRow {
-    value: String,
+pub(crate) fn posting_identifier(trx_id: &str, posting_index: usize) -> String {
+    format!("{}:{}", trx_id, posting_index)
 }
 
-pub struct Operations {
-    pub(crate) pool: PoolConnection<Sqlite>,
+/// domain queries over the ledger store, which is a pooled sqlite connection, the one of processing transaction, or the in memory store
+pub struct Operations<'a> {
+    pub(crate) store: Box<dyn LedgerStore + 'a>,
     pub timezone: Tz,
 }
-impl Operations {
-    pub async fn options(&mut self) -> ZhangResult<Vec<OptionDomain>> {
-        let conn = self.pool.acquire().await?;
 
-        let options = sqlx::query_as::<_, OptionDomain>(
-            r#"
-                select key, value from options
-                "#,
-        )
-        .fetch_all(conn)
-        .await?;
-        Ok(options)
-    }
-    pub async fn option(&mut self, key: impl AsRef<str>) -> ZhangResult<Option<OptionDomain>> {
-        let conn = self.pool.acquire().await?;
-
-        let option = sqlx::query_as::<_, OptionDomain>(
-            r#"
-                select key, value from options where key = $1
-                "#,
-        )
-        .bind(key.as_ref())
-        .fetch_optional(conn)
-        .await?;
-        Ok(option)
-    }
-
-    pub async fn accounts_latest_balance(&mut self) -> ZhangResult<Vec<AccountDailyBalanceDomain>> {
-        let conn = self.pool.acquire().await?;
-        Ok(sqlx::query_as::<_, AccountDailyBalanceDomain>(
-            r#"
-                SELECT
-                    date(datetime) AS date,
-                    account,
-                    balance_number,
-                    balance_commodity
-                FROM
-                    account_daily_balance
-                GROUP BY
-                    account
-                HAVING
-                    max(datetime)
-            "#,
-        )
-        .fetch_all(conn)
-        .await?)
+impl<'a> Operations<'a> {
+    pub fn new(store: Box<dyn LedgerStore + 'a>, timezone: Tz) -> Self {
+        Self { store, timezone }
     }
 
     pub async fn get_price(&mut self, date: NaiveDateTime, from: impl AsRef<str>, to: impl AsRef<str>) -> ZhangResult<Option<PriceDomain>> {
         let datetime = self.timezone.from_local_datetime(&date).unwrap();
-        let conn = self.pool.acquire().await?;
-        Ok(sqlx::query_as::<_, PriceDomain>(
-            "select datetime, commodity, amount, target_commodity from prices where datetime <= $1 and commodity = $2 and target_commodity = $3",
-        )
-        .bind(datetime)
-        .bind(from.as_ref())
-        .bind(to.as_ref())
-        .fetch_optional(conn)
-        .await?)
-    }
-
-    pub async fn metas(&mut self, type_: MetaType, type_identifier: impl AsRef<str>) -> ZhangResult<Vec<MetaDomain>> {
-        let conn = self.pool.acquire().await?;
-
-        let rows = sqlx::query_as::<_, MetaDomain>(
-            r#"
-            select type as meta_type, type_identifier, key, value from metas where type = $1 and type_identifier = $2
-        "#,
-        )
-        .bind(type_.as_ref())
-        .bind(type_identifier.as_ref())
-        .fetch_all(conn)
-        .await?;
-        Ok(rows)
-    }
-
-    pub async fn trx_tags(&mut self, trx_id: impl AsRef<str>) -> ZhangResult<Vec<String>> {
-        let conn = self.pool.acquire().await?;
-
-        let rows = sqlx::query_as::<_, ValueRow>(
-            r#"
-        select tag as value from transaction_tags where trx_id = $1
-        "#,
-        )
-        .bind(trx_id.as_ref())
-        .fetch_all(conn)
-        .await?;
-        Ok(rows.into_iter().map(|it| it.value).collect_vec())
-    }
-
-    pub async fn trx_links(&mut self, trx_id: impl AsRef<str>) -> ZhangResult<Vec<String>> {
-        let conn = self.pool.acquire().await?;
-
-        let rows = sqlx::query_as::<_, ValueRow>(
-            r#"
-        select link as value from transaction_links where trx_id = $1
-        "#,
-        )
-        .bind(trx_id.as_ref())
-        .fetch_all(conn)
-        .await?;
-        Ok(rows.into_iter().map(|it| it.value).collect_vec())
-    }
-
-    pub async fn commodity(&mut self, name: &str) -> ZhangResult<Option<CommodityDomain>> {
-        let conn = self.pool.acquire().await?;
-
-        let option = sqlx::query_as::<_, CommodityDomain>(
-            r#"
-                select * from commodities where name = $1
-                "#,
-        )
-        .bind(name)
-        .fetch_optional(conn)
-        .await?;
-        Ok(option)
-    }
-    pub async fn exist_commodity(&mut self, name: &str) -> ZhangResult<bool> {
-        let conn = self.pool.acquire().await?;
-
-        Ok(sqlx::query("select 1 from commodities where name = $1")
-            .bind(name)
-            .fetch_optional(conn)
-            .await?
-            .is_some())
-    }
-
-    pub async fn exist_account(&mut self, name: &str) -> ZhangResult<bool> {
-        let conn = self.pool.acquire().await?;
-
-        Ok(sqlx::query("select 1 from accounts where name = $1")
-            .bind(name)
-            .fetch_optional(conn)
-            .await?
-            .is_some())
-    }
-
-    pub async fn transaction_counts(&mut self) -> ZhangResult<i64> {
-        let conn = self.pool.acquire().await?;
-        Ok(sqlx::query_as::<_, (i64,)>(r#"select count(1) from transactions"#).fetch_one(conn).await?.0)
-    }
-
-    pub async fn transaction_span(&mut self, id: &str) -> ZhangResult<TransactionInfoDomain> {
-        let conn = self.pool.acquire().await?;
-        Ok(
-            sqlx::query_as::<_, TransactionInfoDomain>(r#"select id, source_file, span_start, span_end from transactions where id = $1"#)
-                .bind(id)
-                .fetch_one(conn)
-                .await?,
-        )
-    }
-
-    pub async fn account_balances(&mut self) -> ZhangResult<Vec<AccountBalanceDomain>> {
-        let conn = self.pool.acquire().await?;
-        Ok(sqlx::query_as::<_, AccountBalanceDomain>(
-            r#"
-                        select datetime, account, account_status, balance_number, balance_commodity
-                        from account_balance
-            "#,
-        )
-        .fetch_all(conn)
-        .await?)
+        self.store.latest_price(datetime, from.as_ref(), to.as_ref()).await
     }
 
-    pub async fn single_account_balances(&mut self, account_name: &str) -> ZhangResult<Vec<AccountBalanceDomain>> {
-        let conn = self.pool.acquire().await?;
-        Ok(sqlx::query_as::<_, AccountBalanceDomain>(
-            r#"
-                select datetime, account, account_status, balance_number, balance_commodity
-                from account_balance
-                where account = $1
-            "#,
-        )
-        .bind(account_name)
-        .fetch_all(conn)
-        .await?)
+    /// posting meta is identified by transaction id and the position of posting in transaction
+    pub async fn posting_metas(&mut self, trx_id: impl AsRef<str>, posting_index: usize) -> ZhangResult<Vec<MetaDomain>> {
+        self.store
+            .metas(MetaType::PostingMeta, &posting_identifier(trx_id.as_ref(), posting_index))
+            .await
     }
 
-    pub async fn account_journals(&mut self, account: &str) -> ZhangResult<Vec<AccountJournalDomain>> {
-        let conn = self.pool.acquire().await?;
-        Ok(sqlx::query_as::<_, AccountJournalDomain>(
-            r#"
-                    select datetime,
-                           trx_id,
-                           account,
-                           payee,
-                           narration,
-                           inferred_unit_number,
-                           inferred_unit_commodity,
-                           account_after_number,
-                           account_after_commodity
-                    from transaction_postings
-                             join transactions on transactions.id = transaction_postings.trx_id
-                    where account = $1
-                    order by datetime desc, transactions.sequence desc
-            "#,
-        )
-        .bind(account)
-        .fetch_all(conn)
-        .await?)
-    }
-    pub async fn account_dated_journals(&mut self, account_type: &str, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountJournalDomain>> {
-        let conn = self.pool.acquire().await?;
-        Ok(sqlx::query_as::<_, AccountJournalDomain>(
-            r#"
-                select datetime,
-                       trx_id,
-                       account,
-                       payee,
-                       narration,
-                       inferred_unit_number,
-                       inferred_unit_commodity,
-                       account_after_number,
-                       account_after_commodity
-                from transaction_postings
-                         join transactions on transactions.id = transaction_postings.trx_id
-                         join accounts on accounts.name = transaction_postings.account
-                where datetime >= $1
-                  and datetime <= $2
-                  and accounts.type = $3
-            "#,
-        )
-        .bind(from)
-        .bind(to)
-        .bind(account_type)
-        .fetch_all(conn)
-        .await?)
-    }
-
-    pub async fn errors(&mut self) -> ZhangResult<Vec<ErrorDomain>> {
-        let conn = self.pool.acquire().await?;
-
-        #[derive(FromRow)]
-        struct ErrorRow {
-            pub id: String,
-            pub filename: Option<String>,
-            pub span_start: Option<i64>,
-            pub span_end: Option<i64>,
-            pub content: String,
-            pub error_type: ErrorType,
-            pub metas: String,
-        }
-
-        let rows = sqlx::query_as::<_, ErrorRow>(
-            r#"
-            select
-                id, filename, span_start, span_end, content, content, error_type, metas
-            from errors
-        "#,
-        )
-        .fetch_all(conn)
-        .await?;
-        Ok(rows
-            .into_iter()
-            .map(|row| {
-                let span = match (row.span_start, row.span_end) {
-                    (Some(start), Some(end)) => Some(SpanInfo {
-                        start: start as usize,
-                        end: end as usize,
-                        content: row.content,
-                        filename: row.filename.map(PathBuf::from),
-                    }),
-                    _ => None,
-                };
-                ErrorDomain {
-                    id: row.id,
-                    span,
-                    error_type: row.error_type,
-                    metas: serde_json::from_str(&row.metas).unwrap(),
-                }
-            })
-            .collect_vec())
-    }
-
-    pub async fn account(&mut self, account_name: &str) -> ZhangResult<Option<AccountDomain>> {
-        let conn = self.pool.acquire().await?;
-        Ok(
-            sqlx::query_as::<_, AccountDomain>(r#"select date, type, name, status, alias from accounts where name = $1"#)
-                .bind(account_name)
-                .fetch_optional(conn)
-                .await?,
-        )
+    pub async fn insert_posting_meta(&mut self, trx_id: impl AsRef<str>, posting_index: usize, meta: Meta) -> ZhangResult<()> {
+        self.store
+            .insert_meta(MetaType::PostingMeta, &posting_identifier(trx_id.as_ref(), posting_index), meta)
+            .await
     }
 }
 
-// for insert and new operations
-impl Operations {
-    pub async fn new_error(&mut self, error_type: ErrorType, span: &SpanInfo, metas: HashMap<String, String>) -> ZhangResult<()> {
-        let conn = self.pool.acquire().await?;
-        sqlx::query(
-            r#"
-            INSERT OR REPLACE INTO
-                errors(id, filename, span_start, span_end, content, error_type, metas)
-            VALUES
-                ($1, $2, $3, $4, $5, $6, $7);
-        "#,
-        )
-        .bind(Uuid::new_v4().to_string())
-        .bind(span.filename.as_ref().and_then(|it| it.to_str()))
-        .bind(span.start as i64)
-        .bind(span.end as i64)
-        .bind(&span.content)
-        .bind(error_type)
-        .bind(serde_json::to_string(&metas).unwrap())
-        .execute(conn)
-        .await?;
-        Ok(())
-    }
-
-    pub async fn insert_or_update_options(&mut self, key: &str, value: &str) -> ZhangResult<()> {
-        let conn = self.pool.acquire().await?;
-        sqlx::query(r#"INSERT OR REPLACE INTO options VALUES ($1, $2);"#)
-            .bind(key)
-            .bind(value)
-            .execute(conn)
-            .await?;
-        Ok(())
-    }
+impl<'a> Deref for Operations<'a> {
+    type Target = dyn LedgerStore + 'a;
 
-    pub async fn insert_meta(&mut self, type_: MetaType, type_identifier: impl AsRef<str>, meta: Meta) -> ZhangResult<()> {
-        let conn = self.pool.acquire().await?;
-        for (meta_key, meta_value) in meta.get_flatten() {
-            sqlx::query(r#"INSERT OR REPLACE INTO metas VALUES ($1, $2, $3, $4);"#)
-                .bind(type_.as_ref())
-                .bind(type_identifier.as_ref())
-                .bind(meta_key)
-                .bind(meta_value.as_str())
-                .execute(&mut *conn)
-                .await?;
-        }
-        Ok(())
+    fn deref(&self) -> &Self::Target {
+        self.store.as_ref()
     }
+}
 
-    pub async fn close_account(&mut self, account_name: &str) -> ZhangResult<()> {
-        let conn = self.pool.acquire().await?;
-        sqlx::query(r#"update accounts set status = 'Close' where name = $1"#)
-            .bind(account_name)
-            .execute(conn)
-            .await?;
-        Ok(())
+impl<'a> DerefMut for Operations<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.store.as_mut()
     }
 }
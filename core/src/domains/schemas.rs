@@ -1,6 +1,8 @@
 use crate::database::type_ext::big_decimal::ZhangBigDecimal;
+use bigdecimal::BigDecimal;
 use chrono::{NaiveDate, NaiveDateTime};
 use serde::Serialize;
+#[cfg(feature = "sqlite")]
 use sqlx::FromRow;
 use std::collections::HashMap;
 use strum::{AsRefStr, EnumString};
@@ -8,11 +10,13 @@ use zhang_ast::{Currency, SpanInfo};
 
 macro_rules! text_enum {
     ($enum_type:tt) => {
+        #[cfg(feature = "sqlite")]
         impl sqlx::Type<sqlx::sqlite::Sqlite> for $enum_type {
             fn type_info() -> <sqlx::Sqlite as sqlx::Database>::TypeInfo {
                 <String as sqlx::Type<sqlx::Sqlite>>::type_info()
             }
         }
+        #[cfg(feature = "sqlite")]
         impl<'r, DB: sqlx::Database> sqlx::Decode<'r, DB> for $enum_type
         where
             &'r str: sqlx::Decode<'r, DB>,
@@ -23,6 +27,7 @@ macro_rules! text_enum {
                 Ok($enum_type::from_str(value).unwrap())
             }
         }
+        #[cfg(feature = "sqlite")]
         impl<'q, DB: sqlx::Database> sqlx::Encode<'q, DB> for $enum_type
         where
             String: sqlx::Encode<'q, DB>,
@@ -40,15 +45,19 @@ pub enum MetaType {
     AccountMeta,
     CommodityMeta,
     TransactionMeta,
+    PostingMeta,
 }
 text_enum! {MetaType}
 
-#[derive(FromRow, Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct OptionDomain {
     pub key: String,
     pub value: String,
 }
-#[derive(FromRow, Debug, Clone)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
 pub struct AccountDomain {
     pub date: NaiveDateTime,
     pub r#type: String,
@@ -58,13 +67,15 @@ pub struct AccountDomain {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Copy, Serialize, AsRefStr, EnumString)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum AccountStatus {
     Open,
     Close,
 }
 text_enum! {AccountStatus}
 
-#[derive(FromRow, Debug, Clone)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
 pub struct AccountBalanceDomain {
     pub datetime: NaiveDateTime,
     pub account: String,
@@ -73,7 +84,8 @@ pub struct AccountBalanceDomain {
     pub balance_commodity: String,
 }
 
-#[derive(FromRow, Debug, Clone)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
 pub struct AccountDailyBalanceDomain {
     pub date: NaiveDate,
     pub account: String,
@@ -81,7 +93,8 @@ pub struct AccountDailyBalanceDomain {
     pub balance_commodity: String,
 }
 
-#[derive(FromRow, Debug, Clone)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
 pub struct PriceDomain {
     pub datetime: NaiveDateTime,
     pub commodity: Currency,
@@ -89,7 +102,8 @@ pub struct PriceDomain {
     pub target_commodity: Currency,
 }
 
-#[derive(FromRow, Debug, Clone)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
 pub struct MetaDomain {
     pub meta_type: String,
     pub type_identifier: String,
@@ -97,7 +111,8 @@ pub struct MetaDomain {
     pub value: String,
 }
 
-#[derive(Debug, Clone, FromRow)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
 pub struct CommodityDomain {
     pub name: String,
     pub precision: i32,
@@ -106,7 +121,19 @@ pub struct CommodityDomain {
     pub rounding: Option<String>,
 }
 
-#[derive(Debug, Clone, FromRow)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
+pub struct DocumentDomain {
+    pub datetime: NaiveDateTime,
+    pub filename: String,
+    pub path: String,
+    pub extension: Option<String>,
+    pub account: Option<String>,
+    pub trx_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
 pub struct TransactionInfoDomain {
     pub id: String,
     pub source_file: String,
@@ -114,7 +141,92 @@ pub struct TransactionInfoDomain {
     pub span_end: i64,
 }
 
-#[derive(FromRow, Debug, Clone, Serialize)]
+/// conditions of journal query, all given conditions must be satisfied
+#[derive(Debug, Clone, Default)]
+pub struct JournalFilter {
+    pub id: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    /// account name, its sub accounts are included
+    pub account: Option<String>,
+    /// full text search on payee and narration
+    pub keyword: Option<String>,
+    pub tag: Option<String>,
+    pub link: Option<String>,
+    pub flag: Option<String>,
+    /// compared with the absolute value of posting's inferred amount
+    pub min_amount: Option<BigDecimal>,
+    pub max_amount: Option<BigDecimal>,
+    pub commodity: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct JournalDomain {
+    pub id: String,
+    pub sequence: i64,
+    pub datetime: NaiveDateTime,
+    pub journal_type: String,
+    pub payee: String,
+    pub narration: Option<String>,
+    pub tags: Vec<String>,
+    pub links: Vec<String>,
+    pub metas: Vec<MetaDomain>,
+    pub postings: Vec<JournalPostingDomain>,
+}
+
+#[derive(Debug, Clone)]
+pub struct JournalPostingDomain {
+    pub trx_id: String,
+    pub account: String,
+    pub unit_number: Option<ZhangBigDecimal>,
+    pub unit_commodity: Option<String>,
+    pub cost_number: Option<ZhangBigDecimal>,
+    pub cost_commodity: Option<String>,
+    pub price_number: Option<ZhangBigDecimal>,
+    pub price_commodity: Option<String>,
+    pub inferred_unit_number: ZhangBigDecimal,
+    pub inferred_unit_commodity: String,
+    pub account_before_number: ZhangBigDecimal,
+    pub account_before_commodity: String,
+    pub account_after_number: ZhangBigDecimal,
+    pub account_after_commodity: String,
+    pub metas: Vec<MetaDomain>,
+}
+
+/// usage of a tag or link among transactions
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
+pub struct LabelUsageDomain {
+    pub name: String,
+    pub count: i64,
+    pub first_datetime: NaiveDateTime,
+    pub last_datetime: NaiveDateTime,
+}
+
+/// net amount of income and expenses postings of a tag or link within a period
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
+pub struct LabelPeriodTotalDomain {
+    pub name: String,
+    pub period: String,
+    pub commodity: String,
+    pub amount: ZhangBigDecimal,
+}
+
+/// net amount of an account among transactions having the same tag or link
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
+pub struct LabelAccountEffectDomain {
+    pub account: String,
+    pub commodity: String,
+    pub amount: ZhangBigDecimal,
+    /// number of transactions posting to the account
+    pub transactions: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct AccountJournalDomain {
     pub datetime: NaiveDateTime,
     pub account: String,
@@ -128,6 +240,7 @@ pub struct AccountJournalDomain {
 }
 
 #[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct ErrorDomain {
     pub id: String,
     pub span: Option<SpanInfo>,
@@ -136,6 +249,7 @@ pub struct ErrorDomain {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, AsRefStr, EnumString)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum ErrorType {
     AccountBalanceCheckError,
     AccountDoesNotExist,
@@ -146,3 +260,46 @@ pub enum ErrorType {
     CloseNonZeroAccount,
 }
 text_enum! {ErrorType}
+
+/// net amount of postings of an account type in a commodity
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
+pub struct AccountTypeTotalDomain {
+    pub account_type: String,
+    pub commodity: String,
+    pub amount: ZhangBigDecimal,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
+pub struct DailyAccountTypeTotalDomain {
+    pub date: NaiveDate,
+    pub account_type: String,
+    pub commodity: String,
+    pub amount: ZhangBigDecimal,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
+pub struct CommoditySummaryDomain {
+    pub name: String,
+    pub precision: i32,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub rounding: Option<String>,
+    /// total amount held by assets and liabilities accounts
+    pub total_amount: ZhangBigDecimal,
+    pub latest_price_date: Option<NaiveDateTime>,
+    pub latest_price_amount: Option<ZhangBigDecimal>,
+    pub latest_price_commodity: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlite", derive(FromRow))]
+pub struct CommodityLotDomain {
+    pub datetime: Option<NaiveDateTime>,
+    pub amount: ZhangBigDecimal,
+    pub price_amount: Option<ZhangBigDecimal>,
+    pub price_commodity: Option<String>,
+    pub account: String,
+}
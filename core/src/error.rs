@@ -1,5 +1,7 @@
 use std::path::{Path, PathBuf};
 
+use pest::error::LineColLocation;
+use pest::RuleType;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -24,15 +26,38 @@ pub enum ZhangError {
     //
     // #[error("strum error: {0}")]
     // StrumError(#[from] strum::ParseError),
-    #[error("pest error: {0}")]
-    PestError(String),
+    #[error("syntax error: {message}")]
+    SyntaxError { line: usize, column: usize, message: String },
+    #[error("json error: {0}")]
+    JsonError(String),
+    #[error("booking method {0} is invalid")]
+    InvalidBookingMethod(String),
+    #[error("new content of {path} is invalid: {error}")]
+    InvalidContent { path: PathBuf, error: Box<ZhangError> },
+    #[error("file {0} has been changed since last load")]
+    StaleSpan(PathBuf),
 
+    #[cfg(feature = "sqlite")]
     #[error("databaseError: {0}")]
     DatabaseError(#[from] sqlx::Error),
     #[error("cannot found option given key: {0}")]
     OptionNotFound(String),
 }
 
+impl ZhangError {
+    pub fn syntax_error<R: RuleType>(error: pest::error::Error<R>) -> ZhangError {
+        let (line, column) = match error.line_col {
+            LineColLocation::Pos(pos) => pos,
+            LineColLocation::Span(start, _) => start,
+        };
+        ZhangError::SyntaxError {
+            line,
+            column,
+            message: error.to_string(),
+        }
+    }
+}
+
 pub trait IoErrorIntoZhangError<T> {
     fn with_path(self, path: &Path) -> Result<T, ZhangError>;
 }
@@ -1,14 +1,16 @@
+use crate::error::IoErrorIntoZhangError;
 use crate::ledger::Ledger;
-use crate::ZhangResult;
+use crate::{ZhangError, ZhangResult};
 use itertools::Itertools;
 use log::debug;
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::utils::has_path_visited;
 use crate::utils::string_::escape_with_quote;
 use chrono::Datelike;
+use uuid::Uuid;
 use zhang_ast::amount::Amount;
 use zhang_ast::*;
 
@@ -20,6 +22,9 @@ pub trait Exporter: AppendableExporter {
 pub trait AppendableExporter: Send + Sync {
     /// define how the exporter append directives
     fn append_directives(&self, ledger: &Ledger, directives: Vec<Directive>) -> ZhangResult<()>;
+
+    /// replace the directive located at given span, or remove it if `directive` is none
+    fn replace_directive(&self, ledger: &Ledger, span: &SpanInfo, directive: Option<Directive>) -> ZhangResult<()>;
 }
 
 pub struct DebugExporter;
@@ -29,6 +34,69 @@ impl AppendableExporter for DebugExporter {
         debug!("append directive [{:?}]", directives);
         Ok(())
     }
+
+    fn replace_directive(&self, _: &Ledger, span: &SpanInfo, directive: Option<Directive>) -> ZhangResult<()> {
+        debug!("replace directive at [{:?}] with [{:?}]", span, directive);
+        Ok(())
+    }
+}
+
+/// replace the content of span in its source file.
+/// the file is left untouched and [`ZhangError::StaleSpan`] is returned if the content at span is not the one parsed,
+/// or [`ZhangError::InvalidContent`] if the new content cannot be loaded by the ledger's transformer
+pub fn replace_span_content(ledger: &Ledger, span: &SpanInfo, content: Option<String>) -> ZhangResult<()> {
+    let path = span.filename.clone().unwrap_or_default();
+    let mut file_content = std::fs::read_to_string(&path).with_path(&path)?;
+    if file_content.get(span.start..span.end) != Some(span.content.as_str()) {
+        return Err(ZhangError::StaleSpan(path));
+    }
+    match content {
+        Some(content) => file_content.replace_range(span.start..span.end, &content),
+        None => {
+            // remove the line break of the removed directive, and the blank line after it if there is one before it
+            let mut end = span.end;
+            if file_content[end..].starts_with('\n') {
+                end += 1;
+            }
+            if file_content[..span.start].ends_with("\n\n") && file_content[end..].starts_with('\n') {
+                end += 1;
+            }
+            file_content.replace_range(span.start..end, "");
+        }
+    }
+    write_validated_file(ledger, &path, &file_content)
+}
+
+/// validate the new content of file with the ledger's transformer, and write it atomically
+pub fn write_validated_file(ledger: &Ledger, path: &Path, content: &str) -> ZhangResult<()> {
+    ledger
+        .validate_file_content(content, path.to_path_buf())
+        .map_err(|error| ZhangError::InvalidContent {
+            path: path.to_path_buf(),
+            error: Box::new(error),
+        })?;
+    write_file_atomically(path, content)
+}
+
+/// write into a temp file besides the target and rename it, so the target is never left half written
+pub fn write_file_atomically(path: &Path, content: &str) -> ZhangResult<()> {
+    let folder = path.parent().filter(|it| !it.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    std::fs::create_dir_all(folder).with_path(folder)?;
+    let file_name = path.file_name().map(|it| it.to_string_lossy().to_string()).unwrap_or_default();
+    let temp = folder.join(format!(".{}.{}.tmp", file_name, Uuid::new_v4()));
+    let result = (|| {
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(content.as_bytes())?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result.with_path(path)
 }
 
 impl Exporter for DebugExporter {
@@ -85,6 +153,10 @@ impl AppendableExporter for TextExporter {
         }
         Ok(())
     }
+
+    fn replace_directive(&self, ledger: &Ledger, span: &SpanInfo, directive: Option<Directive>) -> ZhangResult<()> {
+        replace_span_content(ledger, span, directive.map(|it| self.export_directive(it)))
+    }
 }
 
 impl Exporter for TextExporter {
@@ -182,7 +254,11 @@ impl TextExportable for Transaction {
         vec1.append(&mut tags);
         vec1.append(&mut links);
 
-        let mut transaction = self.postings.into_iter().map(|it| format!("  {}", it.export())).collect_vec();
+        let mut transaction = self
+            .postings
+            .into_iter()
+            .map(|it| it.export().lines().map(|line| format!("  {}", line)).join("\n"))
+            .collect_vec();
         transaction.insert(0, vec1.into_iter().flatten().join(" "));
         let mut vec2 = self.meta.export().into_iter().map(|it| format!("  {}", it)).collect_vec();
         transaction.append(&mut vec2);
@@ -209,7 +285,7 @@ impl TextExportable for Posting {
             self.price.map(|it| it.export()),
         ];
 
-        vec1.into_iter().flatten().join(" ")
+        append_meta(self.meta, vec1.into_iter().flatten().join(" "))
     }
 }
 
@@ -243,7 +319,7 @@ impl TextExportable for Open {
 impl TextExportable for Close {
     type Output = String;
     fn export(self) -> String {
-        let line = vec![self.date.export(), "close".to_string(), self.account.export()];
+        let line = [self.date.export(), "close".to_string(), self.account.export()];
         append_meta(self.meta, line.join(" "))
     }
 }
@@ -251,7 +327,7 @@ impl TextExportable for Close {
 impl TextExportable for Commodity {
     type Output = String;
     fn export(self) -> String {
-        let line = vec![self.date.export(), "commodity".to_string(), self.currency];
+        let line = [self.date.export(), "commodity".to_string(), self.currency];
         append_meta(self.meta, line.join(" "))
     }
 }
@@ -261,11 +337,11 @@ impl TextExportable for Balance {
     fn export(self) -> String {
         match self {
             Balance::BalanceCheck(check) => {
-                let line = vec![check.date.export(), "balance".to_string(), check.account.export(), check.amount.export()];
+                let line = [check.date.export(), "balance".to_string(), check.account.export(), check.amount.export()];
                 append_meta(check.meta, line.join(" "))
             }
             Balance::BalancePad(pad) => {
-                let line = vec![
+                let line = [
                     pad.date.export(),
                     "balance".to_string(),
                     pad.account.export(),
@@ -282,7 +358,7 @@ impl TextExportable for Balance {
 impl TextExportable for Note {
     type Output = String;
     fn export(self) -> String {
-        let line = vec![self.date.export(), "note".to_string(), self.account.export(), self.comment.export()];
+        let line = [self.date.export(), "note".to_string(), self.account.export(), self.comment.export()];
         append_meta(self.meta, line.join(" "))
     }
 }
@@ -290,7 +366,7 @@ impl TextExportable for Note {
 impl TextExportable for Document {
     type Output = String;
     fn export(self) -> String {
-        let line = vec![self.date.export(), "document".to_string(), self.account.export(), self.filename.export()];
+        let line = [self.date.export(), "document".to_string(), self.account.export(), self.filename.export()];
         append_meta(self.meta, line.join(" "))
     }
 }
@@ -298,7 +374,7 @@ impl TextExportable for Document {
 impl TextExportable for Price {
     type Output = String;
     fn export(self) -> String {
-        let line = vec![self.date.export(), "price".to_string(), self.currency, self.amount.export()];
+        let line = [self.date.export(), "price".to_string(), self.currency, self.amount.export()];
         append_meta(self.meta, line.join(" "))
     }
 }
@@ -306,7 +382,15 @@ impl TextExportable for Price {
 impl TextExportable for Event {
     type Output = String;
     fn export(self) -> String {
-        let line = vec![self.date.export(), "event".to_string(), self.event_type.export(), self.description.export()];
+        let line = [self.date.export(), "event".to_string(), self.event_type.export(), self.description.export()];
+        append_meta(self.meta, line.join(" "))
+    }
+}
+
+impl TextExportable for Query {
+    type Output = String;
+    fn export(self) -> String {
+        let line = [self.date.export(), "query".to_string(), self.name.export(), self.query_string.export()];
         append_meta(self.meta, line.join(" "))
     }
 }
@@ -324,7 +408,7 @@ impl TextExportable for Custom {
 impl TextExportable for Options {
     type Output = String;
     fn export(self) -> String {
-        let line = vec!["option".to_string(), self.key.export(), self.value.export()];
+        let line = ["option".to_string(), self.key.export(), self.value.export()];
         line.join(" ")
     }
 }
@@ -340,7 +424,7 @@ impl TextExportable for Plugin {
 impl TextExportable for Include {
     type Output = String;
     fn export(self) -> String {
-        let line = vec!["include".to_string(), self.file.export()];
+        let line = ["include".to_string(), self.file.export()];
         line.join(" ")
     }
 }
@@ -365,6 +449,7 @@ impl TextExportable for Directive {
             Directive::Document(document) => document.export(),
             Directive::Price(price) => price.export(),
             Directive::Event(event) => event.export(),
+            Directive::Query(query) => query.export(),
             Directive::Custom(custom) => custom.export(),
             Directive::Option(options) => options.export(),
             Directive::Plugin(plugin) => plugin.export(),
@@ -560,6 +645,18 @@ mod test {
               Expenses:TestCategory:One 1 CCC @@ 1 CNY
         "#}
         );
+
+        assert_parse!(
+            "transaction directive with posting meta",
+            indoc! {r#"
+            1970-01-01 * "Payee" "Narration"
+              Assets:123 -1 CNY
+                receipt: "a.jpg"
+              Expenses:TestCategory:One 1 CNY
+                invoice: "INV-001"
+              a: "b"
+        "#}
+        );
     }
 
     #[test]
@@ -602,6 +699,16 @@ mod test {
         );
     }
 
+    #[test]
+    fn query() {
+        assert_parse!(
+            "query directive ",
+            indoc! {r#"
+            1970-01-01 query "france-balances" "SELECT account, sum(position) WHERE 'trip-france-2014' in tags"
+        "#}
+        );
+    }
+
     #[test]
     fn custom() {
         assert_parse!(
@@ -637,4 +744,113 @@ mod test {
         "#}
         );
     }
+
+    mod replace {
+
+        use indoc::indoc;
+        use tempfile::tempdir;
+
+        use crate::exporter::{replace_span_content, write_file_atomically, AppendableExporter, TextExporter};
+        use crate::ledger::Ledger;
+        use crate::ZhangError;
+
+        const CONTENT: &str = indoc! {r#"
+            1970-01-01 open Assets:Bank
+            1970-01-01 open Expenses:Food
+
+            1970-01-02 "KFC" "lunch"
+              Assets:Bank -10 CNY
+              Expenses:Food
+
+            1970-01-03 "KFC" "dinner"
+              Assets:Bank -20 CNY
+              Expenses:Food
+        "#};
+
+        async fn load(content: &str) -> (tempfile::TempDir, Ledger) {
+            let dir = tempdir().unwrap();
+            std::fs::write(dir.path().join("main.zhang"), content).unwrap();
+            let ledger = Ledger::load::<crate::transform::TextTransformer>(dir.path().to_path_buf(), "main.zhang".to_string())
+                .await
+                .unwrap();
+            (dir, ledger)
+        }
+
+        fn transaction_span(ledger: &Ledger, narration: &str) -> zhang_ast::SpanInfo {
+            ledger
+                .directives
+                .iter()
+                .find(|it| it.span.content.contains(narration))
+                .map(|it| it.span.clone())
+                .unwrap()
+        }
+
+        fn main_file(dir: &tempfile::TempDir) -> String {
+            std::fs::read_to_string(dir.path().join("main.zhang")).unwrap()
+        }
+
+        #[tokio::test]
+        async fn should_replace_directive_at_span() {
+            let (dir, ledger) = load(CONTENT).await;
+            let span = transaction_span(&ledger, "lunch");
+            let mut directive = crate::parser::parse(&span.content, None).unwrap().pop().unwrap().data;
+            if let zhang_ast::Directive::Transaction(trx) = &mut directive {
+                trx.narration = Some(zhang_ast::ZhangString::quote("brunch"));
+            }
+            TextExporter {}.replace_directive(&ledger, &span, Some(directive)).unwrap();
+
+            assert_eq!(CONTENT.replace("\"lunch\"", "\"brunch\""), main_file(&dir));
+        }
+
+        #[tokio::test]
+        async fn should_remove_directive_at_span() {
+            let (dir, ledger) = load(CONTENT).await;
+            let span = transaction_span(&ledger, "lunch");
+            TextExporter {}.replace_directive(&ledger, &span, None).unwrap();
+
+            assert_eq!(
+                indoc! {r#"
+                    1970-01-01 open Assets:Bank
+                    1970-01-01 open Expenses:Food
+
+                    1970-01-03 "KFC" "dinner"
+                      Assets:Bank -20 CNY
+                      Expenses:Food
+                "#},
+                main_file(&dir)
+            );
+        }
+
+        #[tokio::test]
+        async fn should_refuse_stale_span() {
+            let (dir, ledger) = load(CONTENT).await;
+            let span = transaction_span(&ledger, "dinner");
+            let changed = CONTENT.replace("\"lunch\"", "\"lunch with friends\"");
+            std::fs::write(dir.path().join("main.zhang"), &changed).unwrap();
+
+            let result = replace_span_content(&ledger, &span, None);
+            assert!(matches!(result, Err(ZhangError::StaleSpan(path)) if Some(path.clone()) == span.filename));
+            assert_eq!(changed, main_file(&dir));
+        }
+
+        #[tokio::test]
+        async fn should_refuse_invalid_content() {
+            let (dir, ledger) = load(CONTENT).await;
+            let span = transaction_span(&ledger, "lunch");
+
+            let result = replace_span_content(&ledger, &span, Some("1970-01-02 \"KFC\" \"lunch\" Assets:Bank".to_string()));
+            assert!(matches!(result, Err(ZhangError::InvalidContent { .. })), "{:?}", result);
+            assert_eq!(CONTENT, main_file(&dir));
+        }
+
+        #[test]
+        fn should_replace_file_content_atomically() {
+            let dir = tempdir().unwrap();
+            let path = dir.path().join("main.zhang");
+            std::fs::write(&path, "old").unwrap();
+            write_file_atomically(&path, "new").unwrap();
+            assert_eq!("new", std::fs::read_to_string(&path).unwrap());
+            assert_eq!(1, std::fs::read_dir(dir.path()).unwrap().count());
+        }
+    }
 }
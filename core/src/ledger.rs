@@ -1,32 +1,119 @@
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::option::Option::None;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "sqlite")]
 use std::str::FromStr;
 use std::sync::Arc;
 
-use bigdecimal::Zero;
+use chrono::NaiveDateTime;
 use glob::Pattern;
-use itertools::Itertools;
+use itertools::{EitherOrBoth, Itertools};
+#[cfg(feature = "sqlite")]
+use log::warn;
 use log::{error, info};
+#[cfg(feature = "sqlite")]
 use sqlx::pool::PoolConnection;
+#[cfg(feature = "sqlite")]
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
-use sqlx::{Sqlite, SqlitePool};
+#[cfg(feature = "sqlite")]
+use sqlx::{Acquire, ConnectOptions, Connection, Sqlite, SqlitePool};
+use uuid::Uuid;
 
-use zhang_ast::{Directive, DirectiveType, Spanned, Transaction};
+use zhang_ast::{Balance, Directive, DirectiveType, Spanned, Transaction};
+#[cfg(feature = "sqlite")]
+use zhang_ast::{Date, SpanInfo};
 
+#[cfg(feature = "sqlite")]
+use crate::database::cache::{self, Fingerprint};
+#[cfg(feature = "sqlite")]
 use crate::database::migrations::Migration;
 use crate::domains::Operations;
+#[cfg(feature = "sqlite")]
 use crate::error::IoErrorIntoZhangError;
 use crate::options::{BuiltinOption, InMemoryOptions};
-use crate::process::DirectiveProcess;
+use crate::process::{is_transaction_balanced, ProcessContext};
+use crate::store::MemoryStore;
+#[cfg(feature = "sqlite")]
+use crate::store::SqliteStore;
 use crate::transform::Transformer;
-use crate::utils::bigdecimal_ext::BigDecimalExt;
+use crate::utils::id::FromSpan;
 use crate::ZhangResult;
 
+/// where the processed data of a ledger lives
+#[derive(Clone)]
+enum Storage {
+    #[cfg(feature = "sqlite")]
+    Sqlite {
+        pool: SqlitePool,
+        /// name of the in memory database of this snapshot
+        snapshot: String,
+    },
+    Memory(MemoryStore),
+}
+
+impl Storage {
+    #[cfg(feature = "sqlite")]
+    async fn sqlite() -> ZhangResult<Storage> {
+        let (snapshot, pool) = Storage::create_snapshot().await?;
+        Ok(Storage::Sqlite { pool, snapshot })
+    }
+
+    /// every snapshot lives in its own named in memory database, so that the next one can be built by attaching it
+    #[cfg(feature = "sqlite")]
+    async fn create_snapshot() -> ZhangResult<(String, SqlitePool)> {
+        let snapshot = format!("file:zhang-{}", Uuid::new_v4());
+        let pool = SqlitePoolOptions::new()
+            .max_lifetime(None)
+            .idle_timeout(None)
+            .connect_with(SqliteConnectOptions::from_str(&format!("sqlite:{}", Storage::memory_uri(&snapshot)))?.journal_mode(SqliteJournalMode::Wal))
+            .await?;
+        Ok((snapshot, pool))
+    }
+
+    /// an empty storage of the same kind
+    async fn renew(&self) -> ZhangResult<Storage> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite { .. } => Storage::sqlite().await,
+            Storage::Memory(_) => Ok(Storage::Memory(MemoryStore::new())),
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn memory_uri(snapshot: &str) -> String {
+        format!("{snapshot}?mode=memory&cache=shared")
+    }
+}
+
+/// changes between the directives of two loads
+struct DirectiveChanges<'a> {
+    /// the earliest datetime of directives which are added, removed or changed
+    since: Option<NaiveDateTime>,
+    /// directives which are added or removed, a modified one is removed and added
+    changed: Vec<&'a Spanned<Directive>>,
+    /// unchanged directives whose span is moved, as pairs of previous and current one
+    moved: Vec<(&'a Spanned<Directive>, &'a Spanned<Directive>)>,
+}
+
+/// what is changed by the latest reload, told by the directives which are changed or moved rather than by querying the ledger
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LedgerChanges {
+    /// ids of transactions which are added, removed, modified or moved, in order
+    pub transactions: Vec<String>,
+    /// accounts which are declared, closed, checked or posted by changed directives, in order
+    pub accounts: Vec<String>,
+    /// commodities or prices are changed
+    pub commodities: bool,
+    pub options: bool,
+}
+
+/// clones share the same processed data, only the directives are copied
+#[derive(Clone)]
 pub struct Ledger {
     pub entry: (PathBuf, String),
+    /// cache database file, which a copy of every loaded snapshot is written into
     pub database: Option<PathBuf>,
-    pub pool_connection: SqlitePool,
     pub visited_files: Vec<Pattern>,
 
     pub options: InMemoryOptions,
@@ -34,107 +121,224 @@ pub struct Ledger {
     pub directives: Vec<Spanned<Directive>>,
     pub metas: Vec<Spanned<Directive>>,
 
+    /// what is changed by the reload which builds this ledger, empty if it's loaded from scratch
+    pub changes: LedgerChanges,
+
     transformer: Arc<dyn Transformer>,
+    storage: Storage,
 }
 
 impl Ledger {
     pub async fn load<T: Transformer + Default + 'static>(entry: PathBuf, endpoint: String) -> ZhangResult<Ledger> {
         let transformer = Arc::new(T::default());
-        Ledger::load_with_database(entry, endpoint, None, transformer).await
+        #[cfg(feature = "sqlite")]
+        return Ledger::load_with_database(entry, endpoint, None, transformer).await;
+        #[cfg(not(feature = "sqlite"))]
+        return Ledger::load_in_memory(entry, endpoint, transformer).await;
     }
 
+    #[cfg(feature = "sqlite")]
     pub async fn load_with_database(entry: PathBuf, endpoint: String, database: Option<PathBuf>, transformer: Arc<dyn Transformer>) -> ZhangResult<Ledger> {
         let entry = entry.canonicalize().with_path(&entry)?;
 
         let transform_result = transformer.load(entry.clone(), endpoint.clone())?;
+        if let Some(path) = database.as_ref().filter(|it| it.is_file()) {
+            match Ledger::restore(path, &transform_result.visited_files).await {
+                Ok(Some((storage, options))) => {
+                    info!("files are not changed since the cache database was built, skip processing");
+                    let (metas, directives) = Ledger::split_directives(transform_result.directives);
+                    return Ok(Ledger {
+                        entry: (entry, endpoint),
+                        database,
+                        visited_files: transform_result.visited_files,
+                        options,
+                        directives,
+                        metas,
+                        changes: LedgerChanges::default(),
+                        transformer,
+                        storage,
+                    });
+                }
+                Ok(None) => info!("files are changed since the cache database was built, rebuilding it"),
+                Err(e) => warn!("cannot read cache database {}, rebuilding it: {}", path.display(), e),
+            }
+        }
         Ledger::process(
             transform_result.directives,
             (entry, endpoint),
             database,
             transform_result.visited_files,
             transformer,
+            Storage::sqlite().await?,
         )
         .await
     }
 
-    pub async fn connection(&self) -> PoolConnection<Sqlite> {
-        self.pool_connection.acquire().await.unwrap()
+    /// load the ledger into the in memory store, which needs no sqlite and is never persisted
+    pub async fn load_in_memory(entry: PathBuf, endpoint: String, transformer: Arc<dyn Transformer>) -> ZhangResult<Ledger> {
+        let transform_result = transformer.load(entry.clone(), endpoint.clone())?;
+        Ledger::process(
+            transform_result.directives,
+            (entry, endpoint),
+            None,
+            transform_result.visited_files,
+            transformer,
+            Storage::Memory(MemoryStore::new()),
+        )
+        .await
     }
 
-    async fn process(
-        directives: Vec<Spanned<Directive>>, entry: (PathBuf, String), database: Option<PathBuf>, visited_files: Vec<Pattern>,
-        transformer: Arc<dyn Transformer>,
-    ) -> ZhangResult<Ledger> {
-        let sqlite_pool = if let Some(ref path) = database {
-            info!("database store at {}", path.display());
-            SqlitePool::connect_with(
-                SqliteConnectOptions::default()
-                    .filename(path)
-                    .journal_mode(SqliteJournalMode::Wal)
-                    .create_if_missing(true),
-            )
-            .await?
-        } else {
-            info!("using in memory database");
-            SqlitePoolOptions::new()
-                .max_lifetime(None)
-                .idle_timeout(None)
-                .connect_with(SqliteConnectOptions::from_str("sqlite::memory:").unwrap().journal_mode(SqliteJournalMode::Wal))
-                .await?
-        };
-        let mut connection = sqlite_pool.acquire().await?;
-
-        Migration::init_database_if_missing(&mut connection).await?;
+    /// connection of the sqlite snapshot
+    ///
+    /// # Panics
+    /// if the ledger is loaded in memory
+    #[cfg(feature = "sqlite")]
+    pub(crate) async fn connection(&self) -> PoolConnection<Sqlite> {
+        match &self.storage {
+            Storage::Sqlite { pool, .. } => pool.acquire().await.unwrap(),
+            Storage::Memory(_) => panic!("ledger loaded in memory has no sqlite connection"),
+        }
+    }
 
-        let (meta_directives, dated_directive): (Vec<Spanned<Directive>>, Vec<Spanned<Directive>>) =
-            directives.into_iter().partition(|it| it.datetime().is_none());
-        let mut directives = Ledger::sort_directives_datetime(dated_directive);
-        let mut ret_ledger = Self {
+    async fn empty(
+        entry: (PathBuf, String), database: Option<PathBuf>, visited_files: Vec<Pattern>, transformer: Arc<dyn Transformer>, storage: Storage,
+    ) -> Ledger {
+        Self {
             options: InMemoryOptions::default(),
             entry,
             database,
-            pool_connection: sqlite_pool,
             visited_files,
             directives: vec![],
             metas: vec![],
+            changes: LedgerChanges::default(),
             transformer,
-        };
-        let mut merged_metas = BuiltinOption::default_options()
+            storage,
+        }
+    }
+
+    async fn process(
+        directives: Vec<Spanned<Directive>>, entry: (PathBuf, String), database: Option<PathBuf>, visited_files: Vec<Pattern>,
+        transformer: Arc<dyn Transformer>, storage: Storage,
+    ) -> ZhangResult<Ledger> {
+        let mut ret_ledger = Ledger::empty(entry, database, visited_files, transformer, storage).await;
+
+        let (mut metas, mut directives) = Ledger::split_directives(directives);
+        let directives_to_process = metas.iter_mut().rev().chain(directives.iter_mut());
+        match &ret_ledger.storage {
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite { pool, .. } => {
+                let mut connection = pool.acquire().await?;
+                Migration::migrate(&mut connection).await?;
+                let mut trx = connection.begin().await?;
+                let mut context = ProcessContext::new(Box::new(SqliteStore::new(&mut *trx)), &mut ret_ledger.options);
+                for directive in directives_to_process {
+                    context.process(directive).await?;
+                }
+                drop(context);
+                trx.commit().await?;
+            }
+            Storage::Memory(store) => {
+                let mut context = ProcessContext::new(Box::new(store.clone()), &mut ret_ledger.options);
+                for directive in directives_to_process {
+                    context.process(directive).await?;
+                }
+            }
+        }
+
+        ret_ledger.metas = metas;
+        ret_ledger.directives = directives;
+        ret_ledger.loaded().await?;
+        Ok(ret_ledger)
+    }
+
+    /// split directives into undated ones merged with builtin options (in reversed order), and dated ones sorted in processing order
+    fn split_directives(directives: Vec<Spanned<Directive>>) -> (Vec<Spanned<Directive>>, Vec<Spanned<Directive>>) {
+        let (meta_directives, dated_directive): (Vec<Spanned<Directive>>, Vec<Spanned<Directive>>) =
+            directives.into_iter().partition(|it| it.datetime().is_none());
+        let merged_metas = BuiltinOption::default_options()
             .into_iter()
-            .chain(meta_directives.into_iter())
+            .chain(meta_directives)
             .rev()
             .dedup_by(|x, y| match (&x.data, &y.data) {
                 (Directive::Option(option_x), Directive::Option(option_y)) => option_x.key.eq(&option_y.key),
                 _ => false,
             })
             .collect_vec();
-        for directive in merged_metas.iter_mut().rev().chain(directives.iter_mut()) {
-            match &mut directive.data {
-                Directive::Option(option) => option.handler(&mut ret_ledger, &directive.span).await?,
-                Directive::Open(open) => open.handler(&mut ret_ledger, &directive.span).await?,
-                Directive::Close(close) => close.handler(&mut ret_ledger, &directive.span).await?,
-                Directive::Commodity(commodity) => commodity.handler(&mut ret_ledger, &directive.span).await?,
-                Directive::Transaction(trx) => trx.handler(&mut ret_ledger, &directive.span).await?,
-                Directive::Balance(balance) => balance.handler(&mut ret_ledger, &directive.span).await?,
-                Directive::Note(_) => {}
-                Directive::Document(document) => document.handler(&mut ret_ledger, &directive.span).await?,
-                Directive::Price(price) => price.handler(&mut ret_ledger, &directive.span).await?,
-                Directive::Event(_) => {}
-                Directive::Custom(_) => {}
-                _ => {}
-            }
-        }
+        (merged_metas, Ledger::sort_directives_datetime(dated_directive))
+    }
 
-        ret_ledger.metas = merged_metas;
-        ret_ledger.directives = directives;
-        let mut operations = ret_ledger.operations().await;
+    async fn loaded(&self) -> ZhangResult<()> {
+        let mut operations = self.operations().await;
         let errors = operations.errors().await?;
         if !errors.is_empty() {
             error!("Ledger loaded with {} error", errors.len());
         } else {
             info!("Ledger loaded");
         }
-        Ok(ret_ledger)
+        #[cfg(feature = "sqlite")]
+        if let (Some(database), Storage::Sqlite { snapshot, .. }) = (&self.database, &self.storage) {
+            self.persist(database, snapshot).await?;
+        }
+        Ok(())
+    }
+
+    /// copy the cache database into a new snapshot if it's built from the same files
+    #[cfg(feature = "sqlite")]
+    async fn restore(database: &Path, visited_files: &[Pattern]) -> ZhangResult<Option<(Storage, InMemoryOptions)>> {
+        // the cache is only written once it's going to be restored
+        let mut cache = SqliteConnectOptions::default().filename(database).read_only(true).connect().await?;
+        let version = Migration::current_version(&mut cache).await?;
+        let fingerprints = cache::read_fingerprints(&mut cache).await?;
+        cache.close().await?;
+        if version > Migration::latest_version() {
+            info!("database is created by a newer version of schema {}, building it again", version);
+            return Ok(None);
+        }
+        if fingerprints != Some(Fingerprint::of_files(visited_files)?) {
+            return Ok(None);
+        }
+        if version < Migration::latest_version() {
+            let mut cache = SqliteConnectOptions::default().filename(database).connect().await?;
+            Migration::migrate(&mut cache).await?;
+            cache.close().await?;
+        }
+
+        let (snapshot, pool) = Storage::create_snapshot().await?;
+        let mut connection = pool.acquire().await?;
+        Migration::migrate(&mut connection).await?;
+        // plain file names are attached as in memory databases, since the snapshot is opened in memory mode
+        let database_uri = format!(
+            "file:{}?mode=ro",
+            database.to_string_lossy().replace('%', "%25").replace('?', "%3f").replace('#', "%23")
+        );
+        Migration::copy_from(&mut connection, &database_uri).await?;
+
+        let mut options = InMemoryOptions::default();
+        let stored_options: Vec<(String, String)> = sqlx::query_as("select key, value from options").fetch_all(&mut connection).await?;
+        for (key, value) in stored_options {
+            options.restore(&key, &value);
+        }
+        drop(connection);
+        Ok(Some((Storage::Sqlite { pool, snapshot }, options)))
+    }
+
+    /// write a copy of the snapshot into the cache database file, replacing the previous one
+    #[cfg(feature = "sqlite")]
+    async fn persist(&self, database: &Path, snapshot: &str) -> ZhangResult<()> {
+        let mut temp = database.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        if temp.exists() {
+            std::fs::remove_file(&temp).with_path(&temp)?;
+        }
+        let mut connection = SqliteConnectOptions::default().filename(&temp).create_if_missing(true).connect().await?;
+        Migration::migrate(&mut connection).await?;
+        Migration::copy_from(&mut connection, &Storage::memory_uri(snapshot)).await?;
+        cache::write_fingerprints(&mut connection, &Fingerprint::of_files(&self.visited_files)?).await?;
+        connection.close().await?;
+        std::fs::rename(&temp, database).with_path(database)?;
+        info!("database store at {}", database.display());
+        Ok(())
     }
 
     fn sort_directives_datetime(mut directives: Vec<Spanned<Directive>>) -> Vec<Spanned<Directive>> {
@@ -168,50 +372,253 @@ impl Ledger {
     }
 
     pub async fn is_transaction_balanced(&self, txn: &Transaction) -> ZhangResult<bool> {
-        // 1. get the txn's inventory
-        Ok(match txn.get_postings_inventory() {
-            Ok(inventory) => {
-                for (currency, amount) in inventory.currencies.iter() {
-                    let mut operations = self.operations().await;
-                    let commodity = operations.commodity(currency).await?;
-                    let precision = commodity
-                        .as_ref()
-                        .map(|it| it.precision)
-                        .unwrap_or(self.options.default_balance_tolerance_precision);
-                    let rounding = commodity
-                        .and_then(|it| it.rounding)
-                        .map(|s| s.eq("RoundUp"))
-                        .unwrap_or_else(|| self.options.default_rounding.is_up());
-                    let decimal = amount.total.round_with(precision as i64, rounding);
-                    if !decimal.is_zero() {
-                        return Ok(false);
-                    }
-                }
-                true
-            }
-            Err(_) => false,
-        })
+        is_transaction_balanced(&mut *self.operations().await, &self.options, txn).await
     }
 
-    pub async fn reload(&mut self) -> ZhangResult<()> {
-        let (entry, endpoint) = &mut self.entry;
+    /// load the ledger again into a new snapshot, the current one is left untouched and keeps serving queries.
+    ///
+    /// only changed files are parsed again, and only directives since the earliest changed one are processed again,
+    /// unless options are changed or the changes cannot be rolled back exactly.
+    pub async fn reload(&self) -> ZhangResult<Ledger> {
+        let mut reloaded = self.reload_files().await?;
+        reloaded.changes = Ledger::changes_between(self, &reloaded);
+        Ok(reloaded)
+    }
+
+    async fn reload_files(&self) -> ZhangResult<Ledger> {
+        let (entry, endpoint) = &self.entry;
         let transform_result = self.transformer.load(entry.clone(), endpoint.clone())?;
-        let reload_ledger = Ledger::process(
-            transform_result.directives,
-            (entry.clone(), endpoint.clone()),
-            self.database.clone(),
+        let (metas, directives) = Ledger::split_directives(transform_result.directives);
+        let entry = self.entry.clone();
+        let database = self.database.clone();
+        let transformer = self.transformer.clone();
+
+        if metas != self.metas {
+            info!("options are changed, processing all directives");
+            return Ledger::process(
+                metas.into_iter().chain(directives).collect(),
+                entry,
+                database,
+                transform_result.visited_files,
+                transformer,
+                self.storage.renew().await?,
+            )
+            .await;
+        }
+        let changes = Ledger::changed_directives(&self.directives, &directives);
+        // directives since the datetime are processed again with their new spans
+        let moved = changes
+            .moved
+            .into_iter()
+            .filter(|(previous, _)| changes.since.map(|since| previous.datetime() < Some(since)).unwrap_or(true))
+            .map(|(previous, current)| (previous.span.clone(), current.span.clone()))
+            .collect_vec();
+        if changes.since.is_none() && moved.is_empty() {
+            info!("no directive is changed");
+            let ledger = Ledger {
+                options: self.options.clone(),
+                entry,
+                database,
+                visited_files: transform_result.visited_files,
+                directives,
+                metas,
+                changes: LedgerChanges::default(),
+                transformer,
+                storage: self.storage.clone(),
+            };
+            // fingerprints of files are changed still
+            ledger.loaded().await?;
+            return Ok(ledger);
+        }
+        match &self.storage {
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite { snapshot, .. }
+                if changes
+                    .since
+                    .map(|since| !Ledger::has_redeclaration(&self.directives, since) && !Ledger::has_redeclaration(&directives, since))
+                    .unwrap_or(true) =>
+            {
+                return self
+                    .reload_since(snapshot, changes.since, moved, metas, directives, transform_result.visited_files)
+                    .await;
+            }
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite { .. } => info!("accounts or commodities are declared more than once, processing all directives"),
+            Storage::Memory(_) => info!("ledger is loaded in memory, processing all directives rather than the changed ones"),
+        }
+        Ledger::process(
+            metas.into_iter().chain(directives).collect(),
+            entry,
+            database,
             transform_result.visited_files,
+            transformer,
+            self.storage.renew().await?,
+        )
+        .await
+    }
+
+    /// copy the previous snapshot into a new one, relocate the moved directives, and process directives since the datetime again
+    #[cfg(feature = "sqlite")]
+    async fn reload_since(
+        &self, previous_snapshot: &str, since: Option<NaiveDateTime>, moved: Vec<(SpanInfo, SpanInfo)>, metas: Vec<Spanned<Directive>>,
+        mut directives: Vec<Spanned<Directive>>, visited_files: Vec<Pattern>,
+    ) -> ZhangResult<Ledger> {
+        match since {
+            Some(since) => info!("processing directives since {}", since),
+            None => info!("relocating {} moved directives", moved.len()),
+        }
+
+        let mut ret_ledger = Ledger::empty(
+            self.entry.clone(),
+            self.database.clone(),
+            visited_files,
             self.transformer.clone(),
+            Storage::sqlite().await?,
         )
-        .await?;
-        *self = reload_ledger;
-        Ok(())
+        .await;
+        let mut connection = ret_ledger.connection().await;
+        Migration::migrate(&mut connection).await?;
+        Migration::copy_from(&mut connection, &Storage::memory_uri(previous_snapshot)).await?;
+
+        // options are the same, and have been written into the previous snapshot
+        ret_ledger.options = self.options.clone();
+        let mut trx = connection.begin().await?;
+        let mut store = SqliteStore::new(&mut *trx);
+        store.move_spans(&moved).await?;
+        if let Some(since) = since {
+            // the same instant as the one of directives written into store, even if the local datetime is skipped or repeated by daylight saving
+            let since_datetime = Date::Datetime(since).to_timezone_datetime(&ret_ledger.options.timezone);
+            let rolled_back = self.directives.iter().filter(|it| it.datetime() >= Some(since)).collect_vec();
+            let accounts = store.rollback_directives(since_datetime, &rolled_back).await?;
+            store.rebuild_commodity_lots(&accounts).await?;
+            let balances = store.running_balances().await?;
+            let mut context = ProcessContext::new(Box::new(store), &mut ret_ledger.options).with_balances(balances);
+            for directive in directives.iter_mut().filter(|it| it.datetime() >= Some(since)) {
+                context.process(directive).await?;
+            }
+        }
+        trx.commit().await?;
+
+        ret_ledger.metas = metas;
+        ret_ledger.directives = directives;
+        ret_ledger.loaded().await?;
+        Ok(ret_ledger)
     }
 
-    pub async fn operations(&self) -> Operations {
-        let pool = self.connection().await;
+    /// directives of two loads are paired by their content within the same file, rather than their position,
+    /// so that directives shifted by the edits before them are not taken as changed
+    fn changed_directives<'a>(previous: &'a [Spanned<Directive>], current: &'a [Spanned<Directive>]) -> DirectiveChanges<'a> {
+        let group = |directives: &'a [Spanned<Directive>]| -> HashMap<(Option<&'a Path>, &'a str), Vec<&'a Spanned<Directive>>> {
+            let mut groups: HashMap<_, Vec<_>> = HashMap::new();
+            for directive in directives {
+                groups
+                    .entry((directive.span.filename.as_deref(), directive.span.content.as_str()))
+                    .or_default()
+                    .push(directive);
+            }
+            for group in groups.values_mut() {
+                group.sort_by_key(|it| it.span.start);
+            }
+            groups
+        };
+        let mut previous_groups = group(previous);
+        let mut current_groups = group(current);
+        let keys = previous_groups.keys().chain(current_groups.keys()).cloned().collect::<HashSet<_>>();
+
+        let mut changed = vec![];
+        let mut moved = vec![];
+        for key in keys {
+            let previous_group = previous_groups.remove(&key).unwrap_or_default();
+            let current_group = current_groups.remove(&key).unwrap_or_default();
+            // the same content in a file is paired in order, the rest are added or removed
+            for pair in previous_group.into_iter().zip_longest(current_group) {
+                match pair {
+                    EitherOrBoth::Both(previous, current) if previous.data == current.data => {
+                        if previous.span != current.span {
+                            moved.push((previous, current));
+                        }
+                    }
+                    EitherOrBoth::Both(previous, current) => changed.extend([previous, current]),
+                    EitherOrBoth::Left(directive) | EitherOrBoth::Right(directive) => changed.push(directive),
+                }
+            }
+        }
+        DirectiveChanges {
+            since: changed.iter().filter_map(|it| it.datetime()).min(),
+            changed,
+            moved,
+        }
+    }
+
+    fn changes_between(previous: &Ledger, current: &Ledger) -> LedgerChanges {
+        let directive_changes = Ledger::changed_directives(&previous.directives, &current.directives);
+        let mut transactions = vec![];
+        let mut accounts = vec![];
+        let mut commodities = false;
+        for directive in &directive_changes.changed {
+            match &directive.data {
+                Directive::Open(open) => accounts.push(open.account.name().to_owned()),
+                Directive::Close(close) => accounts.push(close.account.name().to_owned()),
+                Directive::Balance(Balance::BalanceCheck(check)) => accounts.push(check.account.name().to_owned()),
+                Directive::Balance(Balance::BalancePad(pad)) => accounts.extend([pad.account.name().to_owned(), pad.pad.name().to_owned()]),
+                Directive::Transaction(trx) => {
+                    transactions.push(Uuid::from_span(&directive.span).to_string());
+                    accounts.extend(trx.postings.iter().map(|it| it.account.name().to_owned()));
+                }
+                Directive::Commodity(_) | Directive::Price(_) => commodities = true,
+                _ => {}
+            }
+        }
+        for (previous_directive, current_directive) in &directive_changes.moved {
+            if let Directive::Transaction(_) = previous_directive.data {
+                transactions.push(Uuid::from_span(&previous_directive.span).to_string());
+                transactions.push(Uuid::from_span(&current_directive.span).to_string());
+            }
+        }
+        let options = |metas: &[Spanned<Directive>]| {
+            metas
+                .iter()
+                .filter_map(|it| match &it.data {
+                    Directive::Option(option) => Some((option.key.as_str().to_owned(), option.value.as_str().to_owned())),
+                    _ => None,
+                })
+                .sorted()
+                .collect_vec()
+        };
+        LedgerChanges {
+            transactions: transactions.into_iter().sorted().dedup().collect(),
+            accounts: accounts.into_iter().sorted().dedup().collect(),
+            commodities,
+            options: options(&previous.metas) != options(&current.metas),
+        }
+    }
+
+    /// accounts and commodities are upserted, they cannot be rolled back if declared both before and since the datetime
+    #[cfg(feature = "sqlite")]
+    fn has_redeclaration(directives: &[Spanned<Directive>], since: NaiveDateTime) -> bool {
+        let key = |directive: &Directive| match directive {
+            Directive::Open(open) => Some(format!("open {}", open.account.name())),
+            Directive::Close(close) => Some(format!("close {}", close.account.name())),
+            Directive::Commodity(commodity) => Some(format!("commodity {}", commodity.currency)),
+            _ => None,
+        };
+        let (kept, rolled_back): (Vec<&Spanned<Directive>>, Vec<&Spanned<Directive>>) = directives.iter().partition(|it| it.datetime() < Some(since));
+        let kept: HashSet<String> = kept.into_iter().filter_map(|it| key(&it.data)).collect();
+        rolled_back.into_iter().filter_map(|it| key(&it.data)).any(|it| kept.contains(&it))
+    }
+
+    /// validate the new content of given file with the ledger's transformer
+    pub fn validate_file_content(&self, content: &str, path: PathBuf) -> ZhangResult<()> {
+        self.transformer.validate(content, path)
+    }
+
+    pub async fn operations(&self) -> Operations<'static> {
         let timezone = self.options.timezone;
-        Operations { pool, timezone }
+        match &self.storage {
+            #[cfg(feature = "sqlite")]
+            Storage::Sqlite { .. } => Operations::new(Box::new(SqliteStore::new(self.connection().await)), timezone),
+            Storage::Memory(store) => Operations::new(Box::new(store.clone()), timezone),
+        }
     }
 }
 
@@ -227,7 +634,7 @@ mod test {
     use crate::parser::parse as parse_zhang;
     use zhang_ast::{Directive, SpanInfo, Spanned};
 
-    use crate::ledger::Ledger;
+    use crate::ledger::{Ledger, Storage};
     use crate::transform::{TransformResult, Transformer};
     use crate::ZhangResult;
 
@@ -258,17 +665,26 @@ mod test {
         fn load(&self, _entry: PathBuf, _endpoint: String) -> ZhangResult<TransformResult> {
             todo!()
         }
+
+        fn validate(&self, _content: &str, _path: PathBuf) -> ZhangResult<()> {
+            todo!()
+        }
     }
     async fn load_from_temp_str(content: &str) -> Ledger {
+        load_from_temp_str_with_storage(content, Storage::sqlite().await.unwrap()).await
+    }
+
+    async fn load_from_temp_str_with_storage(content: &str, storage: Storage) -> Ledger {
         let temp_dir = tempdir().unwrap().into_path();
         let example = temp_dir.join("example.zhang");
         std::fs::write(&example, content).unwrap();
         Ledger::process(
-            test_parse_zhang(content),
+            parse_zhang(content, example.clone()).expect("cannot parse zhang"),
             (temp_dir.clone(), "example.zhang".to_string()),
             None,
             vec![Pattern::new(temp_dir.join("example.zhang").as_path().to_str().unwrap()).unwrap()],
             Arc::new(TestTransformer {}),
+            storage,
         )
         .await
         .unwrap()
@@ -594,6 +1010,285 @@ mod test {
             Ok(())
         }
     }
+    mod journals {
+        use std::str::FromStr;
+
+        use bigdecimal::BigDecimal;
+        use chrono::NaiveDate;
+        use indoc::indoc;
+
+        use crate::domains::schemas::JournalFilter;
+        use crate::ledger::test::load_from_temp_str;
+        use crate::ledger::Ledger;
+
+        async fn load() -> Ledger {
+            load_from_temp_str(indoc! {r#"
+                1970-01-01 open Assets:Bank
+                1970-01-01 open Expenses:Travel
+                1970-01-01 open Expenses:Travel:Hotel
+                1970-01-01 open Expenses:Food
+
+                2022-03-01 "Hilton" "hotel in Tokyo" #travel ^trip-2022
+                  memo: "receipt"
+                  Assets:Bank -800 CNY
+                  Expenses:Travel:Hotel 800 CNY
+                    invoice: "INV-1"
+
+                2022-03-02 "KFC" "lunch in Tokyo" #travel
+                  Assets:Bank -50 CNY
+                  Expenses:Food 50 CNY
+
+                2023-01-01 ! "Airline" "flight ticket" #travel
+                  Assets:Bank -600 CNY
+                  Expenses:Travel 600 CNY
+
+                2023-01-02 "KFC" "dinner"
+                  Assets:Bank -40 CNY
+                  Expenses:Food 40 CNY
+            "#})
+            .await
+        }
+
+        async fn narrations(ledger: &Ledger, filter: JournalFilter) -> Vec<String> {
+            let mut operations = ledger.operations().await;
+            let (_, journals) = operations.journals(&filter, 100, 0).await.unwrap();
+            journals.into_iter().map(|it| it.narration.unwrap()).collect()
+        }
+
+        #[tokio::test]
+        async fn should_return_all_journals_with_details() {
+            let ledger = load().await;
+            let mut operations = ledger.operations().await;
+            let (total, journals) = operations.journals(&JournalFilter::default(), 2, 2).await.unwrap();
+            assert_eq!(4, total);
+            assert_eq!(2, journals.len());
+
+            let hotel = &journals[1];
+            assert_eq!(Some("hotel in Tokyo".to_string()), hotel.narration);
+            assert_eq!(vec!["travel".to_string()], hotel.tags);
+            assert_eq!(vec!["trip-2022".to_string()], hotel.links);
+            assert_eq!(vec!["memo".to_string()], hotel.metas.iter().map(|it| it.key.clone()).collect::<Vec<_>>());
+            assert_eq!(2, hotel.postings.len());
+            assert!(hotel.postings[0].metas.is_empty());
+            assert_eq!(
+                vec!["invoice".to_string()],
+                hotel.postings[1].metas.iter().map(|it| it.key.clone()).collect::<Vec<_>>()
+            );
+        }
+
+        #[tokio::test]
+        async fn should_filter_travel_expenses_over_500_in_2022() {
+            let ledger = load().await;
+            let filter = JournalFilter {
+                from: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap().and_hms_opt(0, 0, 0),
+                to: NaiveDate::from_ymd_opt(2022, 12, 31).unwrap().and_hms_opt(23, 59, 59),
+                account: Some("Expenses".to_string()),
+                tag: Some("travel".to_string()),
+                min_amount: Some(BigDecimal::from_str("500").unwrap()),
+                ..Default::default()
+            };
+            assert_eq!(vec!["hotel in Tokyo"], narrations(&ledger, filter).await);
+        }
+
+        #[tokio::test]
+        async fn should_filter_account_with_sub_accounts() {
+            let ledger = load().await;
+            let filter = JournalFilter {
+                account: Some("Expenses:Travel".to_string()),
+                ..Default::default()
+            };
+            assert_eq!(vec!["flight ticket", "hotel in Tokyo"], narrations(&ledger, filter).await);
+        }
+
+        #[tokio::test]
+        async fn should_treat_wildcards_in_account_literally() {
+            let ledger = load().await;
+            let filter = |account: &str| JournalFilter {
+                account: Some(account.to_string()),
+                ..Default::default()
+            };
+            assert!(narrations(&ledger, filter("Expenses%")).await.is_empty());
+            assert!(narrations(&ledger, filter("Expenses:Trave_")).await.is_empty());
+        }
+
+        #[tokio::test]
+        async fn should_count_journals_given_page_out_of_range() {
+            let ledger = load().await;
+            let mut operations = ledger.operations().await;
+            let (total, journals) = operations.journals(&JournalFilter::default(), 2, 10).await.unwrap();
+            assert_eq!(4, total);
+            assert!(journals.is_empty());
+        }
+
+        #[tokio::test]
+        async fn should_search_payee_and_narration() {
+            let ledger = load().await;
+            let filter = |keyword: &str| JournalFilter {
+                keyword: Some(keyword.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(vec!["lunch in Tokyo", "hotel in Tokyo"], narrations(&ledger, filter("tok")).await);
+            assert_eq!(vec!["dinner", "lunch in Tokyo"], narrations(&ledger, filter("kfc")).await);
+            assert_eq!(vec!["lunch in Tokyo"], narrations(&ledger, filter("KFC tokyo")).await);
+            assert!(narrations(&ledger, filter(r#"" OR *"#)).await.is_empty());
+        }
+
+        #[tokio::test]
+        async fn should_filter_flag_link_commodity_and_max_amount() {
+            let ledger = load().await;
+            let flag = JournalFilter {
+                flag: Some("!".to_string()),
+                ..Default::default()
+            };
+            assert_eq!(vec!["flight ticket"], narrations(&ledger, flag).await);
+            let link = JournalFilter {
+                link: Some("trip-2022".to_string()),
+                ..Default::default()
+            };
+            assert_eq!(vec!["hotel in Tokyo"], narrations(&ledger, link).await);
+            let amount = JournalFilter {
+                commodity: Some("CNY".to_string()),
+                max_amount: Some(BigDecimal::from(45)),
+                ..Default::default()
+            };
+            assert_eq!(vec!["dinner"], narrations(&ledger, amount).await);
+        }
+    }
+
+    mod labels {
+        use bigdecimal::BigDecimal;
+        use indoc::indoc;
+
+        use crate::ledger::test::load_from_temp_str;
+        use crate::ledger::Ledger;
+
+        async fn load() -> Ledger {
+            load_from_temp_str(indoc! {r#"
+                1970-01-01 open Assets:Bank
+                1970-01-01 open Assets:Receivable
+                1970-01-01 open Expenses:Travel
+                1970-01-01 open Income:Salary
+
+                2022-03-01 "Hilton" "hotel" #travel #work ^invoice-123
+                  Assets:Bank -800 CNY
+                  Expenses:Travel 800 CNY
+
+                2022-03-05 "Airline" "flight" #travel
+                  Assets:Bank -300 CNY
+                  Expenses:Travel 300 CNY
+
+                2022-04-01 "Company" "reimbursement" #work ^invoice-123
+                  Assets:Bank 800 CNY
+                  Income:Salary -800 CNY
+            "#})
+            .await
+        }
+
+        #[tokio::test]
+        async fn should_count_tag_and_link_usages() {
+            let ledger = load().await;
+            let mut operations = ledger.operations().await;
+
+            let tags = operations.tag_usages().await.unwrap();
+            assert_eq!(
+                vec![("travel".to_string(), 2), ("work".to_string(), 2)],
+                tags.iter().map(|it| (it.name.clone(), it.count)).collect::<Vec<_>>()
+            );
+            assert_eq!("2022-03-01", tags[1].first_datetime.date().to_string());
+            assert_eq!("2022-04-01", tags[1].last_datetime.date().to_string());
+
+            let links = operations.link_usages().await.unwrap();
+            assert_eq!(1, links.len());
+            assert_eq!("invoice-123", links[0].name);
+            assert_eq!(2, links[0].count);
+        }
+
+        #[tokio::test]
+        async fn should_sum_income_and_expenses_per_period() {
+            let ledger = load().await;
+            let mut operations = ledger.operations().await;
+
+            let totals = operations.tag_period_totals("%Y-%m").await.unwrap();
+            assert_eq!(
+                vec![
+                    ("travel".to_string(), "2022-03".to_string(), BigDecimal::from(1100)),
+                    ("work".to_string(), "2022-03".to_string(), BigDecimal::from(800)),
+                    ("work".to_string(), "2022-04".to_string(), BigDecimal::from(-800)),
+                ],
+                totals.into_iter().map(|it| (it.name, it.period, it.amount.0.normalized())).collect::<Vec<_>>()
+            );
+        }
+
+        #[tokio::test]
+        async fn should_sum_account_effects_of_link() {
+            let ledger = load().await;
+            let mut operations = ledger.operations().await;
+
+            let effects = operations.link_account_effects("invoice-123").await.unwrap();
+            assert_eq!(
+                vec![
+                    ("Assets:Bank".to_string(), BigDecimal::from(0), 2),
+                    ("Expenses:Travel".to_string(), BigDecimal::from(800), 1),
+                    ("Income:Salary".to_string(), BigDecimal::from(-800), 1),
+                ],
+                effects
+                    .into_iter()
+                    .map(|it| (it.account, it.amount.0.normalized(), it.transactions))
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    mod account_daily_balances {
+        use bigdecimal::BigDecimal;
+        use chrono::NaiveDate;
+        use indoc::indoc;
+
+        use crate::ledger::test::load_from_temp_str;
+
+        #[tokio::test]
+        async fn should_return_balances_in_range_with_latest_balance_before() {
+            let ledger = load_from_temp_str(indoc! {r#"
+                1970-01-01 open Assets:Bank
+                1970-01-01 open Expenses:Food
+
+                2023-01-01 "KFC" "breakfast"
+                  Assets:Bank -10 CNY
+                  Expenses:Food 10 CNY
+
+                2023-01-01 "KFC" "lunch"
+                  Assets:Bank -20 CNY
+                  Expenses:Food 20 CNY
+
+                2023-02-01 "KFC" "dinner"
+                  Assets:Bank -30 CNY
+                  Expenses:Food 30 CNY
+
+                2023-03-01 "KFC" "dinner"
+                  Assets:Bank -40 CNY
+                  Expenses:Food 40 CNY
+            "#})
+            .await;
+            let mut operations = ledger.operations().await;
+
+            let balances = operations
+                .account_daily_balances(
+                    "Assets:Bank",
+                    NaiveDate::from_ymd_opt(2023, 1, 15).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+                    NaiveDate::from_ymd_opt(2023, 2, 15).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                vec![
+                    (NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(), BigDecimal::from(-30)),
+                    (NaiveDate::from_ymd_opt(2023, 2, 1).unwrap(), BigDecimal::from(-60)),
+                ],
+                balances.into_iter().map(|it| (it.date, it.balance_number.0.normalized())).collect::<Vec<_>>()
+            );
+        }
+    }
+
     // mod txn {
     //     use bigdecimal::BigDecimal;
     //     use indoc::indoc;
@@ -920,4 +1615,551 @@ mod test {
     //         )
     //     }
     // }
+
+    #[tokio::test]
+    async fn should_validate_file_content_with_ledger_transformer() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("main.zhang"), "1970-01-01 open Assets:Bank").unwrap();
+        let ledger = Ledger::load::<crate::transform::TextTransformer>(temp_dir.path().to_path_buf(), "main.zhang".to_string())
+            .await
+            .unwrap();
+        let path = temp_dir.path().join("main.zhang");
+
+        assert!(ledger.validate_file_content("1970-01-01 open Assets:Cash", path.clone()).is_ok());
+        let error = ledger
+            .validate_file_content("1970-01-01 open Assets:Cash\n1970-01-01 opne Assets:Bank", path)
+            .unwrap_err();
+        assert!(matches!(error, crate::ZhangError::SyntaxError { line: 2, .. }), "{:?}", error);
+    }
+
+    mod reload {
+        use indoc::indoc;
+        use sqlx::sqlite::SqliteConnectOptions;
+        use sqlx::{ConnectOptions, Connection};
+        use tempfile::tempdir;
+
+        use uuid::Uuid;
+        use zhang_ast::Directive;
+
+        use crate::ledger::{Ledger, LedgerChanges, Storage};
+        use crate::transform::TextTransformer;
+        use crate::utils::id::FromSpan;
+
+        static TABLES: [(&str, &str); 10] = [
+            ("accounts", "date, type, name, status, alias"),
+            ("commodities", "name, precision, prefix, suffix, rounding"),
+            ("metas", "type, type_identifier, key, value"),
+            ("transactions", "id, datetime, type, payee, narration, source_file, span_start, span_end"),
+            ("transaction_tags", "trx_id, tag"),
+            (
+                "transaction_postings",
+                "trx_id, account, unit_number, unit_commodity, cost_number, cost_commodity, price_number, price_commodity, \
+                 inferred_unit_number, inferred_unit_commodity, account_before_number, account_after_number",
+            ),
+            ("documents", "datetime, filename, path, account, trx_id"),
+            ("prices", "datetime, commodity, amount, target_commodity"),
+            ("commodity_lots", "account, commodity, amount, price_amount, price_commodity"),
+            ("errors", "filename, span_start, span_end, error_type, metas"),
+        ];
+
+        async fn dump(ledger: &Ledger) -> Vec<String> {
+            let mut conn = ledger.connection().await;
+            let mut rows = vec![];
+            for (table, columns) in TABLES {
+                let row = columns
+                    .split(", ")
+                    .map(|column| format!("quote({})", column.trim()))
+                    .collect::<Vec<_>>()
+                    .join(" || ',' || ");
+                let mut table_rows: Vec<String> = sqlx::query_scalar(&format!("select '{table}: ' || {row} from {table}"))
+                    .fetch_all(&mut conn)
+                    .await
+                    .unwrap();
+                table_rows.sort();
+                rows.extend(table_rows);
+            }
+            let sequences: Vec<String> = sqlx::query_scalar("select id from transactions order by sequence")
+                .fetch_all(&mut conn)
+                .await
+                .unwrap();
+            rows.extend(sequences);
+            rows
+        }
+
+        static MAIN: &str = indoc! {r#"
+            option "operating_currency" "CNY"
+            include "2022.zhang"
+            include "2023.zhang"
+
+            1970-01-01 open Assets:Bank
+            1970-01-01 open Assets:Stock
+            1970-01-01 open Expenses:Food
+            1970-01-01 open Equity:Open-Balances
+            1970-01-01 commodity AAPL
+        "#};
+
+        static YEAR_2022: &str = indoc! {r#"
+            2022-01-01 balance Assets:Bank 1000 CNY with pad Equity:Open-Balances
+            2022-02-01 "KFC" "Lunch" #food
+              Assets:Bank -50 CNY
+              Expenses:Food
+            2022-03-01 "Broker" "Buy"
+              Assets:Stock 2 AAPL @ 150 CNY
+              Assets:Bank -300 CNY
+        "#};
+
+        static YEAR_2023: &str = indoc! {r#"
+            2023-01-01 price AAPL 160 CNY
+            2023-02-01 "KFC" "Dinner"
+              Assets:Bank -70 CNY
+              Expenses:Food
+              document: "receipt.pdf"
+            2023-03-01 "Broker" "Buy"
+              Assets:Stock 1 AAPL {155 CNY}
+              Assets:Bank -155 CNY
+            2023-04-01 balance Assets:Bank 500 CNY
+            2023-05-01 close Assets:Stock
+        "#};
+
+        async fn load(files: &[(&str, &str)]) -> (tempfile::TempDir, Ledger) {
+            let temp_dir = tempdir().unwrap();
+            for (name, content) in files {
+                std::fs::write(temp_dir.path().join(name), content).unwrap();
+            }
+            let ledger = Ledger::load::<TextTransformer>(temp_dir.path().to_path_buf(), "main.zhang".to_string())
+                .await
+                .unwrap();
+            (temp_dir, ledger)
+        }
+
+        async fn assert_reload_equals_full_load(changed: &[(&str, &str)]) {
+            let (temp_dir, ledger) = load(&[("main.zhang", MAIN), ("2022.zhang", YEAR_2022), ("2023.zhang", YEAR_2023)]).await;
+            for (name, content) in changed {
+                std::fs::write(temp_dir.path().join(name), content).unwrap();
+            }
+            let reloaded = ledger.reload().await.unwrap();
+            let full = Ledger::load::<TextTransformer>(temp_dir.path().to_path_buf(), "main.zhang".to_string())
+                .await
+                .unwrap();
+
+            assert_eq!(dump(&full).await, dump(&reloaded).await);
+            assert_eq!(full.directives, reloaded.directives);
+            // the previous snapshot is left untouched
+            assert_eq!(dump(&ledger).await.len(), {
+                let (_dir, original) = load(&[("main.zhang", MAIN), ("2022.zhang", YEAR_2022), ("2023.zhang", YEAR_2023)]).await;
+                dump(&original).await.len()
+            });
+        }
+
+        #[tokio::test]
+        async fn should_be_same_as_full_load_given_later_transaction_changed() {
+            assert_reload_equals_full_load(&[("2023.zhang", &YEAR_2023.replace("-70 CNY", "-80 CNY"))]).await;
+        }
+
+        #[tokio::test]
+        async fn should_be_same_as_full_load_given_earlier_directives_removed() {
+            assert_reload_equals_full_load(&[("2022.zhang", "2022-01-01 balance Assets:Bank 1000 CNY with pad Equity:Open-Balances\n")]).await;
+        }
+
+        #[tokio::test]
+        async fn should_be_same_as_full_load_given_close_and_commodity_changed() {
+            let year_2023 = YEAR_2023.replace("2023-05-01 close Assets:Stock", "2023-05-01 commodity MSFT\n2023-06-01 open Assets:Cash");
+            assert_reload_equals_full_load(&[("2023.zhang", &year_2023)]).await;
+        }
+
+        #[tokio::test]
+        async fn should_be_same_as_full_load_given_later_directive_inserted_before_earlier_ones() {
+            let year_2022 = format!("2023-06-01 \"KFC\" \"Late\"\n  Assets:Bank -1 CNY\n  Expenses:Food\n{}", YEAR_2022);
+            assert_reload_equals_full_load(&[("2022.zhang", &year_2022)]).await;
+        }
+
+        #[tokio::test]
+        async fn should_be_same_as_full_load_given_only_spans_moved() {
+            assert_reload_equals_full_load(&[("2023.zhang", &format!("; moved by a comment\n{}", YEAR_2023))]).await;
+            assert_reload_equals_full_load(&[("2022.zhang", &format!("; moved by a comment\n{}", YEAR_2022))]).await;
+        }
+
+        #[tokio::test]
+        async fn should_be_same_as_full_load_given_directive_changed_at_datetime_skipped_or_repeated_by_daylight_saving() {
+            let main = indoc! {r#"
+                option "timezone" "America/New_York"
+                1970-01-01 open Assets:Bank
+                1970-01-01 open Expenses:Food
+                2023-03-12 01:30:00 "KFC" "before skipped"
+                  Assets:Bank -10 CNY
+                  Expenses:Food
+                2023-03-12 03:30:00 "KFC" "after skipped"
+                  Assets:Bank -10 CNY
+                  Expenses:Food
+                2023-11-05 01:30:00 "KFC" "repeated"
+                  Assets:Bank -10 CNY
+                  Expenses:Food
+            "#};
+            for changed in [
+                main.replace("03:30:00 \"KFC\" \"after skipped\"", "02:30:00 \"KFC\" \"skipped\""),
+                main.replace("\"repeated\"\n  Assets:Bank -10 CNY", "\"repeated\"\n  Assets:Bank -20 CNY"),
+            ] {
+                assert_ne!(main, changed);
+                let (temp_dir, ledger) = load(&[("main.zhang", main)]).await;
+                std::fs::write(temp_dir.path().join("main.zhang"), &changed).unwrap();
+                let reloaded = ledger.reload().await.unwrap();
+                let full = Ledger::load::<TextTransformer>(temp_dir.path().to_path_buf(), "main.zhang".to_string())
+                    .await
+                    .unwrap();
+                assert_eq!(dump(&full).await, dump(&reloaded).await);
+            }
+        }
+
+        #[tokio::test]
+        async fn should_tell_changes_by_changed_and_moved_directives() {
+            let transaction_ids = |ledger: &Ledger| {
+                ledger
+                    .directives
+                    .iter()
+                    .filter(|it| matches!(it.data, Directive::Transaction(_)) && it.span.filename.as_ref().unwrap().ends_with("2023.zhang"))
+                    .map(|it| Uuid::from_span(&it.span).to_string())
+                    .collect::<Vec<_>>()
+            };
+            let (temp_dir, ledger) = load(&[("main.zhang", MAIN), ("2022.zhang", YEAR_2022), ("2023.zhang", YEAR_2023)]).await;
+            assert_eq!(LedgerChanges::default(), ledger.changes);
+
+            std::fs::write(temp_dir.path().join("2023.zhang"), YEAR_2023.replace("-70 CNY", "-80 CNY")).unwrap();
+            let reloaded = ledger.reload().await.unwrap();
+            assert_eq!(
+                LedgerChanges {
+                    transactions: vec![transaction_ids(&reloaded)[0].clone()],
+                    accounts: vec!["Assets:Bank".to_string(), "Expenses:Food".to_string()],
+                    commodities: false,
+                    options: false,
+                },
+                reloaded.changes
+            );
+
+            std::fs::write(temp_dir.path().join("2023.zhang"), format!("; moved by a comment\n{}", YEAR_2023)).unwrap();
+            let moved = ledger.reload().await.unwrap();
+            let mut ids = [transaction_ids(&ledger), transaction_ids(&moved)].concat();
+            ids.sort();
+            assert_eq!(ids, moved.changes.transactions);
+            assert!(moved.changes.accounts.is_empty());
+            assert!(!moved.changes.commodities);
+        }
+
+        #[tokio::test]
+        async fn should_be_same_as_full_load_given_options_changed() {
+            assert_reload_equals_full_load(&[("main.zhang", &MAIN.replace("\"CNY\"", "\"USD\""))]).await;
+        }
+
+        #[tokio::test]
+        async fn should_share_snapshot_given_nothing_changed() {
+            let (_temp_dir, ledger) = load(&[("main.zhang", MAIN), ("2022.zhang", YEAR_2022), ("2023.zhang", YEAR_2023)]).await;
+            let reloaded = ledger.reload().await.unwrap();
+            match (&ledger.storage, &reloaded.storage) {
+                (
+                    Storage::Sqlite { snapshot, .. },
+                    Storage::Sqlite {
+                        snapshot: reloaded_snapshot, ..
+                    },
+                ) => assert_eq!(snapshot, reloaded_snapshot),
+                _ => unreachable!("ledger is loaded into sqlite"),
+            }
+            assert_eq!(dump(&ledger).await, dump(&reloaded).await);
+        }
+
+        #[tokio::test]
+        async fn should_persist_snapshot_into_database_file() {
+            let temp_dir = tempdir().unwrap();
+            std::fs::write(temp_dir.path().join("main.zhang"), "1970-01-01 open Assets:Bank").unwrap();
+            let database = temp_dir.path().join("cache.db");
+            let ledger = Ledger::load_with_database(
+                temp_dir.path().to_path_buf(),
+                "main.zhang".to_string(),
+                Some(database.clone()),
+                std::sync::Arc::new(TextTransformer::default()),
+            )
+            .await
+            .unwrap();
+            std::fs::write(temp_dir.path().join("main.zhang"), "1970-01-01 open Assets:Bank\n1970-01-02 open Assets:Cash").unwrap();
+            ledger.reload().await.unwrap();
+
+            let mut conn = SqliteConnectOptions::default().filename(&database).connect().await.unwrap();
+            let accounts: Vec<String> = sqlx::query_scalar("select name from accounts order by name")
+                .fetch_all(&mut conn)
+                .await
+                .unwrap();
+            assert_eq!(vec!["Assets:Bank", "Assets:Cash"], accounts);
+        }
+
+        #[tokio::test]
+        async fn should_restore_from_database_file_given_files_not_changed() {
+            let temp_dir = tempdir().unwrap();
+            let main = temp_dir.path().join("main.zhang");
+            std::fs::write(&main, "option \"timezone\" \"Asia/Tokyo\"\n1970-01-01 open Assets:Bank").unwrap();
+            let database = temp_dir.path().join("cache.db");
+            let load = || {
+                Ledger::load_with_database(
+                    temp_dir.path().to_path_buf(),
+                    "main.zhang".to_string(),
+                    Some(database.clone()),
+                    std::sync::Arc::new(TextTransformer::default()),
+                )
+            };
+            load().await.unwrap();
+
+            // mark the cache database, so that we can tell whether it's rebuilt
+            let mut conn = SqliteConnectOptions::default().filename(&database).connect().await.unwrap();
+            sqlx::query("insert into accounts(date, type, name, status) values ('1970-01-01', 'Assets', 'Assets:Marker', 'Open')")
+                .execute(&mut conn)
+                .await
+                .unwrap();
+            conn.close().await.unwrap();
+
+            let restored = load().await.unwrap();
+            assert_eq!("Asia/Tokyo", restored.options.timezone.name());
+            assert!(restored.operations().await.exist_account("Assets:Marker").await.unwrap());
+            assert!(restored.operations().await.exist_account("Assets:Bank").await.unwrap());
+
+            std::fs::write(&main, "1970-01-01 open Assets:Bank\n1970-01-01 open Assets:Cash").unwrap();
+            let rebuilt = load().await.unwrap();
+            assert!(!rebuilt.operations().await.exist_account("Assets:Marker").await.unwrap());
+            assert!(rebuilt.operations().await.exist_account("Assets:Cash").await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn should_not_write_database_file_given_files_changed() {
+            let temp_dir = tempdir().unwrap();
+            let main = temp_dir.path().join("main.zhang");
+            std::fs::write(&main, "1970-01-01 open Assets:Bank").unwrap();
+            let database = temp_dir.path().join("cache.db");
+            let ledger = Ledger::load_with_database(
+                temp_dir.path().to_path_buf(),
+                "main.zhang".to_string(),
+                Some(database.clone()),
+                std::sync::Arc::new(TextTransformer::default()),
+            )
+            .await
+            .unwrap();
+            let mut conn = SqliteConnectOptions::default().filename(&database).connect().await.unwrap();
+            sqlx::query("delete from schema_migrations where version = $1")
+                .bind(crate::database::migrations::Migration::latest_version())
+                .execute(&mut conn)
+                .await
+                .unwrap();
+            conn.close().await.unwrap();
+            let content = std::fs::read(&database).unwrap();
+
+            std::fs::write(&main, "1970-01-01 open Assets:Bank\n1970-01-01 open Assets:Cash").unwrap();
+            assert!(Ledger::restore(&database, &ledger.visited_files).await.unwrap().is_none());
+            assert_eq!(content, std::fs::read(&database).unwrap(), "outdated cache should not be migrated");
+
+            std::fs::write(&main, "1970-01-01 open Assets:Bank").unwrap();
+            assert!(Ledger::restore(&database, &ledger.visited_files).await.unwrap().is_some());
+            let mut conn = SqliteConnectOptions::default().filename(&database).connect().await.unwrap();
+            assert_eq!(
+                crate::database::migrations::Migration::latest_version(),
+                crate::database::migrations::Migration::current_version(&mut conn).await.unwrap()
+            );
+        }
+
+        #[tokio::test]
+        async fn should_rebuild_database_file_given_other_cache_version() {
+            let temp_dir = tempdir().unwrap();
+            std::fs::write(temp_dir.path().join("main.zhang"), "1970-01-01 open Assets:Bank").unwrap();
+            let database = temp_dir.path().join("cache.db");
+            let mut conn = SqliteConnectOptions::default()
+                .filename(&database)
+                .create_if_missing(true)
+                .connect()
+                .await
+                .unwrap();
+            sqlx::query("create table accounts (name varchar)").execute(&mut conn).await.unwrap();
+            conn.close().await.unwrap();
+
+            let ledger = Ledger::load_with_database(
+                temp_dir.path().to_path_buf(),
+                "main.zhang".to_string(),
+                Some(database.clone()),
+                std::sync::Arc::new(TextTransformer::default()),
+            )
+            .await
+            .unwrap();
+            assert!(ledger.operations().await.exist_account("Assets:Bank").await.unwrap());
+
+            let mut conn = SqliteConnectOptions::default().filename(&database).connect().await.unwrap();
+            let version: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(&mut conn).await.unwrap();
+            assert_eq!(crate::database::cache::CACHE_VERSION, version);
+        }
+
+        #[test]
+        fn should_find_earliest_changed_datetime() {
+            let previous = crate::parser::parse(YEAR_2023, None).unwrap();
+            let mut current = previous.clone();
+            assert_eq!(None, Ledger::changed_directives(&previous, &current).since);
+
+            current.remove(2);
+            assert_eq!(
+                Some(chrono::NaiveDate::from_ymd_opt(2023, 3, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()),
+                Ledger::changed_directives(&previous, &current).since
+            );
+        }
+
+        #[test]
+        fn should_pair_shifted_directives_by_content() {
+            let previous = crate::parser::parse(YEAR_2023, None).unwrap();
+            let current = crate::parser::parse(&format!("; a comment\n{}", YEAR_2023), None).unwrap();
+            let changes = Ledger::changed_directives(&previous, &current);
+            assert_eq!(None, changes.since);
+            assert_eq!(previous.len(), changes.moved.len());
+        }
+    }
+
+    mod memory_store {
+        use std::ops::Bound;
+
+        use bigdecimal::BigDecimal;
+        use indoc::indoc;
+        use itertools::Itertools;
+
+        use crate::domains::schemas::JournalFilter;
+        use crate::ledger::test::load_from_temp_str_with_storage;
+        use crate::ledger::{Ledger, Storage};
+        use crate::store::memory::MemoryStore;
+
+        const CONTENT: &str = indoc! {r#"
+            option "operating_currency" "CNY"
+            option "timezone" "UTC"
+            1970-01-01 commodity CNY
+            1970-01-01 commodity AAPL
+            1970-01-01 open Assets:Bank
+            1970-01-01 open Assets:Stock
+            1970-01-01 open Expenses:Food
+            1970-01-01 open Income:Salary
+            2023-01-01 price AAPL 100 CNY
+            2023-01-01 "Company" "salary" #work ^jan
+              Income:Salary -1000 CNY
+              Assets:Bank
+            2023-01-02 "KFC" "lunch" #food
+              Expenses:Food 25.5 CNY
+              Assets:Bank
+            2023-01-03 "Broker" "buy stock"
+              Assets:Stock 2 AAPL @ 100 CNY
+              Assets:Bank -200 CNY
+            2023-01-04 "Broker" "sell stock"
+              Assets:Stock -1 AAPL @ 120 CNY
+              Assets:Bank 120 CNY
+            2023-01-05 "KFC" "unbalanced dinner"
+              Expenses:Food 10 CNY
+              Assets:Bank -9 CNY
+            2023-01-06 close Expenses:Food
+        "#};
+
+        async fn load_both() -> (Ledger, Ledger) {
+            let sqlite = load_from_temp_str_with_storage(CONTENT, Storage::sqlite().await.unwrap()).await;
+            let memory = load_from_temp_str_with_storage(CONTENT, Storage::Memory(MemoryStore::new())).await;
+            (sqlite, memory)
+        }
+
+        #[tokio::test]
+        async fn should_have_same_accounts_and_balances() {
+            let (sqlite, memory) = load_both().await;
+            let (mut sqlite, mut memory) = (sqlite.operations().await, memory.operations().await);
+
+            let accounts =
+                |it: Vec<crate::domains::schemas::AccountDomain>| it.into_iter().map(|it| (it.name, it.status)).sorted_by(|a, b| a.0.cmp(&b.0)).collect_vec();
+            assert_eq!(accounts(sqlite.accounts().await.unwrap()), accounts(memory.accounts().await.unwrap()));
+
+            let balances = |it: Vec<crate::domains::schemas::AccountBalanceDomain>| {
+                it.into_iter()
+                    .map(|it| (it.account, it.balance_commodity, it.balance_number.0))
+                    .sorted_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)))
+                    .collect_vec()
+            };
+            let memory_balances = balances(memory.account_balances().await.unwrap());
+            assert_eq!(balances(sqlite.account_balances().await.unwrap()), memory_balances);
+            assert!(memory_balances.contains(&(
+                "Assets:Bank".to_string(),
+                "CNY".to_string(),
+                BigDecimal::from(885) + BigDecimal::new(5.into(), 1)
+            )));
+
+            let latest = |it: Vec<crate::domains::schemas::AccountDailyBalanceDomain>| {
+                it.into_iter()
+                    .map(|it| (it.date, it.account, it.balance_commodity, it.balance_number.0))
+                    .sorted_by(|a, b| (&a.1, &a.2).cmp(&(&b.1, &b.2)))
+                    .collect_vec()
+            };
+            assert_eq!(
+                latest(sqlite.accounts_latest_balance(Bound::Unbounded).await.unwrap()),
+                latest(memory.accounts_latest_balance(Bound::Unbounded).await.unwrap())
+            );
+        }
+
+        #[tokio::test]
+        async fn should_have_same_journals_and_errors() {
+            let (sqlite, memory) = load_both().await;
+            let (mut sqlite, mut memory) = (sqlite.operations().await, memory.operations().await);
+
+            let journals = |it: (i64, Vec<crate::domains::schemas::JournalDomain>)| {
+                let items =
+                    it.1.into_iter()
+                        .map(|journal| (journal.payee, journal.narration, journal.tags, journal.links, journal.postings.len()))
+                        .collect_vec();
+                (it.0, items)
+            };
+            let filter = JournalFilter::default();
+            let memory_journals = journals(memory.journals(&filter, 100, 0).await.unwrap());
+            assert_eq!(journals(sqlite.journals(&filter, 100, 0).await.unwrap()), memory_journals);
+            assert_eq!(5, memory_journals.0);
+
+            let filter = JournalFilter {
+                keyword: Some("kf".to_string()),
+                ..Default::default()
+            };
+            assert_eq!(
+                journals(sqlite.journals(&filter, 100, 0).await.unwrap()),
+                journals(memory.journals(&filter, 100, 0).await.unwrap())
+            );
+
+            let errors =
+                |it: Vec<crate::domains::schemas::ErrorDomain>| it.into_iter().map(|it| it.error_type).sorted_by_key(|it| it.as_ref().to_owned()).collect_vec();
+            assert_eq!(errors(sqlite.errors().await.unwrap()), errors(memory.errors().await.unwrap()));
+
+            let usages = |it: Vec<crate::domains::schemas::LabelUsageDomain>| it.into_iter().map(|it| (it.name, it.count)).collect_vec();
+            assert_eq!(usages(sqlite.tag_usages().await.unwrap()), usages(memory.tag_usages().await.unwrap()));
+            assert_eq!(usages(sqlite.link_usages().await.unwrap()), usages(memory.link_usages().await.unwrap()));
+            assert_eq!(sqlite.payees().await.unwrap(), memory.payees().await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn should_have_same_commodities_and_lots() {
+            let (sqlite, memory) = load_both().await;
+            let (mut sqlite, mut memory) = (sqlite.operations().await, memory.operations().await);
+
+            let summaries = |it: Vec<crate::domains::schemas::CommoditySummaryDomain>| {
+                it.into_iter()
+                    .map(|it| (it.name, it.total_amount.0, it.latest_price_amount.map(|it| it.0), it.latest_price_commodity))
+                    .sorted_by(|a, b| a.0.cmp(&b.0))
+                    .collect_vec()
+            };
+            assert_eq!(
+                summaries(sqlite.commodity_summaries().await.unwrap()),
+                summaries(memory.commodity_summaries().await.unwrap())
+            );
+
+            let lots = |it: Vec<crate::domains::schemas::CommodityLotDomain>| {
+                it.into_iter()
+                    .map(|it| (it.account, it.amount.0, it.price_amount.map(|it| it.0), it.price_commodity))
+                    .collect_vec()
+            };
+            let memory_lots = lots(memory.commodity_lots("AAPL").await.unwrap());
+            assert_eq!(lots(sqlite.commodity_lots("AAPL").await.unwrap()), memory_lots);
+            assert!(!memory_lots.is_empty());
+
+            let prices =
+                |it: Vec<crate::domains::schemas::PriceDomain>| it.into_iter().map(|it| (it.commodity, it.amount.0, it.target_commodity)).collect_vec();
+            assert_eq!(
+                prices(sqlite.prices(Some("AAPL")).await.unwrap()),
+                prices(memory.prices(Some("AAPL")).await.unwrap())
+            );
+        }
+    }
 }
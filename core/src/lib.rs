@@ -9,6 +9,7 @@ pub mod options;
 #[allow(clippy::type_complexity)]
 pub mod parser;
 pub(crate) mod process;
+pub mod store;
 pub mod transform;
 pub mod utils;
 
@@ -39,6 +40,10 @@ mod test {
                 visited_files: vec![Pattern::new("example.zhang").unwrap()],
             })
         }
+
+        fn validate(&self, content: &str, path: PathBuf) -> ZhangResult<()> {
+            parse_zhang(content, path).map(|_| ()).map_err(crate::ZhangError::syntax_error)
+        }
     }
     async fn load_from_text(content: &str) -> Ledger {
         let temp_dir = tempdir().unwrap().into_path();
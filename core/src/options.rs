@@ -1,6 +1,5 @@
 use itertools::Itertools;
 use log::{error, info, warn};
-use sqlx::SqliteConnection;
 use std::str::FromStr;
 use std::string::ToString;
 use strum::{AsRefStr, EnumIter, EnumString, IntoEnumIterator};
@@ -9,10 +8,12 @@ use zhang_ast::{Directive, Options, Rounding, SpanInfo, Spanned, ZhangString};
 use crate::constants::{
     DEFAULT_BALANCE_TOLERANCE_PRECISION_PLAIN, DEFAULT_COMMODITY_PRECISION_PLAIN, DEFAULT_OPERATING_CURRENCY, DEFAULT_ROUNDING_PLAIN, DEFAULT_TIMEZONE,
 };
+use crate::domains::schemas::CommodityDomain;
+use crate::store::LedgerStore;
 use crate::ZhangResult;
 use chrono_tz::Tz;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct InMemoryOptions {
     pub operating_currency: String,
     pub default_rounding: Rounding,
@@ -70,28 +71,21 @@ impl BuiltinOption {
 }
 
 impl InMemoryOptions {
-    pub async fn parse(&mut self, key: impl Into<String>, value: impl Into<String>, conn: &mut SqliteConnection) -> ZhangResult<String> {
+    pub async fn parse(&mut self, key: impl Into<String>, value: impl Into<String>, store: &mut (dyn LedgerStore + '_)) -> ZhangResult<String> {
         let value = value.into();
         let key = key.into();
         if let Ok(option) = BuiltinOption::from_str(&key) {
             match option {
                 BuiltinOption::OperatingCurrency => {
-                    let precision = self.default_balance_tolerance_precision;
-                    let prefix: Option<String> = None;
-                    let suffix: Option<String> = None;
-                    let rounding = Some(self.default_rounding);
-
-                    sqlx::query(
-                        r#"INSERT OR REPLACE INTO commodities (name, precision, prefix, suffix, rounding)
-                        VALUES ($1, $2, $3, $4, $5);"#,
-                    )
-                    .bind(&value)
-                    .bind(precision)
-                    .bind(prefix)
-                    .bind(suffix)
-                    .bind(rounding.map(|it| it.to_string()))
-                    .execute(conn)
-                    .await?;
+                    store
+                        .insert_commodity(&CommodityDomain {
+                            name: value.clone(),
+                            precision: self.default_balance_tolerance_precision,
+                            prefix: None,
+                            suffix: None,
+                            rounding: Some(self.default_rounding.to_string()),
+                        })
+                        .await?;
                     self.operating_currency = value.to_owned();
                 }
                 BuiltinOption::DefaultRounding => {
@@ -118,6 +112,31 @@ impl InMemoryOptions {
     }
 }
 
+impl InMemoryOptions {
+    /// restore the in memory options from the value stored in database, which has been parsed before
+    pub fn restore(&mut self, key: &str, value: &str) {
+        match BuiltinOption::from_str(key) {
+            Ok(BuiltinOption::OperatingCurrency) => self.operating_currency = value.to_owned(),
+            Ok(BuiltinOption::DefaultRounding) => {
+                if let Ok(rounding) = Rounding::from_str(value) {
+                    self.default_rounding = rounding;
+                }
+            }
+            Ok(BuiltinOption::DefaultBalanceTolerancePrecision) => {
+                if let Ok(precision) = value.parse::<i32>() {
+                    self.default_balance_tolerance_precision = precision;
+                }
+            }
+            Ok(BuiltinOption::Timezone) => {
+                if let Ok(tz) = value.parse::<Tz>() {
+                    self.timezone = tz;
+                }
+            }
+            Ok(BuiltinOption::DefaultCommodityPrecision) | Err(_) => {}
+        }
+    }
+}
+
 impl Default for InMemoryOptions {
     fn default() -> Self {
         InMemoryOptions {
@@ -208,14 +208,19 @@ impl ZhangParser {
             Option<Flag>,
             Account,
             Option<(Option<Amount>, Option<(Option<Amount>, Option<Date>, Option<SingleTotalPrice>)>)>,
+            Vec<(String, ZhangString)>,
         ) = match_nodes!(input.into_children();
-            [account_name(account_name)] => (None, account_name, None),
-            [account_name(account_name), posting_unit(unit)] => (None, account_name, Some(unit)),
-            [transaction_flag(flag), account_name(account_name)] => (flag, account_name, None),
-            [transaction_flag(flag), account_name(account_name), posting_unit(unit)] => (flag, account_name, Some(unit)),
+            [account_name(account_name)] => (None, account_name, None, vec![]),
+            [account_name(account_name), posting_unit(unit)] => (None, account_name, Some(unit), vec![]),
+            [transaction_flag(flag), account_name(account_name)] => (flag, account_name, None, vec![]),
+            [transaction_flag(flag), account_name(account_name), posting_unit(unit)] => (flag, account_name, Some(unit), vec![]),
+            [account_name(account_name), commodity_meta(meta)] => (None, account_name, None, meta),
+            [account_name(account_name), posting_unit(unit), commodity_meta(meta)] => (None, account_name, Some(unit), meta),
+            [transaction_flag(flag), account_name(account_name), commodity_meta(meta)] => (flag, account_name, None, meta),
+            [transaction_flag(flag), account_name(account_name), posting_unit(unit), commodity_meta(meta)] => (flag, account_name, Some(unit), meta),
         );
 
-        let (flag, account, unit) = ret;
+        let (flag, account, unit, meta) = ret;
 
         let mut line = Posting {
             flag,
@@ -224,7 +229,7 @@ impl ZhangParser {
             cost: None,
             cost_date: None,
             price: None,
-            meta: Default::default(),
+            meta: meta.into_iter().collect(),
         };
 
         if let Some((amount, meta)) = unit {
@@ -387,6 +392,18 @@ impl ZhangParser {
         }))
     }
 
+    fn query(input: Node) -> Result<Directive> {
+        let ret: (Date, ZhangString, ZhangString) = match_nodes!(input.into_children();
+            [date(date), string(name), string(query_string)] => (date, name, query_string),
+        );
+        Ok(Directive::Query(Query {
+            date: ret.0,
+            name: ret.1,
+            query_string: ret.2,
+            meta: Default::default(),
+        }))
+    }
+
     fn balance(input: Node) -> Result<Directive> {
         let ret: (Date, Account, BigDecimal, String, Option<Account>) = match_nodes!(input.into_children();
             [date(date), account_name(name), number(amount), commodity_name(commodity)] => (date, name, amount, commodity, None),
@@ -452,6 +469,7 @@ impl ZhangParser {
             [include(item)] => item,
             [note(item)] => item,
             [event(item)] => item,
+            [query(item)] => item,
             [document(item)] => item,
             [balance(item)] => item,
             [price(item)] => item,
@@ -773,6 +791,33 @@ mod test {
             assert_eq!(vec.len(), 1);
         }
 
+        #[test]
+        fn should_bind_indented_meta_to_posting() {
+            let mut vec = parse(
+                indoc! {r#"
+                            2022-03-24 ""
+                              a: "b"
+                              Assets:B 1 CNY
+                                receipt: "a.jpg"
+                                invoice: "INV-001"
+                              Assets:C
+                              c: "d"
+                        "#},
+                None,
+            )
+            .unwrap();
+            let trx = match vec.pop().unwrap().data {
+                zhang_ast::Directive::Transaction(trx) => trx,
+                _ => unreachable!(),
+            };
+            assert_eq!(2, trx.postings.len());
+            let meta = &trx.postings[0].meta;
+            assert_eq!(Some(&zhang_ast::ZhangString::quote("a.jpg")), meta.get_one("receipt"));
+            assert_eq!(Some(&zhang_ast::ZhangString::quote("INV-001")), meta.get_one("invoice"));
+            assert!(trx.postings[1].meta.clone().get_flatten().is_empty());
+            assert_eq!(2, trx.meta.get_flatten().len());
+        }
+
         mod posting {
             use bigdecimal::{BigDecimal, FromPrimitive};
             use chrono::NaiveDate;
@@ -1,74 +1,152 @@
 use std::collections::HashMap;
-use std::ops::{Add, Sub};
+use std::ops::{Add, Div, Sub};
 use std::path::PathBuf;
 use std::str::FromStr;
+#[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
 
 use crate::constants::{KEY_DEFAULT_COMMODITY_PRECISION, KEY_DEFAULT_ROUNDING};
-use crate::database::type_ext::big_decimal::ZhangBigDecimal;
-use crate::domains::schemas::{AccountStatus, ErrorType, MetaType};
-use crate::ledger::Ledger;
+use crate::domains::posting_identifier;
+use crate::domains::schemas::{AccountStatus, CommodityDomain, ErrorType, MetaType};
+use crate::options::InMemoryOptions;
+use crate::store::{DocumentRecord, LedgerStore, PostingRecord, TransactionRecord};
+use crate::utils::bigdecimal_ext::BigDecimalExt;
 use crate::utils::hashmap::HashMapOfExt;
 use crate::utils::id::FromSpan;
 use crate::ZhangResult;
 use async_trait::async_trait;
-use bigdecimal::{BigDecimal, FromPrimitive, Zero};
-use chrono::NaiveDateTime;
+use bigdecimal::{BigDecimal, Zero};
+#[cfg(not(target_arch = "wasm32"))]
 use log::debug;
-use serde::Deserialize;
-use sqlx::{Acquire, FromRow, SqliteConnection};
 use uuid::Uuid;
 use zhang_ast::amount::Amount;
 use zhang_ast::utils::inventory::LotInfo;
 use zhang_ast::*;
 
-#[derive(Debug, Deserialize, FromRow)]
-struct AccountAmount {
-    number: ZhangBigDecimal,
-    commodity: String,
+/// state shared by directive handlers while processing.
+///
+/// all writes go through the store, which is the one inside a sqlite transaction when processing with sqlite.
+pub(crate) struct ProcessContext<'a> {
+    pub(crate) store: Box<dyn LedgerStore + 'a>,
+    pub(crate) options: &'a mut InMemoryOptions,
+    /// running balance of each account and commodity after the latest processed posting
+    balances: HashMap<(String, String), BigDecimal>,
+}
+
+impl<'a> ProcessContext<'a> {
+    pub(crate) fn new(store: Box<dyn LedgerStore + 'a>, options: &'a mut InMemoryOptions) -> Self {
+        Self {
+            store,
+            options,
+            balances: HashMap::new(),
+        }
+    }
+
+    /// running balances of postings processed before, if directives are not processed from the beginning
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn with_balances(mut self, balances: HashMap<(String, String), BigDecimal>) -> Self {
+        self.balances = balances;
+        self
+    }
+
+    fn balance(&self, account: &str, commodity: &str) -> BigDecimal {
+        self.balances
+            .get(&(account.to_owned(), commodity.to_owned()))
+            .cloned()
+            .unwrap_or_else(BigDecimal::zero)
+    }
+
+    fn set_balance(&mut self, account: &str, commodity: &str, number: BigDecimal) {
+        self.balances.insert((account.to_owned(), commodity.to_owned()), number);
+    }
+
+    async fn is_transaction_balanced(&mut self, txn: &Transaction) -> ZhangResult<bool> {
+        is_transaction_balanced(self.store.as_mut(), self.options, txn).await
+    }
+
+    pub(crate) async fn process(&mut self, directive: &mut Spanned<Directive>) -> ZhangResult<()> {
+        match &mut directive.data {
+            Directive::Option(option) => option.handler(self, &directive.span).await?,
+            Directive::Open(open) => open.handler(self, &directive.span).await?,
+            Directive::Close(close) => close.handler(self, &directive.span).await?,
+            Directive::Commodity(commodity) => commodity.handler(self, &directive.span).await?,
+            Directive::Transaction(trx) => trx.handler(self, &directive.span).await?,
+            Directive::Balance(balance) => balance.handler(self, &directive.span).await?,
+            Directive::Note(_) => {}
+            Directive::Document(document) => document.handler(self, &directive.span).await?,
+            Directive::Price(price) => price.handler(self, &directive.span).await?,
+            Directive::Event(_) => {}
+            Directive::Custom(_) => {}
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+pub(crate) async fn is_transaction_balanced(store: &mut (dyn LedgerStore + '_), options: &InMemoryOptions, txn: &Transaction) -> ZhangResult<bool> {
+    // 1. get the txn's inventory
+    Ok(match txn.get_postings_inventory() {
+        Ok(inventory) => {
+            for (currency, amount) in inventory.currencies.iter() {
+                let commodity = store.commodity(currency).await?;
+                let precision = commodity.as_ref().map(|it| it.precision).unwrap_or(options.default_balance_tolerance_precision);
+                let rounding = commodity
+                    .and_then(|it| it.rounding)
+                    .map(|s| s.eq("RoundUp"))
+                    .unwrap_or_else(|| options.default_rounding.is_up());
+                let decimal = amount.total.round_with(precision as i64, rounding);
+                if !decimal.is_zero() {
+                    return Ok(false);
+                }
+            }
+            true
+        }
+        Err(_) => false,
+    })
 }
 
 #[async_trait]
 pub(crate) trait DirectiveProcess {
-    async fn handler(&mut self, ledger: &mut Ledger, span: &SpanInfo) -> ZhangResult<()> {
+    async fn handler(&mut self, context: &mut ProcessContext<'_>, span: &SpanInfo) -> ZhangResult<()> {
+        // there is no clock in wasm32-unknown-unknown, `Instant::now` panics there
+        #[cfg(not(target_arch = "wasm32"))]
         let start_time = Instant::now();
-        let result = DirectiveProcess::process(self, ledger, span).await;
-        let duration = start_time.elapsed();
-        debug!("directive process is done in {:?}", duration);
+        let result = DirectiveProcess::process(self, context, span).await;
+        #[cfg(not(target_arch = "wasm32"))]
+        debug!("directive process is done in {:?}", start_time.elapsed());
         result
     }
-    async fn process(&mut self, ledger: &mut Ledger, span: &SpanInfo) -> ZhangResult<()>;
+    async fn process(&mut self, context: &mut ProcessContext<'_>, span: &SpanInfo) -> ZhangResult<()>;
 }
 
-async fn check_account_existed(account_name: &str, ledger: &mut Ledger, span: &SpanInfo) -> ZhangResult<()> {
-    let mut operations = ledger.operations().await;
-    let existed = operations.exist_account(account_name).await?;
+async fn check_account_existed(account_name: &str, context: &mut ProcessContext<'_>, span: &SpanInfo) -> ZhangResult<()> {
+    let existed = context.store.exist_account(account_name).await?;
 
     if !existed {
-        operations
+        context
+            .store
             .new_error(ErrorType::AccountDoesNotExist, span, HashMap::of("account_name", account_name.to_string()))
             .await?;
     }
     Ok(())
 }
 
-async fn check_account_closed(account_name: &str, ledger: &mut Ledger, span: &SpanInfo) -> ZhangResult<()> {
-    let mut operations = ledger.operations().await;
-
-    let account = operations.account(account_name).await?;
+async fn check_account_closed(account_name: &str, context: &mut ProcessContext<'_>, span: &SpanInfo) -> ZhangResult<()> {
+    let account = context.store.account(account_name).await?;
     if let Some(true) = account.map(|it| it.status == AccountStatus::Close) {
-        operations
+        context
+            .store
             .new_error(ErrorType::AccountClosed, span, HashMap::of("account_name", account_name.to_string()))
             .await?;
     }
     Ok(())
 }
 
-async fn check_commodity_define(commodity_name: &str, ledger: &mut Ledger, span: &SpanInfo) -> ZhangResult<()> {
-    let mut operations = ledger.operations().await;
-    let existed = operations.exist_commodity(commodity_name).await?;
+async fn check_commodity_define(commodity_name: &str, context: &mut ProcessContext<'_>, span: &SpanInfo) -> ZhangResult<()> {
+    let existed = context.store.exist_commodity(commodity_name).await?;
     if !existed {
-        operations
+        context
+            .store
             .new_error(
                 ErrorType::CommodityDoesNotDefine,
                 span,
@@ -81,34 +159,31 @@ async fn check_commodity_define(commodity_name: &str, ledger: &mut Ledger, span:
 
 #[async_trait]
 impl DirectiveProcess for Options {
-    async fn process(&mut self, ledger: &mut Ledger, _span: &SpanInfo) -> ZhangResult<()> {
-        let mut operations = ledger.operations().await;
-        let mut conn = ledger.connection().await;
-        let option_value = ledger.options.parse(self.key.as_str(), self.value.as_str(), &mut conn).await?;
-        operations.insert_or_update_options(self.key.as_str(), option_value.as_str()).await?;
+    async fn process(&mut self, context: &mut ProcessContext<'_>, _span: &SpanInfo) -> ZhangResult<()> {
+        let option_value = context.options.parse(self.key.as_str(), self.value.as_str(), context.store.as_mut()).await?;
+        context.store.insert_or_update_options(self.key.as_str(), option_value.as_str()).await?;
         Ok(())
     }
 }
 
 #[async_trait]
 impl DirectiveProcess for Open {
-    async fn process(&mut self, ledger: &mut Ledger, span: &SpanInfo) -> ZhangResult<()> {
-        let mut conn = ledger.connection().await;
-        let mut operations = ledger.operations().await;
+    async fn process(&mut self, context: &mut ProcessContext<'_>, span: &SpanInfo) -> ZhangResult<()> {
         for currency in &self.commodities {
-            check_commodity_define(currency, ledger, span).await?;
+            check_commodity_define(currency, context, span).await?;
         }
 
-        sqlx::query(r#"INSERT OR REPLACE INTO accounts(date, type, name, status, alias) VALUES ($1, $2, $3, $4, $5);"#)
-            .bind(self.date.to_timezone_datetime(&ledger.options.timezone))
-            .bind(self.account.account_type.to_string())
-            .bind(self.account.name())
-            .bind("Open")
-            .bind(self.meta.get_one("alias").map(|it| it.as_str()))
-            .execute(&mut conn)
+        context
+            .store
+            .insert_account(
+                self.date.to_timezone_datetime(&context.options.timezone),
+                &self.account.account_type.to_string(),
+                self.account.name(),
+                self.meta.get_one("alias").map(|it| it.as_str()),
+            )
             .await?;
 
-        operations.insert_meta(MetaType::AccountMeta, self.account.name(), self.meta.clone()).await?;
+        context.store.insert_meta(MetaType::AccountMeta, self.account.name(), self.meta.clone()).await?;
 
         Ok(())
     }
@@ -116,30 +191,26 @@ impl DirectiveProcess for Open {
 
 #[async_trait]
 impl DirectiveProcess for Close {
-    async fn process(&mut self, ledger: &mut Ledger, span: &SpanInfo) -> ZhangResult<()> {
-        let mut operations = ledger.operations().await;
+    async fn process(&mut self, context: &mut ProcessContext<'_>, span: &SpanInfo) -> ZhangResult<()> {
         // check if account exist
-        check_account_existed(self.account.name(), ledger, span).await?;
-        check_account_closed(self.account.name(), ledger, span).await?;
+        check_account_existed(self.account.name(), context, span).await?;
+        check_account_closed(self.account.name(), context, span).await?;
 
-        let balances = operations.single_account_balances(self.account.name()).await?;
+        let balances = context.store.single_account_balances(self.account.name()).await?;
         let has_non_zero_balance = balances.into_iter().any(|balance| !balance.balance_number.is_zero());
         if has_non_zero_balance {
-            operations.new_error(ErrorType::CloseNonZeroAccount, span, HashMap::default()).await?;
+            context.store.new_error(ErrorType::CloseNonZeroAccount, span, HashMap::default()).await?;
         }
-        operations.close_account(self.account.name()).await?;
+        context.store.close_account(self.account.name()).await?;
         Ok(())
     }
 }
 
 #[async_trait]
 impl DirectiveProcess for Commodity {
-    async fn process(&mut self, ledger: &mut Ledger, _span: &SpanInfo) -> ZhangResult<()> {
-        let mut conn = ledger.connection().await;
-        let mut operations = ledger.operations().await;
-
-        let default_precision = operations.option(KEY_DEFAULT_COMMODITY_PRECISION).await?.map(|it| it.value);
-        let default_rounding = operations.option(KEY_DEFAULT_ROUNDING).await?.map(|it| it.value);
+    async fn process(&mut self, context: &mut ProcessContext<'_>, _span: &SpanInfo) -> ZhangResult<()> {
+        let default_precision = context.store.option(KEY_DEFAULT_COMMODITY_PRECISION).await?.map(|it| it.value);
+        let default_rounding = context.store.option(KEY_DEFAULT_ROUNDING).await?.map(|it| it.value);
 
         let precision = self
             .meta
@@ -149,8 +220,8 @@ impl DirectiveProcess for Commodity {
             .map(|it| it.as_str().parse::<i32>())
             .transpose()
             .unwrap_or(None);
-        let prefix = self.meta.get_one("prefix").map(|it| it.as_str());
-        let suffix = self.meta.get_one("suffix").map(|it| it.as_str());
+        let prefix = self.meta.get_one("prefix").map(|it| it.as_str().to_owned());
+        let suffix = self.meta.get_one("suffix").map(|it| it.as_str().to_owned());
         let rounding = self
             .meta
             .get_one("rounding")
@@ -160,19 +231,18 @@ impl DirectiveProcess for Commodity {
             .transpose()
             .unwrap_or(None);
 
-        sqlx::query(
-            r#"INSERT OR REPLACE INTO commodities (name, precision, prefix, suffix, rounding)
-                        VALUES ($1, $2, $3, $4, $5);"#,
-        )
-        .bind(&self.currency)
-        .bind(precision)
-        .bind(prefix)
-        .bind(suffix)
-        .bind(rounding.map(|it| it.to_string()))
-        .execute(&mut conn)
-        .await?;
+        context
+            .store
+            .insert_commodity(&CommodityDomain {
+                name: self.currency.clone(),
+                precision: precision.unwrap_or(context.options.default_balance_tolerance_precision),
+                prefix,
+                suffix,
+                rounding: rounding.map(|it| it.to_string()),
+            })
+            .await?;
 
-        operations.insert_meta(MetaType::CommodityMeta, &self.currency, self.meta.clone()).await?;
+        context.store.insert_meta(MetaType::CommodityMeta, &self.currency, self.meta.clone()).await?;
 
         Ok(())
     }
@@ -180,140 +250,101 @@ impl DirectiveProcess for Commodity {
 
 #[async_trait]
 impl DirectiveProcess for Transaction {
-    async fn process(&mut self, ledger: &mut Ledger, span: &SpanInfo) -> ZhangResult<()> {
-        let mut operations = ledger.operations().await;
-        let mut conn = ledger.connection().await;
-
-        if self.flag != Some(Flag::BalancePad) && self.flag != Some(Flag::BalanceCheck) && !ledger.is_transaction_balanced(self).await? {
-            operations.new_error(ErrorType::TransactionDoesNotBalance, span, HashMap::default()).await?;
+    async fn process(&mut self, context: &mut ProcessContext<'_>, span: &SpanInfo) -> ZhangResult<()> {
+        if self.flag != Some(Flag::BalancePad) && self.flag != Some(Flag::BalanceCheck) && !context.is_transaction_balanced(self).await? {
+            context.store.new_error(ErrorType::TransactionDoesNotBalance, span, HashMap::default()).await?;
         }
         let id = Uuid::from_span(span).to_string();
 
-        sqlx::query(
-            r#"INSERT INTO transactions (id, datetime, type, payee, narration, source_file, span_start, span_end)VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
-        )
-        .bind(&id)
-        .bind(self.date.to_timezone_datetime(&ledger.options.timezone))
-        .bind(self.flag.clone().unwrap_or(Flag::Okay).to_string())
-        .bind(self.payee.as_ref().map(|it| it.as_str()))
-        .bind(self.narration.as_ref().map(|it| it.as_str()))
-        .bind(span.filename.as_ref().and_then(|it| it.to_str()).map(|it| it.to_string()))
-        .bind(span.start as i64)
-        .bind(span.end as i64)
-        .execute(&mut conn)
-        .await?;
-
-        for tag in self.tags.iter() {
-            sqlx::query(r#"INSERT INTO transaction_tags (trx_id, tag)VALUES ($1, $2)"#)
-                .bind(&id)
-                .bind(tag)
-                .execute(&mut conn)
-                .await?;
-        }
-        for link in self.links.iter() {
-            sqlx::query(r#"INSERT INTO transaction_links (trx_id, link)VALUES ($1, $2)"#)
-                .bind(&id)
-                .bind(link)
-                .execute(&mut conn)
-                .await?;
-        }
+        context
+            .store
+            .insert_transaction(&TransactionRecord {
+                id: id.clone(),
+                datetime: self.date.to_timezone_datetime(&context.options.timezone),
+                flag: self.flag.clone().unwrap_or(Flag::Okay).to_string(),
+                payee: self.payee.as_ref().map(|it| it.as_str().to_owned()),
+                narration: self.narration.as_ref().map(|it| it.as_str().to_owned()),
+                source_file: span.filename.as_ref().and_then(|it| it.to_str()).map(|it| it.to_string()),
+                span_start: span.start as i64,
+                span_end: span.end as i64,
+                tags: self.tags.iter().cloned().collect(),
+                links: self.links.iter().cloned().collect(),
+            })
+            .await?;
 
         for txn_posting in self.txn_postings() {
             let inferred_amount = txn_posting.infer_trade_amount().unwrap();
 
-            let option: Option<AccountAmount> = sqlx::query_as(
-                r#"select account_after_number as number, account_after_commodity as commodity from transaction_postings
-                                join transactions on transactions.id = transaction_postings.trx_id
-                                where account = $1 and "datetime" <=  $2 and account_after_commodity = $3
-                                order by "datetime" desc, transactions.sequence desc limit 1"#,
-            )
-            .bind(txn_posting.posting.account.name())
-            .bind(self.date.to_timezone_datetime(&ledger.options.timezone))
-            .bind(&inferred_amount.currency)
-            .fetch_optional(&mut conn)
-            .await?;
-            let previous = option.unwrap_or(AccountAmount {
-                number: ZhangBigDecimal(BigDecimal::zero()),
-                commodity: inferred_amount.currency.clone(),
-            });
-
-            let after_number = (&previous.number.0).add(&inferred_amount.number);
-
-            sqlx::query(
-                r#"INSERT INTO transaction_postings
-                               (trx_id, account, unit_number, unit_commodity, cost_number, cost_commodity, inferred_unit_number, inferred_unit_commodity,
-                                account_before_number, account_before_commodity, account_after_number, account_after_commodity
-                               )
-                               VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)"#,
-            )
-            .bind(&id)
-            .bind(txn_posting.posting.account.name())
-            .bind(txn_posting.posting.units.as_ref().map(|it| it.number.to_string()))
-            .bind(txn_posting.posting.units.as_ref().map(|it| &it.currency))
-            .bind(txn_posting.posting.cost.as_ref().map(|it| it.number.to_string()))
-            .bind(txn_posting.posting.cost.as_ref().map(|it| &it.currency))
-            .bind(inferred_amount.number.to_string())
-            .bind(&inferred_amount.currency)
-            .bind(&previous.number)
-            .bind(&previous.commodity)
-            .bind(after_number.to_string())
-            .bind(&previous.commodity)
-            .execute(&mut conn)
-            .await?;
+            let previous_number = context.balance(txn_posting.posting.account.name(), &inferred_amount.currency);
+            let after_number = (&previous_number).add(&inferred_amount.number);
+            context.set_balance(txn_posting.posting.account.name(), &inferred_amount.currency, after_number.clone());
+            let single_price = match (&txn_posting.posting.units, &txn_posting.posting.price) {
+                (Some(unit), Some(SingleTotalPrice::Total(total_price))) => {
+                    Some(Amount::new((&total_price.number).div(&unit.number), total_price.currency.clone()))
+                }
+                (_, Some(SingleTotalPrice::Single(single_price))) => Some(single_price.clone()),
+                _ => None,
+            };
+
+            context
+                .store
+                .insert_posting(&PostingRecord {
+                    trx_id: id.clone(),
+                    account: txn_posting.posting.account.name().to_owned(),
+                    units: txn_posting.posting.units.clone(),
+                    cost: txn_posting.posting.cost.clone(),
+                    price: single_price,
+                    inferred_amount,
+                    before_number: previous_number,
+                    after_number,
+                })
+                .await?;
             let amount = txn_posting.units().unwrap_or_else(|| txn_posting.infer_trade_amount().unwrap());
             let lot_info = txn_posting.lots().unwrap_or(LotInfo::Fifo);
-            lot_add(txn_posting.account_name(), amount, lot_info, &mut conn).await?;
+            context.store.add_lot(txn_posting.account_name().as_str(), &amount, &lot_info).await?;
         }
         for document in self.meta.clone().get_flatten().into_iter().filter(|(key, _)| key.eq("document")) {
             let (_, document_file_name) = document;
             let document_path = document_file_name.to_plain_string();
             let document_pathbuf = PathBuf::from(&document_path);
-            let extension = document_pathbuf.extension().and_then(|it| it.to_str());
-            sqlx::query(r#"INSERT INTO documents (datetime, filename, path, extension, trx_id) VALUES ($1, $2, $3, $4, $5);"#)
-                .bind(self.date.to_timezone_datetime(&ledger.options.timezone))
-                .bind(document_pathbuf.file_name().and_then(|it| it.to_str()).unwrap())
-                .bind(&document_path)
-                .bind(extension)
-                .bind(&id)
-                .execute(&mut conn)
+            context
+                .store
+                .insert_document(&DocumentRecord {
+                    datetime: self.date.to_timezone_datetime(&context.options.timezone),
+                    filename: document_pathbuf.file_name().and_then(|it| it.to_str()).unwrap().to_owned(),
+                    extension: document_pathbuf.extension().and_then(|it| it.to_str()).map(|it| it.to_owned()),
+                    path: document_path,
+                    account: None,
+                    trx_id: Some(id.clone()),
+                })
                 .await?;
         }
 
-        operations.insert_meta(MetaType::TransactionMeta, &id, self.meta.clone()).await?;
+        context.store.insert_meta(MetaType::TransactionMeta, &id, self.meta.clone()).await?;
+        for (idx, posting) in self.postings.iter().enumerate() {
+            context
+                .store
+                .insert_meta(MetaType::PostingMeta, &posting_identifier(&id, idx), posting.meta.clone())
+                .await?;
+        }
         Ok(())
     }
 }
 
 #[async_trait]
 impl DirectiveProcess for Balance {
-    async fn process(&mut self, ledger: &mut Ledger, span: &SpanInfo) -> ZhangResult<()> {
-        let mut conn = ledger.connection().await;
-        let mut operations = ledger.operations().await;
+    async fn process(&mut self, context: &mut ProcessContext<'_>, span: &SpanInfo) -> ZhangResult<()> {
         match self {
             Balance::BalanceCheck(balance_check) => {
-                let option: Option<AccountAmount> = sqlx::query_as(
-                    r#"
-                    select account_after_number as number, account_after_commodity as commodity
-                    from transactions
-                             join transaction_postings on transactions.id = transaction_postings.trx_id
-                    where account = $1
-                      and datetime <= $2 and account_after_commodity = $3
-                    order by datetime desc, sequence desc
-                "#,
-                )
-                .bind(balance_check.account.name())
-                .bind(balance_check.date.to_timezone_datetime(&ledger.options.timezone))
-                .bind(&balance_check.amount.currency)
-                .fetch_optional(&mut conn)
-                .await?;
-                let current_balance_amount = option.map(|it| it.number.0).unwrap_or_else(BigDecimal::zero);
+                let current_balance_amount = context.balance(balance_check.account.name(), &balance_check.amount.currency);
 
                 let distance = Amount::new(
                     (&balance_check.amount.number).sub(&current_balance_amount),
                     balance_check.amount.currency.clone(),
                 );
                 if !distance.is_zero() {
-                    operations
+                    context
+                        .store
                         .new_error(
                             ErrorType::AccountBalanceCheckError,
                             span,
@@ -322,8 +353,8 @@ impl DirectiveProcess for Balance {
                         .await?;
                 }
 
-                check_account_existed(balance_check.account.name(), ledger, span).await?;
-                check_account_closed(balance_check.account.name(), ledger, span).await?;
+                check_account_existed(balance_check.account.name(), context, span).await?;
+                check_account_closed(balance_check.account.name(), context, span).await?;
 
                 let mut transformed_trx = Transaction {
                     date: balance_check.date.clone(),
@@ -344,30 +375,15 @@ impl DirectiveProcess for Balance {
                     meta: Default::default(),
                 };
 
-                transformed_trx.process(ledger, span).await?;
+                transformed_trx.process(context, span).await?;
             }
             Balance::BalancePad(balance_pad) => {
-                check_account_existed(balance_pad.account.name(), ledger, span).await?;
-                check_account_existed(balance_pad.pad.name(), ledger, span).await?;
-                check_account_closed(balance_pad.account.name(), ledger, span).await?;
-                check_account_closed(balance_pad.pad.name(), ledger, span).await?;
-
-                let option: Option<AccountAmount> = sqlx::query_as(
-                    r#"
-                    select account_after_number as number, account_after_commodity as commodity
-                    from transactions
-                             join transaction_postings on transactions.id = transaction_postings.trx_id
-                    where account = $1
-                      and datetime <= $2 and account_after_commodity = $3
-                    order by datetime desc, sequence desc
-                "#,
-                )
-                .bind(balance_pad.account.name())
-                .bind(balance_pad.date.to_timezone_datetime(&ledger.options.timezone))
-                .bind(&balance_pad.amount.currency)
-                .fetch_optional(&mut conn)
-                .await?;
-                let current_balance_amount = option.map(|it| it.number.0).unwrap_or_else(BigDecimal::zero);
+                check_account_existed(balance_pad.account.name(), context, span).await?;
+                check_account_existed(balance_pad.pad.name(), context, span).await?;
+                check_account_closed(balance_pad.account.name(), context, span).await?;
+                check_account_closed(balance_pad.pad.name(), context, span).await?;
+
+                let current_balance_amount = context.balance(balance_pad.account.name(), &balance_pad.amount.currency);
 
                 let distance = Amount::new((&balance_pad.amount.number).sub(&current_balance_amount), balance_pad.amount.currency.clone());
                 let mut transformed_trx = Transaction {
@@ -400,7 +416,7 @@ impl DirectiveProcess for Balance {
                     meta: Default::default(),
                 };
 
-                transformed_trx.process(ledger, span).await?;
+                transformed_trx.process(context, span).await?;
 
                 let _neg_distance = distance.neg();
             }
@@ -412,22 +428,23 @@ impl DirectiveProcess for Balance {
 
 #[async_trait]
 impl DirectiveProcess for Document {
-    async fn process(&mut self, ledger: &mut Ledger, span: &SpanInfo) -> ZhangResult<()> {
-        let mut conn = ledger.connection().await;
-        check_account_existed(self.account.name(), ledger, span).await?;
-        check_account_closed(self.account.name(), ledger, span).await?;
+    async fn process(&mut self, context: &mut ProcessContext<'_>, span: &SpanInfo) -> ZhangResult<()> {
+        check_account_existed(self.account.name(), context, span).await?;
+        check_account_closed(self.account.name(), context, span).await?;
 
         let path = self.filename.clone().to_plain_string();
 
         let document_pathbuf = PathBuf::from(&path);
-        let extension = document_pathbuf.extension().and_then(|it| it.to_str());
-        sqlx::query(r#"INSERT INTO documents (datetime, filename, path, extension, account) VALUES ($1, $2, $3, $4, $5);"#)
-            .bind(self.date.to_timezone_datetime(&ledger.options.timezone))
-            .bind(document_pathbuf.file_name().and_then(|it| it.to_str()).unwrap())
-            .bind(&path)
-            .bind(extension)
-            .bind(self.account.name())
-            .execute(&mut conn)
+        context
+            .store
+            .insert_document(&DocumentRecord {
+                datetime: self.date.to_timezone_datetime(&context.options.timezone),
+                filename: document_pathbuf.file_name().and_then(|it| it.to_str()).unwrap().to_owned(),
+                extension: document_pathbuf.extension().and_then(|it| it.to_str()).map(|it| it.to_owned()),
+                path,
+                account: Some(self.account.name().to_owned()),
+                trx_id: None,
+            })
             .await?;
         Ok(())
     }
@@ -435,140 +452,14 @@ impl DirectiveProcess for Document {
 
 #[async_trait]
 impl DirectiveProcess for Price {
-    async fn process(&mut self, ledger: &mut Ledger, span: &SpanInfo) -> ZhangResult<()> {
-        let mut conn = ledger.connection().await;
-        check_commodity_define(&self.currency, ledger, span).await?;
-        check_commodity_define(&self.amount.currency, ledger, span).await?;
-        sqlx::query(r#"INSERT INTO prices (datetime, commodity, amount, target_commodity)VALUES ($1, $2, $3, $4)"#)
-            .bind(self.date.to_timezone_datetime(&ledger.options.timezone))
-            .bind(&self.currency)
-            .bind(&self.amount.number.to_string())
-            .bind(&self.amount.currency)
-            .execute(&mut conn)
+    async fn process(&mut self, context: &mut ProcessContext<'_>, span: &SpanInfo) -> ZhangResult<()> {
+        check_commodity_define(&self.currency, context, span).await?;
+        check_commodity_define(&self.amount.currency, context, span).await?;
+        context
+            .store
+            .insert_price(self.date.to_timezone_datetime(&context.options.timezone), &self.currency, &self.amount)
             .await?;
 
         Ok(())
     }
 }
-
-#[derive(Debug, Deserialize, FromRow)]
-struct LotRow {
-    amount: f64,
-    price_amount: Option<f64>,
-    price_commodity: Option<String>,
-}
-
-async fn lot_add(account_name: AccountName, amount: Amount, lot_info: LotInfo, conn: &mut SqliteConnection) -> ZhangResult<()> {
-    let mut trx = conn.begin().await?;
-    match lot_info {
-        LotInfo::Lot(target_currency, lot_number) => {
-            let lot: Option<LotRow> = sqlx::query_as(
-                r#"
-            select amount, price_amount, price_commodity
-            from commodity_lots
-            where account = $1 and commodity = $2 and price_amount = $3 and price_commodity = $4"#,
-            )
-            .bind(&account_name)
-            .bind(&amount.currency)
-            .bind(lot_number.to_string())
-            .bind(&target_currency)
-            .fetch_optional(&mut trx)
-            .await?;
-
-            if let Some(lot_row) = lot {
-                sqlx::query(
-                    r#"update commodity_lots
-                        set amount = $1
-                        where account = $2 and commodity = $3  and price_amount = $4 and price_commodity = $5"#,
-                )
-                .bind(BigDecimal::from_f64(lot_row.amount).expect("error").add(&amount.number).to_string())
-                .bind(&account_name)
-                .bind(&amount.currency)
-                .bind(lot_number.to_string())
-                .bind(&target_currency)
-                .execute(&mut trx)
-                .await?;
-            } else {
-                sqlx::query(
-                    r#"INSERT INTO commodity_lots (account, commodity, datetime, amount, price_amount, price_commodity)
-                                    VALUES ($1, $2, $3, $4, $5, $6)"#,
-                )
-                .bind(&account_name)
-                .bind(&amount.currency)
-                .bind(None::<NaiveDateTime>)
-                .bind(amount.number.to_string())
-                .bind(lot_number.to_string())
-                .bind(&target_currency)
-                .execute(&mut trx)
-                .await?;
-            }
-        }
-        LotInfo::Fifo => {
-            let lot: Option<LotRow> = sqlx::query_as(
-                r#"
-                select amount, price_amount, price_commodity
-                from commodity_lots
-                where account = $1 and commodity = $2
-                  and (price_commodity = $3 or price_commodity is null)
-                  and ((amount != 0 and price_amount is not null) or price_amount is null)
-                order by datetime desc
-            "#,
-            )
-            .bind(&account_name)
-            .bind(&amount.currency)
-            .bind(&amount.currency)
-            .fetch_optional(&mut trx)
-            .await?;
-            if let Some(lot) = lot {
-                if lot.price_amount.is_some() {
-                    // target lot
-                    sqlx::query(
-                        r#"update commodity_lots
-                        set amount = $1
-                        where account = $2 and commodity = $3  and price_amount = $4 and price_commodity = $5"#,
-                    )
-                    .bind(BigDecimal::from_f64(lot.amount).expect("error").add(&amount.number).to_string())
-                    .bind(&account_name)
-                    .bind(&amount.currency)
-                    .bind(lot.price_amount)
-                    .bind(&lot.price_commodity)
-                    .execute(&mut trx)
-                    .await?;
-
-                    // todo check negative
-                } else {
-                    // default lot
-                    sqlx::query(
-                        r#"update commodity_lots
-                        set amount = $1
-                        where account = $2 and commodity = $3  and price_amount is NULL and price_commodity is NULL"#,
-                    )
-                    .bind(BigDecimal::from_f64(lot.amount).expect("error").add(&amount.number).to_string())
-                    .bind(&account_name)
-                    .bind(&amount.currency)
-                    .execute(&mut trx)
-                    .await?;
-                }
-            } else {
-                sqlx::query(
-                    r#"INSERT INTO commodity_lots (account, commodity, datetime, amount, price_amount, price_commodity)
-                                    VALUES ($1, $2, $3, $4, $5, $6)"#,
-                )
-                .bind(&account_name)
-                .bind(&amount.currency)
-                .bind(None::<NaiveDateTime>)
-                .bind(amount.number.to_string())
-                .bind(None::<f64>)
-                .bind(None::<String>)
-                .execute(&mut trx)
-                .await?;
-            }
-        }
-        LotInfo::Filo => {
-            unimplemented!()
-        }
-    }
-    trx.commit().await?;
-
-    Ok(())
-}
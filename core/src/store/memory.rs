@@ -0,0 +1,821 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::{Bound, Neg};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use async_trait::async_trait;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, NaiveDateTime};
+use chrono_tz::Tz;
+use itertools::Itertools;
+use uuid::Uuid;
+use zhang_ast::amount::Amount;
+use zhang_ast::utils::inventory::LotInfo;
+use zhang_ast::{Meta, SpanInfo};
+
+use crate::database::type_ext::big_decimal::ZhangBigDecimal;
+use crate::domains::posting_identifier;
+use crate::domains::schemas::{
+    AccountBalanceDomain, AccountDailyBalanceDomain, AccountDomain, AccountJournalDomain, AccountStatus, AccountTypeTotalDomain, CommodityDomain,
+    CommodityLotDomain, CommoditySummaryDomain, DailyAccountTypeTotalDomain, DocumentDomain, ErrorDomain, ErrorType, JournalDomain, JournalFilter,
+    JournalPostingDomain, LabelAccountEffectDomain, LabelPeriodTotalDomain, LabelUsageDomain, MetaDomain, MetaType, OptionDomain, PriceDomain,
+    TransactionInfoDomain,
+};
+use crate::store::{DocumentRecord, Label, LedgerStore, PostingRecord, TransactionRecord};
+use crate::ZhangResult;
+
+struct TransactionRow {
+    sequence: i64,
+    id: String,
+    datetime: NaiveDateTime,
+    flag: String,
+    payee: Option<String>,
+    narration: Option<String>,
+    source_file: Option<String>,
+    span_start: i64,
+    span_end: i64,
+    tags: Vec<String>,
+    links: Vec<String>,
+}
+
+impl TransactionRow {
+    fn labels(&self, label: Label) -> &[String] {
+        match label {
+            Label::Tag => &self.tags,
+            Label::Link => &self.links,
+        }
+    }
+}
+
+struct PostingRow {
+    trx_id: String,
+    account: String,
+    units: Option<Amount>,
+    cost: Option<Amount>,
+    price: Option<Amount>,
+    inferred_amount: Amount,
+    before_number: BigDecimal,
+    after_number: BigDecimal,
+}
+
+struct LotRow {
+    account: String,
+    commodity: String,
+    amount: BigDecimal,
+    /// price amount and commodity of the lot, the default lot has none
+    price: Option<(BigDecimal, String)>,
+}
+
+#[derive(Default)]
+struct MemoryData {
+    options: Vec<OptionDomain>,
+    accounts: Vec<AccountDomain>,
+    metas: Vec<MetaDomain>,
+    commodities: Vec<CommodityDomain>,
+    transactions: Vec<TransactionRow>,
+    postings: Vec<PostingRow>,
+    documents: Vec<DocumentDomain>,
+    prices: Vec<PriceDomain>,
+    lots: Vec<LotRow>,
+    errors: Vec<ErrorDomain>,
+}
+
+impl MemoryData {
+    fn transaction(&self, trx_id: &str) -> Option<&TransactionRow> {
+        self.transactions.iter().find(|it| it.id == trx_id)
+    }
+
+    fn account_type(&self, account_name: &str) -> Option<&str> {
+        self.accounts.iter().find(|it| it.name == account_name).map(|it| it.r#type.as_str())
+    }
+
+    /// postings along with their transaction, in the order of processing
+    fn postings(&self) -> Vec<(&TransactionRow, &PostingRow)> {
+        let transactions: HashMap<&str, &TransactionRow> = self.transactions.iter().map(|it| (it.id.as_str(), it)).collect();
+        self.postings
+            .iter()
+            .filter_map(|posting| transactions.get(posting.trx_id.as_str()).map(|trx| (*trx, posting)))
+            .sorted_by_key(|(trx, _)| trx.sequence)
+            .collect_vec()
+    }
+
+    /// the latest posting of every account and commodity, and of every date as well if `daily` is set
+    fn latest_postings<'a>(postings: impl Iterator<Item = (&'a TransactionRow, &'a PostingRow)>, daily: bool) -> Vec<(&'a TransactionRow, &'a PostingRow)> {
+        let mut latest: Vec<(&TransactionRow, &PostingRow)> = vec![];
+        let mut index: HashMap<(&str, &str, Option<chrono::NaiveDate>), usize> = HashMap::new();
+        for (trx, posting) in postings {
+            let key = (
+                posting.account.as_str(),
+                posting.inferred_amount.currency.as_str(),
+                daily.then(|| trx.datetime.date()),
+            );
+            match index.get(&key) {
+                Some(idx) => latest[*idx] = (trx, posting),
+                None => {
+                    index.insert(key, latest.len());
+                    latest.push((trx, posting));
+                }
+            }
+        }
+        latest
+    }
+
+    fn daily_balance(trx: &TransactionRow, posting: &PostingRow) -> AccountDailyBalanceDomain {
+        AccountDailyBalanceDomain {
+            date: trx.datetime.date(),
+            account: posting.account.clone(),
+            balance_number: ZhangBigDecimal(posting.after_number.clone()),
+            balance_commodity: posting.inferred_amount.currency.clone(),
+        }
+    }
+
+    fn account_balances(&self) -> Vec<AccountBalanceDomain> {
+        let accounts: HashMap<&str, AccountStatus> = self.accounts.iter().map(|it| (it.name.as_str(), it.status)).collect();
+        MemoryData::latest_postings(self.postings().into_iter(), false)
+            .into_iter()
+            .filter_map(|(trx, posting)| {
+                accounts.get(posting.account.as_str()).map(|status| AccountBalanceDomain {
+                    datetime: trx.datetime,
+                    account: posting.account.clone(),
+                    account_status: *status,
+                    balance_number: ZhangBigDecimal(posting.after_number.clone()),
+                    balance_commodity: posting.inferred_amount.currency.clone(),
+                })
+            })
+            .sorted_by(|a, b| (&a.account, &a.balance_commodity).cmp(&(&b.account, &b.balance_commodity)))
+            .collect_vec()
+    }
+
+    fn account_journal(trx: &TransactionRow, posting: &PostingRow) -> AccountJournalDomain {
+        AccountJournalDomain {
+            datetime: trx.datetime,
+            account: posting.account.clone(),
+            trx_id: trx.id.clone(),
+            payee: trx.payee.clone().unwrap_or_default(),
+            narration: trx.narration.clone(),
+            inferred_unit_number: ZhangBigDecimal(posting.inferred_amount.number.clone()),
+            inferred_unit_commodity: posting.inferred_amount.currency.clone(),
+            account_after_number: ZhangBigDecimal(posting.after_number.clone()),
+            account_after_commodity: posting.inferred_amount.currency.clone(),
+        }
+    }
+
+    fn commodity_summary(&self, commodity: &CommodityDomain) -> CommoditySummaryDomain {
+        let total_amount = self
+            .lots
+            .iter()
+            .filter(|lot| lot.commodity == commodity.name)
+            .filter(|lot| matches!(self.account_type(&lot.account), Some("Assets" | "Liabilities")))
+            .fold(BigDecimal::zero(), |total, lot| total + &lot.amount);
+        let latest_price = self.prices.iter().filter(|it| it.commodity == commodity.name).max_by_key(|it| it.datetime);
+        CommoditySummaryDomain {
+            name: commodity.name.clone(),
+            precision: commodity.precision,
+            prefix: commodity.prefix.clone(),
+            suffix: commodity.suffix.clone(),
+            rounding: commodity.rounding.clone(),
+            total_amount: ZhangBigDecimal(total_amount),
+            latest_price_date: latest_price.map(|it| it.datetime),
+            latest_price_amount: latest_price.map(|it| it.amount.clone()),
+            latest_price_commodity: latest_price.map(|it| it.target_commodity.clone()),
+        }
+    }
+
+    fn match_journal(&self, trx: &TransactionRow, filter: &JournalFilter) -> bool {
+        if filter.id.as_ref().map(|id| id != &trx.id).unwrap_or(false)
+            || filter.from.map(|from| trx.datetime < from).unwrap_or(false)
+            || filter.to.map(|to| trx.datetime > to).unwrap_or(false)
+            || filter.flag.as_ref().map(|flag| flag != &trx.flag).unwrap_or(false)
+            || filter.tag.as_ref().map(|tag| !trx.tags.contains(tag)).unwrap_or(false)
+            || filter.link.as_ref().map(|link| !trx.links.contains(link)).unwrap_or(false)
+        {
+            return false;
+        }
+        if let Some(keyword) = &filter.keyword {
+            let tokens = [&trx.payee, &trx.narration]
+                .into_iter()
+                .flatten()
+                .flat_map(|it| it.split(|c: char| !c.is_alphanumeric()))
+                .filter(|it| !it.is_empty())
+                .map(|it| it.to_lowercase())
+                .collect_vec();
+            let matched = keyword
+                .split_whitespace()
+                .map(|word| word.to_lowercase())
+                .all(|word| tokens.iter().any(|token| token.starts_with(&word)));
+            if !matched {
+                return false;
+            }
+        }
+        // posting conditions should be satisfied by the same posting
+        if filter.account.is_some() || filter.commodity.is_some() || filter.min_amount.is_some() || filter.max_amount.is_some() {
+            return self.postings.iter().filter(|it| it.trx_id == trx.id).any(|posting| {
+                let number = posting.inferred_amount.number.abs();
+                filter
+                    .account
+                    .as_ref()
+                    .map(|account| &posting.account == account || posting.account.starts_with(&format!("{}:", account)))
+                    .unwrap_or(true)
+                    && filter.commodity.as_ref().map(|it| it == &posting.inferred_amount.currency).unwrap_or(true)
+                    && filter.min_amount.as_ref().map(|it| &number >= it).unwrap_or(true)
+                    && filter.max_amount.as_ref().map(|it| &number <= it).unwrap_or(true)
+            });
+        }
+        true
+    }
+
+    fn metas_of(&self, type_: &MetaType, type_identifier: &str) -> Vec<MetaDomain> {
+        self.metas
+            .iter()
+            .filter(|it| it.meta_type == type_.as_ref() && it.type_identifier == type_identifier)
+            .cloned()
+            .collect_vec()
+    }
+}
+
+/// the in memory backend, which needs no sqlite. it's a cheap handle, clones share the same data.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    data: Arc<RwLock<MemoryData>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, MemoryData> {
+        self.data.read().expect("memory store is poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, MemoryData> {
+        self.data.write().expect("memory store is poisoned")
+    }
+}
+
+#[async_trait]
+impl LedgerStore for MemoryStore {
+    async fn options(&mut self) -> ZhangResult<Vec<OptionDomain>> {
+        Ok(self.read().options.clone())
+    }
+
+    async fn option(&mut self, key: &str) -> ZhangResult<Option<OptionDomain>> {
+        Ok(self.read().options.iter().find(|it| it.key == key).cloned())
+    }
+
+    async fn accounts_latest_balance(&mut self, until: Bound<NaiveDateTime>) -> ZhangResult<Vec<AccountDailyBalanceDomain>> {
+        let data = self.read();
+        let postings = data.postings();
+        let postings = postings.into_iter().filter(|(trx, _)| match until {
+            Bound::Included(until) => trx.datetime <= until,
+            Bound::Excluded(until) => trx.datetime < until,
+            Bound::Unbounded => true,
+        });
+        Ok(MemoryData::latest_postings(postings, false)
+            .into_iter()
+            .map(|(trx, posting)| MemoryData::daily_balance(trx, posting))
+            .sorted_by(|a, b| (&a.account, &a.balance_commodity).cmp(&(&b.account, &b.balance_commodity)))
+            .collect_vec())
+    }
+
+    async fn daily_balances(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountDailyBalanceDomain>> {
+        let data = self.read();
+        Ok(MemoryData::latest_postings(data.postings().into_iter(), true)
+            .into_iter()
+            .filter(|(trx, _)| trx.datetime >= from && trx.datetime <= to)
+            .map(|(trx, posting)| MemoryData::daily_balance(trx, posting))
+            .sorted_by_key(|it| it.date)
+            .collect_vec())
+    }
+
+    async fn latest_price(&mut self, datetime: DateTime<Tz>, from: &str, to: &str) -> ZhangResult<Option<PriceDomain>> {
+        let datetime = datetime.naive_local();
+        Ok(self
+            .read()
+            .prices
+            .iter()
+            .filter(|it| it.datetime <= datetime && it.commodity == from && it.target_commodity == to)
+            .max_by_key(|it| it.datetime)
+            .cloned())
+    }
+
+    async fn prices(&mut self, commodity: Option<&str>) -> ZhangResult<Vec<PriceDomain>> {
+        Ok(self
+            .read()
+            .prices
+            .iter()
+            .filter(|it| commodity.map(|commodity| it.commodity == commodity).unwrap_or(true))
+            .sorted_by_key(|it| it.datetime)
+            .cloned()
+            .collect_vec())
+    }
+
+    async fn metas(&mut self, type_: MetaType, type_identifier: &str) -> ZhangResult<Vec<MetaDomain>> {
+        Ok(self.read().metas_of(&type_, type_identifier))
+    }
+
+    async fn trx_tags(&mut self, trx_id: &str) -> ZhangResult<Vec<String>> {
+        Ok(self.read().transaction(trx_id).map(|it| it.tags.clone()).unwrap_or_default())
+    }
+
+    async fn trx_links(&mut self, trx_id: &str) -> ZhangResult<Vec<String>> {
+        Ok(self.read().transaction(trx_id).map(|it| it.links.clone()).unwrap_or_default())
+    }
+
+    async fn commodities(&mut self) -> ZhangResult<Vec<CommodityDomain>> {
+        Ok(self.read().commodities.iter().sorted_by(|a, b| a.name.cmp(&b.name)).cloned().collect_vec())
+    }
+
+    async fn commodity(&mut self, name: &str) -> ZhangResult<Option<CommodityDomain>> {
+        Ok(self.read().commodities.iter().find(|it| it.name == name).cloned())
+    }
+
+    async fn exist_commodity(&mut self, name: &str) -> ZhangResult<bool> {
+        Ok(self.read().commodities.iter().any(|it| it.name == name))
+    }
+
+    async fn commodity_summaries(&mut self) -> ZhangResult<Vec<CommoditySummaryDomain>> {
+        let data = self.read();
+        Ok(data
+            .commodities
+            .iter()
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .map(|it| data.commodity_summary(it))
+            .collect_vec())
+    }
+
+    async fn commodity_summary(&mut self, name: &str) -> ZhangResult<Option<CommoditySummaryDomain>> {
+        let data = self.read();
+        Ok(data.commodities.iter().find(|it| it.name == name).map(|it| data.commodity_summary(it)))
+    }
+
+    async fn commodity_lots(&mut self, commodity: &str) -> ZhangResult<Vec<CommodityLotDomain>> {
+        Ok(self
+            .read()
+            .lots
+            .iter()
+            .filter(|it| it.commodity == commodity)
+            .map(|lot| CommodityLotDomain {
+                datetime: None,
+                amount: ZhangBigDecimal(lot.amount.clone()),
+                price_amount: lot.price.as_ref().map(|(amount, _)| ZhangBigDecimal(amount.clone())),
+                price_commodity: lot.price.as_ref().map(|(_, commodity)| commodity.clone()),
+                account: lot.account.clone(),
+            })
+            .collect_vec())
+    }
+
+    async fn documents(&mut self) -> ZhangResult<Vec<DocumentDomain>> {
+        Ok(self.read().documents.iter().sorted_by_key(|it| it.datetime).cloned().collect_vec())
+    }
+
+    async fn account_documents(&mut self, account_name: &str) -> ZhangResult<Vec<DocumentDomain>> {
+        let data = self.read();
+        let trx_ids: HashSet<&str> = data
+            .postings
+            .iter()
+            .filter(|it| it.account == account_name)
+            .map(|it| it.trx_id.as_str())
+            .collect();
+        Ok(data
+            .documents
+            .iter()
+            .filter(|it| it.account.as_deref() == Some(account_name) || it.trx_id.as_deref().map(|id| trx_ids.contains(id)).unwrap_or(false))
+            .sorted_by_key(|it| it.datetime)
+            .cloned()
+            .collect_vec())
+    }
+
+    async fn transaction_documents(&mut self, trx_id: &str) -> ZhangResult<Vec<DocumentDomain>> {
+        Ok(self
+            .read()
+            .documents
+            .iter()
+            .filter(|it| it.trx_id.as_deref() == Some(trx_id))
+            .sorted_by_key(|it| it.datetime)
+            .cloned()
+            .collect_vec())
+    }
+
+    async fn exist_account(&mut self, name: &str) -> ZhangResult<bool> {
+        Ok(self.read().accounts.iter().any(|it| it.name == name))
+    }
+
+    async fn accounts(&mut self) -> ZhangResult<Vec<AccountDomain>> {
+        Ok(self.read().accounts.iter().sorted_by(|a, b| a.name.cmp(&b.name)).cloned().collect_vec())
+    }
+
+    async fn account(&mut self, account_name: &str) -> ZhangResult<Option<AccountDomain>> {
+        Ok(self.read().accounts.iter().find(|it| it.name == account_name).cloned())
+    }
+
+    async fn journals(&mut self, filter: &JournalFilter, limit: u32, offset: u32) -> ZhangResult<(i64, Vec<JournalDomain>)> {
+        let data = self.read();
+        let matched = data
+            .transactions
+            .iter()
+            .filter(|it| data.match_journal(it, filter))
+            .sorted_by_key(|it| it.sequence.neg())
+            .collect_vec();
+        let journals = matched
+            .iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|trx| {
+                let postings = data
+                    .postings
+                    .iter()
+                    .filter(|it| it.trx_id == trx.id)
+                    .enumerate()
+                    .map(|(idx, posting)| JournalPostingDomain {
+                        trx_id: posting.trx_id.clone(),
+                        account: posting.account.clone(),
+                        unit_number: posting.units.as_ref().map(|it| ZhangBigDecimal(it.number.clone())),
+                        unit_commodity: posting.units.as_ref().map(|it| it.currency.clone()),
+                        cost_number: posting.cost.as_ref().map(|it| ZhangBigDecimal(it.number.clone())),
+                        cost_commodity: posting.cost.as_ref().map(|it| it.currency.clone()),
+                        price_number: posting.price.as_ref().map(|it| ZhangBigDecimal(it.number.clone())),
+                        price_commodity: posting.price.as_ref().map(|it| it.currency.clone()),
+                        inferred_unit_number: ZhangBigDecimal(posting.inferred_amount.number.clone()),
+                        inferred_unit_commodity: posting.inferred_amount.currency.clone(),
+                        account_before_number: ZhangBigDecimal(posting.before_number.clone()),
+                        account_before_commodity: posting.inferred_amount.currency.clone(),
+                        account_after_number: ZhangBigDecimal(posting.after_number.clone()),
+                        account_after_commodity: posting.inferred_amount.currency.clone(),
+                        metas: data.metas_of(&MetaType::PostingMeta, &posting_identifier(&trx.id, idx)),
+                    })
+                    .collect_vec();
+                JournalDomain {
+                    id: trx.id.clone(),
+                    sequence: trx.sequence,
+                    datetime: trx.datetime,
+                    journal_type: trx.flag.clone(),
+                    payee: trx.payee.clone().unwrap_or_default(),
+                    narration: trx.narration.clone(),
+                    tags: trx.tags.clone(),
+                    links: trx.links.clone(),
+                    metas: data.metas_of(&MetaType::TransactionMeta, &trx.id),
+                    postings,
+                }
+            })
+            .collect_vec();
+        Ok((matched.len() as i64, journals))
+    }
+
+    async fn payees(&mut self) -> ZhangResult<Vec<String>> {
+        Ok(self
+            .read()
+            .transactions
+            .iter()
+            .filter_map(|it| it.payee.clone())
+            .filter(|it| !it.is_empty())
+            .unique()
+            .collect_vec())
+    }
+
+    async fn transaction_counts(&mut self) -> ZhangResult<i64> {
+        Ok(self.read().transactions.len() as i64)
+    }
+
+    async fn dated_transaction_counts(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<i64> {
+        Ok(self
+            .read()
+            .transactions
+            .iter()
+            .filter(|it| it.flag != "BalancePad" && it.flag != "BalanceCheck")
+            .filter(|it| it.datetime >= from && it.datetime <= to)
+            .count() as i64)
+    }
+
+    async fn transaction_span(&mut self, id: &str) -> ZhangResult<Option<TransactionInfoDomain>> {
+        Ok(self.read().transaction(id).map(|it| TransactionInfoDomain {
+            id: it.id.clone(),
+            source_file: it.source_file.clone().unwrap_or_default(),
+            span_start: it.span_start,
+            span_end: it.span_end,
+        }))
+    }
+
+    async fn label_usages(&mut self, label: Label) -> ZhangResult<Vec<LabelUsageDomain>> {
+        let data = self.read();
+        let mut usages: HashMap<&str, LabelUsageDomain> = HashMap::new();
+        for trx in data.transactions.iter() {
+            for name in trx.labels(label).iter().unique() {
+                let usage = usages.entry(name.as_str()).or_insert_with(|| LabelUsageDomain {
+                    name: name.clone(),
+                    count: 0,
+                    first_datetime: trx.datetime,
+                    last_datetime: trx.datetime,
+                });
+                usage.count += 1;
+                usage.first_datetime = usage.first_datetime.min(trx.datetime);
+                usage.last_datetime = usage.last_datetime.max(trx.datetime);
+            }
+        }
+        Ok(usages
+            .into_values()
+            .sorted_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)))
+            .collect_vec())
+    }
+
+    async fn label_period_totals(&mut self, label: Label, period_format: &str) -> ZhangResult<Vec<LabelPeriodTotalDomain>> {
+        let data = self.read();
+        let mut totals: HashMap<(String, String, String), BigDecimal> = HashMap::new();
+        for (trx, posting) in data.postings() {
+            if !matches!(data.account_type(&posting.account), Some("Income" | "Expenses")) {
+                continue;
+            }
+            for name in trx.labels(label).iter().unique() {
+                let key = (
+                    name.clone(),
+                    trx.datetime.format(period_format).to_string(),
+                    posting.inferred_amount.currency.clone(),
+                );
+                *totals.entry(key).or_insert_with(BigDecimal::zero) += &posting.inferred_amount.number;
+            }
+        }
+        Ok(totals
+            .into_iter()
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|((name, period, commodity), amount)| LabelPeriodTotalDomain {
+                name,
+                period,
+                commodity,
+                amount: ZhangBigDecimal(amount),
+            })
+            .collect_vec())
+    }
+
+    async fn label_account_effects(&mut self, label: Label, name: &str) -> ZhangResult<Vec<LabelAccountEffectDomain>> {
+        let data = self.read();
+        let mut effects: HashMap<(String, String), (BigDecimal, HashSet<&str>)> = HashMap::new();
+        for (trx, posting) in data.postings() {
+            if !trx.labels(label).iter().any(|it| it == name) {
+                continue;
+            }
+            let (amount, transactions) = effects
+                .entry((posting.account.clone(), posting.inferred_amount.currency.clone()))
+                .or_insert_with(|| (BigDecimal::zero(), HashSet::new()));
+            *amount += &posting.inferred_amount.number;
+            transactions.insert(trx.id.as_str());
+        }
+        Ok(effects
+            .into_iter()
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|((account, commodity), (amount, transactions))| LabelAccountEffectDomain {
+                account,
+                commodity,
+                amount: ZhangBigDecimal(amount),
+                transactions: transactions.len() as i64,
+            })
+            .collect_vec())
+    }
+
+    async fn account_balances(&mut self) -> ZhangResult<Vec<AccountBalanceDomain>> {
+        Ok(self.read().account_balances())
+    }
+
+    async fn single_account_balances(&mut self, account_name: &str) -> ZhangResult<Vec<AccountBalanceDomain>> {
+        Ok(self.read().account_balances().into_iter().filter(|it| it.account == account_name).collect_vec())
+    }
+
+    async fn account_daily_balances(&mut self, account_name: &str, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountDailyBalanceDomain>> {
+        let data = self.read();
+        let postings = data.postings().into_iter().filter(|(_, posting)| posting.account == account_name).collect_vec();
+        let before = MemoryData::latest_postings(postings.iter().copied().filter(|(trx, _)| trx.datetime < from), false);
+        let within = MemoryData::latest_postings(postings.iter().copied(), true)
+            .into_iter()
+            .filter(|(trx, _)| trx.datetime >= from && trx.datetime <= to);
+        Ok(before
+            .into_iter()
+            .chain(within)
+            .map(|(trx, posting)| MemoryData::daily_balance(trx, posting))
+            .sorted_by_key(|it| it.date)
+            .collect_vec())
+    }
+
+    async fn account_journals(&mut self, account: &str) -> ZhangResult<Vec<AccountJournalDomain>> {
+        let data = self.read();
+        Ok(data
+            .postings()
+            .into_iter()
+            .filter(|(_, posting)| posting.account == account)
+            .sorted_by(|(a, _), (b, _)| b.datetime.cmp(&a.datetime).then_with(|| b.sequence.cmp(&a.sequence)))
+            .map(|(trx, posting)| MemoryData::account_journal(trx, posting))
+            .collect_vec())
+    }
+
+    async fn account_dated_journals(&mut self, account_type: &str, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountJournalDomain>> {
+        let data = self.read();
+        Ok(data
+            .postings()
+            .into_iter()
+            .filter(|(trx, posting)| trx.datetime >= from && trx.datetime <= to && data.account_type(&posting.account) == Some(account_type))
+            .map(|(trx, posting)| MemoryData::account_journal(trx, posting))
+            .collect_vec())
+    }
+
+    async fn account_type_totals(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountTypeTotalDomain>> {
+        let data = self.read();
+        let mut totals: HashMap<(String, String), BigDecimal> = HashMap::new();
+        for (trx, posting) in data.postings() {
+            if trx.datetime < from || trx.datetime > to {
+                continue;
+            }
+            if let Some(account_type) = data.account_type(&posting.account) {
+                *totals
+                    .entry((account_type.to_owned(), posting.inferred_amount.currency.clone()))
+                    .or_insert_with(BigDecimal::zero) += &posting.inferred_amount.number;
+            }
+        }
+        Ok(totals
+            .into_iter()
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|((account_type, commodity), amount)| AccountTypeTotalDomain {
+                account_type,
+                commodity,
+                amount: ZhangBigDecimal(amount),
+            })
+            .collect_vec())
+    }
+
+    async fn daily_account_type_totals(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<DailyAccountTypeTotalDomain>> {
+        let data = self.read();
+        let mut totals: HashMap<(chrono::NaiveDate, String, String), BigDecimal> = HashMap::new();
+        for (trx, posting) in data.postings() {
+            if trx.datetime < from || trx.datetime > to {
+                continue;
+            }
+            if let Some(account_type) = data.account_type(&posting.account) {
+                *totals
+                    .entry((trx.datetime.date(), account_type.to_owned(), posting.inferred_amount.currency.clone()))
+                    .or_insert_with(BigDecimal::zero) += &posting.inferred_amount.number;
+            }
+        }
+        Ok(totals
+            .into_iter()
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|((date, account_type, commodity), amount)| DailyAccountTypeTotalDomain {
+                date,
+                account_type,
+                commodity,
+                amount: ZhangBigDecimal(amount),
+            })
+            .collect_vec())
+    }
+
+    async fn errors(&mut self) -> ZhangResult<Vec<ErrorDomain>> {
+        Ok(self.read().errors.clone())
+    }
+
+    async fn new_error(&mut self, error_type: ErrorType, span: &SpanInfo, metas: HashMap<String, String>) -> ZhangResult<()> {
+        self.write().errors.push(ErrorDomain {
+            id: Uuid::new_v4().to_string(),
+            span: Some(span.clone()),
+            error_type,
+            metas,
+        });
+        Ok(())
+    }
+
+    async fn insert_or_update_options(&mut self, key: &str, value: &str) -> ZhangResult<()> {
+        let mut data = self.write();
+        data.options.retain(|it| it.key != key);
+        data.options.push(OptionDomain {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        });
+        Ok(())
+    }
+
+    async fn insert_meta(&mut self, type_: MetaType, type_identifier: &str, meta: Meta) -> ZhangResult<()> {
+        let mut data = self.write();
+        for (meta_key, meta_value) in meta.get_flatten() {
+            data.metas.push(MetaDomain {
+                meta_type: type_.as_ref().to_owned(),
+                type_identifier: type_identifier.to_owned(),
+                key: meta_key,
+                value: meta_value.as_str().to_owned(),
+            });
+        }
+        Ok(())
+    }
+
+    async fn insert_account(&mut self, datetime: DateTime<Tz>, account_type: &str, name: &str, alias: Option<&str>) -> ZhangResult<()> {
+        let mut data = self.write();
+        data.accounts.retain(|it| it.name != name);
+        data.accounts.push(AccountDomain {
+            date: datetime.naive_local(),
+            r#type: account_type.to_owned(),
+            name: name.to_owned(),
+            status: AccountStatus::Open,
+            alias: alias.map(|it| it.to_owned()),
+        });
+        Ok(())
+    }
+
+    async fn close_account(&mut self, account_name: &str) -> ZhangResult<()> {
+        for account in self.write().accounts.iter_mut().filter(|it| it.name == account_name) {
+            account.status = AccountStatus::Close;
+        }
+        Ok(())
+    }
+
+    async fn insert_commodity(&mut self, commodity: &CommodityDomain) -> ZhangResult<()> {
+        let mut data = self.write();
+        data.commodities.retain(|it| it.name != commodity.name);
+        data.commodities.push(commodity.clone());
+        Ok(())
+    }
+
+    async fn insert_transaction(&mut self, transaction: &TransactionRecord) -> ZhangResult<()> {
+        let mut data = self.write();
+        let sequence = data.transactions.len() as i64 + 1;
+        data.transactions.push(TransactionRow {
+            sequence,
+            id: transaction.id.clone(),
+            datetime: transaction.datetime.naive_local(),
+            flag: transaction.flag.clone(),
+            payee: transaction.payee.clone(),
+            narration: transaction.narration.clone(),
+            source_file: transaction.source_file.clone(),
+            span_start: transaction.span_start,
+            span_end: transaction.span_end,
+            tags: transaction.tags.clone(),
+            links: transaction.links.clone(),
+        });
+        Ok(())
+    }
+
+    async fn insert_posting(&mut self, posting: &PostingRecord) -> ZhangResult<()> {
+        self.write().postings.push(PostingRow {
+            trx_id: posting.trx_id.clone(),
+            account: posting.account.clone(),
+            units: posting.units.clone(),
+            cost: posting.cost.clone(),
+            price: posting.price.clone(),
+            inferred_amount: posting.inferred_amount.clone(),
+            before_number: posting.before_number.clone(),
+            after_number: posting.after_number.clone(),
+        });
+        Ok(())
+    }
+
+    async fn insert_document(&mut self, document: &DocumentRecord) -> ZhangResult<()> {
+        self.write().documents.push(DocumentDomain {
+            datetime: document.datetime.naive_local(),
+            filename: document.filename.clone(),
+            path: document.path.clone(),
+            extension: document.extension.clone(),
+            account: document.account.clone(),
+            trx_id: document.trx_id.clone(),
+        });
+        Ok(())
+    }
+
+    async fn insert_price(&mut self, datetime: DateTime<Tz>, commodity: &str, amount: &Amount) -> ZhangResult<()> {
+        self.write().prices.push(PriceDomain {
+            datetime: datetime.naive_local(),
+            commodity: commodity.to_owned(),
+            amount: ZhangBigDecimal(amount.number.clone()),
+            target_commodity: amount.currency.clone(),
+        });
+        Ok(())
+    }
+
+    async fn add_lot(&mut self, account: &str, amount: &Amount, lot_info: &LotInfo) -> ZhangResult<()> {
+        let mut data = self.write();
+        let same_lot = |lot: &LotRow, price: &Option<(BigDecimal, String)>| lot.account == account && lot.commodity == amount.currency && &lot.price == price;
+        let price = match lot_info {
+            LotInfo::Lot(target_currency, lot_number) => Some((lot_number.clone(), target_currency.clone())),
+            LotInfo::Fifo => data
+                .lots
+                .iter()
+                .find(|lot| {
+                    lot.account == account
+                        && lot.commodity == amount.currency
+                        && match &lot.price {
+                            Some((_, price_commodity)) => price_commodity == &amount.currency && !lot.amount.is_zero(),
+                            None => true,
+                        }
+                })
+                .and_then(|lot| lot.price.clone()),
+            LotInfo::Filo => {
+                unimplemented!()
+            }
+        };
+        let mut existed = false;
+        for lot in data.lots.iter_mut().filter(|lot| same_lot(lot, &price)) {
+            lot.amount += &amount.number;
+            existed = true;
+        }
+        if !existed {
+            data.lots.push(LotRow {
+                account: account.to_owned(),
+                commodity: amount.currency.clone(),
+                amount: amount.number.clone(),
+                price,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, NaiveDateTime};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use std::ops::Bound;
+use zhang_ast::amount::Amount;
+use zhang_ast::utils::inventory::LotInfo;
+use zhang_ast::{Meta, SpanInfo};
+
+use crate::domains::schemas::{
+    AccountBalanceDomain, AccountDailyBalanceDomain, AccountDomain, AccountJournalDomain, AccountTypeTotalDomain, CommodityDomain, CommodityLotDomain,
+    CommoditySummaryDomain, DailyAccountTypeTotalDomain, DocumentDomain, ErrorDomain, ErrorType, JournalDomain, JournalFilter, LabelAccountEffectDomain,
+    LabelPeriodTotalDomain, LabelUsageDomain, MetaDomain, MetaType, OptionDomain, PriceDomain, TransactionInfoDomain,
+};
+use crate::ZhangResult;
+
+pub mod memory;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use memory::MemoryStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+/// tag or link of transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Label {
+    Tag,
+    Link,
+}
+
+pub struct TransactionRecord {
+    pub id: String,
+    pub datetime: DateTime<Tz>,
+    pub flag: String,
+    pub payee: Option<String>,
+    pub narration: Option<String>,
+    pub source_file: Option<String>,
+    pub span_start: i64,
+    pub span_end: i64,
+    pub tags: Vec<String>,
+    pub links: Vec<String>,
+}
+
+pub struct PostingRecord {
+    pub trx_id: String,
+    pub account: String,
+    pub units: Option<Amount>,
+    pub cost: Option<Amount>,
+    /// price of single unit
+    pub price: Option<Amount>,
+    pub inferred_amount: Amount,
+    /// balance of the account in commodity of inferred amount, before and after the posting
+    pub before_number: BigDecimal,
+    pub after_number: BigDecimal,
+}
+
+pub struct DocumentRecord {
+    pub datetime: DateTime<Tz>,
+    pub filename: String,
+    pub path: String,
+    pub extension: Option<String>,
+    pub account: Option<String>,
+    pub trx_id: Option<String>,
+}
+
+/// storage backend of processed ledger data, which `Operations` queries through.
+///
+/// datetime given to queries is in ledger's timezone.
+#[async_trait]
+pub trait LedgerStore: Send {
+    async fn options(&mut self) -> ZhangResult<Vec<OptionDomain>>;
+    async fn option(&mut self, key: &str) -> ZhangResult<Option<OptionDomain>>;
+
+    /// latest daily balance of every account and commodity, only the ones within the upper bound of datetime are counted
+    async fn accounts_latest_balance(&mut self, until: Bound<NaiveDateTime>) -> ZhangResult<Vec<AccountDailyBalanceDomain>>;
+    /// daily balance of every account between `from` and `to`
+    async fn daily_balances(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountDailyBalanceDomain>>;
+
+    /// the latest price not later than `datetime`
+    async fn latest_price(&mut self, datetime: DateTime<Tz>, from: &str, to: &str) -> ZhangResult<Option<PriceDomain>>;
+    /// prices in order of datetime, all commodities are included if `commodity` is not given
+    async fn prices(&mut self, commodity: Option<&str>) -> ZhangResult<Vec<PriceDomain>>;
+
+    async fn metas(&mut self, type_: MetaType, type_identifier: &str) -> ZhangResult<Vec<MetaDomain>>;
+    async fn trx_tags(&mut self, trx_id: &str) -> ZhangResult<Vec<String>>;
+    async fn trx_links(&mut self, trx_id: &str) -> ZhangResult<Vec<String>>;
+
+    async fn commodities(&mut self) -> ZhangResult<Vec<CommodityDomain>>;
+    async fn commodity(&mut self, name: &str) -> ZhangResult<Option<CommodityDomain>>;
+    async fn exist_commodity(&mut self, name: &str) -> ZhangResult<bool>;
+    /// commodities along with their total amount held by assets and liabilities, and their latest price
+    async fn commodity_summaries(&mut self) -> ZhangResult<Vec<CommoditySummaryDomain>>;
+    async fn commodity_summary(&mut self, name: &str) -> ZhangResult<Option<CommoditySummaryDomain>>;
+    async fn commodity_lots(&mut self, commodity: &str) -> ZhangResult<Vec<CommodityLotDomain>>;
+
+    async fn documents(&mut self) -> ZhangResult<Vec<DocumentDomain>>;
+    /// documents of the account itself and of transactions posting to the account
+    async fn account_documents(&mut self, account_name: &str) -> ZhangResult<Vec<DocumentDomain>>;
+    async fn transaction_documents(&mut self, trx_id: &str) -> ZhangResult<Vec<DocumentDomain>>;
+
+    async fn exist_account(&mut self, name: &str) -> ZhangResult<bool>;
+    async fn accounts(&mut self) -> ZhangResult<Vec<AccountDomain>>;
+    async fn account(&mut self, account_name: &str) -> ZhangResult<Option<AccountDomain>>;
+
+    /// transactions matching filter in reverse order of sequence, along with the count of all matched ones
+    async fn journals(&mut self, filter: &JournalFilter, limit: u32, offset: u32) -> ZhangResult<(i64, Vec<JournalDomain>)>;
+    /// distinct payees of transactions
+    async fn payees(&mut self) -> ZhangResult<Vec<String>>;
+    async fn transaction_counts(&mut self) -> ZhangResult<i64>;
+    /// count of transactions between `from` and `to`, balance checks and pads are excluded
+    async fn dated_transaction_counts(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<i64>;
+    async fn transaction_span(&mut self, id: &str) -> ZhangResult<Option<TransactionInfoDomain>>;
+
+    async fn label_usages(&mut self, label: Label) -> ZhangResult<Vec<LabelUsageDomain>>;
+    /// `period_format` is the `strftime` format grouping transactions' datetime into periods
+    async fn label_period_totals(&mut self, label: Label, period_format: &str) -> ZhangResult<Vec<LabelPeriodTotalDomain>>;
+    async fn label_account_effects(&mut self, label: Label, name: &str) -> ZhangResult<Vec<LabelAccountEffectDomain>>;
+
+    async fn tag_usages(&mut self) -> ZhangResult<Vec<LabelUsageDomain>> {
+        self.label_usages(Label::Tag).await
+    }
+    async fn link_usages(&mut self) -> ZhangResult<Vec<LabelUsageDomain>> {
+        self.label_usages(Label::Link).await
+    }
+    async fn tag_period_totals(&mut self, period_format: &str) -> ZhangResult<Vec<LabelPeriodTotalDomain>> {
+        self.label_period_totals(Label::Tag, period_format).await
+    }
+    async fn link_period_totals(&mut self, period_format: &str) -> ZhangResult<Vec<LabelPeriodTotalDomain>> {
+        self.label_period_totals(Label::Link, period_format).await
+    }
+    async fn tag_account_effects(&mut self, tag: &str) -> ZhangResult<Vec<LabelAccountEffectDomain>> {
+        self.label_account_effects(Label::Tag, tag).await
+    }
+    async fn link_account_effects(&mut self, link: &str) -> ZhangResult<Vec<LabelAccountEffectDomain>> {
+        self.label_account_effects(Label::Link, link).await
+    }
+
+    /// the latest balance of every account and commodity
+    async fn account_balances(&mut self) -> ZhangResult<Vec<AccountBalanceDomain>>;
+    async fn single_account_balances(&mut self, account_name: &str) -> ZhangResult<Vec<AccountBalanceDomain>>;
+    /// daily balances of the account between `from` and `to`, along with its latest balances before `from`
+    async fn account_daily_balances(&mut self, account_name: &str, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountDailyBalanceDomain>>;
+    async fn account_journals(&mut self, account: &str) -> ZhangResult<Vec<AccountJournalDomain>>;
+    async fn account_dated_journals(&mut self, account_type: &str, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountJournalDomain>>;
+    /// net amount of postings between `from` and `to` grouped by account type and commodity
+    async fn account_type_totals(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountTypeTotalDomain>>;
+    /// net amount of postings between `from` and `to` grouped by date, account type and commodity
+    async fn daily_account_type_totals(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<DailyAccountTypeTotalDomain>>;
+
+    async fn errors(&mut self) -> ZhangResult<Vec<ErrorDomain>>;
+
+    // for insert and new operations
+    async fn new_error(&mut self, error_type: ErrorType, span: &SpanInfo, metas: HashMap<String, String>) -> ZhangResult<()>;
+    async fn insert_or_update_options(&mut self, key: &str, value: &str) -> ZhangResult<()>;
+    async fn insert_meta(&mut self, type_: MetaType, type_identifier: &str, meta: Meta) -> ZhangResult<()>;
+    /// open the account, the previous declaration is replaced
+    async fn insert_account(&mut self, datetime: DateTime<Tz>, account_type: &str, name: &str, alias: Option<&str>) -> ZhangResult<()>;
+    async fn close_account(&mut self, account_name: &str) -> ZhangResult<()>;
+    /// the previous declaration of the commodity is replaced
+    async fn insert_commodity(&mut self, commodity: &CommodityDomain) -> ZhangResult<()>;
+    async fn insert_transaction(&mut self, transaction: &TransactionRecord) -> ZhangResult<()>;
+    /// postings are kept in the order of insertion
+    async fn insert_posting(&mut self, posting: &PostingRecord) -> ZhangResult<()>;
+    async fn insert_document(&mut self, document: &DocumentRecord) -> ZhangResult<()>;
+    async fn insert_price(&mut self, datetime: DateTime<Tz>, commodity: &str, amount: &Amount) -> ZhangResult<()>;
+    /// add amount into the lot of commodity held by the account
+    async fn add_lot(&mut self, account: &str, amount: &Amount, lot_info: &LotInfo) -> ZhangResult<()>;
+}
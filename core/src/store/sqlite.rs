@@ -0,0 +1,1317 @@
+use std::collections::HashMap;
+use std::ops::{Add, Bound, DerefMut};
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive};
+use chrono::{DateTime, NaiveDateTime};
+use chrono_tz::Tz;
+use itertools::Itertools;
+use sqlx::pool::PoolConnection;
+use sqlx::{Acquire, FromRow, QueryBuilder, Sqlite, SqliteConnection};
+use uuid::Uuid;
+use zhang_ast::amount::Amount;
+use zhang_ast::utils::inventory::LotInfo;
+use zhang_ast::{Directive, Meta, SpanInfo, Spanned};
+
+use crate::database::type_ext::big_decimal::ZhangBigDecimal;
+use crate::domains::posting_identifier;
+use crate::domains::schemas::{
+    AccountBalanceDomain, AccountDailyBalanceDomain, AccountDomain, AccountJournalDomain, AccountTypeTotalDomain, CommodityDomain, CommodityLotDomain,
+    CommoditySummaryDomain, DailyAccountTypeTotalDomain, DocumentDomain, ErrorDomain, ErrorType, JournalDomain, JournalFilter, JournalPostingDomain,
+    LabelAccountEffectDomain, LabelPeriodTotalDomain, LabelUsageDomain, MetaDomain, MetaType, OptionDomain, PriceDomain, TransactionInfoDomain,
+};
+use crate::store::{DocumentRecord, Label, LedgerStore, PostingRecord, TransactionRecord};
+use crate::utils::id::FromSpan;
+use crate::ZhangResult;
+
+impl Label {
+    /// table and column of the label
+    fn table(&self) -> (&'static str, &'static str) {
+        match self {
+            Label::Tag => ("transaction_tags", "tag"),
+            Label::Link => ("transaction_links", "link"),
+        }
+    }
+}
+
+fn push_journal_filter(query: &mut QueryBuilder<Sqlite>, filter: &JournalFilter) {
+    if let Some(id) = &filter.id {
+        query.push(" and id = ").push_bind(id.clone());
+    }
+    if let Some(from) = filter.from {
+        query.push(" and datetime >= ").push_bind(from);
+    }
+    if let Some(to) = filter.to {
+        query.push(" and datetime <= ").push_bind(to);
+    }
+    if let Some(flag) = &filter.flag {
+        query.push(" and type = ").push_bind(flag.clone());
+    }
+    if let Some(keyword) = filter.keyword.as_deref().and_then(fts_query) {
+        query
+            .push(" and sequence in (select rowid from transactions_fts where transactions_fts match ")
+            .push_bind(keyword)
+            .push(")");
+    }
+    if let Some(tag) = &filter.tag {
+        query
+            .push(" and exists (select 1 from transaction_tags where transaction_tags.trx_id = transactions.id and tag = ")
+            .push_bind(tag.clone())
+            .push(")");
+    }
+    if let Some(link) = &filter.link {
+        query
+            .push(" and exists (select 1 from transaction_links where transaction_links.trx_id = transactions.id and link = ")
+            .push_bind(link.clone())
+            .push(")");
+    }
+    // posting conditions should be satisfied by the same posting
+    if filter.account.is_some() || filter.commodity.is_some() || filter.min_amount.is_some() || filter.max_amount.is_some() {
+        query.push(" and exists (select 1 from transaction_postings where transaction_postings.trx_id = transactions.id");
+        if let Some(account) = &filter.account {
+            query
+                .push(" and (account = ")
+                .push_bind(account.clone())
+                .push(" or account like ")
+                .push_bind(format!("{}:%", escape_like(account)))
+                .push(r" escape '\')");
+        }
+        if let Some(commodity) = &filter.commodity {
+            query.push(" and inferred_unit_commodity = ").push_bind(commodity.clone());
+        }
+        if let Some(min_amount) = filter.min_amount.as_ref().and_then(|it| it.to_f64()) {
+            query.push(" and abs(inferred_unit_number) >= ").push_bind(min_amount);
+        }
+        if let Some(max_amount) = filter.max_amount.as_ref().and_then(|it| it.to_f64()) {
+            query.push(" and abs(inferred_unit_number) <= ").push_bind(max_amount);
+        }
+        query.push(")");
+    }
+}
+
+/// escape the wildcards of `like` pattern, used along with `escape '\'`
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// fill the connection's temp table `journal_page` with the ids of transactions in the requested page,
+/// so that the filter is only run once and the details of journals are joined to the page
+async fn fill_journal_page(conn: &mut SqliteConnection, filter: &JournalFilter, limit: u32, offset: u32) -> ZhangResult<()> {
+    sqlx::query("create temp table if not exists journal_page (id varchar not null primary key, sequence integer not null, total_count integer not null)")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("delete from temp.journal_page").execute(&mut *conn).await?;
+
+    // the total count is computed along with the page by window function, before limit and offset are applied
+    let mut query = QueryBuilder::new("insert into temp.journal_page select id, sequence, count(1) over () from transactions where 1 = 1");
+    push_journal_filter(&mut query, filter);
+    query
+        .push(r#" order by "sequence" desc limit "#)
+        .push_bind(limit)
+        .push(" offset ")
+        .push_bind(offset);
+    query.build().execute(&mut *conn).await?;
+    Ok(())
+}
+
+/// every word of keyword is a prefix query, quoted to escape the fts5 syntax in user input
+fn fts_query(keyword: &str) -> Option<String> {
+    let query = keyword.split_whitespace().map(|word| format!("\"{}\"*", word.replace('"', "\"\""))).join(" ");
+    if query.is_empty() {
+        None
+    } else {
+        Some(query)
+    }
+}
+
+#[derive(FromRow)]
+struct ValueRow {
+    value: String,
+}
+
+#[derive(Debug, FromRow)]
+struct LotRow {
+    amount: f64,
+    price_amount: Option<f64>,
+    price_commodity: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct LotPostingRow {
+    account: String,
+    unit_number: Option<ZhangBigDecimal>,
+    unit_commodity: Option<String>,
+    cost_number: Option<ZhangBigDecimal>,
+    cost_commodity: Option<String>,
+    price_number: Option<ZhangBigDecimal>,
+    price_commodity: Option<String>,
+    inferred_unit_number: ZhangBigDecimal,
+    inferred_unit_commodity: String,
+}
+
+const COMMODITY_SUMMARY_SQL: &str = r#"
+    select commodities.*,
+           IFNULL(commodity_total_amount.total_amount, 0.00) as total_amount,
+           latest_price.datetime         latest_price_date,
+           latest_price.amount           latest_price_amount,
+           latest_price.target_commodity latest_price_commodity
+    from commodities
+             left join (select commodity, max(datetime) as datetime, amount, target_commodity
+                        from prices
+                        group by commodity) latest_price on commodities.name = latest_price.commodity
+             left join (select commodity, total(amount) as total_amount
+                        from commodity_lots
+                                 join accounts on commodity_lots.account = accounts.name
+                        where accounts.type in ('Assets', 'Liabilities')
+                        group by commodity) commodity_total_amount on commodities.name = commodity_total_amount.commodity
+"#;
+
+/// the sqlite backend, over a pooled connection by default, or the one of processing transaction
+pub struct SqliteStore<C = PoolConnection<Sqlite>> {
+    pub(crate) conn: C,
+}
+
+impl<C: DerefMut<Target = SqliteConnection> + Send> SqliteStore<C> {
+    pub fn new(conn: C) -> Self {
+        Self { conn }
+    }
+
+    /// the latest balance of each account and commodity, for processing directives not from the beginning
+    /// relocate the data of unchanged directives whose span is moved by the edits before them in the same file,
+    /// given as pairs of previous and current span. ids of transactions are derived from span, so they are updated as well
+    pub(crate) async fn move_spans(&mut self, moves: &[(SpanInfo, SpanInfo)]) -> ZhangResult<()> {
+        if moves.is_empty() {
+            return Ok(());
+        }
+        let mut trx = self.conn.begin().await?;
+        sqlx::query(
+            r#"create temp table if not exists span_moves (old_id varchar not null, new_id varchar not null, filename varchar,
+                   old_start integer not null, old_end integer not null, new_start integer not null, new_end integer not null)"#,
+        )
+        .execute(&mut trx)
+        .await?;
+        sqlx::query("delete from temp.span_moves").execute(&mut trx).await?;
+        // keep the number of bound parameters under the limit of sqlite
+        for chunk in moves.chunks(1000) {
+            let mut query = QueryBuilder::new("insert into temp.span_moves (old_id, new_id, filename, old_start, old_end, new_start, new_end) ");
+            query.push_values(chunk, |mut row, (previous, current)| {
+                row.push_bind(Uuid::from_span(previous).to_string())
+                    .push_bind(Uuid::from_span(current).to_string())
+                    .push_bind(previous.filename.as_ref().and_then(|it| it.to_str()).map(|it| it.to_owned()))
+                    .push_bind(previous.start as i64)
+                    .push_bind(previous.end as i64)
+                    .push_bind(current.start as i64)
+                    .push_bind(current.end as i64);
+            });
+            query.build().execute(&mut trx).await?;
+        }
+
+        // ids are moved to a temporary one first, since the new id of a transaction may be the previous id of another one
+        sqlx::query(
+            r#"update transactions set id = '~' || m.new_id, span_start = m.new_start, span_end = m.new_end
+               from temp.span_moves m where transactions.id = m.old_id"#,
+        )
+        .execute(&mut trx)
+        .await?;
+        for table in ["transaction_postings", "transaction_tags", "transaction_links", "documents"] {
+            sqlx::query(&format!(
+                "update {table} set trx_id = '~' || m.new_id from temp.span_moves m where {table}.trx_id = m.old_id"
+            ))
+            .execute(&mut trx)
+            .await?;
+        }
+        sqlx::query(r#"update metas set type_identifier = '~' || m.new_id from temp.span_moves m where metas.type = $1 and metas.type_identifier = m.old_id"#)
+            .bind(MetaType::TransactionMeta.as_ref())
+            .execute(&mut trx)
+            .await?;
+        sqlx::query(
+            r#"update metas set type_identifier = '~' || m.new_id || substr(metas.type_identifier, length(m.old_id) + 1)
+               from temp.span_moves m where metas.type = $1 and metas.type_identifier like m.old_id || ':%'"#,
+        )
+        .bind(MetaType::PostingMeta.as_ref())
+        .execute(&mut trx)
+        .await?;
+        sqlx::query("update transactions set id = substr(id, 2) where id like '~%'")
+            .execute(&mut trx)
+            .await?;
+        for table in ["transaction_postings", "transaction_tags", "transaction_links", "documents"] {
+            sqlx::query(&format!("update {table} set trx_id = substr(trx_id, 2) where trx_id like '~%'"))
+                .execute(&mut trx)
+                .await?;
+        }
+        sqlx::query("update metas set type_identifier = substr(type_identifier, 2) where type in ($1, $2) and type_identifier like '~%'")
+            .bind(MetaType::TransactionMeta.as_ref())
+            .bind(MetaType::PostingMeta.as_ref())
+            .execute(&mut trx)
+            .await?;
+
+        sqlx::query(
+            r#"update errors set span_start = m.new_start, span_end = m.new_end
+               from temp.span_moves m where errors.filename is m.filename and errors.span_start = m.old_start and errors.span_end = m.old_end"#,
+        )
+        .execute(&mut trx)
+        .await?;
+        trx.commit().await?;
+        Ok(())
+    }
+
+    pub(crate) async fn running_balances(&mut self) -> ZhangResult<HashMap<(String, String), BigDecimal>> {
+        let rows: Vec<(String, String, ZhangBigDecimal)> = sqlx::query_as(
+            r#"select account, account_after_commodity, account_after_number
+               from transaction_postings
+                        join transactions on transactions.id = transaction_postings.trx_id
+               order by transactions.sequence, transaction_postings.rowid"#,
+        )
+        .fetch_all(&mut *self.conn)
+        .await?;
+        Ok(rows.into_iter().map(|(account, commodity, number)| ((account, commodity), number.0)).collect())
+    }
+
+    /// remove everything which is written by given directives, whose datetime are all not earlier than `since`.
+    /// accounts whose postings are removed are returned, their commodity lots should be built again
+    pub(crate) async fn rollback_directives(&mut self, since: DateTime<Tz>, directives: &[&Spanned<Directive>]) -> ZhangResult<Vec<String>> {
+        let mut trx = self.conn.begin().await?;
+        let accounts: Vec<String> = sqlx::query_scalar(
+            r#"select distinct account from transaction_postings
+               join transactions on transactions.id = transaction_postings.trx_id
+               where datetime >= $1"#,
+        )
+        .bind(since)
+        .fetch_all(&mut trx)
+        .await?;
+        for table in ["transaction_postings", "transaction_tags", "transaction_links"] {
+            sqlx::query(&format!(
+                "delete from {table} where trx_id in (select id from transactions where datetime >= $1)"
+            ))
+            .bind(since)
+            .execute(&mut trx)
+            .await?;
+        }
+        sqlx::query(
+            r#"delete from metas
+               where (type = $1 and type_identifier in (select id from transactions where datetime >= $3))
+                  or (type = $2 and exists(select 1 from transactions where datetime >= $3 and metas.type_identifier like transactions.id || ':%'))"#,
+        )
+        .bind(MetaType::TransactionMeta.as_ref())
+        .bind(MetaType::PostingMeta.as_ref())
+        .bind(since)
+        .execute(&mut trx)
+        .await?;
+        for table in ["documents", "transactions", "prices"] {
+            sqlx::query(&format!("delete from {table} where datetime >= $1"))
+                .bind(since)
+                .execute(&mut trx)
+                .await?;
+        }
+        sqlx::query(r#"delete from metas where type = $1 and type_identifier in (select name from accounts where date >= $2)"#)
+            .bind(MetaType::AccountMeta.as_ref())
+            .bind(since)
+            .execute(&mut trx)
+            .await?;
+        sqlx::query(r#"delete from accounts where date >= $1"#).bind(since).execute(&mut trx).await?;
+
+        for directive in directives {
+            match &directive.data {
+                Directive::Close(close) => {
+                    sqlx::query(r#"update accounts set status = 'Open' where name = $1"#)
+                        .bind(close.account.name())
+                        .execute(&mut trx)
+                        .await?;
+                }
+                Directive::Commodity(commodity) => {
+                    sqlx::query(r#"delete from commodities where name = $1"#)
+                        .bind(&commodity.currency)
+                        .execute(&mut trx)
+                        .await?;
+                    sqlx::query(r#"delete from metas where type = $1 and type_identifier = $2"#)
+                        .bind(MetaType::CommodityMeta.as_ref())
+                        .bind(&commodity.currency)
+                        .execute(&mut trx)
+                        .await?;
+                }
+                _ => {}
+            }
+            sqlx::query(r#"delete from errors where filename is $1 and span_start = $2 and span_end = $3"#)
+                .bind(directive.span.filename.as_ref().and_then(|it| it.to_str()))
+                .bind(directive.span.start as i64)
+                .bind(directive.span.end as i64)
+                .execute(&mut trx)
+                .await?;
+        }
+        trx.commit().await?;
+        Ok(accounts)
+    }
+
+    /// commodity lots are updated in place, so the lots of given accounts are built again from the postings left
+    pub(crate) async fn rebuild_commodity_lots(&mut self, accounts: &[String]) -> ZhangResult<()> {
+        if accounts.is_empty() {
+            return Ok(());
+        }
+        let mut query = QueryBuilder::new("delete from commodity_lots where account in (");
+        let mut separated = query.separated(", ");
+        for account in accounts {
+            separated.push_bind(account);
+        }
+        query.push(")");
+        query.build().execute(&mut *self.conn).await?;
+
+        let mut query = QueryBuilder::new(
+            r#"select account, unit_number, unit_commodity, cost_number, cost_commodity, price_number, price_commodity,
+                      inferred_unit_number, inferred_unit_commodity
+               from transaction_postings
+                        join transactions on transactions.id = transaction_postings.trx_id
+               where account in ("#,
+        );
+        let mut separated = query.separated(", ");
+        for account in accounts {
+            separated.push_bind(account);
+        }
+        query.push(") order by transactions.sequence, transaction_postings.rowid");
+        let postings: Vec<LotPostingRow> = query.build_query_as().fetch_all(&mut *self.conn).await?;
+        for posting in postings {
+            let lot_info = match (
+                posting.unit_number.is_some(),
+                posting.cost_number,
+                posting.cost_commodity,
+                posting.price_number,
+                posting.price_commodity,
+            ) {
+                (true, Some(number), Some(currency), _, _) | (true, None, _, Some(number), Some(currency)) => LotInfo::Lot(currency, number.0),
+                _ => LotInfo::Fifo,
+            };
+            let lot_amount = match (posting.unit_number, posting.unit_commodity) {
+                (Some(number), Some(currency)) => Amount::new(number.0, currency),
+                _ => Amount::new(posting.inferred_unit_number.0, posting.inferred_unit_commodity),
+            };
+            self.add_lot(&posting.account, &lot_amount, &lot_info).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<C: DerefMut<Target = SqliteConnection> + Send> LedgerStore for SqliteStore<C> {
+    async fn options(&mut self) -> ZhangResult<Vec<OptionDomain>> {
+        let options = sqlx::query_as::<_, OptionDomain>(
+            r#"
+                select key, value from options
+                "#,
+        )
+        .fetch_all(&mut *self.conn)
+        .await?;
+        Ok(options)
+    }
+
+    async fn option(&mut self, key: &str) -> ZhangResult<Option<OptionDomain>> {
+        let option = sqlx::query_as::<_, OptionDomain>(
+            r#"
+                select key, value from options where key = $1
+                "#,
+        )
+        .bind(key)
+        .fetch_optional(&mut *self.conn)
+        .await?;
+        Ok(option)
+    }
+
+    async fn accounts_latest_balance(&mut self, until: Bound<NaiveDateTime>) -> ZhangResult<Vec<AccountDailyBalanceDomain>> {
+        let mut query = QueryBuilder::new("SELECT date(max(datetime)) AS date, account, balance_number, balance_commodity FROM account_daily_balance");
+        match until {
+            Bound::Included(until) => {
+                query.push(" WHERE datetime <= ").push_bind(until);
+            }
+            Bound::Excluded(until) => {
+                query.push(" WHERE datetime < ").push_bind(until);
+            }
+            Bound::Unbounded => {}
+        }
+        query.push(" GROUP BY account, balance_commodity");
+        Ok(query.build_query_as::<AccountDailyBalanceDomain>().fetch_all(&mut *self.conn).await?)
+    }
+
+    async fn daily_balances(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountDailyBalanceDomain>> {
+        Ok(sqlx::query_as::<_, AccountDailyBalanceDomain>(
+            r#"
+                SELECT
+                    date(datetime) AS date,
+                    account,
+                    balance_number,
+                    balance_commodity
+                FROM
+                    account_daily_balance
+                WHERE datetime >= $1 and datetime <= $2
+                ORDER BY date
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn latest_price(&mut self, datetime: DateTime<Tz>, from: &str, to: &str) -> ZhangResult<Option<PriceDomain>> {
+        Ok(sqlx::query_as::<_, PriceDomain>(
+            "select datetime, commodity, amount, target_commodity from prices where datetime <= $1 and commodity = $2 and target_commodity = $3 order by datetime desc limit 1",
+        )
+        .bind(datetime)
+        .bind(from)
+        .bind(to)
+        .fetch_optional(&mut *self.conn)
+        .await?)
+    }
+
+    async fn prices(&mut self, commodity: Option<&str>) -> ZhangResult<Vec<PriceDomain>> {
+        Ok(sqlx::query_as::<_, PriceDomain>(
+            r#"select datetime, commodity, amount, target_commodity from prices where $1 is null or commodity = $1 order by datetime"#,
+        )
+        .bind(commodity)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn metas(&mut self, type_: MetaType, type_identifier: &str) -> ZhangResult<Vec<MetaDomain>> {
+        let rows = sqlx::query_as::<_, MetaDomain>(
+            r#"
+            select type as meta_type, type_identifier, key, value from metas where type = $1 and type_identifier = $2
+        "#,
+        )
+        .bind(type_.as_ref())
+        .bind(type_identifier)
+        .fetch_all(&mut *self.conn)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn trx_tags(&mut self, trx_id: &str) -> ZhangResult<Vec<String>> {
+        let rows = sqlx::query_as::<_, ValueRow>(
+            r#"
+        select tag as value from transaction_tags where trx_id = $1
+        "#,
+        )
+        .bind(trx_id)
+        .fetch_all(&mut *self.conn)
+        .await?;
+        Ok(rows.into_iter().map(|it| it.value).collect_vec())
+    }
+
+    async fn trx_links(&mut self, trx_id: &str) -> ZhangResult<Vec<String>> {
+        let rows = sqlx::query_as::<_, ValueRow>(
+            r#"
+        select link as value from transaction_links where trx_id = $1
+        "#,
+        )
+        .bind(trx_id)
+        .fetch_all(&mut *self.conn)
+        .await?;
+        Ok(rows.into_iter().map(|it| it.value).collect_vec())
+    }
+
+    async fn commodities(&mut self) -> ZhangResult<Vec<CommodityDomain>> {
+        Ok(sqlx::query_as::<_, CommodityDomain>(r#"select * from commodities order by name"#)
+            .fetch_all(&mut *self.conn)
+            .await?)
+    }
+
+    async fn commodity(&mut self, name: &str) -> ZhangResult<Option<CommodityDomain>> {
+        let option = sqlx::query_as::<_, CommodityDomain>(
+            r#"
+                select * from commodities where name = $1
+                "#,
+        )
+        .bind(name)
+        .fetch_optional(&mut *self.conn)
+        .await?;
+        Ok(option)
+    }
+
+    async fn exist_commodity(&mut self, name: &str) -> ZhangResult<bool> {
+        Ok(sqlx::query("select 1 from commodities where name = $1")
+            .bind(name)
+            .fetch_optional(&mut *self.conn)
+            .await?
+            .is_some())
+    }
+
+    async fn commodity_summaries(&mut self) -> ZhangResult<Vec<CommoditySummaryDomain>> {
+        Ok(
+            sqlx::query_as::<_, CommoditySummaryDomain>(&format!("{COMMODITY_SUMMARY_SQL} order by commodities.name"))
+                .fetch_all(&mut *self.conn)
+                .await?,
+        )
+    }
+
+    async fn commodity_summary(&mut self, name: &str) -> ZhangResult<Option<CommoditySummaryDomain>> {
+        Ok(
+            sqlx::query_as::<_, CommoditySummaryDomain>(&format!("{COMMODITY_SUMMARY_SQL} where commodities.name = $1"))
+                .bind(name)
+                .fetch_optional(&mut *self.conn)
+                .await?,
+        )
+    }
+
+    async fn commodity_lots(&mut self, commodity: &str) -> ZhangResult<Vec<CommodityLotDomain>> {
+        Ok(sqlx::query_as::<_, CommodityLotDomain>(
+            r#"
+                select datetime, amount, price_amount, price_commodity, account
+                from commodity_lots
+                where commodity = $1
+            "#,
+        )
+        .bind(commodity)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn documents(&mut self) -> ZhangResult<Vec<DocumentDomain>> {
+        Ok(sqlx::query_as::<_, DocumentDomain>(r#"select * from documents order by datetime"#)
+            .fetch_all(&mut *self.conn)
+            .await?)
+    }
+
+    async fn account_documents(&mut self, account_name: &str) -> ZhangResult<Vec<DocumentDomain>> {
+        Ok(sqlx::query_as::<_, DocumentDomain>(
+            r#"
+                select * from documents
+                where account = $1 or trx_id in (select trx_id from transaction_postings where account = $1)
+                order by datetime
+            "#,
+        )
+        .bind(account_name)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn transaction_documents(&mut self, trx_id: &str) -> ZhangResult<Vec<DocumentDomain>> {
+        Ok(
+            sqlx::query_as::<_, DocumentDomain>(r#"select * from documents where trx_id = $1 order by datetime"#)
+                .bind(trx_id)
+                .fetch_all(&mut *self.conn)
+                .await?,
+        )
+    }
+
+    async fn exist_account(&mut self, name: &str) -> ZhangResult<bool> {
+        Ok(sqlx::query("select 1 from accounts where name = $1")
+            .bind(name)
+            .fetch_optional(&mut *self.conn)
+            .await?
+            .is_some())
+    }
+
+    async fn accounts(&mut self) -> ZhangResult<Vec<AccountDomain>> {
+        Ok(
+            sqlx::query_as::<_, AccountDomain>(r#"select date, type, name, status, alias from accounts order by name"#)
+                .fetch_all(&mut *self.conn)
+                .await?,
+        )
+    }
+
+    async fn account(&mut self, account_name: &str) -> ZhangResult<Option<AccountDomain>> {
+        Ok(
+            sqlx::query_as::<_, AccountDomain>(r#"select date, type, name, status, alias from accounts where name = $1"#)
+                .bind(account_name)
+                .fetch_optional(&mut *self.conn)
+                .await?,
+        )
+    }
+
+    async fn journals(&mut self, filter: &JournalFilter, limit: u32, offset: u32) -> ZhangResult<(i64, Vec<JournalDomain>)> {
+        #[derive(FromRow)]
+        struct JournalRow {
+            id: String,
+            sequence: i64,
+            datetime: NaiveDateTime,
+            journal_type: String,
+            payee: String,
+            narration: Option<String>,
+        }
+        #[derive(FromRow)]
+        struct JournalPostingRow {
+            trx_id: String,
+            account: String,
+            unit_number: Option<ZhangBigDecimal>,
+            unit_commodity: Option<String>,
+            cost_number: Option<ZhangBigDecimal>,
+            cost_commodity: Option<String>,
+            price_number: Option<ZhangBigDecimal>,
+            price_commodity: Option<String>,
+            inferred_unit_number: ZhangBigDecimal,
+            inferred_unit_commodity: String,
+            account_before_number: ZhangBigDecimal,
+            account_before_commodity: String,
+            account_after_number: ZhangBigDecimal,
+            account_after_commodity: String,
+        }
+        let conn = &mut *self.conn;
+
+        fill_journal_page(conn, filter, limit, offset).await?;
+        let rows: Vec<JournalRow> = sqlx::query_as(
+            r#"select transactions.id, transactions.sequence, datetime, type as journal_type, payee, narration
+               from temp.journal_page page join transactions on transactions.id = page.id
+               order by page.sequence desc"#,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        let total_count = match sqlx::query_as::<_, (i64,)>("select total_count from temp.journal_page limit 1")
+            .fetch_optional(&mut *conn)
+            .await?
+        {
+            Some((total_count,)) => total_count,
+            None if offset == 0 => 0,
+            // the page is out of range, so the count has to be queried on its own
+            None => {
+                let mut query = QueryBuilder::new("select count(1) from transactions where 1 = 1");
+                push_journal_filter(&mut query, filter);
+                let (total_count,): (i64,) = query.build_query_as().fetch_one(&mut *conn).await?;
+                total_count
+            }
+        };
+        if rows.is_empty() {
+            return Ok((total_count, vec![]));
+        }
+
+        let mut tags = sqlx::query_as::<_, (String, String)>("select trx_id, tag from transaction_tags join temp.journal_page page on page.id = trx_id")
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .into_group_map();
+
+        let mut links = sqlx::query_as::<_, (String, String)>("select trx_id, link from transaction_links join temp.journal_page page on page.id = trx_id")
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .into_group_map();
+
+        let mut postings = sqlx::query_as::<_, JournalPostingRow>(
+            "select transaction_postings.* from transaction_postings join temp.journal_page page on page.id = trx_id order by transaction_postings.rowid",
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|it| (it.trx_id.clone(), it))
+        .into_group_map();
+
+        let mut metas = sqlx::query_as::<_, MetaDomain>(
+            r#"select type as meta_type, type_identifier, key, value from metas join temp.journal_page page on page.id = type_identifier where type = $1
+               union all
+               select type as meta_type, type_identifier, key, value from metas join temp.journal_page page
+                 on page.id = substr(type_identifier, 1, instr(type_identifier, ':') - 1) where type = $2"#,
+        )
+        .bind(MetaType::TransactionMeta.as_ref())
+        .bind(MetaType::PostingMeta.as_ref())
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|it| ((it.meta_type.clone(), it.type_identifier.clone()), it))
+        .into_group_map();
+        let posting_meta_type = MetaType::PostingMeta.as_ref().to_string();
+
+        let journals = rows
+            .into_iter()
+            .map(|row| {
+                let postings = postings
+                    .remove(&row.id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, posting)| JournalPostingDomain {
+                        metas: metas.remove(&(posting_meta_type.clone(), posting_identifier(&row.id, idx))).unwrap_or_default(),
+                        trx_id: posting.trx_id,
+                        account: posting.account,
+                        unit_number: posting.unit_number,
+                        unit_commodity: posting.unit_commodity,
+                        cost_number: posting.cost_number,
+                        cost_commodity: posting.cost_commodity,
+                        price_number: posting.price_number,
+                        price_commodity: posting.price_commodity,
+                        inferred_unit_number: posting.inferred_unit_number,
+                        inferred_unit_commodity: posting.inferred_unit_commodity,
+                        account_before_number: posting.account_before_number,
+                        account_before_commodity: posting.account_before_commodity,
+                        account_after_number: posting.account_after_number,
+                        account_after_commodity: posting.account_after_commodity,
+                    })
+                    .collect_vec();
+                JournalDomain {
+                    tags: tags.remove(&row.id).unwrap_or_default(),
+                    links: links.remove(&row.id).unwrap_or_default(),
+                    metas: metas
+                        .remove(&(MetaType::TransactionMeta.as_ref().to_string(), row.id.clone()))
+                        .unwrap_or_default(),
+                    postings,
+                    id: row.id,
+                    sequence: row.sequence,
+                    datetime: row.datetime,
+                    journal_type: row.journal_type,
+                    payee: row.payee,
+                    narration: row.narration,
+                }
+            })
+            .collect_vec();
+        Ok((total_count, journals))
+    }
+
+    async fn payees(&mut self) -> ZhangResult<Vec<String>> {
+        Ok(
+            sqlx::query_scalar(r#"select distinct payee from transactions where payee is not null and payee != ''"#)
+                .fetch_all(&mut *self.conn)
+                .await?,
+        )
+    }
+
+    async fn transaction_counts(&mut self) -> ZhangResult<i64> {
+        Ok(sqlx::query_as::<_, (i64,)>(r#"select count(1) from transactions"#)
+            .fetch_one(&mut *self.conn)
+            .await?
+            .0)
+    }
+
+    async fn dated_transaction_counts(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<i64> {
+        Ok(sqlx::query_as::<_, (i64,)>(
+            r#"
+                select count(1) as total
+                from transactions
+                where transactions."type" != 'BalancePad'
+                  and transactions."type" != 'BalanceCheck'
+                  and datetime >= $1
+                  and datetime <= $2
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_one(&mut *self.conn)
+        .await?
+        .0)
+    }
+
+    async fn transaction_span(&mut self, id: &str) -> ZhangResult<Option<TransactionInfoDomain>> {
+        Ok(
+            sqlx::query_as::<_, TransactionInfoDomain>(r#"select id, source_file, span_start, span_end from transactions where id = $1"#)
+                .bind(id)
+                .fetch_optional(&mut *self.conn)
+                .await?,
+        )
+    }
+
+    async fn label_usages(&mut self, label: Label) -> ZhangResult<Vec<LabelUsageDomain>> {
+        let (table, column) = label.table();
+        Ok(sqlx::query_as::<_, LabelUsageDomain>(&format!(
+            r#"
+                select labels.{column}                  as name,
+                       count(distinct labels.trx_id)    as count,
+                       min(transactions.datetime)       as first_datetime,
+                       max(transactions.datetime)       as last_datetime
+                from {table} labels
+                         join transactions on transactions.id = labels.trx_id
+                group by labels.{column}
+                order by count desc, name
+            "#
+        ))
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn label_period_totals(&mut self, label: Label, period_format: &str) -> ZhangResult<Vec<LabelPeriodTotalDomain>> {
+        let (table, column) = label.table();
+        Ok(sqlx::query_as::<_, LabelPeriodTotalDomain>(&format!(
+            r#"
+                select labels.{column}                                    as name,
+                       strftime($1, transactions.datetime)                as period,
+                       transaction_postings.inferred_unit_commodity       as commodity,
+                       total(transaction_postings.inferred_unit_number)   as amount
+                from (select distinct trx_id, {column} from {table}) labels
+                         join transactions on transactions.id = labels.trx_id
+                         join transaction_postings on transaction_postings.trx_id = labels.trx_id
+                         join accounts on accounts.name = transaction_postings.account
+                where accounts.type in ('Income', 'Expenses')
+                group by labels.{column}, period, transaction_postings.inferred_unit_commodity
+                order by labels.{column}, period, transaction_postings.inferred_unit_commodity
+            "#
+        ))
+        .bind(period_format)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn label_account_effects(&mut self, label: Label, name: &str) -> ZhangResult<Vec<LabelAccountEffectDomain>> {
+        let (table, column) = label.table();
+        Ok(sqlx::query_as::<_, LabelAccountEffectDomain>(&format!(
+            r#"
+                select account,
+                       inferred_unit_commodity      as commodity,
+                       total(inferred_unit_number)  as amount,
+                       count(distinct trx_id)       as transactions
+                from transaction_postings
+                where trx_id in (select trx_id from {table} where {column} = $1)
+                group by account, inferred_unit_commodity
+                order by account, inferred_unit_commodity
+            "#
+        ))
+        .bind(name)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn account_balances(&mut self) -> ZhangResult<Vec<AccountBalanceDomain>> {
+        Ok(sqlx::query_as::<_, AccountBalanceDomain>(
+            r#"
+                        select datetime, account, account_status, balance_number, balance_commodity
+                        from account_balance
+            "#,
+        )
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn single_account_balances(&mut self, account_name: &str) -> ZhangResult<Vec<AccountBalanceDomain>> {
+        Ok(sqlx::query_as::<_, AccountBalanceDomain>(
+            r#"
+                select datetime, account, account_status, balance_number, balance_commodity
+                from account_balance
+                where account = $1
+            "#,
+        )
+        .bind(account_name)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn account_daily_balances(&mut self, account_name: &str, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountDailyBalanceDomain>> {
+        Ok(sqlx::query_as::<_, AccountDailyBalanceDomain>(
+            r#"
+                select date(max(datetime)) as date, account, balance_number, balance_commodity
+                from account_daily_balance
+                where account = $1 and datetime < $2
+                group by balance_commodity
+                union all
+                select date(datetime) as date, account, balance_number, balance_commodity
+                from account_daily_balance
+                where account = $1 and datetime >= $2 and datetime <= $3
+                order by date
+            "#,
+        )
+        .bind(account_name)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn account_journals(&mut self, account: &str) -> ZhangResult<Vec<AccountJournalDomain>> {
+        Ok(sqlx::query_as::<_, AccountJournalDomain>(
+            r#"
+                    select datetime,
+                           trx_id,
+                           account,
+                           payee,
+                           narration,
+                           inferred_unit_number,
+                           inferred_unit_commodity,
+                           account_after_number,
+                           account_after_commodity
+                    from transaction_postings
+                             join transactions on transactions.id = transaction_postings.trx_id
+                    where account = $1
+                    order by datetime desc, transactions.sequence desc
+            "#,
+        )
+        .bind(account)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn account_dated_journals(&mut self, account_type: &str, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountJournalDomain>> {
+        Ok(sqlx::query_as::<_, AccountJournalDomain>(
+            r#"
+                select datetime,
+                       trx_id,
+                       account,
+                       payee,
+                       narration,
+                       inferred_unit_number,
+                       inferred_unit_commodity,
+                       account_after_number,
+                       account_after_commodity
+                from transaction_postings
+                         join transactions on transactions.id = transaction_postings.trx_id
+                         join accounts on accounts.name = transaction_postings.account
+                where datetime >= $1
+                  and datetime <= $2
+                  and accounts.type = $3
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .bind(account_type)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn account_type_totals(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<AccountTypeTotalDomain>> {
+        Ok(sqlx::query_as::<_, AccountTypeTotalDomain>(
+            r#"
+                SELECT accounts.type             AS account_type,
+                       sum(inferred_unit_number) AS amount,
+                       inferred_unit_commodity   AS commodity
+                FROM transaction_postings
+                         JOIN transactions ON transactions.id = transaction_postings.trx_id
+                         JOIN accounts ON accounts.name = transaction_postings.account
+                WHERE transactions.datetime >= $1 and transactions.datetime <= $2
+                GROUP BY
+                    accounts.type,
+                    inferred_unit_commodity
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn daily_account_type_totals(&mut self, from: NaiveDateTime, to: NaiveDateTime) -> ZhangResult<Vec<DailyAccountTypeTotalDomain>> {
+        Ok(sqlx::query_as::<_, DailyAccountTypeTotalDomain>(
+            r#"
+                SELECT
+                    date(datetime) AS date,
+                    accounts.type AS account_type,
+                    sum(inferred_unit_number) AS amount,
+                    inferred_unit_commodity AS commodity
+                FROM
+                    transaction_postings
+                    JOIN transactions ON transactions.id = transaction_postings.trx_id
+                    JOIN accounts ON accounts.name = transaction_postings.account
+                    where transactions.datetime >= $1 and transactions.datetime <= $2
+                GROUP BY
+                    date(datetime),
+                    accounts.type,
+                    inferred_unit_commodity
+                ORDER BY date
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&mut *self.conn)
+        .await?)
+    }
+
+    async fn errors(&mut self) -> ZhangResult<Vec<ErrorDomain>> {
+        #[derive(FromRow)]
+        struct ErrorRow {
+            pub id: String,
+            pub filename: Option<String>,
+            pub span_start: Option<i64>,
+            pub span_end: Option<i64>,
+            pub content: String,
+            pub error_type: ErrorType,
+            pub metas: String,
+        }
+
+        let rows = sqlx::query_as::<_, ErrorRow>(
+            r#"
+            select
+                id, filename, span_start, span_end, content, content, error_type, metas
+            from errors
+        "#,
+        )
+        .fetch_all(&mut *self.conn)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let span = match (row.span_start, row.span_end) {
+                    (Some(start), Some(end)) => Some(SpanInfo {
+                        start: start as usize,
+                        end: end as usize,
+                        content: row.content,
+                        filename: row.filename.map(PathBuf::from),
+                    }),
+                    _ => None,
+                };
+                ErrorDomain {
+                    id: row.id,
+                    span,
+                    error_type: row.error_type,
+                    metas: serde_json::from_str(&row.metas).unwrap(),
+                }
+            })
+            .collect_vec())
+    }
+
+    async fn new_error(&mut self, error_type: ErrorType, span: &SpanInfo, metas: HashMap<String, String>) -> ZhangResult<()> {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO
+                errors(id, filename, span_start, span_end, content, error_type, metas)
+            VALUES
+                ($1, $2, $3, $4, $5, $6, $7);
+        "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(span.filename.as_ref().and_then(|it| it.to_str()))
+        .bind(span.start as i64)
+        .bind(span.end as i64)
+        .bind(&span.content)
+        .bind(error_type)
+        .bind(serde_json::to_string(&metas).unwrap())
+        .execute(&mut *self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_or_update_options(&mut self, key: &str, value: &str) -> ZhangResult<()> {
+        sqlx::query(r#"INSERT OR REPLACE INTO options VALUES ($1, $2);"#)
+            .bind(key)
+            .bind(value)
+            .execute(&mut *self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_meta(&mut self, type_: MetaType, type_identifier: &str, meta: Meta) -> ZhangResult<()> {
+        for (meta_key, meta_value) in meta.get_flatten() {
+            sqlx::query(r#"INSERT OR REPLACE INTO metas VALUES ($1, $2, $3, $4);"#)
+                .bind(type_.as_ref())
+                .bind(type_identifier)
+                .bind(meta_key)
+                .bind(meta_value.as_str())
+                .execute(&mut *self.conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_account(&mut self, datetime: DateTime<Tz>, account_type: &str, name: &str, alias: Option<&str>) -> ZhangResult<()> {
+        sqlx::query(r#"INSERT OR REPLACE INTO accounts(date, type, name, status, alias) VALUES ($1, $2, $3, $4, $5);"#)
+            .bind(datetime)
+            .bind(account_type)
+            .bind(name)
+            .bind("Open")
+            .bind(alias)
+            .execute(&mut *self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn close_account(&mut self, account_name: &str) -> ZhangResult<()> {
+        sqlx::query(r#"update accounts set status = 'Close' where name = $1"#)
+            .bind(account_name)
+            .execute(&mut *self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_commodity(&mut self, commodity: &CommodityDomain) -> ZhangResult<()> {
+        sqlx::query(
+            r#"INSERT OR REPLACE INTO commodities (name, precision, prefix, suffix, rounding)
+                        VALUES ($1, $2, $3, $4, $5);"#,
+        )
+        .bind(&commodity.name)
+        .bind(commodity.precision)
+        .bind(&commodity.prefix)
+        .bind(&commodity.suffix)
+        .bind(&commodity.rounding)
+        .execute(&mut *self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_transaction(&mut self, transaction: &TransactionRecord) -> ZhangResult<()> {
+        sqlx::query(
+            r#"INSERT INTO transactions (id, datetime, type, payee, narration, source_file, span_start, span_end)VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+        )
+        .bind(&transaction.id)
+        .bind(transaction.datetime)
+        .bind(&transaction.flag)
+        .bind(&transaction.payee)
+        .bind(&transaction.narration)
+        .bind(&transaction.source_file)
+        .bind(transaction.span_start)
+        .bind(transaction.span_end)
+        .execute(&mut *self.conn)
+        .await?;
+
+        for tag in transaction.tags.iter() {
+            sqlx::query(r#"INSERT INTO transaction_tags (trx_id, tag)VALUES ($1, $2)"#)
+                .bind(&transaction.id)
+                .bind(tag)
+                .execute(&mut *self.conn)
+                .await?;
+        }
+        for link in transaction.links.iter() {
+            sqlx::query(r#"INSERT INTO transaction_links (trx_id, link)VALUES ($1, $2)"#)
+                .bind(&transaction.id)
+                .bind(link)
+                .execute(&mut *self.conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_posting(&mut self, posting: &PostingRecord) -> ZhangResult<()> {
+        sqlx::query(
+            r#"INSERT INTO transaction_postings
+                               (trx_id, account, unit_number, unit_commodity, cost_number, cost_commodity, price_number, price_commodity,
+                                inferred_unit_number, inferred_unit_commodity, account_before_number, account_before_commodity,
+                                account_after_number, account_after_commodity
+                               )
+                               VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)"#,
+        )
+        .bind(&posting.trx_id)
+        .bind(&posting.account)
+        .bind(posting.units.as_ref().map(|it| it.number.to_string()))
+        .bind(posting.units.as_ref().map(|it| &it.currency))
+        .bind(posting.cost.as_ref().map(|it| it.number.to_string()))
+        .bind(posting.cost.as_ref().map(|it| &it.currency))
+        .bind(posting.price.as_ref().map(|it| it.number.to_string()))
+        .bind(posting.price.as_ref().map(|it| &it.currency))
+        .bind(posting.inferred_amount.number.to_string())
+        .bind(&posting.inferred_amount.currency)
+        .bind(posting.before_number.to_string())
+        .bind(&posting.inferred_amount.currency)
+        .bind(posting.after_number.to_string())
+        .bind(&posting.inferred_amount.currency)
+        .execute(&mut *self.conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_document(&mut self, document: &DocumentRecord) -> ZhangResult<()> {
+        sqlx::query(r#"INSERT INTO documents (datetime, filename, path, extension, account, trx_id) VALUES ($1, $2, $3, $4, $5, $6);"#)
+            .bind(document.datetime)
+            .bind(&document.filename)
+            .bind(&document.path)
+            .bind(&document.extension)
+            .bind(&document.account)
+            .bind(&document.trx_id)
+            .execute(&mut *self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_price(&mut self, datetime: DateTime<Tz>, commodity: &str, amount: &Amount) -> ZhangResult<()> {
+        sqlx::query(r#"INSERT INTO prices (datetime, commodity, amount, target_commodity)VALUES ($1, $2, $3, $4)"#)
+            .bind(datetime)
+            .bind(commodity)
+            .bind(amount.number.to_string())
+            .bind(&amount.currency)
+            .execute(&mut *self.conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_lot(&mut self, account: &str, amount: &Amount, lot_info: &LotInfo) -> ZhangResult<()> {
+        let mut trx = self.conn.begin().await?;
+        match lot_info {
+            LotInfo::Lot(target_currency, lot_number) => {
+                let lot: Option<LotRow> = sqlx::query_as(
+                    r#"
+            select amount, price_amount, price_commodity
+            from commodity_lots
+            where account = $1 and commodity = $2 and price_amount = $3 and price_commodity = $4"#,
+                )
+                .bind(account)
+                .bind(&amount.currency)
+                .bind(lot_number.to_string())
+                .bind(target_currency)
+                .fetch_optional(&mut trx)
+                .await?;
+
+                if let Some(lot_row) = lot {
+                    sqlx::query(
+                        r#"update commodity_lots
+                        set amount = $1
+                        where account = $2 and commodity = $3  and price_amount = $4 and price_commodity = $5"#,
+                    )
+                    .bind(BigDecimal::from_f64(lot_row.amount).expect("error").add(&amount.number).to_string())
+                    .bind(account)
+                    .bind(&amount.currency)
+                    .bind(lot_number.to_string())
+                    .bind(target_currency)
+                    .execute(&mut trx)
+                    .await?;
+                } else {
+                    sqlx::query(
+                        r#"INSERT INTO commodity_lots (account, commodity, datetime, amount, price_amount, price_commodity)
+                                    VALUES ($1, $2, $3, $4, $5, $6)"#,
+                    )
+                    .bind(account)
+                    .bind(&amount.currency)
+                    .bind(None::<NaiveDateTime>)
+                    .bind(amount.number.to_string())
+                    .bind(lot_number.to_string())
+                    .bind(target_currency)
+                    .execute(&mut trx)
+                    .await?;
+                }
+            }
+            LotInfo::Fifo => {
+                let lot: Option<LotRow> = sqlx::query_as(
+                    r#"
+                select amount, price_amount, price_commodity
+                from commodity_lots
+                where account = $1 and commodity = $2
+                  and (price_commodity = $3 or price_commodity is null)
+                  and ((amount != 0 and price_amount is not null) or price_amount is null)
+                order by datetime desc
+            "#,
+                )
+                .bind(account)
+                .bind(&amount.currency)
+                .bind(&amount.currency)
+                .fetch_optional(&mut trx)
+                .await?;
+                if let Some(lot) = lot {
+                    if lot.price_amount.is_some() {
+                        // target lot
+                        sqlx::query(
+                            r#"update commodity_lots
+                        set amount = $1
+                        where account = $2 and commodity = $3  and price_amount = $4 and price_commodity = $5"#,
+                        )
+                        .bind(BigDecimal::from_f64(lot.amount).expect("error").add(&amount.number).to_string())
+                        .bind(account)
+                        .bind(&amount.currency)
+                        .bind(lot.price_amount)
+                        .bind(&lot.price_commodity)
+                        .execute(&mut trx)
+                        .await?;
+
+                        // todo check negative
+                    } else {
+                        // default lot
+                        sqlx::query(
+                            r#"update commodity_lots
+                        set amount = $1
+                        where account = $2 and commodity = $3  and price_amount is NULL and price_commodity is NULL"#,
+                        )
+                        .bind(BigDecimal::from_f64(lot.amount).expect("error").add(&amount.number).to_string())
+                        .bind(account)
+                        .bind(&amount.currency)
+                        .execute(&mut trx)
+                        .await?;
+                    }
+                } else {
+                    sqlx::query(
+                        r#"INSERT INTO commodity_lots (account, commodity, datetime, amount, price_amount, price_commodity)
+                                    VALUES ($1, $2, $3, $4, $5, $6)"#,
+                    )
+                    .bind(account)
+                    .bind(&amount.currency)
+                    .bind(None::<NaiveDateTime>)
+                    .bind(amount.number.to_string())
+                    .bind(None::<f64>)
+                    .bind(None::<String>)
+                    .execute(&mut trx)
+                    .await?;
+                }
+            }
+            LotInfo::Filo => {
+                unimplemented!()
+            }
+        }
+        trx.commit().await?;
+
+        Ok(())
+    }
+}
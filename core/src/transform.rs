@@ -4,8 +4,11 @@ use crate::{utils, ZhangError, ZhangResult};
 use glob::{glob, Pattern};
 use itertools::Itertools;
 use log::debug;
-use std::collections::{HashSet, VecDeque};
-use std::path::PathBuf;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use zhang_ast::{Directive, Spanned};
 
 pub struct TransformResult {
@@ -18,13 +21,61 @@ where
     Self: Send + Sync,
 {
     fn load(&self, entry: PathBuf, endpoint: String) -> ZhangResult<TransformResult>;
+
+    /// check whether the content of a single file can be transformed, without touching the file system
+    fn validate(&self, content: &str, path: PathBuf) -> ZhangResult<()>;
+}
+
+/// content hash and parsed outputs of a file
+type CachedFile<T> = (u64, Vec<T>);
+
+/// parsed outputs of each file with the hash of its content, so that unchanged files are not parsed again on reloading
+pub struct ParseCache<T> {
+    files: Arc<Mutex<HashMap<PathBuf, CachedFile<T>>>>,
+}
+
+impl<T> Default for ParseCache<T> {
+    fn default() -> Self {
+        ParseCache { files: Default::default() }
+    }
+}
+
+impl<T> Clone for ParseCache<T> {
+    fn clone(&self) -> Self {
+        ParseCache { files: self.files.clone() }
+    }
+}
+
+impl<T: Clone> ParseCache<T> {
+    fn get(&self, path: &Path, hash: u64) -> Option<Vec<T>> {
+        let files = self.files.lock().unwrap();
+        files
+            .get(path)
+            .filter(|(cached_hash, _)| *cached_hash == hash)
+            .map(|(_, outputs)| outputs.clone())
+    }
+
+    fn insert(&self, path: PathBuf, hash: u64, outputs: Vec<T>) {
+        self.files.lock().unwrap().insert(path, (hash, outputs));
+    }
+
+    /// drop the files which are no longer included
+    fn retain(&self, visited: &HashSet<PathBuf>) {
+        self.files.lock().unwrap().retain(|path, _| visited.contains(path));
+    }
+}
+
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
 }
 
 pub trait TextFileBasedTransformer
 where
     Self: Send + Sync,
 {
-    type FileOutput;
+    type FileOutput: Clone;
     fn get_file_content(&self, path: PathBuf) -> ZhangResult<String> {
         let content = std::fs::read_to_string(&path).with_path(&path)?;
         Ok(content)
@@ -32,6 +83,26 @@ where
     fn parse(&self, content: &str, path: PathBuf) -> ZhangResult<Vec<Self::FileOutput>>;
     fn go_next(&self, directive: &Self::FileOutput) -> Option<String>;
     fn transform(&self, directives: Vec<Self::FileOutput>) -> ZhangResult<Vec<Spanned<Directive>>>;
+
+    /// files are parsed every time if the transformer has no cache
+    fn parse_cache(&self) -> Option<&ParseCache<Self::FileOutput>> {
+        None
+    }
+
+    fn parse_with_cache(&self, content: &str, path: PathBuf) -> ZhangResult<Vec<Self::FileOutput>> {
+        let cache = match self.parse_cache() {
+            Some(cache) => cache,
+            None => return self.parse(content, path),
+        };
+        let hash = content_hash(content);
+        if let Some(outputs) = cache.get(&path, hash) {
+            debug!("file {} is not changed, skip parsing", path.display());
+            return Ok(outputs);
+        }
+        let outputs = self.parse(content, path.clone())?;
+        cache.insert(path, hash, outputs.clone());
+        Ok(outputs)
+    }
 }
 
 impl<T> Transformer for T
@@ -46,6 +117,7 @@ where
         load_queue.push_back(Pattern::new(main_endpoint.as_path().to_str().unwrap()).unwrap());
 
         let mut visited: HashSet<Pattern> = HashSet::new();
+        let mut visited_paths: HashSet<PathBuf> = HashSet::new();
         let mut directives = vec![];
         while let Some(load_entity) = load_queue.pop_front() {
             debug!("visited path pattern: {}", load_entity);
@@ -57,7 +129,8 @@ where
                             continue;
                         }
                         let file_content = self.get_file_content(path.clone())?;
-                        let entity_directives = self.parse(&file_content, path.clone())?;
+                        let entity_directives = self.parse_with_cache(&file_content, path.clone())?;
+                        visited_paths.insert(path.clone());
 
                         entity_directives.iter().filter_map(|directive| self.go_next(directive)).for_each(|buf| {
                             let fullpath = if buf.starts_with('/') {
@@ -79,21 +152,35 @@ where
             }
             visited.insert(load_entity);
         }
+        if let Some(cache) = self.parse_cache() {
+            cache.retain(&visited_paths);
+        }
         Ok(TransformResult {
             directives: self.transform(directives)?,
             visited_files: visited.into_iter().collect_vec(),
         })
     }
+
+    fn validate(&self, content: &str, path: PathBuf) -> ZhangResult<()> {
+        let directives = self.parse(content, path)?;
+        self.transform(directives).map(|_| ())
+    }
 }
 
 #[derive(Clone, Default)]
-pub struct TextTransformer {}
+pub struct TextTransformer {
+    cache: ParseCache<Spanned<Directive>>,
+}
 
 impl TextFileBasedTransformer for TextTransformer {
     type FileOutput = Spanned<Directive>;
 
+    fn parse_cache(&self) -> Option<&ParseCache<Self::FileOutput>> {
+        Some(&self.cache)
+    }
+
     fn parse(&self, content: &str, path: PathBuf) -> ZhangResult<Vec<Self::FileOutput>> {
-        parse(content, path).map_err(|it| ZhangError::PestError(it.to_string()))
+        parse(content, path).map_err(ZhangError::syntax_error)
     }
 
     fn go_next(&self, directive: &Self::FileOutput) -> Option<String> {
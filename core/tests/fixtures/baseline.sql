@@ -0,0 +1,165 @@
+-- schema of cache databases written before migrations are versioned
+
+create table if not exists options
+(
+    key   varchar not null primary key,
+    value varchar
+);
+
+create table if not exists prices
+(
+    datetime         datetime not null,
+    commodity        varchar  not null,
+    amount           REAL  not null,
+    target_commodity varchar  not null
+);
+
+
+create table accounts
+(
+    date   datetime           not null,
+    type   varchar            not null,
+    name   varchar            not null
+        primary key,
+    status varchar            not null,
+    alias  varchar
+);
+
+create table if not exists metas
+(
+    type            varchar not null,
+    type_identifier varchar not null,
+    key             varchar not null,
+    value           varchar
+);
+
+create table if not exists commodities
+(
+    name      varchar not null
+        constraint commodities_pk
+            primary key,
+    precision INTEGER,
+    prefix    varchar,
+    suffix    varchar,
+    rounding  varchar
+);
+
+create table if not exists commodity_lots
+(
+    commodity       varchar not null,
+    datetime        datetime,
+    amount          REAL,
+    price_amount    REAL,
+    price_commodity varchar,
+    account         varchar
+);
+
+
+create table if not exists documents
+(
+    datetime  datetime not null,
+    filename  varchar  not null,
+    path      varchar  not null,
+    extension varchar,
+    account   varchar,
+    trx_id    varchar
+);
+
+create table transactions
+(
+    sequence    integer  not null
+        primary key autoincrement
+        unique,
+    id          varchar  not null
+        unique,
+    datetime    datetime not null,
+    type        varchar,
+    payee       varchar,
+    narration   varchar,
+    source_file varchar  not null,
+    span_start  integer  not null,
+    span_end    integer  not null
+);
+
+create index transactions_id_index
+    on transactions (id);
+
+create table if not exists transaction_links
+(
+    trx_id varchar not null,
+    link   varchar not null
+);
+
+create table if not exists transaction_tags
+(
+    trx_id varchar not null,
+    tag    varchar not null
+);
+
+create table if not exists transaction_postings
+(
+    trx_id                   varchar not null,
+    account                  varchar not null,
+    unit_number              REAL,
+    unit_commodity           varchar,
+    cost_number              REAL,
+    cost_commodity           varchar,
+    price_number             REAL,
+    price_commodity          varchar,
+    inferred_unit_number     REAL,
+    inferred_unit_commodity  varchar,
+    account_before_number    REAL,
+    account_before_commodity varchar,
+    account_after_number     REAL,
+    account_after_commodity  varchar
+);
+
+CREATE VIEW if not exists account_balance as
+select transactions.datetime,
+       account_max_datetime.account,
+       account_max_datetime.account_status,
+       account_after_number                         as balance_number,
+       transaction_postings.account_after_commodity as balance_commodity
+from transactions
+         join transaction_postings on transactions.id = transaction_postings.trx_id
+
+         join (select datetime, transactions.id, account, accounts.status as account_status, account_after_commodity
+               from transaction_postings
+                        join transactions on transactions.id = transaction_postings.trx_id
+                        join accounts on accounts.name = transaction_postings.account
+               group by account, account_after_commodity
+               having max(sequence)) account_max_datetime
+              on transactions.id = account_max_datetime.id and
+                 transaction_postings.account = account_max_datetime.account
+                  and transaction_postings.account_after_commodity = account_max_datetime.account_after_commodity;
+
+CREATE VIEW if not exists account_daily_balance as
+select transactions.datetime,
+       account_max_datetime.account,
+       account_after_number                         as balance_number,
+       transaction_postings.account_after_commodity as balance_commodity
+from transactions
+         join transaction_postings on transactions.id = transaction_postings.trx_id
+
+         join (select datetime, transactions.id, account, account_after_commodity
+               from transaction_postings
+                        join transactions on transactions.id = transaction_postings.trx_id
+               group by account, account_after_commodity, date(datetime)
+having max(sequence)) account_max_datetime
+on transactions.id = account_max_datetime.id and
+    transaction_postings.account = account_max_datetime.account
+    and transaction_postings.account_after_commodity = account_max_datetime.account_after_commodity;
+
+create table errors
+(
+    id         varchat              not null
+        primary key
+        unique,
+    filename   varchar,
+    span_start integer,
+    span_end   integer,
+    content    varchar              not null,
+    error_type varchar              not null,
+    metas      varchar default '{}' not null
+);
+
@@ -1,13 +1,21 @@
 use itertools::Either;
 use zhang_ast::amount::Amount;
-use zhang_ast::{Account, Date, Directive, Meta};
+use zhang_ast::{Account, Date, Directive, Meta, ZhangString};
+
+/// beancount keeps the booking method of `open` directive, which is stored as meta of zhang's `Open`
+pub const BOOKING_METHOD_KEY: &str = "booking_method";
+
+/// booking methods accepted by beancount, for both `open` directive and `option "booking_method"`
+pub const BOOKING_METHODS: [&str; 7] = ["STRICT", "STRICT_WITH_SIZE", "FIFO", "LIFO", "HIFO", "AVERAGE", "NONE"];
 
 pub type BeancountDirective = Either<Directive, BeancountOnlyDirective>;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum BeancountOnlyDirective {
     PushTag(String),
     PopTag(String),
+    PushMeta(String, ZhangString),
+    PopMeta(String),
     Pad(PadDirective),
     Balance(BalanceDirective),
 }
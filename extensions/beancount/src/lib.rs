@@ -3,15 +3,15 @@ use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::PathBuf;
 
-use crate::directives::{BalanceDirective, BeancountDirective, BeancountOnlyDirective, PadDirective};
+use crate::directives::{BalanceDirective, BeancountDirective, BeancountOnlyDirective, PadDirective, BOOKING_METHODS, BOOKING_METHOD_KEY};
 use crate::parser::{parse, parse_time};
 use chrono::{Datelike, NaiveDate};
 use itertools::{Either, Itertools};
 use latestmap::LatestMap;
 use zhang_ast::*;
-use zhang_core::exporter::{append_meta, AppendableExporter, Exporter, TextExportable, TextExporter};
+use zhang_core::exporter::{append_meta, replace_span_content, AppendableExporter, Exporter, TextExportable, TextExporter};
 use zhang_core::ledger::Ledger;
-use zhang_core::transform::TextFileBasedTransformer;
+use zhang_core::transform::{ParseCache, TextFileBasedTransformer};
 use zhang_core::utils::has_path_visited;
 use zhang_core::{ZhangError, ZhangResult};
 
@@ -26,7 +26,9 @@ pub(crate) fn create_folder_if_not_exist(filename: &std::path::Path) {
 }
 
 #[derive(Clone, Default)]
-pub struct Beancount {}
+pub struct Beancount {
+    cache: ParseCache<Spanned<BeancountDirective>>,
+}
 
 impl Beancount {
     fn append_directive(&self, ledger: &Ledger, directive: Directive, file: Option<PathBuf>, check_file_visit: bool) -> ZhangResult<()> {
@@ -68,6 +70,10 @@ impl AppendableExporter for Beancount {
         }
         Ok(())
     }
+
+    fn replace_directive(&self, ledger: &Ledger, span: &SpanInfo, directive: Option<Directive>) -> ZhangResult<()> {
+        replace_span_content(ledger, span, directive.map(|it| self.export_directive(it)))
+    }
 }
 
 impl Exporter for Beancount {
@@ -77,6 +83,17 @@ impl Exporter for Beancount {
         let text_exporter = TextExporter {};
         let directive = convert_datetime_to_date(directive);
         match directive {
+            Directive::Open(mut open) => match open.meta.pop_one(BOOKING_METHOD_KEY) {
+                Some(booking_method) => {
+                    let mut line = vec![TextExportable::export(open.date), "open".to_string(), TextExportable::export(open.account)];
+                    if !open.commodities.is_empty() {
+                        line.push(open.commodities.iter().join(", "));
+                    }
+                    line.push(TextExportable::export(ZhangString::quote(booking_method.to_plain_string())));
+                    append_meta(open.meta, line.join(" "))
+                }
+                None => text_exporter.export_directive(Directive::Open(open)),
+            },
             Directive::Balance(balance) => match balance {
                 Balance::BalanceCheck(check) => {
                     let balance_directive = BalanceDirective {
@@ -104,7 +121,7 @@ impl Exporter for Beancount {
 
                         meta: pad.meta,
                     };
-                    vec![
+                    [
                         BeancountOnlyExportable::export(pad_directive),
                         BeancountOnlyExportable::export(balance_directive),
                     ]
@@ -122,7 +139,7 @@ trait BeancountOnlyExportable {
 
 impl BeancountOnlyExportable for BalanceDirective {
     fn export(self) -> String {
-        let line = vec![
+        let line = [
             TextExportable::export(self.date),
             "balance".to_string(),
             TextExportable::export(self.account),
@@ -135,7 +152,7 @@ impl BeancountOnlyExportable for BalanceDirective {
 
 impl BeancountOnlyExportable for PadDirective {
     fn export(self) -> String {
-        let line = vec![
+        let line = [
             TextExportable::export(self.date),
             "pad".to_string(),
             TextExportable::export(self.account),
@@ -209,6 +226,7 @@ fn convert_datetime_to_date(directive: Directive) -> Directive {
         Directive::Document(mut directive) => Directive::Document(convert_to_datetime!(directive)),
         Directive::Price(mut directive) => Directive::Price(convert_to_datetime!(directive)),
         Directive::Event(mut directive) => Directive::Event(convert_to_datetime!(directive)),
+        Directive::Query(mut directive) => Directive::Query(convert_to_datetime!(directive)),
         Directive::Custom(mut directive) => Directive::Custom(convert_to_datetime!(directive)),
         _ => directive,
     }
@@ -223,6 +241,16 @@ macro_rules! extract_time {
     }};
 }
 
+macro_rules! append_pushed_meta {
+    ($directive: tt, $metas: expr) => {{
+        for (key, value) in $metas {
+            if $directive.meta.get_one(key).is_none() {
+                $directive.meta.insert(key.clone(), value.clone());
+            }
+        }
+    }};
+}
+
 impl Beancount {
     fn extract_time_from_meta(&self, directive: &mut BeancountDirective) {
         match directive {
@@ -239,6 +267,7 @@ impl Beancount {
                 Directive::Document(directive) => extract_time!(directive),
                 Directive::Price(directive) => extract_time!(directive),
                 Directive::Event(directive) => extract_time!(directive),
+                Directive::Query(directive) => extract_time!(directive),
                 Directive::Custom(directive) => extract_time!(directive),
                 _ => {}
             },
@@ -249,13 +278,61 @@ impl Beancount {
             },
         }
     }
+
+    /// apply the metas pushed by `pushmeta` to directive, the meta written in directive itself takes precedence
+    fn append_pushed_meta(&self, directive: &mut BeancountDirective, metas: &[(String, ZhangString)]) {
+        match directive {
+            Either::Left(zhang_directive) => match zhang_directive {
+                Directive::Open(directive) => append_pushed_meta!(directive, metas),
+                Directive::Close(directive) => append_pushed_meta!(directive, metas),
+                Directive::Commodity(directive) => append_pushed_meta!(directive, metas),
+                Directive::Transaction(directive) => append_pushed_meta!(directive, metas),
+                Directive::Note(directive) => append_pushed_meta!(directive, metas),
+                Directive::Document(directive) => append_pushed_meta!(directive, metas),
+                Directive::Price(directive) => append_pushed_meta!(directive, metas),
+                Directive::Event(directive) => append_pushed_meta!(directive, metas),
+                Directive::Query(directive) => append_pushed_meta!(directive, metas),
+                Directive::Custom(directive) => append_pushed_meta!(directive, metas),
+                _ => {}
+            },
+            Either::Right(beancount_onyly_directive) => match beancount_onyly_directive {
+                BeancountOnlyDirective::Pad(directive) => append_pushed_meta!(directive, metas),
+                BeancountOnlyDirective::Balance(directive) => append_pushed_meta!(directive, metas),
+                _ => {}
+            },
+        }
+    }
+
+    /// value of `option "booking_method"`, the last one wins like other options
+    fn default_booking_method(&self, directives: &[Spanned<BeancountDirective>]) -> ZhangResult<Option<ZhangString>> {
+        let booking_method = directives.iter().rev().find_map(|it| match &it.data {
+            Either::Left(Directive::Option(option)) if option.key.as_str() == BOOKING_METHOD_KEY => Some(option.value.clone()),
+            _ => None,
+        });
+        if let Some(booking_method) = &booking_method {
+            validate_booking_method(booking_method)?;
+        }
+        Ok(booking_method)
+    }
+}
+
+fn validate_booking_method(booking_method: &ZhangString) -> ZhangResult<()> {
+    if BOOKING_METHODS.contains(&booking_method.as_str()) {
+        Ok(())
+    } else {
+        Err(ZhangError::InvalidBookingMethod(booking_method.as_str().to_owned()))
+    }
 }
 
 impl TextFileBasedTransformer for Beancount {
     type FileOutput = Spanned<BeancountDirective>;
 
+    fn parse_cache(&self) -> Option<&ParseCache<Self::FileOutput>> {
+        Some(&self.cache)
+    }
+
     fn parse(&self, content: &str, path: PathBuf) -> ZhangResult<Vec<Self::FileOutput>> {
-        parse(content, path).map_err(|it| ZhangError::PestError(it.to_string()))
+        parse(content, path).map_err(ZhangError::syntax_error)
     }
 
     fn go_next(&self, directive: &Self::FileOutput) -> Option<String> {
@@ -267,14 +344,32 @@ impl TextFileBasedTransformer for Beancount {
     fn transform(&self, directives: Vec<Self::FileOutput>) -> ZhangResult<Vec<Spanned<Directive>>> {
         let mut ret = vec![];
         let mut tags_stack: Vec<String> = vec![];
+        let mut meta_stack: Vec<(String, ZhangString)> = vec![];
+        let default_booking_method = self.default_booking_method(&directives)?;
 
         let mut pad_info: LatestMap<NaiveDate, HashMap<String, Account>> = LatestMap::default();
 
         for directives in directives {
             let Spanned { span, mut data } = directives;
             self.extract_time_from_meta(&mut data);
+            let pushed_metas = meta_stack.iter().rev().unique_by(|(key, _)| key).cloned().collect_vec();
+            self.append_pushed_meta(&mut data, &pushed_metas);
             match data {
                 Either::Left(zhang_directive) => match zhang_directive {
+                    Directive::Open(mut open) => {
+                        match open.meta.get_one(BOOKING_METHOD_KEY) {
+                            Some(booking_method) => validate_booking_method(booking_method)?,
+                            None => {
+                                if let Some(booking_method) = &default_booking_method {
+                                    open.meta.insert(BOOKING_METHOD_KEY.to_string(), booking_method.clone());
+                                }
+                            }
+                        }
+                        ret.push(Spanned {
+                            span,
+                            data: Directive::Open(open),
+                        });
+                    }
                     Directive::Transaction(mut trx) => {
                         for tag in &tags_stack {
                             trx.tags.insert(tag.to_owned());
@@ -288,7 +383,17 @@ impl TextFileBasedTransformer for Beancount {
                 },
                 Either::Right(beancount_directive) => match beancount_directive {
                     BeancountOnlyDirective::PushTag(tag) => tags_stack.push(tag),
-                    BeancountOnlyDirective::PopTag(tag) => tags_stack = tags_stack.into_iter().filter(|it| it.ne(&tag)).collect_vec(),
+                    BeancountOnlyDirective::PopTag(tag) => {
+                        if let Some(position) = tags_stack.iter().rposition(|it| it.eq(&tag)) {
+                            tags_stack.remove(position);
+                        }
+                    }
+                    BeancountOnlyDirective::PushMeta(key, value) => meta_stack.push((key, value)),
+                    BeancountOnlyDirective::PopMeta(key) => {
+                        if let Some(position) = meta_stack.iter().rposition(|(it, _)| it.eq(&key)) {
+                            meta_stack.remove(position);
+                        }
+                    }
                     BeancountOnlyDirective::Pad(pad) => {
                         let date = pad.date.naive_date();
                         if !pad_info.contains_key(&date) {
@@ -335,16 +440,17 @@ impl TextFileBasedTransformer for Beancount {
 
 #[cfg(test)]
 mod test {
-    use crate::directives::{BalanceDirective, BeancountDirective, BeancountOnlyDirective, PadDirective};
+    use crate::directives::{BalanceDirective, BeancountDirective, BeancountOnlyDirective, PadDirective, BOOKING_METHOD_KEY};
     use crate::{parse, Beancount};
     use bigdecimal::BigDecimal;
     use chrono::NaiveDate;
     use indoc::indoc;
     use std::str::FromStr;
     use zhang_ast::amount::Amount;
-    use zhang_ast::{Account, Balance, BalanceCheck, BalancePad, Date, Directive, Meta, Open, SpanInfo, Spanned, Transaction, ZhangString};
+    use zhang_ast::{Account, Balance, BalanceCheck, BalancePad, Close, Date, Directive, Meta, Open, SpanInfo, Spanned, Transaction, ZhangString};
     use zhang_core::exporter::Exporter;
     use zhang_core::transform::TextFileBasedTransformer;
+    use zhang_core::ZhangError;
 
     macro_rules! test_parse_zhang {
         ($content: expr) => {{
@@ -376,7 +482,7 @@ mod test {
             _ => unreachable!(),
         }
 
-        let beancount_exporter = Beancount {};
+        let beancount_exporter = Beancount::default();
         assert_eq!(
             indoc! {r#"
                 1970-01-01 open Assets:BankAccount
@@ -402,7 +508,7 @@ mod test {
             _ => unreachable!(),
         };
 
-        let beancount_exporter = Beancount {};
+        let beancount_exporter = Beancount::default();
         assert_eq!(
             indoc! {r#"
                 1970-01-01 pad Assets:BankAccount Equity:Open-Balances
@@ -474,6 +580,145 @@ mod test {
         }
     }
 
+    #[test]
+    fn should_only_pop_latest_pushed_tag_given_pop_tag_directive() {
+        let transformer = Beancount::default();
+        let mut directives = transformer
+            .transform(vec![
+                Spanned::new(BeancountDirective::Right(BeancountOnlyDirective::PushTag("onetag".to_string())), fake_span()),
+                Spanned::new(BeancountDirective::Right(BeancountOnlyDirective::PushTag("onetag".to_string())), fake_span()),
+                Spanned::new(BeancountDirective::Right(BeancountOnlyDirective::PopTag("onetag".to_string())), fake_span()),
+                Spanned::new(
+                    BeancountDirective::Left(Directive::Transaction(Transaction {
+                        date: Date::Date(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()),
+                        flag: None,
+                        payee: None,
+                        narration: None,
+                        tags: Default::default(),
+                        links: Default::default(),
+                        postings: vec![],
+                        meta: Default::default(),
+                    })),
+                    fake_span(),
+                ),
+            ])
+            .unwrap();
+
+        assert_eq!(directives.len(), 1);
+        let directive = directives.pop().unwrap().data;
+        match directive {
+            Directive::Transaction(mut trx) => assert_eq!("onetag", trx.tags.pop().unwrap()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_append_meta_to_directive_given_push_meta_directive() {
+        let transformer = Beancount::default();
+        let mut own_meta = Meta::default();
+        own_meta.insert("location".to_string(), ZhangString::quote("Paris"));
+        let mut directives = transformer
+            .transform(vec![
+                Spanned::new(
+                    BeancountDirective::Right(BeancountOnlyDirective::PushMeta("location".to_string(), ZhangString::quote("Beijing"))),
+                    fake_span(),
+                ),
+                Spanned::new(
+                    BeancountDirective::Right(BeancountOnlyDirective::PushMeta("trip".to_string(), ZhangString::quote("france"))),
+                    fake_span(),
+                ),
+                Spanned::new(
+                    BeancountDirective::Right(BeancountOnlyDirective::PushMeta("trip".to_string(), ZhangString::quote("china"))),
+                    fake_span(),
+                ),
+                Spanned::new(
+                    BeancountDirective::Left(Directive::Open(Open {
+                        date: Date::Date(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()),
+                        account: Account::from_str("Assets::BankAccount").unwrap(),
+                        commodities: vec![],
+                        meta: own_meta,
+                    })),
+                    fake_span(),
+                ),
+                Spanned::new(BeancountDirective::Right(BeancountOnlyDirective::PopMeta("trip".to_string())), fake_span()),
+                Spanned::new(BeancountDirective::Right(BeancountOnlyDirective::PopMeta("location".to_string())), fake_span()),
+                Spanned::new(
+                    BeancountDirective::Left(Directive::Close(Close {
+                        date: Date::Date(NaiveDate::from_ymd_opt(1970, 1, 2).unwrap()),
+                        account: Account::from_str("Assets::BankAccount").unwrap(),
+                        meta: Default::default(),
+                    })),
+                    fake_span(),
+                ),
+            ])
+            .unwrap();
+
+        assert_eq!(directives.len(), 2);
+
+        let mut close_meta = Meta::default();
+        close_meta.insert("trip".to_string(), ZhangString::quote("france"));
+        assert_eq!(
+            directives.pop().unwrap().data,
+            Directive::Close(Close {
+                date: Date::Date(NaiveDate::from_ymd_opt(1970, 1, 2).unwrap()),
+                account: Account::from_str("Assets::BankAccount").unwrap(),
+                meta: close_meta,
+            })
+        );
+
+        let mut open_meta = Meta::default();
+        open_meta.insert("location".to_string(), ZhangString::quote("Paris"));
+        open_meta.insert("trip".to_string(), ZhangString::quote("china"));
+        assert_eq!(
+            directives.pop().unwrap().data,
+            Directive::Open(Open {
+                date: Date::Date(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()),
+                account: Account::from_str("Assets::BankAccount").unwrap(),
+                commodities: vec![],
+                meta: open_meta,
+            })
+        );
+    }
+
+    #[test]
+    fn should_export_booking_method_of_open_directive() {
+        let directive = test_parse_zhang! {r#"1970-01-01 open Assets:Broker GOOG "FIFO""#};
+
+        let beancount_exporter = Beancount::default();
+        assert_eq!(r#"1970-01-01 open Assets:Broker GOOG "FIFO""#, beancount_exporter.export_directive(directive),);
+    }
+
+    #[test]
+    fn should_apply_booking_method_option_to_open_directive_without_booking_method() {
+        let transformer = Beancount::default();
+        let content = indoc! {r#"
+            1970-01-01 open Assets:Broker GOOG
+            1970-01-01 open Assets:Fund FUND "AVERAGE"
+            option "booking_method" "LIFO"
+        "#};
+        let directives = transformer.transform(parse(content, None).unwrap()).unwrap();
+
+        let booking_methods: Vec<_> = directives
+            .iter()
+            .filter_map(|it| match &it.data {
+                Directive::Open(open) => open.meta.get_one(BOOKING_METHOD_KEY).map(|it| it.as_str().to_owned()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec!["LIFO", "AVERAGE"], booking_methods);
+        assert_eq!(3, directives.len(), "option directive should be kept");
+    }
+
+    #[test]
+    fn should_reject_invalid_booking_method() {
+        let transformer = Beancount::default();
+        let result = transformer.transform(parse(r#"option "booking_method" "FILO""#, None).unwrap());
+        assert!(matches!(result, Err(ZhangError::InvalidBookingMethod(method)) if method == "FILO"));
+
+        let result = transformer.transform(parse(r#"1970-01-01 open Assets:Broker GOOG "fifo""#, None).unwrap());
+        assert!(matches!(result, Err(ZhangError::InvalidBookingMethod(method)) if method == "fifo"));
+    }
+
     #[test]
     fn should_transform_to_non_given_pad_directive() {
         let transformer = Beancount::default();
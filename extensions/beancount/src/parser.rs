@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 use std::str::FromStr;
 
-use crate::directives::{BalanceDirective, BeancountDirective, BeancountOnlyDirective, PadDirective};
+use crate::directives::{BalanceDirective, BeancountDirective, BeancountOnlyDirective, PadDirective, BOOKING_METHOD_KEY};
 use bigdecimal::BigDecimal;
 use chrono::{NaiveDate, NaiveTime};
 use itertools::{Either, Itertools};
@@ -94,29 +94,49 @@ impl BeancountParer {
         }))
     }
 
+    fn booking_method(input: Node) -> Result<ZhangString> {
+        let ret: ZhangString = match_nodes!(input.into_children();
+            [quote_string(method)] => method,
+        );
+        Ok(ret)
+    }
+
+    fn open_suffix(input: Node) -> Result<(Option<ZhangString>, Vec<(String, ZhangString)>)> {
+        let ret: (Option<ZhangString>, Vec<(String, ZhangString)>) = match_nodes!(input.into_children();
+            [] => (None, vec![]),
+            [booking_method(booking)] => (Some(booking), vec![]),
+            [commodity_meta(metas)] => (None, metas),
+            [booking_method(booking), commodity_meta(metas)] => (Some(booking), metas),
+        );
+        Ok(ret)
+    }
+
     fn open(input: Node) -> Result<Directive> {
-        let ret: (Date, Account, Vec<String>, Vec<(String, ZhangString)>) = match_nodes!(input.into_children();
-            [date(date), account_name(a), commodity_name(commodities).., commodity_meta(metas)] => (date, a, commodities.collect(), metas),
-            [date(date), account_name(a), commodity_name(commodities)..] => (date, a, commodities.collect(), vec![]),
-            [date(date), account_name(a), commodity_meta(metas)] => (date, a, vec![], metas),
+        let ret: (Date, Account, Vec<String>, (Option<ZhangString>, Vec<(String, ZhangString)>)) = match_nodes!(input.into_children();
+            [date(date), account_name(a), commodity_name(commodities).., open_suffix(suffix)] => (date, a, commodities.collect(), suffix),
         );
+        let (booking_method, metas) = ret.3;
 
-        let open = Open {
+        let mut open = Open {
             date: ret.0,
             account: ret.1,
             commodities: ret.2,
-            meta: ret.3.into_iter().collect(),
+            meta: metas.into_iter().collect(),
         };
+        if let Some(booking_method) = booking_method {
+            open.meta.insert(BOOKING_METHOD_KEY.to_string(), booking_method);
+        }
         Ok(Directive::Open(open))
     }
     fn close(input: Node) -> Result<Directive> {
-        let ret: (Date, Account) = match_nodes!(input.into_children();
-            [date(date), account_name(a)] => (date, a)
+        let ret: (Date, Account, Vec<(String, ZhangString)>) = match_nodes!(input.into_children();
+            [date(date), account_name(a)] => (date, a, vec![]),
+            [date(date), account_name(a), commodity_meta(metas)] => (date, a, metas),
         );
         Ok(Directive::Close(Close {
             date: ret.0,
             account: ret.1,
-            meta: Default::default(),
+            meta: ret.2.into_iter().collect(),
         }))
     }
 
@@ -335,14 +355,15 @@ impl BeancountParer {
     }
 
     fn custom(input: Node) -> Result<Directive> {
-        let ret: (Date, ZhangString, Vec<StringOrAccount>) = match_nodes!(input.into_children();
-            [date(date), string(module), string_or_account(options)..] => (date, module, options.collect()),
+        let ret: (Date, ZhangString, Vec<StringOrAccount>, Vec<(String, ZhangString)>) = match_nodes!(input.into_children();
+            [date(date), string(module), string_or_account(options).., commodity_meta(metas)] => (date, module, options.collect(), metas),
+            [date(date), string(module), string_or_account(options)..] => (date, module, options.collect(), vec![]),
         );
         Ok(Directive::Custom(Custom {
             date: ret.0,
             custom_type: ret.1,
             values: ret.2,
-            meta: Default::default(),
+            meta: ret.3.into_iter().collect(),
         }))
     }
 
@@ -355,8 +376,9 @@ impl BeancountParer {
     }
 
     fn note(input: Node) -> Result<Directive> {
-        let ret: (Date, Account, ZhangString) = match_nodes!(input.into_children();
-            [date(date), account_name(a), string(path)] => (date, a, path),
+        let ret: (Date, Account, ZhangString, Vec<(String, ZhangString)>) = match_nodes!(input.into_children();
+            [date(date), account_name(a), string(path)] => (date, a, path, vec![]),
+            [date(date), account_name(a), string(path), commodity_meta(metas)] => (date, a, path, metas),
         );
         Ok(Directive::Note(Note {
             date: ret.0,
@@ -364,48 +386,65 @@ impl BeancountParer {
             comment: ret.2,
             tags: None,
             links: None,
-            meta: Default::default(),
+            meta: ret.3.into_iter().collect(),
         }))
     }
 
     fn event(input: Node) -> Result<Directive> {
-        let ret: (Date, ZhangString, ZhangString) = match_nodes!(input.into_children();
-            [date(date), string(name), string(value)] => (date, name, value),
+        let ret: (Date, ZhangString, ZhangString, Vec<(String, ZhangString)>) = match_nodes!(input.into_children();
+            [date(date), string(name), string(value)] => (date, name, value, vec![]),
+            [date(date), string(name), string(value), commodity_meta(metas)] => (date, name, value, metas),
         );
         Ok(Directive::Event(Event {
             date: ret.0,
             event_type: ret.1,
             description: ret.2,
-            meta: Default::default(),
+            meta: ret.3.into_iter().collect(),
+        }))
+    }
+
+    fn query(input: Node) -> Result<Directive> {
+        let ret: (Date, ZhangString, ZhangString, Vec<(String, ZhangString)>) = match_nodes!(input.into_children();
+            [date(date), string(name), string(query_string)] => (date, name, query_string, vec![]),
+            [date(date), string(name), string(query_string), commodity_meta(metas)] => (date, name, query_string, metas),
+        );
+        Ok(Directive::Query(Query {
+            date: ret.0,
+            name: ret.1,
+            query_string: ret.2,
+            meta: ret.3.into_iter().collect(),
         }))
     }
 
     fn balance(input: Node) -> Result<BeancountOnlyDirective> {
-        let (date, account, amount, commodity): (Date, Account, BigDecimal, String) = match_nodes!(input.into_children();
-            [date(date), account_name(name), number(amount), commodity_name(commodity)] => (date, name, amount, commodity),
+        let (date, account, amount, commodity, metas): (Date, Account, BigDecimal, String, Vec<(String, ZhangString)>) = match_nodes!(input.into_children();
+            [date(date), account_name(name), number(amount), commodity_name(commodity)] => (date, name, amount, commodity, vec![]),
+            [date(date), account_name(name), number(amount), commodity_name(commodity), commodity_meta(metas)] => (date, name, amount, commodity, metas),
         );
         Ok(BeancountOnlyDirective::Balance(BalanceDirective {
             date,
             account,
             amount: Amount::new(amount, commodity),
-            meta: Default::default(),
+            meta: metas.into_iter().collect(),
         }))
     }
     fn pad(input: Node) -> Result<BeancountOnlyDirective> {
-        let (date, name, pad): (Date, Account, Account) = match_nodes!(input.into_children();
-            [date(date), account_name(name), account_name(pad)] => (date, name, pad),
+        let (date, name, pad, metas): (Date, Account, Account, Vec<(String, ZhangString)>) = match_nodes!(input.into_children();
+            [date(date), account_name(name), account_name(pad)] => (date, name, pad, vec![]),
+            [date(date), account_name(name), account_name(pad), commodity_meta(metas)] => (date, name, pad, metas),
         );
         Ok(BeancountOnlyDirective::Pad(PadDirective {
             date,
             account: name,
             pad,
-            meta: Default::default(),
+            meta: metas.into_iter().collect(),
         }))
     }
 
     fn document(input: Node) -> Result<Directive> {
-        let ret: (Date, Account, ZhangString) = match_nodes!(input.into_children();
-            [date(date), account_name(name), string(path)] => (date, name, path),
+        let ret: (Date, Account, ZhangString, Vec<(String, ZhangString)>) = match_nodes!(input.into_children();
+            [date(date), account_name(name), string(path)] => (date, name, path, vec![]),
+            [date(date), account_name(name), string(path), commodity_meta(metas)] => (date, name, path, metas),
         );
         Ok(Directive::Document(Document {
             date: ret.0,
@@ -413,19 +452,20 @@ impl BeancountParer {
             filename: ret.2,
             tags: None,
             links: None,
-            meta: Default::default(),
+            meta: ret.3.into_iter().collect(),
         }))
     }
 
     fn price(input: Node) -> Result<Directive> {
-        let ret: (Date, String, BigDecimal, String) = match_nodes!(input.into_children();
-            [date(date), commodity_name(source), number(price), commodity_name(target)] => (date, source, price, target)
+        let ret: (Date, String, BigDecimal, String, Vec<(String, ZhangString)>) = match_nodes!(input.into_children();
+            [date(date), commodity_name(source), number(price), commodity_name(target)] => (date, source, price, target, vec![]),
+            [date(date), commodity_name(source), number(price), commodity_name(target), commodity_meta(metas)] => (date, source, price, target, metas),
         );
         Ok(Directive::Price(Price {
             date: ret.0,
             currency: ret.1,
             amount: Amount::new(ret.2, ret.3),
-            meta: Default::default(),
+            meta: ret.4.into_iter().collect(),
         }))
     }
     fn push_tag(input: Node) -> Result<BeancountOnlyDirective> {
@@ -440,6 +480,18 @@ impl BeancountParer {
         );
         Ok(BeancountOnlyDirective::PopTag(ret))
     }
+    fn push_meta(input: Node) -> Result<BeancountOnlyDirective> {
+        let (key, value): (String, ZhangString) = match_nodes!(input.into_children();
+            [commodity_line(meta)] => meta
+        );
+        Ok(BeancountOnlyDirective::PushMeta(key, value))
+    }
+    fn pop_meta(input: Node) -> Result<BeancountOnlyDirective> {
+        let ret: String = match_nodes!(input.into_children();
+            [string(key)] => key.to_plain_string()
+        );
+        Ok(BeancountOnlyDirective::PopMeta(ret))
+    }
 
     fn item(input: Node) -> Result<(BeancountDirective, SpanInfo)> {
         let span = input.as_span();
@@ -457,11 +509,14 @@ impl BeancountParer {
             [include(item)]     => Either::Left(item),
             [note(item)]        => Either::Left(item),
             [event(item)]       => Either::Left(item),
+            [query(item)]       => Either::Left(item),
             [document(item)]    => Either::Left(item),
             [balance(item)]     => Either::Right(item), // balance
             [pad(item)]         => Either::Right(item), // pad
             [push_tag(item)]    => Either::Right(item),
             [pop_tag(item)]     => Either::Right(item),
+            [push_meta(item)]   => Either::Right(item),
+            [pop_meta(item)]    => Either::Right(item),
             [price(item)]       => Either::Left(item),
             [commodity(item)]   => Either::Left(item),
             [custom(item)]      => Either::Left(item),
@@ -521,7 +576,7 @@ mod test {
         use chrono::NaiveDate;
         use std::str::FromStr;
         use zhang_ast::amount::Amount;
-        use zhang_ast::{Account, Date};
+        use zhang_ast::{Account, Date, ZhangString};
 
         #[test]
         fn should_support_push_tag() {
@@ -534,6 +589,17 @@ mod test {
             assert_eq!(BeancountOnlyDirective::PopTag("mytag".to_string()), directive);
         }
 
+        #[test]
+        fn should_support_push_meta() {
+            let directive = parse(r#"pushmeta location: "Paris""#, None).unwrap().pop().unwrap().data.right().unwrap();
+            assert_eq!(BeancountOnlyDirective::PushMeta("location".to_string(), ZhangString::quote("Paris")), directive);
+        }
+        #[test]
+        fn should_support_pop_meta() {
+            let directive = parse("popmeta location:", None).unwrap().pop().unwrap().data.right().unwrap();
+            assert_eq!(BeancountOnlyDirective::PopMeta("location".to_string()), directive);
+        }
+
         #[test]
         fn should_parse_balance() {
             let directive = parse("1970-01-01 balance Assets:BankAccount 2 CNY", None)
@@ -573,4 +639,85 @@ mod test {
             );
         }
     }
+
+    mod directive {
+        use crate::directives::{BalanceDirective, BeancountOnlyDirective, BOOKING_METHOD_KEY};
+        use crate::parser::parse;
+        use bigdecimal::BigDecimal;
+        use chrono::NaiveDate;
+        use indoc::indoc;
+        use std::str::FromStr;
+        use zhang_ast::amount::Amount;
+        use zhang_ast::{Account, Date, Directive, Meta, Open, Query, ZhangString};
+
+        #[test]
+        fn should_parse_query() {
+            let directive = parse(r#"1970-01-01 query "cash" "SELECT account, sum(position) WHERE account ~ 'Cash'""#, None)
+                .unwrap()
+                .pop()
+                .unwrap()
+                .data
+                .left()
+                .unwrap();
+            assert_eq!(
+                Directive::Query(Query {
+                    date: Date::Date(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()),
+                    name: ZhangString::quote("cash"),
+                    query_string: ZhangString::quote("SELECT account, sum(position) WHERE account ~ 'Cash'"),
+                    meta: Default::default(),
+                }),
+                directive
+            );
+        }
+
+        #[test]
+        fn should_keep_booking_method_of_open_into_meta() {
+            let directive = parse(r#"1970-01-01 open Assets:Broker GOOG, AAPL "FIFO""#, None)
+                .unwrap()
+                .pop()
+                .unwrap()
+                .data
+                .left()
+                .unwrap();
+            let mut meta = Meta::default();
+            meta.insert(BOOKING_METHOD_KEY.to_string(), ZhangString::quote("FIFO"));
+            assert_eq!(
+                Directive::Open(Open {
+                    date: Date::Date(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()),
+                    account: Account::from_str("Assets:Broker").unwrap(),
+                    commodities: vec!["GOOG".to_string(), "AAPL".to_string()],
+                    meta,
+                }),
+                directive
+            );
+        }
+
+        #[test]
+        fn should_parse_meta_of_balance() {
+            let directive = parse(
+                indoc! {r#"
+                    1970-01-01 balance Assets:BankAccount 2 CNY
+                      source: "statement"
+                "#},
+                None,
+            )
+            .unwrap()
+            .pop()
+            .unwrap()
+            .data
+            .right()
+            .unwrap();
+            let mut meta = Meta::default();
+            meta.insert("source".to_string(), ZhangString::quote("statement"));
+            assert_eq!(
+                BeancountOnlyDirective::Balance(BalanceDirective {
+                    date: Date::Date(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()),
+                    account: Account::from_str("Assets:BankAccount").unwrap(),
+                    amount: Amount::new(BigDecimal::from(2i32), "CNY"),
+                    meta,
+                }),
+                directive
+            );
+        }
+    }
 }
@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use beancount::parser::parse;
+use beancount::Beancount;
+use itertools::Itertools;
+use zhang_ast::Directive;
+use zhang_core::exporter::Exporter;
+use zhang_core::transform::TextFileBasedTransformer;
+
+fn load(content: &str) -> Vec<Directive> {
+    let transformer = Beancount::default();
+    let directives = parse(content, None).unwrap_or_else(|e| panic!("cannot parse content: {}\n{}", e, content));
+    transformer.transform(directives).unwrap().into_iter().map(|it| it.data).collect_vec()
+}
+
+fn assert_round_trip(path: PathBuf) {
+    let content = std::fs::read_to_string(&path).unwrap();
+    let directives = load(&content);
+    assert!(!directives.is_empty(), "{} should contain directives", path.display());
+
+    let exporter = Beancount::default();
+    let exported = directives.iter().cloned().map(|it| exporter.export_directive(it)).join("\n");
+    let reparsed_directives = load(&exported);
+
+    assert_eq!(
+        directives.len(),
+        reparsed_directives.len(),
+        "directive count changed after round trip of {}",
+        path.display()
+    );
+    for (origin, reparsed) in directives.into_iter().zip(reparsed_directives) {
+        assert_eq!(origin, reparsed, "directive changed after round trip of {}", path.display());
+    }
+}
+
+#[test]
+fn should_round_trip_directives_corpus() {
+    assert_round_trip(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/directives.bean"));
+}
+
+#[test]
+fn should_round_trip_example_ledger() {
+    assert_round_trip(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/example.bean"));
+}
@@ -0,0 +1,139 @@
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+use crate::{GnuCashError, GnuCashResult};
+
+/// the in-memory representation of a gnucash book, shared by xml and sqlite backend
+#[derive(Debug, Default)]
+pub struct Book {
+    pub commodities: Vec<Commodity>,
+    pub prices: Vec<Price>,
+    pub accounts: Vec<Account>,
+    pub transactions: Vec<Transaction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CommodityRef {
+    pub namespace: String,
+    pub mnemonic: String,
+}
+
+impl CommodityRef {
+    /// gnucash uses `template` namespace for scheduled transactions, which is not a real commodity
+    pub fn is_template(&self) -> bool {
+        self.namespace.eq_ignore_ascii_case("template")
+    }
+
+    /// currencies are in `CURRENCY` namespace, which is named `ISO4217` by earlier versions of gnucash
+    pub fn is_currency(&self) -> bool {
+        self.namespace.eq_ignore_ascii_case("currency") || self.namespace.eq_ignore_ascii_case("iso4217")
+    }
+}
+
+#[derive(Debug)]
+pub struct Commodity {
+    pub id: CommodityRef,
+    pub name: Option<String>,
+    pub fraction: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct Price {
+    pub commodity: CommodityRef,
+    pub currency: CommodityRef,
+    pub date: NaiveDate,
+    pub value: BigDecimal,
+}
+
+#[derive(Debug)]
+pub struct Account {
+    pub guid: String,
+    pub name: String,
+    pub account_type: String,
+    pub commodity: Option<CommodityRef>,
+    pub parent: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Account {
+    pub fn is_root(&self) -> bool {
+        self.account_type.eq("ROOT")
+    }
+}
+
+#[derive(Debug)]
+pub struct Transaction {
+    pub guid: String,
+    pub currency: CommodityRef,
+    pub num: Option<String>,
+    pub date: NaiveDate,
+    pub description: Option<String>,
+    pub splits: Vec<Split>,
+}
+
+#[derive(Debug)]
+pub struct Split {
+    pub account: String,
+    pub memo: Option<String>,
+    /// amount in transaction's currency
+    pub value: BigDecimal,
+    /// amount in account's commodity
+    pub quantity: BigDecimal,
+}
+
+/// gnucash persists amount as rational number like `12345/100`
+pub(crate) fn parse_rational(content: &str) -> GnuCashResult<BigDecimal> {
+    let content = content.trim();
+    let invalid = || GnuCashError::InvalidBook(format!("invalid number: {}", content));
+    match content.split_once('/') {
+        Some((num, denom)) => {
+            let num = BigDecimal::from_str(num).map_err(|_| invalid())?;
+            let denom = BigDecimal::from_str(denom).map_err(|_| invalid())?;
+            if denom.is_zero() {
+                return Err(invalid());
+            }
+            Ok(num / denom)
+        }
+        None => BigDecimal::from_str(content).map_err(|_| invalid()),
+    }
+}
+
+/// parse the date part of gnucash timestamp.
+///
+/// xml backend uses `2020-01-15 10:59:00 +0000`, sqlite backend uses `2020-01-15 10:59:00` or `20200115105900`
+pub(crate) fn parse_date(content: &str) -> GnuCashResult<NaiveDate> {
+    let content = content.trim();
+    DateTime::parse_from_str(content, "%Y-%m-%d %H:%M:%S %z")
+        .map(|it| it.date_naive())
+        .or_else(|_| NaiveDateTime::parse_from_str(content, "%Y-%m-%d %H:%M:%S").map(|it| it.date()))
+        .or_else(|_| NaiveDateTime::parse_from_str(content, "%Y%m%d%H%M%S").map(|it| it.date()))
+        .or_else(|_| NaiveDate::parse_from_str(content, "%Y-%m-%d"))
+        .map_err(|_| GnuCashError::InvalidBook(format!("invalid date: {}", content)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::book::{parse_date, parse_rational};
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+    use std::str::FromStr;
+
+    #[test]
+    fn should_parse_rational_number() {
+        assert_eq!(BigDecimal::from_str("123.45").unwrap(), parse_rational("12345/100").unwrap());
+        assert_eq!(BigDecimal::from_str("-1.5").unwrap(), parse_rational("-3/2").unwrap());
+        assert_eq!(BigDecimal::from_str("7").unwrap(), parse_rational("7").unwrap());
+        assert!(parse_rational("1/0").is_err());
+    }
+
+    #[test]
+    fn should_parse_date_of_both_backend() {
+        let date = NaiveDate::from_ymd_opt(2020, 1, 15).unwrap();
+        assert_eq!(date, parse_date("2020-01-15 10:59:00 +0000").unwrap());
+        assert_eq!(date, parse_date("2020-01-15 00:00:00 +0800").unwrap());
+        assert_eq!(date, parse_date("2020-01-15 10:59:00").unwrap());
+        assert_eq!(date, parse_date("20200115105900").unwrap());
+    }
+}
@@ -0,0 +1,512 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Datelike, NaiveDate};
+use flate2::read::GzDecoder;
+use itertools::Itertools;
+use log::{info, warn};
+use thiserror::Error;
+use zhang_ast::amount::Amount;
+use zhang_ast::*;
+use zhang_core::exporter::{Exporter, TextExporter};
+
+use crate::book::{Book, CommodityRef};
+
+pub mod book;
+pub mod sqlite;
+pub mod xml;
+
+#[derive(Debug, Error)]
+pub enum GnuCashError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+    #[error("invalid gnucash book: {0}")]
+    InvalidBook(String),
+    #[error("unsupported gnucash file: {0}")]
+    UnsupportedFormat(PathBuf),
+    #[error("zhang project already exists: {0}")]
+    ProjectExists(PathBuf),
+    #[error("gnucash commodities {0} and {1} are both imported as {2}")]
+    CommodityConflict(String, String, String),
+}
+
+pub type GnuCashResult<T> = Result<T, GnuCashError>;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+/// load gnucash book from file, both (gzipped) xml and sqlite backend are supported.
+pub async fn load(path: &Path) -> GnuCashResult<Book> {
+    let content = std::fs::read(path)?;
+    if content.starts_with(SQLITE_MAGIC) {
+        info!("loading gnucash sqlite book {}", path.display());
+        sqlite::load(path).await
+    } else if content.starts_with(GZIP_MAGIC) {
+        info!("loading gnucash compressed xml book {}", path.display());
+        let mut xml_content = String::new();
+        GzDecoder::new(content.as_slice()).read_to_string(&mut xml_content)?;
+        xml::load(&xml_content)
+    } else if content.iter().find(|it| !it.is_ascii_whitespace()) == Some(&b'<') {
+        info!("loading gnucash xml book {}", path.display());
+        xml::load(&String::from_utf8_lossy(&content))
+    } else {
+        Err(GnuCashError::UnsupportedFormat(path.to_path_buf()))
+    }
+}
+
+/// replace the chars which cannot be used in zhang account component
+fn account_component(name: &str) -> String {
+    let component: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '"' | ':' | '(' | ')' | ',' | '#' | '^' | ';' => '-',
+            c if c.is_whitespace() => '-',
+            c => c,
+        })
+        .collect();
+    if component.is_empty() {
+        "Unnamed".to_string()
+    } else {
+        component
+    }
+}
+
+fn commodity_name(commodity: &CommodityRef) -> String {
+    let name: String = commodity
+        .mnemonic
+        .trim()
+        .to_uppercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '\'') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("C{}", name),
+    }
+}
+
+/// zhang names of all commodities referred by the book.
+///
+/// commodities sharing one mnemonic in different namespaces, like `NASDAQ:ABC` and `FUND:ABC`, are qualified by their namespace,
+/// except the one of currency namespace, otherwise their prices and lots would be merged.
+struct CommodityNames(HashMap<CommodityRef, String>);
+
+impl CommodityNames {
+    fn of(book: &Book) -> GnuCashResult<CommodityNames> {
+        let commodities = book
+            .commodities
+            .iter()
+            .map(|it| &it.id)
+            .chain(book.prices.iter().flat_map(|it| [&it.commodity, &it.currency]))
+            .chain(book.accounts.iter().filter_map(|it| it.commodity.as_ref()))
+            .chain(book.transactions.iter().map(|it| &it.currency))
+            .filter(|it| !it.is_template())
+            .unique()
+            .map(|it| (commodity_name(it), it))
+            .into_group_map();
+
+        let mut names: HashMap<CommodityRef, String> = HashMap::new();
+        let mut owners: HashMap<String, &CommodityRef> = HashMap::new();
+        for (name, group) in commodities.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+            let is_shared = group.len() > 1;
+            for commodity in group.into_iter().sorted_by_key(|it| (&it.namespace, &it.mnemonic)) {
+                let name = if is_shared && !commodity.is_currency() {
+                    let qualified = commodity_name(&CommodityRef {
+                        namespace: commodity.namespace.clone(),
+                        mnemonic: format!("{}-{}", commodity.namespace, commodity.mnemonic),
+                    });
+                    warn!(
+                        "commodity {}:{} is imported as {} since {} is used by other namespaces",
+                        commodity.namespace, commodity.mnemonic, qualified, name
+                    );
+                    qualified
+                } else {
+                    name.clone()
+                };
+                if let Some(owner) = owners.insert(name.clone(), commodity) {
+                    return Err(GnuCashError::CommodityConflict(
+                        format!("{}:{}", owner.namespace, owner.mnemonic),
+                        format!("{}:{}", commodity.namespace, commodity.mnemonic),
+                        name,
+                    ));
+                }
+                names.insert(commodity.clone(), name);
+            }
+        }
+        Ok(CommodityNames(names))
+    }
+
+    fn get(&self, commodity: &CommodityRef) -> String {
+        self.0.get(commodity).cloned().unwrap_or_else(|| commodity_name(commodity))
+    }
+}
+
+fn account_type(gnucash_type: &str) -> Option<AccountType> {
+    match gnucash_type {
+        "ASSET" | "BANK" | "CASH" | "STOCK" | "MUTUAL" | "RECEIVABLE" | "CURRENCY" => Some(AccountType::Assets),
+        "LIABILITY" | "CREDIT" | "PAYABLE" => Some(AccountType::Liabilities),
+        "INCOME" => Some(AccountType::Income),
+        "EXPENSE" => Some(AccountType::Expenses),
+        "EQUITY" | "TRADING" => Some(AccountType::Equity),
+        _ => None,
+    }
+}
+
+/// build zhang account name of all gnucash accounts, keyed by account guid.
+///
+/// the top level gnucash account is dropped if its name is the same as zhang's account type, e.g. `Assets:Current Assets:Checking`
+/// is mapped to `Assets:Current-Assets:Checking` instead of `Assets:Assets:Current-Assets:Checking`
+fn account_names(book: &Book) -> HashMap<String, Account> {
+    let accounts: HashMap<&str, &book::Account> = book.accounts.iter().map(|it| (it.guid.as_str(), it)).collect();
+    let mut ret = HashMap::new();
+    for account in book.accounts.iter() {
+        if account.is_root() || account.commodity.as_ref().map(|it| it.is_template()).unwrap_or(false) {
+            continue;
+        }
+        let account_type = match account_type(&account.account_type) {
+            Some(account_type) => account_type,
+            None => {
+                warn!("skip gnucash account {} with unknown type {}", account.name, account.account_type);
+                continue;
+            }
+        };
+        let mut components = vec![account_component(&account.name)];
+        let mut parent = account.parent.as_deref().and_then(|it| accounts.get(it));
+        while let Some(parent_account) = parent {
+            if parent_account.is_root() {
+                break;
+            }
+            components.insert(0, account_component(&parent_account.name));
+            parent = parent_account.parent.as_deref().and_then(|it| accounts.get(it));
+        }
+        let type_name = account_type.to_string().to_lowercase();
+        let first_component = components[0].to_lowercase();
+        if components.len() > 1 && (first_component == type_name || format!("{}s", first_component) == type_name) {
+            components.remove(0);
+        }
+        let content = format!("{}:{}", account_type, components.join(":"));
+        match Account::from_str(&content) {
+            Ok(zhang_account) => {
+                ret.insert(account.guid.clone(), zhang_account);
+            }
+            Err(_) => warn!("skip gnucash account {} since it cannot be converted to zhang account", account.name),
+        }
+    }
+    ret
+}
+
+/// convert gnucash fraction like `100` into precision `2`
+fn precision(fraction: i64) -> Option<usize> {
+    let fraction = fraction.to_string();
+    if fraction.starts_with('1') && fraction.chars().skip(1).all(|c| c == '0') {
+        Some(fraction.len() - 1)
+    } else {
+        None
+    }
+}
+
+/// the currency used by most transactions, which would be the operating currency of zhang project
+pub fn operating_currency(book: &Book) -> GnuCashResult<Option<String>> {
+    let names = CommodityNames::of(book)?;
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for transaction in book.transactions.iter() {
+        *counts.entry(names.get(&transaction.currency)).or_default() += 1;
+    }
+    Ok(counts.into_iter().max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0))).map(|it| it.0))
+}
+
+/// map gnucash book onto zhang directives
+pub fn convert(book: &Book) -> GnuCashResult<Vec<Directive>> {
+    let names = CommodityNames::of(book)?;
+    let accounts = account_names(book);
+    let account_commodities: HashMap<&str, &CommodityRef> = book
+        .accounts
+        .iter()
+        .filter_map(|account| account.commodity.as_ref().map(|commodity| (account.guid.as_str(), commodity)))
+        .collect();
+
+    let earliest_date = book
+        .transactions
+        .iter()
+        .map(|it| it.date)
+        .chain(book.prices.iter().map(|it| it.date))
+        .min()
+        .unwrap_or_else(|| NaiveDate::from_ymd_opt(1970, 1, 1).unwrap());
+
+    let mut account_open_dates: HashMap<&str, NaiveDate> = HashMap::new();
+    let mut commodity_first_dates: HashMap<String, NaiveDate> = HashMap::new();
+    for transaction in book.transactions.iter() {
+        let mut update_commodity = |commodity: &CommodityRef| {
+            let first_date = commodity_first_dates.entry(names.get(commodity)).or_insert(transaction.date);
+            *first_date = (*first_date).min(transaction.date);
+        };
+        update_commodity(&transaction.currency);
+        for split in transaction.splits.iter() {
+            let open_date = account_open_dates.entry(split.account.as_str()).or_insert(transaction.date);
+            *open_date = (*open_date).min(transaction.date);
+            if let Some(commodity) = account_commodities.get(split.account.as_str()) {
+                update_commodity(commodity);
+            }
+        }
+    }
+    for price in book.prices.iter() {
+        for commodity in [&price.commodity, &price.currency] {
+            let first_date = commodity_first_dates.entry(names.get(commodity)).or_insert(price.date);
+            *first_date = (*first_date).min(price.date);
+        }
+    }
+
+    let mut directives = vec![];
+
+    for commodity in book.commodities.iter().filter(|it| !it.id.is_template()) {
+        let name = names.get(&commodity.id);
+        let mut meta = Meta::default();
+        if let Some(full_name) = &commodity.name {
+            meta.insert("name".to_string(), ZhangString::quote(full_name));
+        }
+        if let Some(precision) = commodity.fraction.and_then(precision) {
+            meta.insert("precision".to_string(), ZhangString::UnquoteString(precision.to_string()));
+        }
+        directives.push(Directive::Commodity(Commodity {
+            date: Date::Date(commodity_first_dates.get(&name).cloned().unwrap_or(earliest_date)),
+            currency: name,
+            meta,
+        }));
+    }
+
+    for account in book.accounts.iter() {
+        let zhang_account = match accounts.get(&account.guid) {
+            Some(zhang_account) => zhang_account,
+            None => continue,
+        };
+        let mut meta = Meta::default();
+        if let Some(description) = &account.description {
+            meta.insert("description".to_string(), ZhangString::quote(description));
+        }
+        directives.push(Directive::Open(Open {
+            date: Date::Date(account_open_dates.get(account.guid.as_str()).cloned().unwrap_or(earliest_date)),
+            account: zhang_account.clone(),
+            commodities: account.commodity.iter().map(|it| names.get(it)).collect_vec(),
+            meta,
+        }));
+    }
+
+    for price in book.prices.iter().filter(|it| !it.commodity.is_template()) {
+        directives.push(Directive::Price(Price {
+            date: Date::Date(price.date),
+            currency: names.get(&price.commodity),
+            amount: Amount::new(price.value.clone(), names.get(&price.currency)),
+            meta: Default::default(),
+        }));
+    }
+
+    for transaction in book.transactions.iter().sorted_by_key(|it| it.date) {
+        let currency = names.get(&transaction.currency);
+        let mut postings = vec![];
+        for split in transaction.splits.iter() {
+            let account = match accounts.get(&split.account) {
+                Some(account) => account,
+                None => {
+                    warn!("skip split of transaction {} since its account cannot be found", transaction.guid);
+                    continue;
+                }
+            };
+            let commodity = account_commodities
+                .get(split.account.as_str())
+                .map(|it| names.get(it))
+                .unwrap_or_else(|| currency.clone());
+            let price = if commodity != currency && !split.quantity.is_zero() {
+                let single_price: BigDecimal = (&split.value / &split.quantity).with_scale(12).normalized();
+                Some(SingleTotalPrice::Single(Amount::new(single_price, currency.clone())))
+            } else {
+                None
+            };
+            let mut meta = Meta::default();
+            if let Some(memo) = &split.memo {
+                meta.insert("memo".to_string(), ZhangString::quote(memo));
+            }
+            postings.push(Posting {
+                flag: None,
+                account: account.clone(),
+                units: Some(Amount::new(split.quantity.clone(), commodity)),
+                cost: None,
+                cost_date: None,
+                price,
+                meta,
+            });
+        }
+        if postings.is_empty() {
+            continue;
+        }
+        let mut meta = Meta::default();
+        if let Some(num) = &transaction.num {
+            meta.insert("num".to_string(), ZhangString::quote(num));
+        }
+        directives.push(Directive::Transaction(Transaction {
+            date: Date::Date(transaction.date),
+            flag: Some(Flag::Okay),
+            payee: None,
+            narration: Some(ZhangString::quote(transaction.description.clone().unwrap_or_default())),
+            tags: Default::default(),
+            links: Default::default(),
+            postings,
+            meta,
+        }));
+    }
+    Ok(directives)
+}
+
+/// import gnucash book into a fresh zhang project, the directives are written into `data/{year}/{month}.zhang` and included
+/// by main file
+pub async fn import(source: &Path, target: &Path, endpoint: &str) -> GnuCashResult<()> {
+    let main_file = target.join(endpoint);
+    if main_file.exists() {
+        return Err(GnuCashError::ProjectExists(main_file));
+    }
+    let book = load(source).await?;
+    let operating_currency = operating_currency(&book)?;
+    let directives = convert(&book)?;
+    info!(
+        "gnucash book contains {} accounts, {} commodities, {} prices and {} transactions",
+        book.accounts.len(),
+        book.commodities.len(),
+        book.prices.len(),
+        book.transactions.len()
+    );
+
+    let exporter = TextExporter {};
+    let mut main_file_content = vec![];
+    if let Some(operating_currency) = operating_currency {
+        main_file_content.push(exporter.export_directive(Directive::Option(Options {
+            key: ZhangString::quote("operating_currency"),
+            value: ZhangString::quote(operating_currency),
+        })));
+    }
+
+    let grouped_directives = directives
+        .into_iter()
+        .filter_map(|directive| directive.datetime().map(|datetime| ((datetime.year(), datetime.month()), directive)))
+        .into_group_map();
+    for ((year, month), month_directives) in grouped_directives.into_iter().sorted_by_key(|it| it.0) {
+        let relative_path = format!("data/{}/{}.zhang", year, month);
+        let path = target.join(&relative_path);
+        std::fs::create_dir_all(path.parent().expect("data file must have parent folder"))?;
+        let content = month_directives.into_iter().map(|it| exporter.export_directive(it)).join("\n\n");
+        std::fs::write(&path, format!("{}\n", content))?;
+        main_file_content.push(exporter.export_directive(Directive::Include(Include {
+            file: ZhangString::quote(relative_path),
+        })));
+    }
+    std::fs::create_dir_all(target)?;
+    std::fs::write(&main_file, format!("{}\n", main_file_content.join("\n")))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+    use zhang_ast::Directive;
+
+    use crate::book::{Book, Commodity, CommodityRef, Price};
+    use crate::{account_component, commodity_name, convert, precision, GnuCashError};
+
+    fn commodity_ref(namespace: &str, mnemonic: &str) -> CommodityRef {
+        CommodityRef {
+            namespace: namespace.to_string(),
+            mnemonic: mnemonic.to_string(),
+        }
+    }
+
+    fn book_of(commodities: &[(&str, &str)]) -> Book {
+        Book {
+            commodities: commodities
+                .iter()
+                .map(|(namespace, mnemonic)| Commodity {
+                    id: commodity_ref(namespace, mnemonic),
+                    name: None,
+                    fraction: None,
+                })
+                .collect(),
+            prices: commodities
+                .iter()
+                .filter(|(namespace, _)| *namespace != "CURRENCY")
+                .map(|(namespace, mnemonic)| Price {
+                    commodity: commodity_ref(namespace, mnemonic),
+                    currency: commodity_ref("CURRENCY", "USD"),
+                    date: NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(),
+                    value: BigDecimal::from_str("10").unwrap(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn should_sanitize_account_component() {
+        assert_eq!("Current-Assets", account_component("Current Assets"));
+        assert_eq!("Food-and-Drink-", account_component("Food and Drink:"));
+        assert_eq!("Unnamed", account_component("  "));
+    }
+
+    #[test]
+    fn should_sanitize_commodity_name() {
+        let commodity = |mnemonic: &str| CommodityRef {
+            namespace: "NASDAQ".to_string(),
+            mnemonic: mnemonic.to_string(),
+        };
+        assert_eq!("AAPL", commodity_name(&commodity("aapl")));
+        assert_eq!("BRK.B", commodity_name(&commodity("BRK.B")));
+        assert_eq!("C0700-HK", commodity_name(&commodity("0700 HK")));
+    }
+
+    #[test]
+    fn should_convert_fraction_to_precision() {
+        assert_eq!(Some(2), precision(100));
+        assert_eq!(Some(0), precision(1));
+        assert_eq!(None, precision(250));
+    }
+
+    #[test]
+    fn should_qualify_commodities_sharing_mnemonic_in_different_namespaces() {
+        let book = book_of(&[("CURRENCY", "USD"), ("NASDAQ", "ABC"), ("FUND", "ABC"), ("FUND", "USD"), ("NYSE", "XYZ")]);
+        let directives = convert(&book).unwrap();
+
+        let commodities: Vec<_> = directives
+            .iter()
+            .filter_map(|it| match it {
+                Directive::Commodity(commodity) => Some(commodity.currency.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec!["USD", "NASDAQ-ABC", "FUND-ABC", "FUND-USD", "XYZ"], commodities);
+        let prices: Vec<_> = directives
+            .iter()
+            .filter_map(|it| match it {
+                Directive::Price(price) => Some((price.currency.as_str(), price.amount.currency.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![("NASDAQ-ABC", "USD"), ("FUND-ABC", "USD"), ("FUND-USD", "USD"), ("XYZ", "USD")], prices);
+    }
+
+    #[test]
+    fn should_refuse_commodities_which_cannot_be_told_apart() {
+        let book = book_of(&[("CURRENCY", "USD"), ("CURRENCY", "usd")]);
+        assert!(matches!(convert(&book), Err(GnuCashError::CommodityConflict(_, _, name)) if name == "USD"));
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use bigdecimal::BigDecimal;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, FromRow};
+
+use crate::book::{parse_date, Account, Book, Commodity, CommodityRef, Price, Split, Transaction};
+use crate::{GnuCashError, GnuCashResult};
+
+#[derive(FromRow)]
+struct CommodityRow {
+    guid: String,
+    namespace: String,
+    mnemonic: String,
+    fullname: Option<String>,
+    fraction: i64,
+}
+
+#[derive(FromRow)]
+struct PriceRow {
+    commodity_guid: String,
+    currency_guid: String,
+    date: String,
+    value_num: i64,
+    value_denom: i64,
+}
+
+#[derive(FromRow)]
+struct AccountRow {
+    guid: String,
+    name: String,
+    account_type: String,
+    commodity_guid: Option<String>,
+    parent_guid: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(FromRow)]
+struct TransactionRow {
+    guid: String,
+    currency_guid: String,
+    num: String,
+    post_date: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(FromRow)]
+struct SplitRow {
+    tx_guid: String,
+    account_guid: String,
+    memo: String,
+    value_num: i64,
+    value_denom: i64,
+    quantity_num: i64,
+    quantity_denom: i64,
+}
+
+fn rational(num: i64, denom: i64) -> GnuCashResult<BigDecimal> {
+    if denom == 0 {
+        return Err(GnuCashError::InvalidBook(format!("invalid number: {}/{}", num, denom)));
+    }
+    Ok(BigDecimal::from(num) / BigDecimal::from(denom))
+}
+
+fn non_empty(content: Option<String>) -> Option<String> {
+    content.map(|it| it.trim().to_string()).filter(|it| !it.is_empty())
+}
+
+/// load book from gnucash sqlite backend
+pub async fn load(path: &Path) -> GnuCashResult<Book> {
+    let mut conn = SqliteConnectOptions::new().filename(path).read_only(true).connect().await?;
+
+    let commodity_rows = sqlx::query_as::<_, CommodityRow>("select guid, namespace, mnemonic, fullname, fraction from commodities")
+        .fetch_all(&mut conn)
+        .await?;
+    let commodity_refs: HashMap<String, CommodityRef> = commodity_rows
+        .iter()
+        .map(|row| {
+            (
+                row.guid.clone(),
+                CommodityRef {
+                    namespace: row.namespace.clone(),
+                    mnemonic: row.mnemonic.clone(),
+                },
+            )
+        })
+        .collect();
+    let commodity_ref = |guid: &str| {
+        commodity_refs
+            .get(guid)
+            .cloned()
+            .ok_or_else(|| GnuCashError::InvalidBook(format!("unknown commodity: {}", guid)))
+    };
+
+    let mut book = Book::default();
+    for row in commodity_rows.iter() {
+        book.commodities.push(Commodity {
+            id: commodity_ref(&row.guid)?,
+            name: non_empty(row.fullname.clone()),
+            fraction: Some(row.fraction),
+        });
+    }
+
+    let price_rows = sqlx::query_as::<_, PriceRow>("select commodity_guid, currency_guid, date, value_num, value_denom from prices")
+        .fetch_all(&mut conn)
+        .await?;
+    for row in price_rows {
+        book.prices.push(Price {
+            commodity: commodity_ref(&row.commodity_guid)?,
+            currency: commodity_ref(&row.currency_guid)?,
+            date: parse_date(&row.date)?,
+            value: rational(row.value_num, row.value_denom)?,
+        });
+    }
+
+    let account_rows = sqlx::query_as::<_, AccountRow>("select guid, name, account_type, commodity_guid, parent_guid, description from accounts")
+        .fetch_all(&mut conn)
+        .await?;
+    for row in account_rows {
+        book.accounts.push(Account {
+            guid: row.guid,
+            name: row.name,
+            account_type: row.account_type,
+            commodity: row.commodity_guid.as_deref().map(commodity_ref).transpose()?,
+            parent: row.parent_guid,
+            description: non_empty(row.description),
+        });
+    }
+
+    let split_rows = sqlx::query_as::<_, SplitRow>("select tx_guid, account_guid, memo, value_num, value_denom, quantity_num, quantity_denom from splits")
+        .fetch_all(&mut conn)
+        .await?;
+    let mut splits: HashMap<String, Vec<Split>> = HashMap::new();
+    for row in split_rows {
+        splits.entry(row.tx_guid).or_default().push(Split {
+            account: row.account_guid,
+            memo: non_empty(Some(row.memo)),
+            value: rational(row.value_num, row.value_denom)?,
+            quantity: rational(row.quantity_num, row.quantity_denom)?,
+        });
+    }
+
+    let transaction_rows = sqlx::query_as::<_, TransactionRow>("select guid, currency_guid, num, post_date, description from transactions")
+        .fetch_all(&mut conn)
+        .await?;
+    book.transactions = transaction_rows
+        .into_iter()
+        .map(|row| {
+            let post_date = row
+                .post_date
+                .ok_or_else(|| GnuCashError::InvalidBook(format!("missing post date of transaction: {}", row.guid)))?;
+            Ok(Transaction {
+                currency: commodity_ref(&row.currency_guid)?,
+                num: non_empty(Some(row.num)),
+                date: parse_date(&post_date)?,
+                description: non_empty(row.description),
+                splits: splits.remove(&row.guid).unwrap_or_default(),
+                guid: row.guid,
+            })
+        })
+        .collect::<GnuCashResult<Vec<Transaction>>>()?;
+    Ok(book)
+}
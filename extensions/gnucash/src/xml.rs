@@ -0,0 +1,114 @@
+use roxmltree::{Document, Node};
+
+use crate::book::{parse_date, parse_rational, Account, Book, Commodity, CommodityRef, Price, Split, Transaction};
+use crate::{GnuCashError, GnuCashResult};
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|it| it.is_element() && it.tag_name().name() == name)
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |it| it.is_element() && it.tag_name().name() == name)
+}
+
+fn text(node: Node, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|it| it.text())
+        .map(|it| it.trim().to_string())
+        .filter(|it| !it.is_empty())
+}
+
+fn required_text(node: Node, name: &str) -> GnuCashResult<String> {
+    text(node, name).ok_or_else(|| GnuCashError::InvalidBook(format!("missing <{}> in <{}>", name, node.tag_name().name())))
+}
+
+fn commodity_ref(node: Node) -> GnuCashResult<CommodityRef> {
+    Ok(CommodityRef {
+        namespace: required_text(node, "space")?,
+        mnemonic: required_text(node, "id")?,
+    })
+}
+
+fn timestamp(node: Node, name: &str) -> GnuCashResult<chrono::NaiveDate> {
+    let date = child(node, name)
+        .and_then(|it| text(it, "date"))
+        .ok_or_else(|| GnuCashError::InvalidBook(format!("missing <{}> in <{}>", name, node.tag_name().name())))?;
+    parse_date(&date)
+}
+
+fn commodity(node: Node) -> GnuCashResult<Commodity> {
+    Ok(Commodity {
+        id: commodity_ref(node)?,
+        name: text(node, "name"),
+        fraction: text(node, "fraction").and_then(|it| it.parse().ok()),
+    })
+}
+
+fn price(node: Node) -> GnuCashResult<Price> {
+    let commodity = child(node, "commodity").ok_or_else(|| GnuCashError::InvalidBook("missing commodity of price".to_string()))?;
+    let currency = child(node, "currency").ok_or_else(|| GnuCashError::InvalidBook("missing currency of price".to_string()))?;
+    Ok(Price {
+        commodity: commodity_ref(commodity)?,
+        currency: commodity_ref(currency)?,
+        date: timestamp(node, "time")?,
+        value: parse_rational(&required_text(node, "value")?)?,
+    })
+}
+
+fn account(node: Node) -> GnuCashResult<Account> {
+    Ok(Account {
+        guid: required_text(node, "id")?,
+        name: required_text(node, "name")?,
+        account_type: required_text(node, "type")?,
+        commodity: child(node, "commodity").map(commodity_ref).transpose()?,
+        parent: text(node, "parent"),
+        description: text(node, "description"),
+    })
+}
+
+fn split(node: Node) -> GnuCashResult<Split> {
+    Ok(Split {
+        account: required_text(node, "account")?,
+        memo: text(node, "memo"),
+        value: parse_rational(&required_text(node, "value")?)?,
+        quantity: parse_rational(&required_text(node, "quantity")?)?,
+    })
+}
+
+fn transaction(node: Node) -> GnuCashResult<Transaction> {
+    let currency = child(node, "currency").ok_or_else(|| GnuCashError::InvalidBook("missing currency of transaction".to_string()))?;
+    let splits = match child(node, "splits") {
+        Some(splits) => children(splits, "split").map(split).collect::<GnuCashResult<Vec<Split>>>()?,
+        None => vec![],
+    };
+    Ok(Transaction {
+        guid: required_text(node, "id")?,
+        currency: commodity_ref(currency)?,
+        num: text(node, "num"),
+        date: timestamp(node, "date-posted")?,
+        description: text(node, "description"),
+        splits,
+    })
+}
+
+/// load book from the content of gnucash xml file, the gzip compression should be handled by caller
+pub fn load(content: &str) -> GnuCashResult<Book> {
+    let document = Document::parse(content).map_err(|e| GnuCashError::InvalidBook(e.to_string()))?;
+    let book_node = child(document.root_element(), "book").ok_or_else(|| GnuCashError::InvalidBook("missing <gnc:book>".to_string()))?;
+
+    let mut book = Book::default();
+    for node in book_node.children().filter(|it| it.is_element()) {
+        match node.tag_name().name() {
+            "commodity" => book.commodities.push(commodity(node)?),
+            "pricedb" => {
+                for price_node in children(node, "price") {
+                    book.prices.push(price(price_node)?);
+                }
+            }
+            "account" => book.accounts.push(account(node)?),
+            "transaction" => book.transactions.push(transaction(node)?),
+            _ => {}
+        }
+    }
+    Ok(book)
+}
@@ -0,0 +1,123 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use indoc::indoc;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection};
+use tempfile::tempdir;
+use zhang_core::ledger::Ledger;
+use zhang_core::transform::TextTransformer;
+
+fn fixture() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/book.gnucash.xml")
+}
+
+async fn create_sqlite_book(path: &Path) {
+    let mut conn = SqliteConnectOptions::new().filename(path).create_if_missing(true).connect().await.unwrap();
+    let statements = indoc! {r#"
+        CREATE TABLE commodities (guid text PRIMARY KEY NOT NULL, namespace text NOT NULL, mnemonic text NOT NULL, fullname text, cusip text, fraction integer NOT NULL, quote_flag integer NOT NULL, quote_source text, quote_tz text);
+        CREATE TABLE prices (guid text PRIMARY KEY NOT NULL, commodity_guid text NOT NULL, currency_guid text NOT NULL, date text NOT NULL, source text, type text, value_num bigint NOT NULL, value_denom bigint NOT NULL);
+        CREATE TABLE accounts (guid text PRIMARY KEY NOT NULL, name text NOT NULL, account_type text NOT NULL, commodity_guid text, commodity_scu integer NOT NULL, non_std_scu integer NOT NULL, parent_guid text, code text, description text, hidden integer, placeholder integer);
+        CREATE TABLE transactions (guid text PRIMARY KEY NOT NULL, currency_guid text NOT NULL, num text NOT NULL, post_date text, enter_date text, description text);
+        CREATE TABLE splits (guid text PRIMARY KEY NOT NULL, tx_guid text NOT NULL, account_guid text NOT NULL, memo text NOT NULL, action text NOT NULL, reconcile_state text NOT NULL, reconcile_date text, value_num bigint NOT NULL, value_denom bigint NOT NULL, quantity_num bigint NOT NULL, quantity_denom bigint NOT NULL, lot_guid text);
+
+        INSERT INTO commodities VALUES ('c1', 'CURRENCY', 'USD', 'US Dollar', '840', 100, 1, 'currency', '');
+        INSERT INTO commodities VALUES ('c2', 'NASDAQ', 'AAPL', 'Apple Inc.', '', 10000, 0, NULL, NULL);
+        INSERT INTO commodities VALUES ('c3', 'template', 'template', 'template', 'template', 1, 0, NULL, NULL);
+
+        INSERT INTO prices VALUES ('p1', 'c2', 'c1', '2014-01-10 10:59:00', 'user:price-editor', 'unknown', 5500, 100);
+
+        INSERT INTO accounts VALUES ('a0', 'Root Account', 'ROOT', NULL, 0, 0, NULL, '', '', 0, 0);
+        INSERT INTO accounts VALUES ('a1', 'Assets', 'ASSET', 'c1', 100, 0, 'a0', '', '', 0, 1);
+        INSERT INTO accounts VALUES ('a2', 'Checking Account', 'BANK', 'c1', 100, 0, 'a1', '', 'Main bank account', 0, 0);
+        INSERT INTO accounts VALUES ('a3', 'Broker', 'STOCK', 'c2', 10000, 0, 'a1', '', '', 0, 0);
+        INSERT INTO accounts VALUES ('a4', 'Expenses', 'EXPENSE', 'c1', 100, 0, 'a0', '', '', 0, 1);
+        INSERT INTO accounts VALUES ('a5', 'Groceries', 'EXPENSE', 'c1', 100, 0, 'a4', '', '', 0, 0);
+        INSERT INTO accounts VALUES ('a6', 'Opening Balances', 'EQUITY', 'c1', 100, 0, 'a0', '', '', 0, 0);
+        INSERT INTO accounts VALUES ('a7', 'Template Root', 'ROOT', NULL, 0, 0, NULL, '', '', 0, 0);
+
+        INSERT INTO transactions VALUES ('t1', 'c1', '', '2014-01-01 10:59:00', '2014-01-01 12:00:00', 'Opening Balance');
+        INSERT INTO transactions VALUES ('t2', 'c1', '1001', '2014-01-05 10:59:00', '2014-01-05 12:00:00', 'Supermarket');
+        INSERT INTO transactions VALUES ('t3', 'c1', '', '2014-02-10 10:59:00', '2014-02-10 12:00:00', 'Buy AAPL');
+
+        INSERT INTO splits VALUES ('s1', 't1', 'a2', '', '', 'n', NULL, 100000, 100, 100000, 100, NULL);
+        INSERT INTO splits VALUES ('s2', 't1', 'a6', '', '', 'n', NULL, -100000, 100, -100000, 100, NULL);
+        INSERT INTO splits VALUES ('s3', 't2', 'a5', 'weekly groceries', '', 'c', NULL, 4550, 100, 4550, 100, NULL);
+        INSERT INTO splits VALUES ('s4', 't2', 'a2', '', '', 'c', NULL, -4550, 100, -4550, 100, NULL);
+        INSERT INTO splits VALUES ('s5', 't3', 'a3', '', '', 'n', NULL, 55000, 100, 100000, 10000, NULL);
+        INSERT INTO splits VALUES ('s6', 't3', 'a2', '', '', 'n', NULL, -55000, 100, -55000, 100, NULL);
+    "#};
+    for statement in statements.split(";\n").map(str::trim).filter(|it| !it.is_empty()) {
+        sqlx::query(statement).execute(&mut conn).await.unwrap();
+    }
+    conn.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn should_import_xml_book_into_zhang_project() {
+    let target = tempdir().unwrap();
+    gnucash::import(&fixture(), target.path(), "main.zhang").await.unwrap();
+
+    let main_file = std::fs::read_to_string(target.path().join("main.zhang")).unwrap();
+    assert_eq!(
+        indoc! {r#"
+            option "operating_currency" "USD"
+            include "data/2014/1.zhang"
+            include "data/2014/2.zhang"
+        "#},
+        main_file
+    );
+    let january = std::fs::read_to_string(target.path().join("data/2014/1.zhang")).unwrap();
+    assert!(january.contains("2014-01-01 open Assets:Checking-Account USD\n  description: \"Main bank account\""));
+    assert!(january.contains("2014-01-01 open Equity:Opening-Balances USD"));
+    assert!(january.contains("2014-01-05 open Expenses:Groceries USD"));
+    assert!(january.contains("2014-01-10 price AAPL 55 USD"));
+    assert!(january.contains(
+        "2014-01-05 * \"Supermarket\"\n  Expenses:Groceries 45.5 USD\n    memo: \"weekly groceries\"\n  Assets:Checking-Account -45.5 USD\n  num: \"1001\""
+    ));
+    let february = std::fs::read_to_string(target.path().join("data/2014/2.zhang")).unwrap();
+    assert!(february.contains("2014-02-10 open Assets:Broker AAPL"));
+    assert!(february.contains("Assets:Broker 10 AAPL @ 55 USD"));
+
+    let ledger = Ledger::load::<TextTransformer>(target.path().to_path_buf(), "main.zhang".to_string())
+        .await
+        .unwrap();
+    let mut operations = ledger.operations().await;
+    assert!(operations.errors().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn should_not_import_into_existing_project() {
+    let target = tempdir().unwrap();
+    std::fs::write(target.path().join("main.zhang"), "").unwrap();
+    assert!(matches!(
+        gnucash::import(&fixture(), target.path(), "main.zhang").await,
+        Err(gnucash::GnuCashError::ProjectExists(_))
+    ));
+}
+
+#[tokio::test]
+async fn should_load_gzipped_xml_book() {
+    let folder = tempdir().unwrap();
+    let compressed = folder.path().join("book.gnucash");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&std::fs::read(fixture()).unwrap()).unwrap();
+    std::fs::write(&compressed, encoder.finish().unwrap()).unwrap();
+
+    let plain_book = gnucash::load(&fixture()).await.unwrap();
+    let compressed_book = gnucash::load(&compressed).await.unwrap();
+    assert_eq!(gnucash::convert(&plain_book).unwrap(), gnucash::convert(&compressed_book).unwrap());
+}
+
+#[tokio::test]
+async fn should_load_sqlite_book_same_as_xml_book() {
+    let folder = tempdir().unwrap();
+    let database = folder.path().join("book.gnucash");
+    create_sqlite_book(&database).await;
+
+    let xml_book = gnucash::load(&fixture()).await.unwrap();
+    let sqlite_book = gnucash::load(&database).await.unwrap();
+    assert_eq!(gnucash::convert(&xml_book).unwrap(), gnucash::convert(&sqlite_book).unwrap());
+}
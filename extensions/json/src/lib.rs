@@ -0,0 +1,267 @@
+use std::path::{Path, PathBuf};
+
+use itertools::Itertools;
+use zhang_ast::{Directive, SpanInfo, Spanned};
+use zhang_core::error::IoErrorIntoZhangError;
+use zhang_core::exporter::{write_file_atomically, write_validated_file, AppendableExporter, Exporter};
+use zhang_core::ledger::Ledger;
+use zhang_core::transform::{ParseCache, TextFileBasedTransformer};
+use zhang_core::{ZhangError, ZhangResult};
+
+use crate::schema::{JsonDirective, JsonLedger, JsonSpanned};
+
+pub mod schema;
+
+/// version of the json layout, written to every exported document as `version`.
+/// documents with a newer version are refused by [`JsonTransformer`]
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Default)]
+pub struct JsonExporter {}
+
+impl JsonExporter {
+    /// export the whole directive list, with span info, as a json document
+    pub fn export_directives(&self, directives: Vec<Spanned<Directive>>) -> String {
+        let ledger = JsonLedger {
+            version: SCHEMA_VERSION,
+            directives: directives.into_iter().map(JsonSpanned::from).collect_vec(),
+        };
+        serde_json::to_string_pretty(&ledger).expect("json ledger should always be serializable")
+    }
+}
+
+impl AppendableExporter for JsonExporter {
+    fn append_directives(&self, ledger: &Ledger, directives: Vec<Directive>) -> ZhangResult<()> {
+        let (entry, endpoint) = &ledger.entry;
+        let endpoint = entry.join(endpoint);
+        let mut document = if endpoint.exists() {
+            let content = std::fs::read_to_string(&endpoint).with_path(&endpoint)?;
+            parse_document(&content)?
+        } else {
+            JsonLedger {
+                version: SCHEMA_VERSION,
+                directives: vec![],
+            }
+        };
+        document.directives.extend(directives.into_iter().map(|directive| JsonSpanned {
+            directive: directive.into(),
+            span: None,
+        }));
+        let content = serde_json::to_string_pretty(&document).expect("json ledger should always be serializable");
+        write_file_atomically(&endpoint, &content)
+    }
+
+    fn replace_directive(&self, ledger: &Ledger, span: &SpanInfo, directive: Option<Directive>) -> ZhangResult<()> {
+        let path = span.filename.clone().unwrap_or_default();
+        let content = std::fs::read_to_string(&path).with_path(&path)?;
+        let mut document = parse_document(&content)?;
+        // the stored span of directive is kept even if the directive is edited, so the content is compared as well
+        let loaded = ledger.directives.iter().find(|it| &it.span == span).map(|it| &it.data);
+        let position = document
+            .directives
+            .iter()
+            .enumerate()
+            .position(|(idx, directive)| &directive_span(idx, directive, &path) == span)
+            .filter(|position| {
+                let current = Spanned::<Directive>::try_from(document.directives[*position].clone()).ok();
+                loaded.is_some() && current.as_ref().map(|it| &it.data) == loaded
+            })
+            .ok_or_else(|| ZhangError::StaleSpan(path.clone()))?;
+        match directive {
+            Some(directive) => document.directives[position].directive = directive.into(),
+            None => {
+                document.directives.remove(position);
+            }
+        }
+        let content = serde_json::to_string_pretty(&document).expect("json ledger should always be serializable");
+        write_validated_file(ledger, &path, &content)
+    }
+}
+
+impl Exporter for JsonExporter {
+    type Output = String;
+
+    fn export_directive(&self, directive: Directive) -> Self::Output {
+        serde_json::to_string(&JsonDirective::from(directive)).expect("json directive should always be serializable")
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct JsonTransformer {
+    cache: ParseCache<Spanned<Directive>>,
+}
+
+impl TextFileBasedTransformer for JsonTransformer {
+    type FileOutput = Spanned<Directive>;
+
+    fn parse_cache(&self) -> Option<&ParseCache<Self::FileOutput>> {
+        Some(&self.cache)
+    }
+
+    fn parse(&self, content: &str, path: PathBuf) -> ZhangResult<Vec<Self::FileOutput>> {
+        parse_document(content)?
+            .directives
+            .into_iter()
+            .enumerate()
+            .map(|(idx, directive)| {
+                let span = directive_span(idx, &directive, &path);
+                let mut directive = Spanned::<Directive>::try_from(directive)?;
+                directive.span = span;
+                Ok(directive)
+            })
+            .collect()
+    }
+
+    fn go_next(&self, directive: &Self::FileOutput) -> Option<String> {
+        match &directive.data {
+            Directive::Include(include) => Some(include.file.clone().to_plain_string()),
+            _ => None,
+        }
+    }
+
+    fn transform(&self, directives: Vec<Self::FileOutput>) -> ZhangResult<Vec<Spanned<Directive>>> {
+        Ok(directives)
+    }
+}
+
+/// directive id is derived from span, directives without span use the position in document to keep them unique,
+/// and the compact json of directive as span content
+fn directive_span(idx: usize, directive: &JsonSpanned, path: &Path) -> SpanInfo {
+    match &directive.span {
+        Some(span) => span.clone().into(),
+        None => SpanInfo {
+            start: idx,
+            end: idx + 1,
+            content: serde_json::to_string(&directive.directive).expect("json directive should always be serializable"),
+            filename: Some(path.to_path_buf()),
+        },
+    }
+}
+
+fn parse_document(content: &str) -> ZhangResult<JsonLedger> {
+    let document: JsonLedger = serde_json::from_str(content).map_err(|e| ZhangError::JsonError(e.to_string()))?;
+    if document.version > SCHEMA_VERSION {
+        return Err(ZhangError::JsonError(format!(
+            "unsupported schema version {}, the latest supported version is {}",
+            document.version, SCHEMA_VERSION
+        )));
+    }
+    Ok(document)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use indoc::indoc;
+    use zhang_core::exporter::Exporter;
+    use zhang_core::parser::parse;
+    use zhang_core::transform::TextFileBasedTransformer;
+
+    use zhang_core::exporter::AppendableExporter;
+    use zhang_core::ledger::Ledger;
+    use zhang_core::ZhangError;
+
+    use crate::{JsonExporter, JsonTransformer};
+
+    fn round_trip(content: &str) {
+        let directives = parse(content, None).unwrap();
+        let expected = parse(content, None).unwrap();
+        let json = JsonExporter {}.export_directives(directives);
+        let directives = JsonTransformer::default().parse(&json, PathBuf::from("main.json")).unwrap();
+        assert_eq!(expected, directives);
+    }
+
+    #[test]
+    fn should_round_trip_all_directives() {
+        round_trip(indoc! {r#"
+            option "operating_currency" "CNY"
+            plugin "module" "123" "345"
+            include "data/a.zhang"
+            ; a comment
+            1970-01-01 open Assets:Bank CNY, USD
+              alias: "bank"
+            1970-01-01 close Assets:Bank
+            1970-01-01 commodity CNY
+              precision: 2
+            1970-01-01 12:00 balance Assets:Bank 100.00 CNY
+            1970-01-01 12:00:01 balance Assets:Bank 100 CNY with pad Equity:Open-Balances
+            1970-01-01 note Assets:Bank "a note"
+            1970-01-01 document Assets:Bank "a.pdf"
+            1970-01-01 price USD 7.1 CNY
+            1970-01-01 event "location" "China"
+            1970-01-01 query "cash" "SELECT 1"
+            1970-01-01 custom "budget" Expenses:Food "monthly"
+            1970-01-01 * "Payee" "Narration" #tag ^link
+              a: "b"
+              Assets:Bank -100 CNY
+              Assets:Stock 10 AAPL { 10 CNY, 1970-01-01 } @ 10 CNY
+              Assets:Stock 10 AAPL @@ 100 CNY
+              Expenses:Food
+        "#});
+    }
+
+    #[test]
+    fn should_export_single_directive_as_json_object() {
+        let mut directives = parse("1970-01-01 open Assets:Bank CNY", None).unwrap();
+        let json = JsonExporter {}.export_directive(directives.pop().unwrap().data);
+        assert_eq!(r#"{"type":"open","date":"1970-01-01","account":"Assets:Bank","commodities":["CNY"]}"#, json);
+    }
+
+    #[test]
+    fn should_keep_unquoted_string() {
+        let json = indoc! {r#"
+            {
+              "version": 1,
+              "directives": [
+                { "type": "commodity", "date": "1970-01-01", "currency": "CNY", "meta": { "precision": [{ "unquoted": "2" }] } }
+              ]
+            }
+        "#};
+        let directives = JsonTransformer::default().parse(json, PathBuf::from("main.json")).unwrap();
+        let expected = parse("1970-01-01 commodity CNY\n  precision: 2", None).unwrap();
+        assert_eq!(
+            expected.into_iter().map(|it| it.data).collect::<Vec<_>>(),
+            directives.into_iter().map(|it| it.data).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn should_refuse_newer_schema_version() {
+        let result = JsonTransformer::default().parse(r#"{"version": 999, "directives": []}"#, PathBuf::from("main.json"));
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_refuse_to_replace_edited_directive_of_stored_span() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.json");
+        let document = |narration: &str| {
+            format!(
+                r#"{{"version": 1, "directives": [{{"type": "transaction", "date": "1970-01-01", "narration": "{}", "tags": [], "links": [], "postings": [],
+                    "span": {{"start": 0, "end": 10, "content": "origin", "filename": {:?}}}}}]}}"#,
+                narration, path
+            )
+        };
+        std::fs::write(&path, document("lunch")).unwrap();
+        let ledger = Ledger::load::<JsonTransformer>(dir.path().to_path_buf(), "main.json".to_string())
+            .await
+            .unwrap();
+        let span = ledger.directives[0].span.clone();
+
+        std::fs::write(&path, document("dinner")).unwrap();
+        let result = JsonExporter {}.replace_directive(&ledger, &span, None);
+        assert!(matches!(result, Err(ZhangError::StaleSpan(_))), "{:?}", result);
+        assert_eq!(document("dinner"), std::fs::read_to_string(&path).unwrap());
+
+        std::fs::write(&path, document("lunch")).unwrap();
+        JsonExporter {}.replace_directive(&ledger, &span, None).unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("lunch"));
+    }
+
+    #[test]
+    fn should_refuse_invalid_account() {
+        let json = r#"{"version": 1, "directives": [{"type": "close", "date": "1970-01-01", "account": "Unknown:Bank"}]}"#;
+        assert!(JsonTransformer::default().parse(json, PathBuf::from("main.json")).is_err());
+    }
+}
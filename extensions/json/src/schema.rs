@@ -0,0 +1,604 @@
+//! Serializable mirror of the zhang directive model.
+//!
+//! The structs here are what actually hits the wire, so they are decoupled from `zhang_ast` on purpose:
+//! refactoring the ast must not change the json layout. Any breaking change of the layout must bump
+//! [`SCHEMA_VERSION`](crate::SCHEMA_VERSION).
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDate, NaiveDateTime};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use zhang_ast::amount::Amount;
+use zhang_ast::*;
+use zhang_core::exporter::TextExportable;
+use zhang_core::{ZhangError, ZhangResult};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonLedger {
+    pub version: u32,
+    pub directives: Vec<JsonSpanned>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonSpanned {
+    #[serde(flatten)]
+    pub directive: JsonDirective,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<JsonSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonSpan {
+    pub start: usize,
+    pub end: usize,
+    pub content: String,
+    pub filename: Option<PathBuf>,
+}
+
+/// quoted strings are plain json strings, unquoted ones are wrapped as `{"unquoted": "..."}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum JsonString {
+    Quoted(String),
+    Unquoted { unquoted: String },
+}
+
+pub type JsonMeta = BTreeMap<String, Vec<JsonString>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonAmount {
+    pub number: String,
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonPrice {
+    Single(JsonAmount),
+    Total(JsonAmount),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonCustomValue {
+    String(JsonString),
+    Account(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonPosting {
+    pub flag: Option<String>,
+    pub account: String,
+    pub units: Option<JsonAmount>,
+    pub cost: Option<JsonAmount>,
+    pub cost_date: Option<String>,
+    pub price: Option<JsonPrice>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub meta: JsonMeta,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JsonDirective {
+    Open {
+        date: String,
+        account: String,
+        commodities: Vec<String>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    Close {
+        date: String,
+        account: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    Commodity {
+        date: String,
+        currency: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    Transaction {
+        date: String,
+        flag: Option<String>,
+        payee: Option<JsonString>,
+        narration: Option<JsonString>,
+        tags: Vec<String>,
+        links: Vec<String>,
+        postings: Vec<JsonPosting>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    BalanceCheck {
+        date: String,
+        account: String,
+        amount: JsonAmount,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    BalancePad {
+        date: String,
+        account: String,
+        amount: JsonAmount,
+        pad: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    Note {
+        date: String,
+        account: String,
+        comment: JsonString,
+        tags: Option<Vec<String>>,
+        links: Option<Vec<String>>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    Document {
+        date: String,
+        account: String,
+        filename: JsonString,
+        tags: Option<Vec<String>>,
+        links: Option<Vec<String>>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    Price {
+        date: String,
+        currency: String,
+        amount: JsonAmount,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    Event {
+        date: String,
+        event_type: JsonString,
+        description: JsonString,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    Query {
+        date: String,
+        name: JsonString,
+        query_string: JsonString,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    Custom {
+        date: String,
+        custom_type: JsonString,
+        values: Vec<JsonCustomValue>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        meta: JsonMeta,
+    },
+    Option {
+        key: JsonString,
+        value: JsonString,
+    },
+    Plugin {
+        module: JsonString,
+        value: Vec<JsonString>,
+    },
+    Include {
+        file: JsonString,
+    },
+    Comment {
+        content: String,
+    },
+}
+
+impl From<SpanInfo> for JsonSpan {
+    fn from(span: SpanInfo) -> Self {
+        JsonSpan {
+            start: span.start,
+            end: span.end,
+            content: span.content,
+            filename: span.filename,
+        }
+    }
+}
+
+impl From<JsonSpan> for SpanInfo {
+    fn from(span: JsonSpan) -> Self {
+        SpanInfo {
+            start: span.start,
+            end: span.end,
+            content: span.content,
+            filename: span.filename,
+        }
+    }
+}
+
+impl From<ZhangString> for JsonString {
+    fn from(value: ZhangString) -> Self {
+        match value {
+            ZhangString::UnquoteString(unquoted) => JsonString::Unquoted { unquoted },
+            ZhangString::QuoteString(quoted) => JsonString::Quoted(quoted),
+        }
+    }
+}
+
+impl From<JsonString> for ZhangString {
+    fn from(value: JsonString) -> Self {
+        match value {
+            JsonString::Quoted(quoted) => ZhangString::QuoteString(quoted),
+            JsonString::Unquoted { unquoted } => ZhangString::UnquoteString(unquoted),
+        }
+    }
+}
+
+impl From<Amount> for JsonAmount {
+    fn from(amount: Amount) -> Self {
+        JsonAmount {
+            number: amount.number.to_string(),
+            currency: amount.currency,
+        }
+    }
+}
+
+impl TryFrom<JsonAmount> for Amount {
+    type Error = ZhangError;
+
+    fn try_from(amount: JsonAmount) -> Result<Self, Self::Error> {
+        let number = BigDecimal::from_str(&amount.number).map_err(|e| ZhangError::JsonError(format!("invalid number {}: {}", amount.number, e)))?;
+        Ok(Amount::new(number, amount.currency))
+    }
+}
+
+/// hash sets have no stable order, sort them to keep the output deterministic
+fn sorted(set: HashSet<String>) -> Vec<String> {
+    set.into_iter().sorted().collect_vec()
+}
+
+fn meta_to_json(meta: Meta) -> JsonMeta {
+    let mut ret = JsonMeta::new();
+    for (key, value) in meta.get_flatten() {
+        ret.entry(key).or_default().push(value.into());
+    }
+    ret
+}
+
+fn meta_from_json(meta: JsonMeta) -> Meta {
+    meta.into_iter()
+        .flat_map(|(key, values)| values.into_iter().map(move |value| (key.clone(), value.into())))
+        .collect()
+}
+
+fn parse_date(content: &str) -> ZhangResult<Date> {
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(content, "%Y-%m-%d %H:%M:%S") {
+        return Ok(Date::Datetime(datetime));
+    }
+    if let Ok(date_hour) = NaiveDateTime::parse_from_str(content, "%Y-%m-%d %H:%M") {
+        return Ok(Date::DateHour(date_hour));
+    }
+    NaiveDate::parse_from_str(content, "%Y-%m-%d")
+        .map(Date::Date)
+        .map_err(|_| ZhangError::InvalidDate)
+}
+
+fn parse_account(content: &str) -> ZhangResult<Account> {
+    Account::from_str(content).map_err(|_| ZhangError::InvalidAccount)
+}
+
+fn parse_flag(content: &str) -> ZhangResult<Flag> {
+    Flag::from_str(content).map_err(|_| ZhangError::JsonError(format!("invalid flag {}", content)))
+}
+
+impl From<Posting> for JsonPosting {
+    fn from(posting: Posting) -> Self {
+        JsonPosting {
+            flag: posting.flag.map(|it| it.to_string()),
+            account: posting.account.content,
+            units: posting.units.map(JsonAmount::from),
+            cost: posting.cost.map(JsonAmount::from),
+            cost_date: posting.cost_date.map(|it| it.export()),
+            price: posting.price.map(|price| match price {
+                SingleTotalPrice::Single(amount) => JsonPrice::Single(amount.into()),
+                SingleTotalPrice::Total(amount) => JsonPrice::Total(amount.into()),
+            }),
+            meta: meta_to_json(posting.meta),
+        }
+    }
+}
+
+impl TryFrom<JsonPosting> for Posting {
+    type Error = ZhangError;
+
+    fn try_from(posting: JsonPosting) -> Result<Self, Self::Error> {
+        Ok(Posting {
+            flag: posting.flag.as_deref().map(parse_flag).transpose()?,
+            account: parse_account(&posting.account)?,
+            units: posting.units.map(Amount::try_from).transpose()?,
+            cost: posting.cost.map(Amount::try_from).transpose()?,
+            cost_date: posting.cost_date.as_deref().map(parse_date).transpose()?,
+            price: posting
+                .price
+                .map(|price| match price {
+                    JsonPrice::Single(amount) => Amount::try_from(amount).map(SingleTotalPrice::Single),
+                    JsonPrice::Total(amount) => Amount::try_from(amount).map(SingleTotalPrice::Total),
+                })
+                .transpose()?,
+            meta: meta_from_json(posting.meta),
+        })
+    }
+}
+
+impl From<Directive> for JsonDirective {
+    fn from(directive: Directive) -> Self {
+        match directive {
+            Directive::Open(open) => JsonDirective::Open {
+                date: open.date.export(),
+                account: open.account.content,
+                commodities: open.commodities,
+                meta: meta_to_json(open.meta),
+            },
+            Directive::Close(close) => JsonDirective::Close {
+                date: close.date.export(),
+                account: close.account.content,
+                meta: meta_to_json(close.meta),
+            },
+            Directive::Commodity(commodity) => JsonDirective::Commodity {
+                date: commodity.date.export(),
+                currency: commodity.currency,
+                meta: meta_to_json(commodity.meta),
+            },
+            Directive::Transaction(trx) => JsonDirective::Transaction {
+                date: trx.date.export(),
+                flag: trx.flag.map(|it| it.to_string()),
+                payee: trx.payee.map(JsonString::from),
+                narration: trx.narration.map(JsonString::from),
+                tags: trx.tags.into_iter().collect(),
+                links: trx.links.into_iter().collect(),
+                postings: trx.postings.into_iter().map(JsonPosting::from).collect(),
+                meta: meta_to_json(trx.meta),
+            },
+            Directive::Balance(Balance::BalanceCheck(check)) => JsonDirective::BalanceCheck {
+                date: check.date.export(),
+                account: check.account.content,
+                amount: check.amount.into(),
+                meta: meta_to_json(check.meta),
+            },
+            Directive::Balance(Balance::BalancePad(pad)) => JsonDirective::BalancePad {
+                date: pad.date.export(),
+                account: pad.account.content,
+                amount: pad.amount.into(),
+                pad: pad.pad.content,
+                meta: meta_to_json(pad.meta),
+            },
+            Directive::Note(note) => JsonDirective::Note {
+                date: note.date.export(),
+                account: note.account.content,
+                comment: note.comment.into(),
+                tags: note.tags.map(sorted),
+                links: note.links.map(sorted),
+                meta: meta_to_json(note.meta),
+            },
+            Directive::Document(document) => JsonDirective::Document {
+                date: document.date.export(),
+                account: document.account.content,
+                filename: document.filename.into(),
+                tags: document.tags.map(sorted),
+                links: document.links.map(sorted),
+                meta: meta_to_json(document.meta),
+            },
+            Directive::Price(price) => JsonDirective::Price {
+                date: price.date.export(),
+                currency: price.currency,
+                amount: price.amount.into(),
+                meta: meta_to_json(price.meta),
+            },
+            Directive::Event(event) => JsonDirective::Event {
+                date: event.date.export(),
+                event_type: event.event_type.into(),
+                description: event.description.into(),
+                meta: meta_to_json(event.meta),
+            },
+            Directive::Query(query) => JsonDirective::Query {
+                date: query.date.export(),
+                name: query.name.into(),
+                query_string: query.query_string.into(),
+                meta: meta_to_json(query.meta),
+            },
+            Directive::Custom(custom) => JsonDirective::Custom {
+                date: custom.date.export(),
+                custom_type: custom.custom_type.into(),
+                values: custom
+                    .values
+                    .into_iter()
+                    .map(|value| match value {
+                        StringOrAccount::String(string) => JsonCustomValue::String(string.into()),
+                        StringOrAccount::Account(account) => JsonCustomValue::Account(account.content),
+                    })
+                    .collect(),
+                meta: meta_to_json(custom.meta),
+            },
+            Directive::Option(option) => JsonDirective::Option {
+                key: option.key.into(),
+                value: option.value.into(),
+            },
+            Directive::Plugin(plugin) => JsonDirective::Plugin {
+                module: plugin.module.into(),
+                value: plugin.value.into_iter().map(JsonString::from).collect(),
+            },
+            Directive::Include(include) => JsonDirective::Include { file: include.file.into() },
+            Directive::Comment(comment) => JsonDirective::Comment { content: comment.content },
+        }
+    }
+}
+
+impl TryFrom<JsonDirective> for Directive {
+    type Error = ZhangError;
+
+    fn try_from(directive: JsonDirective) -> Result<Self, Self::Error> {
+        Ok(match directive {
+            JsonDirective::Open {
+                date,
+                account,
+                commodities,
+                meta,
+            } => Directive::Open(Open {
+                date: parse_date(&date)?,
+                account: parse_account(&account)?,
+                commodities,
+                meta: meta_from_json(meta),
+            }),
+            JsonDirective::Close { date, account, meta } => Directive::Close(Close {
+                date: parse_date(&date)?,
+                account: parse_account(&account)?,
+                meta: meta_from_json(meta),
+            }),
+            JsonDirective::Commodity { date, currency, meta } => Directive::Commodity(Commodity {
+                date: parse_date(&date)?,
+                currency,
+                meta: meta_from_json(meta),
+            }),
+            JsonDirective::Transaction {
+                date,
+                flag,
+                payee,
+                narration,
+                tags,
+                links,
+                postings,
+                meta,
+            } => Directive::Transaction(Transaction {
+                date: parse_date(&date)?,
+                flag: flag.as_deref().map(parse_flag).transpose()?,
+                payee: payee.map(ZhangString::from),
+                narration: narration.map(ZhangString::from),
+                tags: tags.into_iter().collect(),
+                links: links.into_iter().collect(),
+                postings: postings.into_iter().map(Posting::try_from).collect::<ZhangResult<Vec<_>>>()?,
+                meta: meta_from_json(meta),
+            }),
+            JsonDirective::BalanceCheck { date, account, amount, meta } => Directive::Balance(Balance::BalanceCheck(BalanceCheck {
+                date: parse_date(&date)?,
+                account: parse_account(&account)?,
+                amount: amount.try_into()?,
+                meta: meta_from_json(meta),
+            })),
+            JsonDirective::BalancePad {
+                date,
+                account,
+                amount,
+                pad,
+                meta,
+            } => Directive::Balance(Balance::BalancePad(BalancePad {
+                date: parse_date(&date)?,
+                account: parse_account(&account)?,
+                amount: amount.try_into()?,
+                pad: parse_account(&pad)?,
+                meta: meta_from_json(meta),
+            })),
+            JsonDirective::Note {
+                date,
+                account,
+                comment,
+                tags,
+                links,
+                meta,
+            } => Directive::Note(Note {
+                date: parse_date(&date)?,
+                account: parse_account(&account)?,
+                comment: comment.into(),
+                tags: tags.map(|it| it.into_iter().collect::<HashSet<_>>()),
+                links: links.map(|it| it.into_iter().collect::<HashSet<_>>()),
+                meta: meta_from_json(meta),
+            }),
+            JsonDirective::Document {
+                date,
+                account,
+                filename,
+                tags,
+                links,
+                meta,
+            } => Directive::Document(Document {
+                date: parse_date(&date)?,
+                account: parse_account(&account)?,
+                filename: filename.into(),
+                tags: tags.map(|it| it.into_iter().collect::<HashSet<_>>()),
+                links: links.map(|it| it.into_iter().collect::<HashSet<_>>()),
+                meta: meta_from_json(meta),
+            }),
+            JsonDirective::Price { date, currency, amount, meta } => Directive::Price(Price {
+                date: parse_date(&date)?,
+                currency,
+                amount: amount.try_into()?,
+                meta: meta_from_json(meta),
+            }),
+            JsonDirective::Event {
+                date,
+                event_type,
+                description,
+                meta,
+            } => Directive::Event(Event {
+                date: parse_date(&date)?,
+                event_type: event_type.into(),
+                description: description.into(),
+                meta: meta_from_json(meta),
+            }),
+            JsonDirective::Query {
+                date,
+                name,
+                query_string,
+                meta,
+            } => Directive::Query(Query {
+                date: parse_date(&date)?,
+                name: name.into(),
+                query_string: query_string.into(),
+                meta: meta_from_json(meta),
+            }),
+            JsonDirective::Custom {
+                date,
+                custom_type,
+                values,
+                meta,
+            } => Directive::Custom(Custom {
+                date: parse_date(&date)?,
+                custom_type: custom_type.into(),
+                values: values
+                    .into_iter()
+                    .map(|value| match value {
+                        JsonCustomValue::String(string) => Ok(StringOrAccount::String(string.into())),
+                        JsonCustomValue::Account(account) => parse_account(&account).map(StringOrAccount::Account),
+                    })
+                    .collect::<ZhangResult<Vec<_>>>()?,
+                meta: meta_from_json(meta),
+            }),
+            JsonDirective::Option { key, value } => Directive::Option(Options {
+                key: key.into(),
+                value: value.into(),
+            }),
+            JsonDirective::Plugin { module, value } => Directive::Plugin(Plugin {
+                module: module.into(),
+                value: value.into_iter().map(ZhangString::from).collect(),
+            }),
+            JsonDirective::Include { file } => Directive::Include(Include { file: file.into() }),
+            JsonDirective::Comment { content } => Directive::Comment(Comment { content }),
+        })
+    }
+}
+
+impl From<Spanned<Directive>> for JsonSpanned {
+    fn from(directive: Spanned<Directive>) -> Self {
+        JsonSpanned {
+            directive: directive.data.into(),
+            span: Some(directive.span.into()),
+        }
+    }
+}
+
+impl TryFrom<JsonSpanned> for Spanned<Directive> {
+    type Error = ZhangError;
+
+    fn try_from(directive: JsonSpanned) -> Result<Self, Self::Error> {
+        Ok(Spanned::new(
+            directive.directive.try_into()?,
+            directive.span.map(SpanInfo::from).unwrap_or_default(),
+        ))
+    }
+}
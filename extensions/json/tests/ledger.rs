@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use indoc::indoc;
+use json::{JsonExporter, JsonTransformer};
+use tempfile::tempdir;
+use zhang_ast::amount::Amount;
+use zhang_ast::{Account, Date, Directive, Flag, Posting, Transaction, ZhangString};
+use zhang_core::exporter::AppendableExporter;
+use zhang_core::ledger::Ledger;
+
+fn posting(account: &str, number: i32) -> Posting {
+    Posting {
+        flag: None,
+        account: Account::from_str(account).unwrap(),
+        units: Some(Amount::new(BigDecimal::from(number), "CNY")),
+        cost: None,
+        cost_date: None,
+        price: None,
+        meta: Default::default(),
+    }
+}
+
+#[tokio::test]
+async fn should_load_and_append_json_ledger() {
+    let dir = tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("main.json"),
+        indoc! {r#"
+            {
+              "version": 1,
+              "directives": [
+                { "type": "option", "key": "operating_currency", "value": "CNY" },
+                { "type": "include", "file": "accounts.json" },
+                { "type": "transaction", "date": "2023-01-02", "flag": "*", "payee": null, "narration": "lunch", "tags": [], "links": [],
+                  "postings": [
+                    { "flag": null, "account": "Assets:Bank", "units": { "number": "-10", "currency": "CNY" }, "cost": null, "cost_date": null, "price": null },
+                    { "flag": null, "account": "Expenses:Food", "units": null, "cost": null, "cost_date": null, "price": null }
+                  ]
+                }
+              ]
+            }
+        "#},
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("accounts.json"),
+        indoc! {r#"
+            {
+              "version": 1,
+              "directives": [
+                { "type": "open", "date": "2023-01-01", "account": "Assets:Bank", "commodities": ["CNY"] },
+                { "type": "open", "date": "2023-01-01", "account": "Expenses:Food", "commodities": [] }
+              ]
+            }
+        "#},
+    )
+    .unwrap();
+
+    let ledger = Ledger::load::<JsonTransformer>(dir.path().to_path_buf(), "main.json".to_string())
+        .await
+        .unwrap();
+    assert!(ledger.operations().await.errors().await.unwrap().is_empty());
+    assert_eq!(3, ledger.directives.len());
+
+    JsonExporter {}
+        .append_directives(
+            &ledger,
+            vec![Directive::Transaction(Transaction {
+                date: Date::Date(chrono::NaiveDate::from_ymd_opt(2023, 1, 3).unwrap()),
+                flag: Some(Flag::Okay),
+                payee: None,
+                narration: Some(ZhangString::quote("dinner")),
+                tags: Default::default(),
+                links: Default::default(),
+                postings: vec![posting("Assets:Bank", -20), posting("Expenses:Food", 20)],
+                meta: Default::default(),
+            })],
+        )
+        .unwrap();
+
+    let ledger = Ledger::load::<JsonTransformer>(dir.path().to_path_buf(), "main.json".to_string())
+        .await
+        .unwrap();
+    assert!(ledger.operations().await.errors().await.unwrap().is_empty());
+    assert_eq!(4, ledger.directives.len());
+    let last = ledger.directives.last().unwrap();
+    assert_eq!(Some(dir.path().canonicalize().unwrap().join("main.json")), last.span.filename);
+}
+
+#[tokio::test]
+async fn should_replace_and_remove_json_directive() {
+    let dir = tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("main.json"),
+        indoc! {r#"
+            {
+              "version": 1,
+              "directives": [
+                { "type": "open", "date": "2023-01-01", "account": "Assets:Bank", "commodities": ["CNY"] },
+                { "type": "open", "date": "2023-01-01", "account": "Expenses:Food", "commodities": [] },
+                { "type": "transaction", "date": "2023-01-02", "flag": "*", "payee": null, "narration": "lunch", "tags": [], "links": [],
+                  "postings": [
+                    { "flag": null, "account": "Assets:Bank", "units": { "number": "-10", "currency": "CNY" }, "cost": null, "cost_date": null, "price": null },
+                    { "flag": null, "account": "Expenses:Food", "units": null, "cost": null, "cost_date": null, "price": null }
+                  ]
+                }
+              ]
+            }
+        "#},
+    )
+    .unwrap();
+    let load = || Ledger::load::<JsonTransformer>(dir.path().to_path_buf(), "main.json".to_string());
+
+    let ledger = load().await.unwrap();
+    let trx = ledger.directives.iter().find(|it| matches!(it.data, Directive::Transaction(_))).unwrap();
+    let replacement = Directive::Transaction(Transaction {
+        date: Date::Date(chrono::NaiveDate::from_ymd_opt(2023, 1, 2).unwrap()),
+        flag: Some(Flag::Okay),
+        payee: None,
+        narration: Some(ZhangString::quote("brunch")),
+        tags: Default::default(),
+        links: Default::default(),
+        postings: vec![posting("Assets:Bank", -10), posting("Expenses:Food", 10)],
+        meta: Default::default(),
+    });
+    JsonExporter {}.replace_directive(&ledger, &trx.span, Some(replacement)).unwrap();
+    // span is stale once the document is changed
+    assert!(JsonExporter {}.replace_directive(&ledger, &trx.span, None).is_err());
+
+    let ledger = load().await.unwrap();
+    let trx = ledger.directives.iter().find(|it| matches!(it.data, Directive::Transaction(_))).unwrap();
+    match &trx.data {
+        Directive::Transaction(trx) => assert_eq!(Some(ZhangString::quote("brunch")), trx.narration),
+        _ => unreachable!(),
+    }
+    JsonExporter {}.replace_directive(&ledger, &trx.span, None).unwrap();
+
+    let ledger = load().await.unwrap();
+    assert_eq!(2, ledger.directives.len());
+}
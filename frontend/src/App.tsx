@@ -40,7 +40,7 @@ import ToolList from './pages/tools/ToolList';
 import WechatExporter from './pages/tools/WechatExporter';
 import { useAppDispatch, useAppSelector } from './states';
 import { accountsSlice } from './states/account';
-import { basicInfoSlice, fetchBasicInfo } from './states/basic';
+import { basicInfoSlice, fetchBasicInfo, fetchUpdateStatus } from './states/basic';
 import { fetchCommodities } from './states/commodity';
 import { fetchError } from './states/errors';
 import { journalsSlice } from './states/journals';
@@ -188,11 +188,30 @@ export default function App() {
             title: 'Change Detected',
             message: 'trigger ledger info reload',
           });
+          break;
+        case 'TransactionsChanged':
+          dispatch(journalsSlice.actions.clear());
+          break;
+        case 'AccountsChanged':
+          dispatch(accountsSlice.actions.clear());
+          break;
+        case 'CommoditiesChanged':
+          dispatch(fetchCommodities());
+          break;
+        case 'OptionsChanged':
           dispatch(fetchBasicInfo());
+          break;
+        case 'ErrorsChanged':
           dispatch(fetchError(1));
-          dispatch(fetchCommodities());
-          dispatch(accountsSlice.actions.clear());
-          dispatch(journalsSlice.actions.clear());
+          break;
+        case 'ReloadFailed':
+          showNotification({
+            id: 'reload',
+            title: 'Fail to reload ledger',
+            color: 'red',
+            autoClose: false,
+            message: data.error,
+          });
           break;
         case 'Connected':
           showNotification({
@@ -201,6 +220,7 @@ export default function App() {
             message: '',
           });
           dispatch(fetchBasicInfo());
+          dispatch(fetchUpdateStatus());
           break;
         case 'NewVersionFound':
           dispatch(basicInfoSlice.actions.setUpdatableVersion({ newVersion: data.version }));
@@ -7,6 +7,11 @@ export const fetchBasicInfo = createAsyncThunk('basic/fetch', async (thunkApi) =
   return ret;
 });
 
+export const fetchUpdateStatus = createAsyncThunk('basic/fetchUpdateStatus', async (thunkApi) => {
+  const ret = await fetcher(`/api/system/update`);
+  return ret;
+});
+
 interface BasicInfoState {
   title?: string;
   version?: string;
@@ -48,5 +53,11 @@ export const basicInfoSlice = createSlice({
       state.title = action.payload.title;
       state.version = action.payload.version;
     });
+
+    builder.addCase(fetchUpdateStatus.fulfilled, (state, action) => {
+      if (action.payload.has_update) {
+        state.updatableVersion = action.payload.latest_version;
+      }
+    });
   },
 });
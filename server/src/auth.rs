@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::http::Method;
+use actix_web::web::Data;
+use actix_web::Error;
+use actix_web_lab::middleware::Next;
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use zhang_core::error::IoErrorIntoZhangError;
+
+use crate::error::ServerError;
+use crate::ServerResult;
+
+/// credentials accepted by server, requests are rejected unless one of them is given
+#[derive(Debug, Clone, Default)]
+pub struct AuthConfig {
+    /// username to argon2 password hash in PHC string format
+    pub credentials: HashMap<String, String>,
+    /// bearer tokens for api access
+    pub tokens: Vec<String>,
+}
+
+impl AuthConfig {
+    /// build auth config from credential file, credential string and tokens.
+    /// credentials are whitespace separated `username:hash` pairs, lines starting with `#` are ignored.
+    /// return none if nothing is given
+    pub fn load(file: Option<&Path>, credentials: Option<&str>, tokens: Vec<String>) -> ServerResult<Option<AuthConfig>> {
+        let mut config = AuthConfig {
+            credentials: HashMap::new(),
+            tokens: tokens.into_iter().filter(|it| !it.is_empty()).collect(),
+        };
+        if let Some(file) = file {
+            let content = std::fs::read_to_string(file).with_path(file)?;
+            config.add_credentials(&content)?;
+        }
+        if let Some(credentials) = credentials {
+            config.add_credentials(credentials)?;
+        }
+        if config.credentials.is_empty() && config.tokens.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(config))
+    }
+
+    fn add_credentials(&mut self, content: &str) -> ServerResult<()> {
+        let lines = content.lines().map(str::trim).filter(|line| !line.starts_with('#'));
+        for credential in lines.flat_map(str::split_whitespace) {
+            let (username, hash) = credential
+                .split_once(':')
+                .ok_or_else(|| ServerError::BadRequest(format!("credential of `{}` should be in `username:hash` format", credential)))?;
+            PasswordHash::new(hash).map_err(|e| ServerError::BadRequest(format!("invalid password hash of user {}: {}", username, e)))?;
+            self.credentials.insert(username.to_string(), hash.to_string());
+        }
+        Ok(())
+    }
+
+    fn verify_basic(&self, username: &str, password: &str) -> bool {
+        self.credentials
+            .get(username)
+            .and_then(|hash| PasswordHash::new(hash).ok())
+            .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+            .unwrap_or(false)
+    }
+
+    fn verify_token(&self, token: &str) -> bool {
+        self.tokens.iter().any(|it| constant_time_eq(it.as_bytes(), token.as_bytes()))
+    }
+
+    pub fn verify(&self, authorization: &str) -> bool {
+        match authorization.split_once(' ') {
+            Some((scheme, value)) if scheme.eq_ignore_ascii_case("basic") => base64::decode(value.trim())
+                .ok()
+                .and_then(|it| String::from_utf8(it).ok())
+                .and_then(|it| it.split_once(':').map(|(username, password)| self.verify_basic(username, password)))
+                .unwrap_or(false),
+            Some((scheme, value)) if scheme.eq_ignore_ascii_case("bearer") => self.verify_token(value.trim()),
+            _ => false,
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// reject request without valid credential if auth is configured
+pub async fn authenticate(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if let Some(config) = req.app_data::<Data<AuthConfig>>() {
+        let authorization = req.headers().get(AUTHORIZATION).and_then(|it| it.to_str().ok()).unwrap_or_default();
+        if !config.verify(authorization) {
+            return Err(ServerError::Unauthorized.into());
+        }
+    }
+    next.call(req).await
+}
+
+/// marker of server started with `--readonly`
+#[derive(Debug, Clone, Copy)]
+pub struct ReadOnly;
+
+/// reject all mutating requests if server is readonly
+pub async fn guard_readonly(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    // graphql schema has no mutation, queries are posted
+    let is_mutating = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) && !is_graphql(req.path());
+    if is_mutating && req.app_data::<Data<ReadOnly>>().is_some() {
+        return Err(ServerError::ReadOnly.into());
+    }
+    next.call(req).await
+}
+
+/// graphql of the default ledger, or of the ledger scoped by `/api/ledgers/{id}`
+fn is_graphql(path: &str) -> bool {
+    path == "/graphql"
+        || path
+            .strip_prefix("/api/ledgers/")
+            .and_then(|it| it.strip_suffix("/graphql"))
+            .map(|id| !id.is_empty() && !id.contains('/'))
+            .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, try_call_service, TestRequest};
+    use actix_web::web::{self, Data};
+    use actix_web::{App, HttpResponse};
+    use actix_web_lab::middleware::from_fn;
+    use argon2::password_hash::SaltString;
+    use argon2::{Argon2, PasswordHasher};
+
+    use crate::auth::{guard_readonly, AuthConfig, ReadOnly};
+
+    fn hash(password: &str) -> String {
+        let salt = SaltString::encode_b64(b"zhang-test-salt").unwrap();
+        Argon2::default().hash_password(password.as_bytes(), &salt).unwrap().to_string()
+    }
+
+    #[test]
+    fn should_return_none_given_nothing_configured() {
+        assert!(AuthConfig::load(None, None, vec![]).unwrap().is_none());
+    }
+
+    #[test]
+    fn should_verify_basic_credential() {
+        let credentials = format!("# comment\nalice:{}", hash("secret"));
+        let config = AuthConfig::load(None, Some(&credentials), vec![]).unwrap().unwrap();
+
+        assert!(config.verify(&format!("Basic {}", base64::encode("alice:secret"))));
+        assert!(!config.verify(&format!("Basic {}", base64::encode("alice:wrong"))));
+        assert!(!config.verify(&format!("Basic {}", base64::encode("bob:secret"))));
+        assert!(!config.verify("Basic not-base64"));
+        assert!(!config.verify(""));
+    }
+
+    #[test]
+    fn should_verify_bearer_token() {
+        let config = AuthConfig::load(None, None, vec!["token-1".to_string()]).unwrap().unwrap();
+        assert!(config.verify("Bearer token-1"));
+        assert!(!config.verify("Bearer token-2"));
+        assert!(!config.verify("token-1"));
+    }
+
+    #[test]
+    fn should_refuse_invalid_hash() {
+        assert!(AuthConfig::load(None, Some("alice:plain-password"), vec![]).is_err());
+        assert!(AuthConfig::load(None, Some("alice"), vec![]).is_err());
+    }
+
+    #[actix_web::test]
+    async fn should_allow_graphql_queries_of_every_ledger_given_readonly() {
+        let app = init_service(
+            App::new()
+                .app_data(Data::new(ReadOnly))
+                .wrap(from_fn(guard_readonly))
+                .service(
+                    web::scope("/api/ledgers/{id}")
+                        .route("/graphql", web::post().to(HttpResponse::Ok))
+                        .route("/transactions", web::post().to(HttpResponse::Ok)),
+                )
+                .route("/graphql", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        for path in ["/graphql", "/api/ledgers/work/graphql"] {
+            let response = call_service(&app, TestRequest::post().uri(path).to_request()).await;
+            assert_eq!(StatusCode::OK, response.status(), "{} should be queried", path);
+        }
+        for path in ["/api/ledgers/work/transactions", "/api/ledgers/work/transactions/graphql"] {
+            let error = try_call_service(&app, TestRequest::post().uri(path).to_request()).await.err().unwrap();
+            assert_eq!(StatusCode::FORBIDDEN, error.as_response_error().status_code(), "{} should be refused", path);
+        }
+    }
+}
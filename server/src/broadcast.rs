@@ -7,12 +7,33 @@ use serde::Serialize;
 use tokio::sync::Mutex;
 use tokio::time::interval;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type")]
 pub enum BroadcastEvent {
+    /// the ledger is reloaded, sent after the events of what is changed
     Reload,
     Connected,
-    NewVersionFound { version: String },
+    NewVersionFound {
+        version: String,
+    },
+    /// transactions are added, modified or removed
+    TransactionsChanged {
+        ids: Vec<String>,
+    },
+    /// accounts are declared, closed or have different balances
+    AccountsChanged {
+        accounts: Vec<String>,
+    },
+    /// commodities or prices are changed
+    CommoditiesChanged,
+    OptionsChanged,
+    ErrorsChanged {
+        count: usize,
+    },
+    /// the ledger cannot be reloaded, and the previous one is still served
+    ReloadFailed {
+        error: String,
+    },
 }
 
 impl BroadcastEvent {
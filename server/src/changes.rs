@@ -0,0 +1,121 @@
+use itertools::Itertools;
+use zhang_core::domains::schemas::ErrorDomain;
+use zhang_core::ledger::{Ledger, LedgerChanges};
+use zhang_core::ZhangResult;
+
+use crate::broadcast::BroadcastEvent;
+
+/// comparable summary of errors, which are diffed after reloading since they cannot be told by the changed directives.
+///
+/// the rest of the events are told by the changes recorded in reloading, without querying the whole ledger again
+#[derive(Debug, Default)]
+pub struct LedgerDigest {
+    errors: Vec<String>,
+}
+
+impl LedgerDigest {
+    pub async fn of(ledger: &Ledger) -> ZhangResult<LedgerDigest> {
+        let errors = ledger.operations().await.errors().await?.iter().map(error_fingerprint).sorted().collect();
+        Ok(LedgerDigest { errors })
+    }
+
+    /// events of what is changed by the reload since the previous digest, empty if nothing is changed
+    pub fn changes_since(&self, previous: &LedgerDigest, changes: &LedgerChanges) -> Vec<BroadcastEvent> {
+        let mut events = vec![];
+        if !changes.transactions.is_empty() {
+            events.push(BroadcastEvent::TransactionsChanged {
+                ids: changes.transactions.clone(),
+            });
+        }
+        if !changes.accounts.is_empty() {
+            events.push(BroadcastEvent::AccountsChanged {
+                accounts: changes.accounts.clone(),
+            });
+        }
+        if changes.commodities {
+            events.push(BroadcastEvent::CommoditiesChanged);
+        }
+        if changes.options {
+            events.push(BroadcastEvent::OptionsChanged);
+        }
+        if self.errors != previous.errors {
+            events.push(BroadcastEvent::ErrorsChanged { count: self.errors.len() });
+        }
+        events
+    }
+}
+
+fn error_fingerprint(error: &ErrorDomain) -> String {
+    let metas = error.metas.iter().map(|(key, value)| format!("{}={}", key, value)).sorted().join(",");
+    match &error.span {
+        Some(span) => format!("{}|{:?}|{}|{}", error.error_type.as_ref(), span.filename, span.start, metas),
+        None => format!("{}|{}", error.error_type.as_ref(), metas),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use indoc::indoc;
+    use tempfile::tempdir;
+    use zhang_core::ledger::Ledger;
+    use zhang_core::transform::TextTransformer;
+
+    use crate::broadcast::BroadcastEvent;
+    use crate::changes::LedgerDigest;
+
+    const CONTENT: &str = indoc! {r#"
+        option "title" "Example"
+        1970-01-01 open Assets:Bank
+        1970-01-01 open Expenses:Food
+        1970-01-02 "KFC" "lunch"
+          Assets:Bank -10 CNY
+          Expenses:Food 10 CNY
+    "#};
+
+    /// events of reloading the ledger after its content is replaced
+    async fn reload_events(dir: &Path, content: &str) -> Vec<BroadcastEvent> {
+        std::fs::write(dir.join("main.zhang"), CONTENT).unwrap();
+        let ledger = Ledger::load::<TextTransformer>(dir.to_path_buf(), "main.zhang".to_string()).await.unwrap();
+        let previous = LedgerDigest::of(&ledger).await.unwrap();
+
+        std::fs::write(dir.join("main.zhang"), content).unwrap();
+        let reloaded = ledger.reload().await.unwrap();
+        LedgerDigest::of(&reloaded).await.unwrap().changes_since(&previous, &reloaded.changes)
+    }
+
+    #[tokio::test]
+    async fn should_have_no_change_given_same_content() {
+        let dir = tempdir().unwrap();
+        assert!(reload_events(dir.path(), CONTENT).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_detect_appended_transaction() {
+        let dir = tempdir().unwrap();
+        let content = format!("{}1970-01-03 \"KFC\" \"dinner\"\n  Assets:Bank -20 CNY\n  Expenses:Food 20 CNY\n", CONTENT);
+
+        let events = reload_events(dir.path(), &content).await;
+        assert_eq!(2, events.len());
+        match &events[0] {
+            BroadcastEvent::TransactionsChanged { ids } => assert_eq!(1, ids.len()),
+            other => panic!("unexpected event {:?}", other),
+        }
+        match &events[1] {
+            BroadcastEvent::AccountsChanged { accounts } => assert_eq!(vec!["Assets:Bank", "Expenses:Food"], *accounts),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_detect_errors_and_options() {
+        let dir = tempdir().unwrap();
+        let content = format!("{}1970-01-03 balance Assets:Bank 10 CNY\n", CONTENT.replace("Example", "Renamed"));
+
+        let events = reload_events(dir.path(), &content).await;
+        assert!(events.iter().any(|it| matches!(it, BroadcastEvent::OptionsChanged)));
+        assert!(events.iter().any(|it| matches!(it, BroadcastEvent::ErrorsChanged { count: 1 })));
+        assert!(!events.iter().any(|it| matches!(it, BroadcastEvent::CommoditiesChanged)));
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+use zhang_core::error::IoErrorIntoZhangError;
+
+use crate::{ServerError, ServerResult};
+
+/// content of `zhang.toml`, every item can be overridden by command line arguments or `ZHANG_*` environment variables
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    /// address to listen on, `0.0.0.0` by default
+    pub bind: Option<IpAddr>,
+    /// port to listen on, 8000 by default
+    pub port: Option<u16>,
+    /// cache database file of the ledger given by path argument, relative to the config file
+    pub database: Option<PathBuf>,
+    pub readonly: Option<bool>,
+    /// origins allowed by cors, all origins are allowed if empty
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+    #[serde(default)]
+    pub auth: AuthSection,
+    #[serde(default)]
+    pub watcher: WatcherConfig,
+    #[serde(default)]
+    pub update: UpdateSection,
+    /// ledgers served by one process, routes of each are scoped under `/api/ledgers/{id}`
+    #[serde(default)]
+    pub ledgers: Vec<LedgerEntry>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthSection {
+    /// file of `username:hash` lines, relative to the config file
+    pub file: Option<PathBuf>,
+    /// whitespace separated `username:hash` credentials
+    pub credentials: Option<String>,
+    /// bearer tokens for api access
+    #[serde(default)]
+    pub tokens: Vec<String>,
+}
+
+/// file events are batched before reloading, so that saving several files at once triggers only one reload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct WatcherConfig {
+    /// milliseconds to wait after the first event of a batch
+    pub debounce_ms: u64,
+    /// milliseconds to wait after each following event of a batch
+    pub interval_ms: u64,
+}
+
+impl Default for WatcherConfig {
+    fn default() -> Self {
+        WatcherConfig {
+            debounce_ms: 500,
+            interval_ms: 100,
+        }
+    }
+}
+
+impl WatcherConfig {
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateSection {
+    /// check new releases on github, disabled by default
+    pub check: Option<bool>,
+    /// report version info for anonymous statistics, disabled by default
+    pub report: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LedgerEntry {
+    pub id: String,
+    /// base path of the ledger, relative to the config file
+    pub path: PathBuf,
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+    /// cache database file, relative to the config file
+    pub database: Option<PathBuf>,
+}
+
+fn default_endpoint() -> String {
+    "main.zhang".to_owned()
+}
+
+impl FileConfig {
+    /// read and validate the config file, relative paths are resolved against the directory of it
+    pub fn load(file: &Path) -> ServerResult<FileConfig> {
+        let content = std::fs::read_to_string(file).with_path(file)?;
+        let mut config = FileConfig::parse(&content)?;
+        let base = file.parent().unwrap_or_else(|| Path::new("."));
+        config.database = config.database.as_ref().map(|it| base.join(it));
+        config.auth.file = config.auth.file.as_ref().map(|it| base.join(it));
+        for ledger in config.ledgers.iter_mut() {
+            ledger.path = base.join(&ledger.path);
+            ledger.database = ledger.database.as_ref().map(|it| base.join(it));
+        }
+        Ok(config)
+    }
+
+    pub fn parse(content: &str) -> ServerResult<FileConfig> {
+        let config: FileConfig = toml::from_str(content).map_err(|e| ServerError::InvalidConfig(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// check values which cannot be expressed by types, should be called again after overriding any of them
+    pub fn validate(&self) -> ServerResult<()> {
+        for origin in &self.cors_origins {
+            if !(origin.starts_with("http://") || origin.starts_with("https://")) || origin.ends_with('/') {
+                return Err(ServerError::InvalidConfig(format!(
+                    "cors origin `{}` should be like `https://example.com`, with scheme and without trailing slash",
+                    origin
+                )));
+            }
+        }
+        let mut ids = HashSet::new();
+        for ledger in &self.ledgers {
+            if ledger.id.is_empty() || !ledger.id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(ServerError::InvalidConfig(format!(
+                    "ledger id `{}` should only contain ascii letters, digits, `-` and `_`",
+                    ledger.id
+                )));
+            }
+            if !ids.insert(&ledger.id) {
+                return Err(ServerError::InvalidConfig(format!("ledger id `{}` is used more than once", ledger.id)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use indoc::indoc;
+    use tempfile::tempdir;
+
+    use crate::config::FileConfig;
+
+    #[test]
+    fn should_parse_ledgers() {
+        let config = FileConfig::parse(indoc! {r#"
+            [[ledgers]]
+            id = "personal"
+            path = "/data/personal"
+
+            [[ledgers]]
+            id = "business"
+            path = "/data/business"
+            endpoint = "main.bean"
+            database = "/data/business.db"
+        "#})
+        .unwrap();
+        assert_eq!(2, config.ledgers.len());
+        assert_eq!("main.zhang", config.ledgers[0].endpoint);
+        assert_eq!(None, config.ledgers[0].database);
+        assert_eq!("main.bean", config.ledgers[1].endpoint);
+        assert_eq!(Some(PathBuf::from("/data/business.db")), config.ledgers[1].database);
+    }
+
+    #[test]
+    fn should_parse_server_options() {
+        let config = FileConfig::parse(indoc! {r#"
+            bind = "127.0.0.1"
+            port = 18000
+            cors_origins = ["https://zhang.example.com"]
+
+            [auth]
+            tokens = ["token"]
+
+            [watcher]
+            debounce_ms = 1000
+
+            [update]
+            check = false
+        "#})
+        .unwrap();
+        assert_eq!(Some("127.0.0.1".parse().unwrap()), config.bind);
+        assert_eq!(Some(18000), config.port);
+        assert_eq!(vec!["token".to_string()], config.auth.tokens);
+        assert_eq!(1000, config.watcher.debounce_ms);
+        assert_eq!(100, config.watcher.interval_ms);
+        assert_eq!(Some(false), config.update.check);
+        assert_eq!(None, config.update.report);
+        assert!(config.ledgers.is_empty());
+    }
+
+    #[test]
+    fn should_refuse_invalid_server_options() {
+        assert!(FileConfig::parse("bind = \"localhost\"").is_err());
+        assert!(FileConfig::parse("port = 70000").is_err());
+        assert!(FileConfig::parse("cors_origins = [\"zhang.example.com\"]").is_err());
+        assert!(FileConfig::parse("cors_origins = [\"https://zhang.example.com/\"]").is_err());
+        assert!(FileConfig::parse("[watcher]\ndebounce = 1000").is_err());
+    }
+
+    #[test]
+    fn should_refuse_invalid_ledger_id() {
+        assert!(FileConfig::parse("[[ledgers]]\nid = \"my ledger\"\npath = \"/data\"").is_err());
+        assert!(FileConfig::parse("[[ledgers]]\nid = \"\"\npath = \"/data\"").is_err());
+        assert!(FileConfig::parse("[[ledgers]]\nid = \"a\"\npath = \"/a\"\n[[ledgers]]\nid = \"a\"\npath = \"/b\"").is_err());
+        assert!(FileConfig::parse("[[ledgers]]\nid = \"a\"\npath = \"/a\"\nunknown = 1").is_err());
+    }
+
+    #[test]
+    fn should_resolve_paths_against_config_file() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("zhang.toml");
+        std::fs::write(
+            &file,
+            "database = \"cache.db\"\n[auth]\nfile = \"users\"\n[[ledgers]]\nid = \"family\"\npath = \"family\"\ndatabase = \"family.db\"\n",
+        )
+        .unwrap();
+        let config = FileConfig::load(&file).unwrap();
+        assert_eq!(Some(dir.path().join("cache.db")), config.database);
+        assert_eq!(Some(dir.path().join("users")), config.auth.file);
+        assert_eq!(dir.path().join("family"), config.ledgers[0].path);
+        assert_eq!(Some(dir.path().join("family.db")), config.ledgers[0].database);
+    }
+}
@@ -1,5 +1,6 @@
 use thiserror::Error;
 use zhang_ast::account::InvalidAccountError;
+use zhang_ast::SpanInfo;
 use zhang_core::ZhangError;
 
 #[derive(Error, Debug)]
@@ -15,6 +16,36 @@ pub enum ServerError {
 
     #[error("io error: {0}")]
     IoError(#[from] std::io::Error),
+
+    #[error("csv error: {0}")]
+    CsvError(#[from] csv::Error),
+
+    #[error("xlsx error: {0}")]
+    XlsxError(#[from] rust_xlsxwriter::XlsxError),
+
+    #[error("{0} not found")]
+    NotFound(String),
+
+    #[error("bad request: {0}")]
+    BadRequest(String),
+
+    #[error("invalid content of file {path}: {error}")]
+    InvalidFileContent { path: String, error: ZhangError },
+
+    #[error("directive has been changed since the ledger was loaded")]
+    StaleDirective(SpanInfo),
+
+    #[error("unauthorized")]
+    Unauthorized,
+
+    #[error("ledger is served in readonly mode")]
+    ReadOnly,
+
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+
+    #[error("release source error: {0}")]
+    ReleaseSourceError(String),
 }
 
 impl From<InvalidAccountError> for ServerError {
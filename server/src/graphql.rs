@@ -0,0 +1,540 @@
+use std::str::FromStr;
+
+use actix_web::web::{Data, Json};
+use actix_web::{get, post, HttpRequest, HttpResponse, Responder};
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{ComplexObject, Context, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use zhang_core::database::type_ext::big_decimal::ZhangBigDecimal;
+use zhang_core::domains::schemas::{
+    AccountDomain, CommodityDomain, DocumentDomain, ErrorDomain, JournalDomain, JournalFilter, JournalPostingDomain, MetaDomain, MetaType, OptionDomain,
+    PriceDomain,
+};
+use zhang_core::domains::Operations;
+
+use crate::LedgerState;
+
+pub type LedgerSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// nesting deeper than this is most likely a cyclic query, like `posting { transaction { postings { ... } } }`
+const MAX_QUERY_DEPTH: usize = 16;
+
+pub fn schema(ledger: LedgerState) -> LedgerSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(ledger)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .finish()
+}
+
+#[post("/graphql")]
+pub async fn graphql(schema: Data<LedgerSchema>, Json(request): Json<async_graphql::Request>) -> Json<async_graphql::Response> {
+    Json(schema.execute(request).await)
+}
+
+/// playground of the graphql endpoint on the same path, which differs between ledgers
+#[get("/graphql")]
+pub async fn graphiql(req: HttpRequest) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(GraphiQLSource::build().endpoint(req.path()).finish())
+}
+
+async fn operations(ctx: &Context<'_>) -> async_graphql::Result<Operations<'static>> {
+    let ledger = ctx.data::<LedgerState>()?.read().await;
+    Ok(ledger.operations().await)
+}
+
+fn parse_amount(amount: Option<String>) -> async_graphql::Result<Option<BigDecimal>> {
+    Ok(amount.map(|it| BigDecimal::from_str(&it)).transpose()?)
+}
+
+#[derive(SimpleObject)]
+pub struct Amount {
+    pub number: String,
+    pub commodity: String,
+}
+
+impl Amount {
+    fn new(number: &ZhangBigDecimal, commodity: impl Into<String>) -> Self {
+        Amount {
+            number: number.0.to_string(),
+            commodity: commodity.into(),
+        }
+    }
+    fn optional(number: &Option<ZhangBigDecimal>, commodity: &Option<String>) -> Option<Self> {
+        match (number, commodity) {
+            (Some(number), Some(commodity)) => Some(Amount::new(number, commodity.clone())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Meta {
+    pub key: String,
+    pub value: String,
+}
+
+impl From<MetaDomain> for Meta {
+    fn from(value: MetaDomain) -> Self {
+        Meta {
+            key: value.key,
+            value: value.value,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Account {
+    pub name: String,
+    pub account_type: String,
+    pub status: String,
+    pub alias: Option<String>,
+    pub open_date: NaiveDateTime,
+}
+
+impl From<AccountDomain> for Account {
+    fn from(value: AccountDomain) -> Self {
+        Account {
+            name: value.name,
+            account_type: value.r#type,
+            status: value.status.as_ref().to_string(),
+            alias: value.alias,
+            open_date: value.date,
+        }
+    }
+}
+
+#[ComplexObject]
+impl Account {
+    /// latest balance of each commodity
+    async fn balances(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Amount>> {
+        let balances = operations(ctx).await?.single_account_balances(&self.name).await?;
+        Ok(balances.iter().map(|it| Amount::new(&it.balance_number, &it.balance_commodity)).collect())
+    }
+
+    /// transactions posting to the account or its sub accounts, latest first
+    async fn journals(
+        &self, ctx: &Context<'_>, from: Option<NaiveDateTime>, to: Option<NaiveDateTime>, #[graphql(default = 100)] limit: u32, #[graphql(default)] offset: u32,
+    ) -> async_graphql::Result<TransactionPage> {
+        let filter = JournalFilter {
+            from,
+            to,
+            account: Some(self.name.clone()),
+            ..Default::default()
+        };
+        TransactionPage::query(ctx, &filter, limit, offset).await
+    }
+
+    async fn documents(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Document>> {
+        let documents = operations(ctx).await?.account_documents(&self.name).await?;
+        Ok(documents.into_iter().map(Document::from).collect())
+    }
+
+    async fn metas(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Meta>> {
+        let metas = operations(ctx).await?.metas(MetaType::AccountMeta, &self.name).await?;
+        Ok(metas.into_iter().map(Meta::from).collect())
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct TransactionPage {
+    pub total_count: i64,
+    pub records: Vec<Transaction>,
+}
+
+impl TransactionPage {
+    async fn query(ctx: &Context<'_>, filter: &JournalFilter, limit: u32, offset: u32) -> async_graphql::Result<Self> {
+        let (total_count, journals) = operations(ctx).await?.journals(filter, limit, offset).await?;
+        Ok(TransactionPage {
+            total_count,
+            records: journals.into_iter().map(Transaction).collect(),
+        })
+    }
+}
+
+pub struct Transaction(JournalDomain);
+
+#[Object]
+impl Transaction {
+    async fn id(&self) -> &str {
+        &self.0.id
+    }
+    async fn sequence(&self) -> i64 {
+        self.0.sequence
+    }
+    async fn datetime(&self) -> NaiveDateTime {
+        self.0.datetime
+    }
+    /// flag of transaction, or `BalanceCheck` and `BalancePad` for balance directives
+    async fn flag(&self) -> &str {
+        &self.0.journal_type
+    }
+    async fn payee(&self) -> &str {
+        &self.0.payee
+    }
+    async fn narration(&self) -> Option<&str> {
+        self.0.narration.as_deref()
+    }
+    async fn tags(&self) -> &[String] {
+        &self.0.tags
+    }
+    async fn links(&self) -> &[String] {
+        &self.0.links
+    }
+    async fn metas(&self) -> Vec<Meta> {
+        self.0.metas.iter().cloned().map(Meta::from).collect()
+    }
+    async fn postings(&self) -> Vec<Posting> {
+        self.0.postings.iter().cloned().map(Posting).collect()
+    }
+    async fn documents(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Document>> {
+        let documents = operations(ctx).await?.transaction_documents(&self.0.id).await?;
+        Ok(documents.into_iter().map(Document::from).collect())
+    }
+}
+
+pub struct Posting(JournalPostingDomain);
+
+#[Object]
+impl Posting {
+    async fn account_name(&self) -> &str {
+        &self.0.account
+    }
+    async fn account(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Account>> {
+        Ok(operations(ctx).await?.account(&self.0.account).await?.map(Account::from))
+    }
+    async fn transaction(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Transaction>> {
+        transaction(ctx, self.0.trx_id.clone()).await
+    }
+    async fn unit(&self) -> Option<Amount> {
+        Amount::optional(&self.0.unit_number, &self.0.unit_commodity)
+    }
+    async fn cost(&self) -> Option<Amount> {
+        Amount::optional(&self.0.cost_number, &self.0.cost_commodity)
+    }
+    async fn price(&self) -> Option<Amount> {
+        Amount::optional(&self.0.price_number, &self.0.price_commodity)
+    }
+    /// unit of posting, which is inferred if it's omitted in the ledger
+    async fn inferred_unit(&self) -> Amount {
+        Amount::new(&self.0.inferred_unit_number, &self.0.inferred_unit_commodity)
+    }
+    async fn balance_before(&self) -> Amount {
+        Amount::new(&self.0.account_before_number, &self.0.account_before_commodity)
+    }
+    async fn balance_after(&self) -> Amount {
+        Amount::new(&self.0.account_after_number, &self.0.account_after_commodity)
+    }
+    async fn metas(&self) -> Vec<Meta> {
+        self.0.metas.iter().cloned().map(Meta::from).collect()
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Commodity {
+    pub name: String,
+    pub precision: i32,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub rounding: Option<String>,
+}
+
+impl From<CommodityDomain> for Commodity {
+    fn from(value: CommodityDomain) -> Self {
+        Commodity {
+            name: value.name,
+            precision: value.precision,
+            prefix: value.prefix,
+            suffix: value.suffix,
+            rounding: value.rounding,
+        }
+    }
+}
+
+#[ComplexObject]
+impl Commodity {
+    async fn prices(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Price>> {
+        let prices = operations(ctx).await?.prices(Some(&self.name)).await?;
+        Ok(prices.into_iter().map(Price::from).collect())
+    }
+
+    async fn metas(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Meta>> {
+        let metas = operations(ctx).await?.metas(MetaType::CommodityMeta, &self.name).await?;
+        Ok(metas.into_iter().map(Meta::from).collect())
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Price {
+    pub datetime: NaiveDateTime,
+    pub commodity: String,
+    pub amount: Amount,
+}
+
+impl From<PriceDomain> for Price {
+    fn from(value: PriceDomain) -> Self {
+        Price {
+            datetime: value.datetime,
+            amount: Amount::new(&value.amount, value.target_commodity),
+            commodity: value.commodity,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Document {
+    pub datetime: NaiveDateTime,
+    pub filename: String,
+    pub path: String,
+    pub extension: Option<String>,
+    pub account_name: Option<String>,
+    pub transaction_id: Option<String>,
+}
+
+impl From<DocumentDomain> for Document {
+    fn from(value: DocumentDomain) -> Self {
+        Document {
+            datetime: value.datetime,
+            filename: value.filename,
+            path: value.path,
+            extension: value.extension,
+            account_name: value.account,
+            transaction_id: value.trx_id,
+        }
+    }
+}
+
+#[ComplexObject]
+impl Document {
+    async fn account(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Account>> {
+        match &self.account_name {
+            Some(name) => Ok(operations(ctx).await?.account(name).await?.map(Account::from)),
+            None => Ok(None),
+        }
+    }
+
+    async fn transaction(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Transaction>> {
+        match &self.transaction_id {
+            Some(id) => transaction(ctx, id.clone()).await,
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Span {
+    pub filename: Option<String>,
+    pub start: usize,
+    pub end: usize,
+    pub content: String,
+}
+
+#[derive(SimpleObject)]
+pub struct LedgerError {
+    pub id: String,
+    pub error_type: String,
+    pub span: Option<Span>,
+    pub metas: Vec<Meta>,
+}
+
+impl From<ErrorDomain> for LedgerError {
+    fn from(value: ErrorDomain) -> Self {
+        LedgerError {
+            id: value.id,
+            error_type: value.error_type.as_ref().to_string(),
+            span: value.span.map(|span| Span {
+                filename: span.filename.map(|it| it.display().to_string()),
+                start: span.start,
+                end: span.end,
+                content: span.content,
+            }),
+            metas: value.metas.into_iter().map(|(key, value)| Meta { key, value }).collect(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct LedgerOption {
+    pub key: String,
+    pub value: String,
+}
+
+impl From<OptionDomain> for LedgerOption {
+    fn from(value: OptionDomain) -> Self {
+        LedgerOption {
+            key: value.key,
+            value: value.value,
+        }
+    }
+}
+
+async fn transaction(ctx: &Context<'_>, id: String) -> async_graphql::Result<Option<Transaction>> {
+    let filter = JournalFilter {
+        id: Some(id),
+        ..Default::default()
+    };
+    let (_, journals) = operations(ctx).await?.journals(&filter, 1, 0).await?;
+    Ok(journals.into_iter().next().map(Transaction))
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn accounts(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Account>> {
+        let accounts = operations(ctx).await?.accounts().await?;
+        Ok(accounts.into_iter().map(Account::from).collect())
+    }
+
+    async fn account(&self, ctx: &Context<'_>, name: String) -> async_graphql::Result<Option<Account>> {
+        Ok(operations(ctx).await?.account(&name).await?.map(Account::from))
+    }
+
+    /// transactions matching all given conditions, latest first
+    #[allow(clippy::too_many_arguments)]
+    async fn transactions(
+        &self, ctx: &Context<'_>, from: Option<NaiveDateTime>, to: Option<NaiveDateTime>,
+        #[graphql(desc = "account name, its sub accounts are included")] account: Option<String>,
+        #[graphql(desc = "full text search on payee and narration")] keyword: Option<String>, tag: Option<String>, link: Option<String>, flag: Option<String>,
+        min_amount: Option<String>, max_amount: Option<String>, commodity: Option<String>, #[graphql(default = 100)] limit: u32,
+        #[graphql(default)] offset: u32,
+    ) -> async_graphql::Result<TransactionPage> {
+        let filter = JournalFilter {
+            id: None,
+            from,
+            to,
+            account,
+            keyword,
+            tag,
+            link,
+            flag,
+            min_amount: parse_amount(min_amount)?,
+            max_amount: parse_amount(max_amount)?,
+            commodity,
+        };
+        TransactionPage::query(ctx, &filter, limit, offset).await
+    }
+
+    async fn transaction(&self, ctx: &Context<'_>, id: String) -> async_graphql::Result<Option<Transaction>> {
+        transaction(ctx, id).await
+    }
+
+    async fn commodities(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Commodity>> {
+        let commodities = operations(ctx).await?.commodities().await?;
+        Ok(commodities.into_iter().map(Commodity::from).collect())
+    }
+
+    async fn commodity(&self, ctx: &Context<'_>, name: String) -> async_graphql::Result<Option<Commodity>> {
+        Ok(operations(ctx).await?.commodity(&name).await?.map(Commodity::from))
+    }
+
+    /// prices of all commodities if `commodity` is not given
+    async fn prices(&self, ctx: &Context<'_>, commodity: Option<String>) -> async_graphql::Result<Vec<Price>> {
+        let prices = operations(ctx).await?.prices(commodity.as_deref()).await?;
+        Ok(prices.into_iter().map(Price::from).collect())
+    }
+
+    async fn documents(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Document>> {
+        let documents = operations(ctx).await?.documents().await?;
+        Ok(documents.into_iter().map(Document::from).collect())
+    }
+
+    async fn errors(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<LedgerError>> {
+        let errors = operations(ctx).await?.errors().await?;
+        Ok(errors.into_iter().map(LedgerError::from).collect())
+    }
+
+    async fn options(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<LedgerOption>> {
+        let options = operations(ctx).await?.options().await?;
+        Ok(options.into_iter().map(LedgerOption::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use bigdecimal::BigDecimal;
+    use indoc::indoc;
+    use tempfile::tempdir;
+    use tokio::sync::RwLock;
+    use zhang_core::ledger::Ledger;
+    use zhang_core::transform::TextTransformer;
+
+    use crate::graphql::{schema, LedgerSchema};
+
+    async fn schema_from_str(content: &str) -> LedgerSchema {
+        let dir = tempdir().unwrap().into_path();
+        std::fs::write(dir.join("main.zhang"), content).unwrap();
+        let ledger = Ledger::load::<TextTransformer>(dir, "main.zhang".to_string()).await.unwrap();
+        schema(Arc::new(RwLock::new(ledger)))
+    }
+
+    #[tokio::test]
+    async fn should_query_nested_account_journals() {
+        let schema = schema_from_str(indoc! {r#"
+            1970-01-01 open Assets:Bank
+            1970-01-01 open Expenses:Food
+            1970-01-02 "KFC" "lunch" #food
+              Assets:Bank -10 CNY
+              Expenses:Food 10 CNY
+        "#})
+        .await;
+
+        let response = schema
+            .execute(r#"{ account(name: "Assets:Bank") { balances { number commodity } journals { totalCount records { payee tags postings { accountName unit { number } } } } } }"#)
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        let account = &data["account"];
+        let balance = BigDecimal::from_str(account["balances"][0]["number"].as_str().unwrap()).unwrap();
+        assert_eq!(BigDecimal::from(-10), balance);
+        assert_eq!(1, account["journals"]["totalCount"]);
+        let record = &account["journals"]["records"][0];
+        assert_eq!("KFC", record["payee"]);
+        assert_eq!("food", record["tags"][0]);
+        assert_eq!(2, record["postings"].as_array().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn should_resolve_transaction_from_posting() {
+        let schema = schema_from_str(indoc! {r#"
+            1970-01-01 open Assets:Bank
+            1970-01-01 open Expenses:Food
+            1970-01-02 "KFC" "lunch"
+              Assets:Bank -10 CNY
+              Expenses:Food 10 CNY
+        "#})
+        .await;
+
+        let response = schema
+            .execute(r#"{ transactions(account: "Expenses") { records { id postings { account { name } transaction { id narration } } } } }"#)
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        let record = &data["transactions"]["records"][0];
+        let posting = &record["postings"][0];
+        assert_eq!(record["id"], posting["transaction"]["id"]);
+        assert_eq!("lunch", posting["transaction"]["narration"]);
+        assert!(posting["account"]["name"].is_string());
+    }
+
+    #[tokio::test]
+    async fn should_reject_too_deep_query() {
+        let schema = schema_from_str("1970-01-01 open Assets:Bank\n").await;
+        let nested = "transaction { postings { ".repeat(10);
+        let query = format!(
+            "{{ transactions {{ records {{ postings {{ {}accountName{} }} }} }} }}",
+            nested,
+            " } }".repeat(10)
+        );
+        let response = schema.execute(query).await;
+        assert!(!response.errors.is_empty());
+    }
+}
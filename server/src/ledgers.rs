@@ -0,0 +1,189 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use actix_web::web::{Data, ServiceConfig};
+use futures_util::future;
+use log::{debug, error, info, trace};
+use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::sync::RwLock;
+use zhang_core::exporter::AppendableExporter;
+use zhang_core::ledger::Ledger;
+use zhang_core::utils::has_path_visited;
+use zhang_core::ZhangResult;
+
+use crate::broadcast::{BroadcastEvent, Broadcaster};
+use crate::changes::LedgerDigest;
+use crate::config::WatcherConfig;
+use crate::graphql::LedgerSchema;
+use crate::{LedgerConfig, LedgerState};
+
+/// a ledger served by the server, with its own watcher and broadcaster
+pub struct HostedLedger {
+    pub id: String,
+    pub ledger: LedgerState,
+    pub broadcaster: Arc<Broadcaster>,
+    exporter: Arc<dyn AppendableExporter>,
+    schema: LedgerSchema,
+}
+
+impl HostedLedger {
+    pub async fn load(config: LedgerConfig) -> ZhangResult<HostedLedger> {
+        info!("loading ledger {} from {}", config.id, config.path.display());
+        let ledger = Ledger::load_with_database(config.path, config.endpoint, config.database, config.transformer).await?;
+        let ledger = Arc::new(RwLock::new(ledger));
+        Ok(HostedLedger {
+            id: config.id,
+            schema: crate::graphql::schema(ledger.clone()),
+            ledger,
+            broadcaster: Broadcaster::create(),
+            exporter: config.exporter,
+        })
+    }
+
+    /// share the ledger with all routes of the scope
+    pub fn configure(&self, cfg: &mut ServiceConfig) {
+        cfg.app_data(Data::from(self.broadcaster.clone()))
+            .app_data(Data::new(self.ledger.clone()))
+            .app_data(Data::new(self.schema.clone()))
+            .app_data(Data::from(self.exporter.clone()));
+    }
+
+    /// reload the ledger whenever any of its visited files is modified
+    pub fn watch(&self, config: WatcherConfig) {
+        let ledger = self.ledger.clone();
+        let broadcaster = self.broadcaster.clone();
+        let id = self.id.clone();
+        tokio::spawn(async move {
+            let (mut watcher, mut rx) = async_watcher().unwrap();
+
+            let (entry_path, mut digest) = {
+                let guard1 = ledger.read().await;
+                (guard1.entry.0.clone(), digest_of(&guard1).await)
+            };
+            info!("watching {} for ledger {}", &entry_path.to_str().unwrap_or(""), id);
+            watcher.watch(entry_path.as_path(), RecursiveMode::Recursive).expect("cannot watch entry path");
+            'looper: loop {
+                let mut all = vec![];
+                match rx.recv().await {
+                    Some(event) => all.push(event),
+                    None => break 'looper,
+                }
+                tokio::time::sleep(config.debounce()).await;
+                'each_time: loop {
+                    let result = rx.try_recv();
+                    match result {
+                        Ok(event) => {
+                            all.push(event);
+                            tokio::time::sleep(config.interval()).await;
+                        }
+                        Err(TryRecvError::Empty) => break 'each_time,
+                        Err(TryRecvError::Disconnected) => break 'looper,
+                    }
+                }
+                trace!("receive all file changes: {:?}", all);
+                let guard = ledger.read().await;
+                let is_visited_file_updated = all
+                    .into_iter()
+                    .filter_map(|event| event.ok())
+                    .filter(|event| {
+                        let include_visited_files = event.paths.iter().any(|path| has_path_visited(&guard.visited_files, path));
+                        include_visited_files && event.kind.is_modify()
+                    })
+                    .count()
+                    > 0;
+
+                drop(guard);
+
+                if is_visited_file_updated {
+                    debug!("gotcha event, start reloading...");
+                    info!("receive file event and reload ledger {}", id);
+                    let start_time = Instant::now();
+                    // the new ledger is built aside from a copy, so that neither requests nor writes are blocked until swapping it in
+                    let current = ledger.read().await.clone();
+                    let reloaded = current.reload().await;
+                    match reloaded {
+                        Ok(reloaded) => {
+                            let reloaded_digest = digest_of(&reloaded).await;
+                            let events = reloaded_digest.changes_since(&digest, &reloaded.changes);
+                            let mut guard = ledger.write().await;
+                            debug!("watcher: got the lock");
+                            *guard = reloaded;
+                            drop(guard);
+                            let duration = start_time.elapsed();
+                            info!("ledger {} is reloaded successfully in {:?}", id, duration);
+                            for event in events {
+                                broadcaster.broadcast(event).await;
+                            }
+                            digest = reloaded_digest;
+                            broadcaster.broadcast(BroadcastEvent::Reload).await;
+                        }
+                        Err(err) => {
+                            error!("error on reloading ledger {}: {}", id, err);
+                            broadcaster.broadcast(BroadcastEvent::ReloadFailed { error: err.to_string() }).await;
+                        }
+                    };
+                }
+            }
+        });
+    }
+}
+
+/// an empty digest is used if errors of the ledger cannot be queried, so that they are reported as changed on next reload
+async fn digest_of(ledger: &Ledger) -> LedgerDigest {
+    LedgerDigest::of(ledger).await.unwrap_or_else(|e| {
+        error!("cannot summarize the ledger: {}", e);
+        LedgerDigest::default()
+    })
+}
+
+fn async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
+    let (tx, rx) = channel(1);
+
+    // Automatically select the best implementation for your platform.
+    // You can also access each implementation directly e.g. INotifyWatcher.
+    let watcher = RecommendedWatcher::new(
+        move |res| {
+            futures::executor::block_on(async {
+                tx.send(res).await.unwrap();
+            })
+        },
+        Config::default(),
+    )?;
+
+    Ok((watcher, rx))
+}
+
+/// all ledgers served by one process, the first one is the default ledger
+pub struct Ledgers {
+    ledgers: Vec<HostedLedger>,
+}
+
+impl Ledgers {
+    pub fn new(ledgers: Vec<HostedLedger>) -> Ledgers {
+        assert!(!ledgers.is_empty(), "at least one ledger should be served");
+        Ledgers { ledgers }
+    }
+
+    pub fn default_ledger(&self) -> &HostedLedger {
+        &self.ledgers[0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HostedLedger> {
+        self.ledgers.iter()
+    }
+
+    /// number of sse clients of all ledgers
+    pub async fn client_number(&self) -> usize {
+        future::join_all(self.ledgers.iter().map(|it| it.broadcaster.client_number()))
+            .await
+            .into_iter()
+            .sum()
+    }
+
+    /// broadcast the event to sse clients of all ledgers
+    pub async fn broadcast(&self, event: BroadcastEvent) {
+        future::join_all(self.ledgers.iter().map(|it| it.broadcaster.broadcast(event.clone()))).await;
+    }
+}
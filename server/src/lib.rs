@@ -1,200 +1,185 @@
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use actix_cors::Cors;
-use actix_web::web::Data;
-use actix_web::{App, HttpServer};
-use log::{debug, error, info, trace};
-use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
-use self_update::version::bump_is_greater;
-use serde::Serialize;
-use tokio::sync::mpsc::error::TryRecvError;
-use tokio::sync::mpsc::{channel, Receiver};
+use actix_web::web::{Data, JsonConfig, PathConfig, QueryConfig, ServiceConfig};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use actix_web_lab::middleware::from_fn;
+use log::{info, warn};
 use tokio::sync::RwLock;
 use zhang_core::exporter::AppendableExporter;
 use zhang_core::ledger::Ledger;
 use zhang_core::transform::Transformer;
-use zhang_core::utils::has_path_visited;
 use zhang_core::ZhangResult;
 
-use crate::broadcast::{BroadcastEvent, Broadcaster};
+use crate::auth::{authenticate, guard_readonly, AuthConfig, ReadOnly};
+use crate::broadcast::BroadcastEvent;
+use crate::config::WatcherConfig;
 use crate::error::ServerError;
+use crate::graphql::graphiql;
+use crate::ledgers::{HostedLedger, Ledgers};
 use crate::response::ResponseWrapper;
 use crate::route::*;
+use crate::update::{ReleaseSource, UpdateChecker};
 
+pub mod auth;
 pub mod broadcast;
+pub mod changes;
+pub mod config;
 pub mod error;
+pub mod graphql;
+pub mod ledgers;
+pub mod openapi;
 pub mod request;
 pub mod response;
 pub mod route;
+pub mod tabular;
+pub mod update;
 pub mod util;
 
 pub type ServerResult<T> = Result<T, ServerError>;
 
 pub type LedgerState = Arc<RwLock<Ledger>>;
 
-fn async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
-    let (tx, rx) = channel(1);
+/// interval of checking new releases and reporting version
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
-    // Automatically select the best implementation for your platform.
-    // You can also access each implementation directly e.g. INotifyWatcher.
-    let watcher = RecommendedWatcher::new(
-        move |res| {
-            futures::executor::block_on(async {
-                tx.send(res).await.unwrap();
-            })
-        },
-        Config::default(),
-    )?;
-
-    Ok((watcher, rx))
-}
-
-pub struct ServeConfig {
+/// a ledger to serve, routes of it are scoped under `/api/ledgers/{id}`
+pub struct LedgerConfig {
+    pub id: String,
     pub path: PathBuf,
     pub endpoint: String,
-    pub port: u16,
     pub database: Option<PathBuf>,
-    pub no_report: bool,
     pub exporter: Arc<dyn AppendableExporter>,
     pub transformer: Arc<dyn Transformer>,
 }
 
-pub async fn serve(opts: ServeConfig) -> ZhangResult<()> {
-    info!("version: {}, build date: {}", env!("CARGO_PKG_VERSION"), env!("ZHANG_BUILD_DATE"));
-    let database = opts.database.clone();
-    let ledger = Ledger::load_with_database(opts.path.clone(), opts.endpoint.clone(), database, opts.transformer.clone()).await?;
-    let ledger_data = Arc::new(RwLock::new(ledger));
-
-    let cloned_ledger = ledger_data.clone();
-    let broadcaster = Broadcaster::create();
-    let cloned_broadcaster = broadcaster.clone();
-    tokio::spawn(async move {
-        let (mut watcher, mut rx) = async_watcher().unwrap();
+pub struct ServeConfig {
+    /// the first ledger is the default one, which is also served under `/api/v1`, `/api` and `/graphql`
+    pub ledgers: Vec<LedgerConfig>,
+    pub bind: IpAddr,
+    pub port: u16,
+    /// origins allowed by cors, all origins are allowed if empty
+    pub cors_origins: Vec<String>,
+    pub watcher: WatcherConfig,
+    /// check new releases of the release source, disabled by default for air-gapped environments
+    pub update_check: bool,
+    /// report version info to the release source for anonymous statistics, disabled by default
+    pub report: bool,
+    pub release_source: Arc<dyn ReleaseSource>,
+    /// require credentials for all requests if present
+    pub auth: Option<AuthConfig>,
+    /// disable all routes that modify the ledger
+    pub readonly: bool,
+}
 
-        let entry_path = {
-            let guard1 = cloned_ledger.read().await;
-            guard1.entry.0.clone()
-        };
-        info!("watching {}", &entry_path.to_str().unwrap_or(""));
-        watcher.watch(entry_path.as_path(), RecursiveMode::Recursive).expect("cannot watch entry path");
-        'looper: loop {
-            let mut all = vec![];
-            match rx.recv().await {
-                Some(event) => all.push(event),
-                None => break 'looper,
-            }
-            tokio::time::sleep(Duration::from_millis(500)).await;
-            'each_time: loop {
-                let result = rx.try_recv();
-                match result {
-                    Ok(event) => {
-                        all.push(event);
-                        tokio::time::sleep(Duration::from_millis(100)).await;
-                    }
-                    Err(TryRecvError::Empty) => break 'each_time,
-                    Err(TryRecvError::Disconnected) => break 'looper,
+pub async fn serve(mut opts: ServeConfig) -> ZhangResult<()> {
+    info!("version: {}, build date: {}", env!("CARGO_PKG_VERSION"), env!("ZHANG_BUILD_DATE"));
+    let mut hosted = vec![];
+    for config in std::mem::take(&mut opts.ledgers) {
+        hosted.push(HostedLedger::load(config).await?);
+    }
+    let ledgers = Arc::new(Ledgers::new(hosted));
+    for ledger in ledgers.iter() {
+        ledger.watch(opts.watcher);
+    }
+    let update_checker = Arc::new(UpdateChecker::new(opts.release_source.clone(), opts.update_check));
+    if opts.update_check {
+        let update_checker = update_checker.clone();
+        let ledgers = ledgers.clone();
+        tokio::spawn(async move {
+            let mut check_interval = tokio::time::interval(UPDATE_INTERVAL);
+            info!("start zhang's update checking task");
+            loop {
+                check_interval.tick().await;
+                if let Some(version) = update_checker.check().await {
+                    ledgers.broadcast(BroadcastEvent::NewVersionFound { version }).await;
                 }
             }
-            trace!("receive all file changes: {:?}", all);
-            let guard = cloned_ledger.read().await;
-            let is_visited_file_updated = all
-                .into_iter()
-                .filter_map(|event| event.ok())
-                .filter(|event| {
-                    let include_visited_files = event.paths.iter().any(|path| has_path_visited(&guard.visited_files, path));
-                    include_visited_files && event.kind.is_modify()
-                })
-                .count()
-                > 0;
-
-            drop(guard);
-
-            if is_visited_file_updated {
-                debug!("gotcha event, start reloading...");
-                let mut guard = cloned_ledger.write().await;
-                debug!("watcher: got the lock");
-                info!("receive file event and reload ledger");
-                let start_time = Instant::now();
-                match guard.reload().await {
-                    Ok(_) => {
-                        let duration = start_time.elapsed();
-                        info!("ledger is reloaded successfully in {:?}", duration);
-                        cloned_broadcaster.broadcast(BroadcastEvent::Reload).await;
-                    }
-                    Err(err) => {
-                        error!("error on reload: {}", err)
-                    }
-                };
-            }
-        }
-    });
-    let update_checker_broadcaster = broadcaster.clone();
-    tokio::spawn(async move {
-        let mut report_interval = tokio::time::interval(Duration::from_secs(60));
-        loop {
-            report_interval.tick().await;
-            update_checker(update_checker_broadcaster.clone()).await.ok();
-        }
-    });
-
-    if !opts.no_report {
-        tokio::spawn(async {
-            let mut report_interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        });
+    }
+    if opts.report {
+        let update_checker = update_checker.clone();
+        tokio::spawn(async move {
+            let mut report_interval = tokio::time::interval(UPDATE_INTERVAL);
             info!("start zhang's version report task");
             loop {
                 report_interval.tick().await;
-                match version_report_task().await {
-                    Ok(_) => {
-                        debug!("report zhang's version successfully");
-                    }
-                    Err(e) => {
-                        debug!("fail to report zhang's version: {}", e);
-                    }
-                }
+                update_checker.report().await;
             }
         });
     }
-    start_server(opts, ledger_data, broadcaster.clone()).await
+    start_server(opts, ledgers, update_checker).await
 }
 
-async fn start_server(opts: ServeConfig, ledger_data: Arc<RwLock<Ledger>>, broadcaster: Arc<Broadcaster>) -> ZhangResult<()> {
-    let addr = SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), opts.port);
-    info!("zhang is listening on http://127.0.0.1:{}/", opts.port);
-    let exporter: Data<dyn AppendableExporter> = Data::from(opts.exporter);
+async fn start_server(opts: ServeConfig, ledgers: Arc<Ledgers>, update_checker: Arc<UpdateChecker>) -> ZhangResult<()> {
+    let addr = SocketAddr::new(opts.bind, opts.port);
+    // an unspecified address is not reachable in browsers
+    let visible_addr = match opts.bind.is_unspecified() {
+        true => SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), opts.port),
+        false => addr,
+    };
+    info!("zhang is listening on http://{}/", visible_addr);
+    if opts.auth.is_none() {
+        warn!("authentication is not configured, everyone who can reach the port has full access to the ledger");
+    }
+    if opts.readonly {
+        info!("ledger is served in readonly mode");
+    }
+    let auth = opts.auth.map(Data::new);
+    let readonly = opts.readonly.then(|| Data::new(ReadOnly));
+    let ledgers = Data::from(ledgers);
+    let update_checker = Data::from(update_checker);
+    let cors_origins = opts.cors_origins;
     Ok(HttpServer::new(move || {
-        let app = App::new()
-            .wrap(Cors::permissive())
-            .app_data(Data::from(broadcaster.clone()))
-            .app_data(Data::new(ledger_data.clone()))
-            .app_data(exporter.clone())
-            .service(get_basic_info)
-            .service(get_info_for_new_transactions)
-            .service(get_statistic_data)
-            .service(current_statistic)
-            .service(get_journals)
-            .service(create_new_transaction)
-            .service(get_account_list)
-            .service(get_account_info)
-            .service(get_account_documents)
-            .service(get_account_journals)
-            .service(upload_account_document)
-            .service(upload_transaction_document)
-            .service(create_account_balance)
-            .service(create_batch_account_balances)
-            .service(get_documents)
-            .service(download_document)
-            .service(get_all_commodities)
-            .service(get_single_commodity)
-            .service(get_files)
-            .service(get_file_content)
-            .service(update_file_content)
-            .service(get_report)
-            .service(get_errors)
-            .service(get_all_options)
-            .service(sse);
+        let mut app = App::new();
+        if let Some(auth) = &auth {
+            app = app.app_data(auth.clone());
+        }
+        if let Some(readonly) = &readonly {
+            app = app.app_data(readonly.clone());
+        }
+        let mut app = app
+            .wrap(from_fn(guard_readonly))
+            .wrap(from_fn(authenticate))
+            .wrap(cors(&cors_origins))
+            .app_data(ledgers.clone())
+            .app_data(update_checker.clone())
+            .configure(|cfg| ledgers.default_ledger().configure(cfg))
+            .app_data(JsonConfig::default().error_handler(|e, _| ServerError::BadRequest(e.to_string()).into()))
+            .app_data(QueryConfig::default().error_handler(|e, _| ServerError::BadRequest(e.to_string()).into()))
+            .app_data(PathConfig::default().error_handler(|e, _| ServerError::BadRequest(e.to_string()).into()));
+        // scopes of ledgers go first, otherwise they are taken by the `/api` scope
+        for ledger in ledgers.iter() {
+            app = app.service(
+                web::scope(&format!("/api/ledgers/{}", ledger.id))
+                    .configure(|cfg| ledger.configure(cfg))
+                    .configure(api_services)
+                    .service(graphql::graphql)
+                    .service(graphiql)
+                    .default_service(web::to(api_not_found)),
+            );
+        }
+        let app = app
+            // `/api` is kept as an alias of the first version for existing clients
+            .service(
+                web::scope("/api/v1")
+                    .service(get_ledgers)
+                    .service(get_update_status)
+                    .configure(api_services)
+                    .default_service(web::to(api_not_found)),
+            )
+            .service(
+                web::scope("/api")
+                    .service(get_ledgers)
+                    .service(get_update_status)
+                    .configure(api_services)
+                    .default_service(web::to(api_not_found)),
+            )
+            .service(graphql::graphql)
+            .service(graphiql);
 
         #[cfg(feature = "frontend")]
         {
@@ -211,50 +196,59 @@ async fn start_server(opts: ServeConfig, ledger_data: Arc<RwLock<Ledger>>, broad
     .await?)
 }
 
-async fn version_report_task() -> ServerResult<()> {
-    #[derive(Serialize)]
-    struct VersionReport<'a> {
-        version: &'a str,
-        build_date: &'a str,
+fn cors(origins: &[String]) -> Cors {
+    if origins.is_empty() {
+        return Cors::permissive();
     }
-    debug!("reporting zhang's version");
-    let client = reqwest::Client::new();
-    client
-        .post("https://zhang.resource.rs")
-        .json(&VersionReport {
-            version: env!("CARGO_PKG_VERSION"),
-            build_date: env!("ZHANG_BUILD_DATE"),
-        })
-        .timeout(Duration::from_secs(10))
-        .send()
-        .await?;
-    Ok(())
+    origins
+        .iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .allow_any_method()
+        .allow_any_header()
+        .supports_credentials()
+        .max_age(3600)
 }
 
-async fn update_checker(broadcast: Arc<Broadcaster>) -> ServerResult<()> {
-    if broadcast.client_number().await < 1 {
-        return Ok(());
-    }
+/// all rest api of a ledger, mounted under both `/api/v1` and `/api` for the default ledger, and `/api/ledgers/{id}` for each ledger
+fn api_services(cfg: &mut ServiceConfig) {
+    cfg.service(get_basic_info)
+        .service(get_info_for_new_transactions)
+        .service(get_statistic_data)
+        .service(current_statistic)
+        .service(get_journals)
+        .service(create_new_transaction)
+        .service(update_transaction)
+        .service(delete_transaction)
+        .service(get_account_list)
+        .service(get_account_tree)
+        .service(get_account_info)
+        .service(get_account_documents)
+        .service(get_account_journals)
+        .service(get_account_balance_history)
+        .service(upload_account_document)
+        .service(upload_transaction_document)
+        .service(create_account_balance)
+        .service(create_batch_account_balances)
+        .service(get_documents)
+        .service(download_document)
+        .service(get_all_commodities)
+        .service(get_single_commodity)
+        .service(get_tags)
+        .service(get_tag)
+        .service(get_links)
+        .service(get_link)
+        .service(get_files)
+        .service(get_file_content)
+        .service(update_file_content)
+        .service(get_report)
+        .service(get_errors)
+        .service(get_all_options)
+        .service(get_openapi)
+        .service(sse);
+}
 
-    let latest_release = tokio::task::spawn_blocking(move || {
-        self_update::backends::github::Update::configure()
-            .repo_owner("zhang-accounting")
-            .repo_name("zhang")
-            .bin_name("zhang")
-            .current_version(env!("CARGO_PKG_VERSION"))
-            .build()
-            .unwrap()
-            .get_latest_release()
-    })
-    .await
-    .expect("cannot spawn update checker task");
-    dbg!(&latest_release);
-    if let Ok(release) = latest_release {
-        if bump_is_greater(env!("CARGO_PKG_VERSION"), &release.version).unwrap_or(false) {
-            broadcast.broadcast(BroadcastEvent::NewVersionFound { version: release.version }).await;
-        }
-    }
-    Ok(())
+async fn api_not_found(req: HttpRequest) -> ServerResult<HttpResponse> {
+    Err(ServerError::NotFound(format!("api {}", req.path())))
 }
 
 pub type ApiResult<T> = ServerResult<ResponseWrapper<T>>;
@@ -0,0 +1,103 @@
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::route;
+
+/// openapi document of all rest api, served at `/api/v1/openapi.json`
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "zhang",
+        description = "rest api of zhang, json responses are wrapped in `data` field, and errors are wrapped in `error` field with a machine readable `code`. \
+                       routes of the default ledger are listed, and each served ledger has the same routes except `/ledgers` and `/system/update` under `/api/ledgers/{id}`",
+        license(name = "Apache-2.0")
+    ),
+    paths(
+        route::sse,
+        route::get_ledgers,
+        route::get_update_status,
+        route::get_basic_info,
+        route::get_info_for_new_transactions,
+        route::get_statistic_data,
+        route::current_statistic,
+        route::get_journals,
+        route::create_new_transaction,
+        route::update_transaction,
+        route::delete_transaction,
+        route::upload_transaction_document,
+        route::download_document,
+        route::get_account_list,
+        route::get_account_tree,
+        route::get_account_info,
+        route::get_documents,
+        route::upload_account_document,
+        route::get_account_documents,
+        route::get_account_journals,
+        route::get_account_balance_history,
+        route::create_account_balance,
+        route::create_batch_account_balances,
+        route::get_all_commodities,
+        route::get_single_commodity,
+        route::get_tags,
+        route::get_tag,
+        route::get_links,
+        route::get_link,
+        route::get_files,
+        route::get_file_content,
+        route::update_file_content,
+        route::get_errors,
+        route::get_report,
+        route::get_all_options,
+        route::get_openapi,
+    ),
+    modifiers(&SecuritySchemes, &ApiVersion),
+    security(("basic" = []), ("bearer" = []))
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("basic", SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)));
+        components.add_security_scheme("bearer", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+/// routes are declared relative to the api scope, so the version prefix is added here
+struct ApiVersion;
+
+impl Modify for ApiVersion {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let paths = std::mem::take(&mut openapi.paths.paths);
+        openapi.paths.paths = paths.into_iter().map(|(path, item)| (format!("/api/v1{}", path), item)).collect();
+    }
+}
+
+/// pretty printed openapi document, used for exporting
+pub fn openapi_json() -> String {
+    ApiDoc::openapi().to_pretty_json().expect("cannot serialize openapi document")
+}
+
+#[cfg(test)]
+mod test {
+    use utoipa::OpenApi;
+
+    use crate::openapi::ApiDoc;
+
+    #[test]
+    fn should_document_all_routes_with_wrapped_schemas() {
+        let openapi = ApiDoc::openapi();
+        assert!(openapi.paths.paths.contains_key("/api/v1/journals"));
+        assert!(openapi.paths.paths.contains_key("/api/v1/accounts/{account_name}/balances"));
+        assert!(openapi.paths.paths.contains_key("/api/v1/openapi.json"));
+
+        let schemas = openapi.components.unwrap().schemas;
+        assert!(schemas.contains_key("JournalItemResponse"));
+        assert!(schemas.contains_key("AccountJournalDomain"));
+        assert!(schemas.keys().any(|it| it.starts_with("SuccessWrapper")));
+        assert!(schemas.contains_key("ErrorWrapper"));
+        assert!(schemas.contains_key("SpanInfo"));
+    }
+}
@@ -1,47 +1,124 @@
 use std::cmp::max;
 
 use bigdecimal::BigDecimal;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, Utc, Weekday};
 use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Deserialize)]
+use zhang_core::domains::schemas::JournalFilter;
+
+use crate::tabular::ExportFormat;
+
+#[derive(Deserialize, ToSchema)]
 #[serde(tag = "type")]
 pub enum AccountBalanceRequest {
     Check { account_name: String, amount: AmountRequest },
     Pad { account_name: String, amount: AmountRequest, pad: String },
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct FileUpdateRequest {
     pub content: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub enum StatisticInterval {
     Day,
     Week,
     Month,
 }
 
-#[derive(Deserialize)]
+impl StatisticInterval {
+    /// sqlite `strftime` format grouping datetime into the interval
+    pub fn period_format(&self) -> &'static str {
+        match self {
+            StatisticInterval::Day => "%Y-%m-%d",
+            StatisticInterval::Week => "%Y-W%W",
+            StatisticInterval::Month => "%Y-%m",
+        }
+    }
+
+    /// whether the date is the last day of its interval, weeks end on sunday
+    pub fn is_period_end(&self, date: NaiveDate) -> bool {
+        match self {
+            StatisticInterval::Day => true,
+            StatisticInterval::Week => date.weekday() == Weekday::Sun,
+            StatisticInterval::Month => date.succ_opt().map(|next| next.month() != date.month()).unwrap_or(true),
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct StatisticRequest {
     pub from: DateTime<Utc>,
     pub to: DateTime<Utc>,
     pub interval: StatisticInterval,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AccountBalanceHistoryRequest {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub interval: Option<StatisticInterval>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ReportRequest {
     pub from: DateTime<Utc>,
     pub to: DateTime<Utc>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LabelRequest {
+    pub interval: Option<StatisticInterval>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportRequest {
+    pub format: Option<ExportFormat>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct JournalRequest {
     page: Option<u32>,
     size: Option<u32>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// account name, its sub accounts are included
+    pub account: Option<String>,
+    /// full text search on payee and narration
+    pub keyword: Option<String>,
+    pub tag: Option<String>,
+    pub link: Option<String>,
+    pub flag: Option<String>,
+    #[param(value_type = Option<String>)]
+    pub min_amount: Option<BigDecimal>,
+    #[param(value_type = Option<String>)]
+    pub max_amount: Option<BigDecimal>,
+    pub commodity: Option<String>,
 }
 impl JournalRequest {
+    pub fn filter(&self) -> JournalFilter {
+        JournalFilter {
+            from: self.from.map(|it| it.naive_local()),
+            to: self.to.map(|it| it.naive_local()),
+            account: self.account.clone(),
+            keyword: self.keyword.clone(),
+            tag: self.tag.clone(),
+            link: self.link.clone(),
+            flag: self.flag.clone(),
+            min_amount: self.min_amount.clone(),
+            max_amount: self.max_amount.clone(),
+            commodity: self.commodity.clone(),
+            ..Default::default()
+        }
+    }
     pub fn page(&self) -> u32 {
         max(self.page.unwrap_or(1), 1)
     }
@@ -54,7 +131,7 @@ impl JournalRequest {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct CreateTransactionRequest {
     pub datetime: DateTime<Utc>,
     pub payee: String,
@@ -63,21 +140,37 @@ pub struct CreateTransactionRequest {
     pub metas: Vec<MetaRequest>,
     pub tags: Vec<String>,
     pub links: Vec<String>,
+    /// `*` or `!`, the flag of updated transaction is kept if it is not given
+    #[serde(default)]
+    pub flag: Option<String>,
 }
 
-#[derive(Deserialize)]
+/// cost, price, flag and metas of the posting in updated transaction are kept if they are not given
+#[derive(Deserialize, ToSchema)]
 pub struct CreateTransactionPostingRequest {
     pub account: String,
     pub unit: Option<AmountRequest>,
+    /// cost per unit, like `{10 USD}`
+    #[serde(default)]
+    pub cost: Option<AmountRequest>,
+    /// price per unit, like `@ 10 USD`
+    #[serde(default)]
+    pub price: Option<AmountRequest>,
+    /// `*` or `!`
+    #[serde(default)]
+    pub flag: Option<String>,
+    #[serde(default)]
+    pub metas: Option<Vec<MetaRequest>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct AmountRequest {
+    #[schema(value_type = String)]
     pub number: BigDecimal,
     pub commodity: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct MetaRequest {
     pub key: String,
     pub value: String,
@@ -1,62 +1,208 @@
 use std::collections::HashMap;
 
 use actix_web::body::EitherBody;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType, WWW_AUTHENTICATE};
 use actix_web::http::StatusCode;
 use actix_web::{HttpRequest, HttpResponse, Responder, ResponseError};
 use chrono::{NaiveDate, NaiveDateTime};
+use log::error;
 use serde::Serialize;
-use sqlx::FromRow;
+use utoipa::ToSchema;
 use zhang_ast::amount::Amount;
+use zhang_ast::SpanInfo;
 use zhang_core::database::type_ext::big_decimal::ZhangBigDecimal;
-use zhang_core::domains::schemas::{AccountJournalDomain, AccountStatus, MetaDomain};
+use zhang_core::domains::schemas::{
+    AccountJournalDomain, AccountStatus, CommodityLotDomain, CommoditySummaryDomain, DocumentDomain, JournalDomain, MetaDomain, PriceDomain,
+};
+use zhang_core::ZhangError;
 
+use crate::tabular::{render, ExportFormat, Tabular};
 use crate::{ServerError, ServerResult};
 
+/// envelope of all json responses
+#[derive(Serialize, ToSchema)]
+pub struct SuccessWrapper<T: Serialize> {
+    pub data: T,
+}
+
 pub enum ResponseWrapper<T: Serialize> {
     Json(T),
+    File {
+        filename: String,
+        content_type: &'static str,
+        content: Vec<u8>,
+    },
     Created,
-    NotFound,
 }
 
 impl<T: Serialize> ResponseWrapper<T> {
     pub fn json(data: T) -> ServerResult<ResponseWrapper<T>> {
         Ok(ResponseWrapper::Json(data))
     }
+    /// respond as spreadsheet file if format is given, otherwise fallback to json
+    pub fn tabular(data: T, format: Option<ExportFormat>, name: &str) -> ServerResult<ResponseWrapper<T>>
+    where
+        T: Tabular,
+    {
+        match format {
+            Some(format) => Ok(ResponseWrapper::File {
+                filename: format!("{}.{}", name, format.extension()),
+                content_type: format.content_type(),
+                content: render(format, data.sheets())?,
+            }),
+            None => ResponseWrapper::json(data),
+        }
+    }
     pub fn created() -> ServerResult<ResponseWrapper<T>> {
         Ok(ResponseWrapper::Created)
     }
-    pub fn not_found() -> ServerResult<ResponseWrapper<T>> {
-        Ok(ResponseWrapper::NotFound)
-    }
 }
 
 impl<T: Serialize> Responder for ResponseWrapper<T> {
     type Body = EitherBody<String>;
 
     fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
-        #[derive(Serialize)]
-        pub struct SuccessWrapper<T: Serialize> {
-            data: T,
-        }
         match self {
             ResponseWrapper::Json(data) => {
                 let wrapper = SuccessWrapper { data };
                 let json = actix_web::web::Json(wrapper);
                 json.respond_to(req)
             }
+            ResponseWrapper::File {
+                filename,
+                content_type,
+                content,
+            } => HttpResponse::Ok()
+                .content_type(content_type)
+                .insert_header(ContentDisposition {
+                    disposition: DispositionType::Attachment,
+                    parameters: vec![DispositionParam::Filename(filename)],
+                })
+                .body(content)
+                .map_into_right_body(),
             ResponseWrapper::Created => HttpResponse::Created().message_body(EitherBody::new("".to_string())).unwrap(),
-            ResponseWrapper::NotFound => HttpResponse::NotFound().message_body(EitherBody::new("".to_string())).unwrap(),
+        }
+    }
+}
+
+/// envelope of all error responses
+#[derive(Serialize, ToSchema)]
+pub struct ErrorWrapper {
+    pub error: ErrorResponse,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Option<ErrorDetails>,
+}
+
+/// machine readable kind of error, clients should rely on it rather than the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    BadRequest,
+    InvalidFileContent,
+    Unauthorized,
+    ReadOnly,
+    NotFound,
+    Conflict,
+    InternalError,
+}
+
+impl ErrorCode {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest | ErrorCode::InvalidFileContent => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::ReadOnly => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ErrorDetails {
+    /// position of the invalid content in submitted file
+    FileContent(FileValidationErrorResponse),
+    /// source of the directive which has been changed
+    Span(SpanInfo),
+}
+
+impl ServerError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ServerError::BadRequest(_) | ServerError::CoreError(ZhangError::InvalidAccount | ZhangError::InvalidDate) => ErrorCode::BadRequest,
+            ServerError::InvalidFileContent { .. } => ErrorCode::InvalidFileContent,
+            ServerError::Unauthorized => ErrorCode::Unauthorized,
+            ServerError::ReadOnly => ErrorCode::ReadOnly,
+            ServerError::NotFound(_) => ErrorCode::NotFound,
+            ServerError::IoError(e) if e.kind() == std::io::ErrorKind::NotFound => ErrorCode::NotFound,
+            ServerError::DatabaseError(sqlx::Error::RowNotFound) | ServerError::CoreError(ZhangError::DatabaseError(sqlx::Error::RowNotFound)) => {
+                ErrorCode::NotFound
+            }
+            ServerError::StaleDirective(_) | ServerError::CoreError(ZhangError::StaleSpan(_)) => ErrorCode::Conflict,
+            _ => ErrorCode::InternalError,
+        }
+    }
+
+    fn details(&self) -> Option<ErrorDetails> {
+        match self {
+            ServerError::InvalidFileContent { path, error } => {
+                let (line, column) = match error {
+                    ZhangError::SyntaxError { line, column, .. } => (Some(*line), Some(*column)),
+                    _ => (None, None),
+                };
+                Some(ErrorDetails::FileContent(FileValidationErrorResponse {
+                    path: path.clone(),
+                    line,
+                    column,
+                    message: error.to_string(),
+                }))
+            }
+            ServerError::StaleDirective(span) => Some(ErrorDetails::Span(span.clone())),
+            _ => None,
         }
     }
 }
 
 impl ResponseError for ServerError {
     fn status_code(&self) -> StatusCode {
-        StatusCode::INTERNAL_SERVER_ERROR
+        self.code().status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let code = self.code();
+        if code == ErrorCode::InternalError {
+            error!("{}", self);
+        }
+        let mut response = HttpResponse::build(code.status_code());
+        if let ServerError::Unauthorized = self {
+            response.insert_header((WWW_AUTHENTICATE, r#"Basic realm="zhang""#));
+        }
+        response.json(ErrorWrapper {
+            error: ErrorResponse {
+                code,
+                message: self.to_string(),
+                details: self.details(),
+            },
+        })
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
+pub struct FileValidationErrorResponse {
+    pub path: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct Pageable<T: Serialize> {
     pub total_count: u32,
     pub total_page: u32,
@@ -67,7 +213,7 @@ pub struct Pageable<T: Serialize> {
 
 impl<T: Serialize> Pageable<T> {
     pub fn new(total_count: u32, page: u32, size: u32, records: Vec<T>) -> Self {
-        let total_page = total_count / size + u32::from(total_count % size != 0);
+        let total_page = total_count / size + u32::from(!total_count.is_multiple_of(size));
         Self {
             total_count,
             total_page,
@@ -78,7 +224,7 @@ impl<T: Serialize> Pageable<T> {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct AccountResponse {
     pub name: String,
     pub status: AccountStatus,
@@ -86,7 +232,29 @@ pub struct AccountResponse {
     pub amount: CalculatedAmount,
 }
 
-#[derive(Serialize, FromRow)]
+#[derive(Serialize, ToSchema)]
+pub struct AccountTreeNodeResponse {
+    pub name: String,
+    pub leaf: String,
+    /// parent account which is never opened
+    pub synthetic: bool,
+    pub status: Option<AccountStatus>,
+    pub alias: Option<String>,
+    /// balance of the account itself
+    pub amount: CalculatedAmount,
+    /// balance of the account and all its sub accounts
+    pub subtree_amount: CalculatedAmount,
+    #[schema(no_recursion)]
+    pub children: Vec<AccountTreeNodeResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AccountBalancePointResponse {
+    pub date: NaiveDate,
+    pub balance: CalculatedAmount,
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct DocumentResponse {
     pub datetime: NaiveDateTime,
     pub filename: String,
@@ -95,21 +263,33 @@ pub struct DocumentResponse {
     pub account: Option<String>,
     pub trx_id: Option<String>,
 }
+impl From<DocumentDomain> for DocumentResponse {
+    fn from(value: DocumentDomain) -> Self {
+        DocumentResponse {
+            datetime: value.datetime,
+            filename: value.filename,
+            path: value.path,
+            extension: value.extension,
+            account: value.account,
+            trx_id: value.trx_id,
+        }
+    }
+}
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct StatisticFrameResponse {
     datetime: NaiveDateTime,
     amount: ZhangBigDecimal,
     commodity: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct StatisticResponse {
     pub changes: HashMap<NaiveDate, HashMap<String, AmountResponse>>, // summaries:
     pub details: HashMap<NaiveDate, HashMap<String, AmountResponse>>,
 }
 
-#[derive(Serialize, FromRow)]
+#[derive(Serialize, ToSchema)]
 pub struct MetaResponse {
     key: String,
     value: String,
@@ -123,7 +303,7 @@ impl From<MetaDomain> for MetaResponse {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(tag = "type")]
 pub enum JournalItemResponse {
     Transaction(JournalTransactionItemResponse),
@@ -141,7 +321,65 @@ impl JournalItemResponse {
     }
 }
 
-#[derive(Serialize)]
+impl From<JournalDomain> for JournalItemResponse {
+    fn from(journal: JournalDomain) -> Self {
+        let postings = journal
+            .postings
+            .into_iter()
+            .map(|posting| JournalTransactionPostingResponse {
+                account: posting.account,
+                unit_number: posting.unit_number,
+                unit_commodity: posting.unit_commodity,
+                cost_number: posting.cost_number,
+                cost_commodity: posting.cost_commodity,
+                price_number: posting.price_number,
+                price_commodity: posting.price_commodity,
+                inferred_unit_number: posting.inferred_unit_number,
+                inferred_unit_commodity: posting.inferred_unit_commodity,
+                account_before_number: posting.account_before_number,
+                account_before_commodity: posting.account_before_commodity,
+                account_after_number: posting.account_after_number,
+                account_after_commodity: posting.account_after_commodity,
+                metas: posting.metas.into_iter().map(|it| it.into()).collect(),
+            })
+            .collect();
+        match journal.journal_type.as_str() {
+            "BalancePad" => JournalItemResponse::BalancePad(JournalBalancePadItemResponse {
+                id: journal.id,
+                sequence: journal.sequence,
+                datetime: journal.datetime,
+                payee: journal.payee,
+                narration: journal.narration,
+                type_: journal.journal_type,
+                postings,
+            }),
+            "BalanceCheck" => JournalItemResponse::BalanceCheck(JournalBalanceCheckItemResponse {
+                id: journal.id,
+                sequence: journal.sequence,
+                datetime: journal.datetime,
+                payee: journal.payee,
+                narration: journal.narration,
+                type_: journal.journal_type,
+                postings,
+            }),
+            _ => JournalItemResponse::Transaction(JournalTransactionItemResponse {
+                id: journal.id,
+                sequence: journal.sequence,
+                datetime: journal.datetime,
+                payee: journal.payee,
+                narration: journal.narration,
+                tags: journal.tags,
+                links: journal.links,
+                flag: journal.journal_type,
+                is_balanced: true,
+                postings,
+                metas: journal.metas.into_iter().map(|it| it.into()).collect(),
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct JournalTransactionItemResponse {
     pub id: String,
     pub sequence: i64,
@@ -155,7 +393,7 @@ pub struct JournalTransactionItemResponse {
     pub postings: Vec<JournalTransactionPostingResponse>,
     pub metas: Vec<MetaResponse>,
 }
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct JournalTransactionPostingResponse {
     pub account: String,
     pub unit_number: Option<ZhangBigDecimal>,
@@ -170,9 +408,10 @@ pub struct JournalTransactionPostingResponse {
     pub account_before_commodity: String,
     pub account_after_number: ZhangBigDecimal,
     pub account_after_commodity: String,
+    pub metas: Vec<MetaResponse>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct JournalBalanceCheckItemResponse {
     pub id: String,
     pub sequence: i64,
@@ -183,7 +422,7 @@ pub struct JournalBalanceCheckItemResponse {
     pub(crate) postings: Vec<JournalTransactionPostingResponse>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct JournalBalancePadItemResponse {
     pub id: String,
     pub sequence: i64,
@@ -194,19 +433,19 @@ pub struct JournalBalancePadItemResponse {
     pub(crate) postings: Vec<JournalTransactionPostingResponse>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct InfoForNewTransaction {
     pub payee: Vec<String>,
     pub account_name: Vec<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct CalculatedAmount {
     pub calculated: AmountResponse,
     pub detail: HashMap<String, ZhangBigDecimal>,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, ToSchema)]
 pub struct AmountResponse {
     pub number: ZhangBigDecimal,
     pub commodity: String,
@@ -221,7 +460,7 @@ impl From<Amount> for AmountResponse {
     }
 }
 
-#[derive(FromRow, Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct CommodityListItemResponse {
     pub name: String,
     pub precision: i32,
@@ -233,8 +472,23 @@ pub struct CommodityListItemResponse {
     pub latest_price_amount: Option<ZhangBigDecimal>,
     pub latest_price_commodity: Option<String>,
 }
+impl From<CommoditySummaryDomain> for CommodityListItemResponse {
+    fn from(value: CommoditySummaryDomain) -> Self {
+        CommodityListItemResponse {
+            name: value.name,
+            precision: value.precision,
+            prefix: value.prefix,
+            suffix: value.suffix,
+            rounding: value.rounding,
+            total_amount: value.total_amount,
+            latest_price_date: value.latest_price_date,
+            latest_price_amount: value.latest_price_amount,
+            latest_price_commodity: value.latest_price_commodity,
+        }
+    }
+}
 
-#[derive(FromRow, Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct CommodityLot {
     pub datetime: Option<NaiveDateTime>,
     pub amount: ZhangBigDecimal,
@@ -242,28 +496,48 @@ pub struct CommodityLot {
     pub price_commodity: Option<String>,
     pub account: String,
 }
+impl From<CommodityLotDomain> for CommodityLot {
+    fn from(value: CommodityLotDomain) -> Self {
+        CommodityLot {
+            datetime: value.datetime,
+            amount: value.amount,
+            price_amount: value.price_amount,
+            price_commodity: value.price_commodity,
+            account: value.account,
+        }
+    }
+}
 
-#[derive(FromRow, Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct CommodityPrice {
     pub datetime: NaiveDateTime,
     pub amount: ZhangBigDecimal,
     pub target_commodity: Option<String>,
 }
+impl From<PriceDomain> for CommodityPrice {
+    fn from(value: PriceDomain) -> Self {
+        CommodityPrice {
+            datetime: value.datetime,
+            amount: value.amount,
+            target_commodity: Some(value.target_commodity),
+        }
+    }
+}
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct CommodityDetailResponse {
     pub info: CommodityListItemResponse,
     pub lots: Vec<CommodityLot>,
     pub prices: Vec<CommodityPrice>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct FileDetailResponse {
     pub path: String,
     pub content: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct CurrentStatisticResponse {
     pub balance: CalculatedAmount,
     pub liability: CalculatedAmount,
@@ -271,7 +545,7 @@ pub struct CurrentStatisticResponse {
     pub expense: AmountResponse,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct ReportResponse {
     pub from: NaiveDateTime,
     pub to: NaiveDateTime,
@@ -287,20 +561,39 @@ pub struct ReportResponse {
     pub expense_rank: Vec<ReportRankItemResponse>,
     pub expense_top_transactions: Vec<AccountJournalDomain>,
 }
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct ReportRankItemResponse {
     pub account: String,
     pub percent: ZhangBigDecimal,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct BasicInfo {
     pub title: Option<String>,
     pub version: String,
     pub build_date: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
+pub struct LedgerItemResponse {
+    pub id: String,
+    pub title: Option<String>,
+    /// the default ledger is also served under `/api/v1`
+    pub default: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UpdateStatusResponse {
+    /// whether update checking is enabled by config
+    pub enabled: bool,
+    pub current_version: String,
+    /// version of the latest release, absent if it has never been checked successfully
+    pub latest_version: Option<String>,
+    pub has_update: bool,
+    pub checked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct AccountInfoResponse {
     pub date: NaiveDateTime,
     pub r#type: String,
@@ -309,3 +602,90 @@ pub struct AccountInfoResponse {
     pub alias: Option<String>,
     pub amount: CalculatedAmount,
 }
+
+#[derive(Serialize, ToSchema)]
+pub struct LabelResponse {
+    pub name: String,
+    pub count: i64,
+    pub first_datetime: NaiveDateTime,
+    pub last_datetime: NaiveDateTime,
+    pub periods: Vec<LabelPeriodResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LabelPeriodResponse {
+    pub period: String,
+    /// net amount of income and expenses postings
+    pub amounts: Vec<AmountResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LabelDetailResponse {
+    pub name: String,
+    /// whether every account shared by multiple transactions is cleared to zero
+    pub balanced: bool,
+    pub accounts: Vec<LabelAccountEffectResponse>,
+    pub journals: Vec<JournalItemResponse>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LabelAccountEffectResponse {
+    pub account: String,
+    pub amounts: Vec<AmountResponse>,
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::body::to_bytes;
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+    use serde_json::{json, Value};
+    use zhang_ast::SpanInfo;
+
+    use crate::ServerError;
+
+    async fn error_body(error: ServerError) -> (StatusCode, Value) {
+        let response = error.error_response();
+        let status = response.status();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn should_wrap_error_in_envelope() {
+        let (status, body) = error_body(ServerError::NotFound("account Assets:Bank".to_string())).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        assert_eq!(
+            json!({"error": {"code": "NOT_FOUND", "message": "account Assets:Bank not found", "details": null}}),
+            body
+        );
+    }
+
+    #[actix_web::test]
+    async fn should_report_span_of_stale_directive() {
+        let span = SpanInfo {
+            start: 10,
+            end: 20,
+            content: "content".to_string(),
+            filename: Some("main.zhang".into()),
+        };
+        let (status, body) = error_body(ServerError::StaleDirective(span)).await;
+        assert_eq!(StatusCode::CONFLICT, status);
+        assert_eq!("CONFLICT", body["error"]["code"]);
+        assert_eq!(
+            json!({"type": "span", "start": 10, "end": 20, "content": "content", "filename": "main.zhang"}),
+            body["error"]["details"]
+        );
+    }
+
+    #[actix_web::test]
+    async fn should_map_missing_file_to_not_found() {
+        let (status, body) = error_body(ServerError::IoError(std::io::Error::from(std::io::ErrorKind::NotFound))).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        assert_eq!("NOT_FOUND", body["error"]["code"]);
+
+        let (status, body) = error_body(ServerError::IoError(std::io::Error::from(std::io::ErrorKind::PermissionDenied))).await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+        assert_eq!("INTERNAL_ERROR", body["error"]["code"]);
+    }
+}
@@ -1,68 +1,75 @@
-use std::collections::HashMap;
+use std::cmp::max;
+use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
 use std::io::Write;
 use std::iter::FromIterator;
-use std::ops::{Add, AddAssign, Div, Mul};
-use std::path::PathBuf;
+use std::ops::{Add, AddAssign, Bound, Div, Mul};
+use std::path::{Component, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 
 use actix_files::NamedFile;
 use actix_multipart::Multipart;
 use actix_web::web::{Data, Json, Path, Query};
-use actix_web::{get, post, put, web, Responder};
+use actix_web::{delete, get, post, put, web, Responder};
 use bigdecimal::{BigDecimal, Zero};
-use chrono::{Local, NaiveDate, NaiveDateTime};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
 use futures_util::StreamExt;
 use glob::glob;
 use indexmap::IndexSet;
 use itertools::Itertools;
 use log::{error, info};
 use now::TimeZoneNow;
-use sqlx::FromRow;
 use tokio::sync::RwLock;
+use utoipa::OpenApi;
 use uuid::Uuid;
 
 use zhang_core::database::type_ext::big_decimal::ZhangBigDecimal;
 use zhang_core::error::IoErrorIntoZhangError;
 use zhang_core::ledger::Ledger;
+use zhang_core::utils::id::FromSpan;
 use zhang_core::utils::string_::StringExt;
 
 use crate::broadcast::Broadcaster;
-use crate::request::{AccountBalanceRequest, CreateTransactionRequest, FileUpdateRequest, JournalRequest, ReportRequest, StatisticRequest};
+use crate::ledgers::Ledgers;
+use crate::openapi::ApiDoc;
+use crate::request::{
+    AccountBalanceHistoryRequest, AccountBalanceRequest, CreateTransactionRequest, ExportRequest, FileUpdateRequest, JournalRequest, LabelRequest,
+    ReportRequest, StatisticInterval, StatisticRequest,
+};
 use crate::response::{
-    AccountInfoResponse, AccountResponse, AmountResponse, BasicInfo, CalculatedAmount, CommodityDetailResponse, CommodityListItemResponse, CommodityLot,
-    CommodityPrice, CurrentStatisticResponse, DocumentResponse, FileDetailResponse, InfoForNewTransaction, JournalBalanceCheckItemResponse,
-    JournalBalancePadItemResponse, JournalItemResponse, JournalTransactionItemResponse, JournalTransactionPostingResponse, Pageable, ReportRankItemResponse,
-    ReportResponse, ResponseWrapper, StatisticResponse,
+    AccountBalancePointResponse, AccountInfoResponse, AccountResponse, AccountTreeNodeResponse, AmountResponse, BasicInfo, CalculatedAmount,
+    CommodityDetailResponse, CommodityListItemResponse, CommodityLot, CommodityPrice, CurrentStatisticResponse, DocumentResponse, ErrorWrapper,
+    FileDetailResponse, InfoForNewTransaction, JournalItemResponse, LabelAccountEffectResponse, LabelDetailResponse, LabelPeriodResponse, LabelResponse,
+    LedgerItemResponse, Pageable, ReportRankItemResponse, ReportResponse, ResponseWrapper, StatisticResponse, SuccessWrapper, UpdateStatusResponse,
 };
-use crate::{ApiResult, ServerResult};
+use crate::update::UpdateChecker;
+use crate::{ApiResult, ServerError, ServerResult};
 use zhang_ast::amount::Amount;
-use zhang_ast::{Account, Balance, BalanceCheck, BalancePad, Date, Directive, Document, Flag, Meta, Posting, Transaction, ZhangString};
+use zhang_ast::{
+    Account, AccountType, Balance, BalanceCheck, BalancePad, Date, Directive, Document, Flag, Meta, Posting, SingleTotalPrice, SpanInfo, Transaction,
+    ZhangString,
+};
 use zhang_core::utils::date_range::NaiveDateRange;
 
 pub(crate) fn create_folder_if_not_exist(filename: &std::path::Path) {
     std::fs::create_dir_all(filename.parent().unwrap()).expect("cannot create folder recursive");
 }
 
-#[derive(FromRow)]
-struct ValueRow {
-    value: String,
-}
-#[derive(FromRow)]
-pub struct DetailRow {
-    date: NaiveDate,
-    account: String,
-    balance_number: ZhangBigDecimal,
-    balance_commodity: String,
+#[utoipa::path(tag = "system", responses((status = 200, description = "openapi document of the rest api", content_type = "application/json")))]
+#[get("/openapi.json")]
+pub async fn get_openapi() -> impl Responder {
+    web::Json(ApiDoc::openapi())
 }
 
-#[get("/api/sse")]
+#[utoipa::path(tag = "system", responses((status = 200, description = "server sent events of ledger changes", content_type = "text/event-stream")))]
+#[get("/sse")]
 pub async fn sse(broadcaster: Data<Broadcaster>) -> impl Responder {
     broadcaster.new_client().await
 }
 
-#[get("/api/info")]
+#[utoipa::path(tag = "system", responses((status = 200, body = SuccessWrapper<BasicInfo>)))]
+#[get("/info")]
 pub async fn get_basic_info(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<BasicInfo> {
     let ledger = ledger.read().await;
     let mut operations = ledger.operations().await;
@@ -74,79 +81,62 @@ pub async fn get_basic_info(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<Basi
     })
 }
 
+#[utoipa::path(tag = "system", responses((status = 200, body = SuccessWrapper<Vec<LedgerItemResponse>>)))]
+#[get("/ledgers")]
+pub async fn get_ledgers(ledgers: Data<Ledgers>) -> ApiResult<Vec<LedgerItemResponse>> {
+    let mut ret = vec![];
+    for (idx, hosted) in ledgers.iter().enumerate() {
+        let ledger = hosted.ledger.read().await;
+        let mut operations = ledger.operations().await;
+        ret.push(LedgerItemResponse {
+            id: hosted.id.clone(),
+            title: operations.option("title").await?.map(|it| it.value),
+            default: idx == 0,
+        });
+    }
+    ResponseWrapper::json(ret)
+}
+
+#[utoipa::path(tag = "system", responses((status = 200, body = SuccessWrapper<UpdateStatusResponse>)))]
+#[get("/system/update")]
+pub async fn get_update_status(checker: Data<UpdateChecker>) -> ApiResult<UpdateStatusResponse> {
+    ResponseWrapper::json(checker.status().await)
+}
+
 // todo rename api
-#[get("/api/for-new-transaction")]
+#[utoipa::path(tag = "journals", responses((status = 200, body = SuccessWrapper<InfoForNewTransaction>)))]
+#[get("/for-new-transaction")]
 pub async fn get_info_for_new_transactions(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<InfoForNewTransaction> {
-    let guard = ledger.read().await;
-    let mut connection = guard.connection().await;
-
-    #[derive(FromRow)]
-    struct AccountNameRow {
-        name: String,
-    }
-    let account_names = sqlx::query_as::<_, AccountNameRow>(
-        r#"
-        SELECT name FROM accounts WHERE status = 'Open'
-        "#,
-    )
-    .fetch_all(&mut connection)
-    .await?;
+    let ledger = ledger.read().await;
+    let mut operations = ledger.operations().await;
 
-    #[derive(FromRow)]
-    struct PayeeRow {
-        payee: String,
-    }
-    let payees = sqlx::query_as::<_, PayeeRow>(
-        r#"
-        select distinct payee from transactions
-        "#,
-    )
-    .fetch_all(&mut connection)
-    .await?;
+    let account_names = operations
+        .accounts()
+        .await?
+        .into_iter()
+        .filter(|it| it.status == AccountStatus::Open)
+        .map(|it| it.name)
+        .collect_vec();
+    let payees = operations.payees().await?;
 
     ResponseWrapper::json(InfoForNewTransaction {
-        payee: payees.into_iter().map(|it| it.payee).filter(|it| !it.is_empty()).collect_vec(),
-        account_name: account_names.into_iter().map(|it| it.name).collect_vec(),
+        payee: payees,
+        account_name: account_names,
     })
 }
 
-#[get("/api/statistic")]
-pub async fn get_statistic_data(ledger: Data<Arc<RwLock<Ledger>>>, params: Query<StatisticRequest>) -> ApiResult<StatisticResponse> {
+#[utoipa::path(tag = "statistics", params(StatisticRequest, ExportRequest), responses((status = 200, body = SuccessWrapper<StatisticResponse>, description = "json, or spreadsheet file when `format` is given")))]
+#[get("/statistic")]
+pub async fn get_statistic_data(
+    ledger: Data<Arc<RwLock<Ledger>>>, params: Query<StatisticRequest>, export: Query<ExportRequest>,
+) -> ApiResult<StatisticResponse> {
     let ledger = ledger.read().await;
-    let mut connection = ledger.connection().await;
+    let mut operations = ledger.operations().await;
     let params = params.into_inner();
-    #[derive(FromRow)]
-    pub struct StaticRow {
-        date: NaiveDate,
-        account_type: String,
-        amount: ZhangBigDecimal,
-        commodity: String,
-    }
-    let rows = sqlx::query_as::<_, StaticRow>(
-        r#"
-        SELECT
-            date(datetime) AS date,
-            accounts.type AS account_type,
-            sum(inferred_unit_number) AS amount,
-            inferred_unit_commodity AS commodity
-        FROM
-            transaction_postings
-            JOIN transactions ON transactions.id = transaction_postings.trx_id
-            JOIN accounts ON accounts.name = transaction_postings.account
-            where transactions.datetime >= $1 and transactions.datetime <= $2
-        GROUP BY
-            date(datetime),
-            accounts.type,
-            inferred_unit_commodity
-    "#,
-    )
-    .bind(params.from.naive_local())
-    .bind(params.to.naive_local())
-    .fetch_all(&mut connection)
-    .await?;
+    let rows = operations.daily_account_type_totals(params.from.naive_local(), params.to.naive_local()).await?;
     let mut ret: HashMap<NaiveDate, HashMap<String, AmountResponse>> = HashMap::new();
     for (date, dated_rows) in &rows.into_iter().group_by(|row| row.date) {
-        let date_entry = ret.entry(date).or_insert_with(HashMap::new);
+        let date_entry = ret.entry(date).or_default();
         for row in dated_rows {
             date_entry.insert(
                 row.account_type,
@@ -158,36 +148,12 @@ pub async fn get_statistic_data(ledger: Data<Arc<RwLock<Ledger>>>, params: Query
         }
     }
     for day in NaiveDateRange::new(params.from.date_naive(), params.to.date_naive()) {
-        ret.entry(day).or_insert_with(HashMap::new);
+        ret.entry(day).or_default();
     }
 
-    let accounts = sqlx::query_as::<_, ValueRow>("select name as value from accounts")
-        .fetch_all(&mut connection)
-        .await?
-        .into_iter()
-        .map(|it| it.value)
-        .collect_vec();
+    let accounts = operations.accounts().await?.into_iter().map(|it| it.name).collect_vec();
 
-    let existing_account_balance = sqlx::query_as::<_, DetailRow>(
-        r#"
-        SELECT
-            date(datetime) AS date,
-            account,
-            balance_number,
-            balance_commodity
-        FROM
-            account_daily_balance
-        WHERE
-            datetime < $1
-        GROUP BY
-            account
-        HAVING
-            max(datetime)
-    "#,
-    )
-    .bind(params.from.naive_local())
-    .fetch_all(&mut connection)
-    .await?;
+    let existing_account_balance = operations.accounts_latest_balance(Bound::Excluded(params.from.naive_local())).await?;
 
     let mut existing_balances: HashMap<String, AmountResponse> = existing_account_balance
         .into_iter()
@@ -202,26 +168,11 @@ pub async fn get_statistic_data(ledger: Data<Arc<RwLock<Ledger>>>, params: Query
         })
         .collect();
 
-    let details = sqlx::query_as::<_, DetailRow>(
-        r#"
-        SELECT
-        date(datetime) AS date,
-        account,
-        balance_number,
-        balance_commodity
-    FROM
-        account_daily_balance
-    where datetime >= $1 and datetime <= $2
-    "#,
-    )
-    .bind(params.from.naive_local())
-    .bind(params.to.naive_local())
-    .fetch_all(&mut connection)
-    .await?;
+    let details = operations.daily_balances(params.from.naive_local(), params.to.naive_local()).await?;
 
     let mut detail_map: HashMap<NaiveDate, HashMap<String, AmountResponse>> = HashMap::new();
     for (date, dated_rows) in &details.into_iter().group_by(|row| row.date) {
-        let date_entry = detail_map.entry(date).or_insert_with(HashMap::new);
+        let date_entry = detail_map.entry(date).or_default();
         for row in dated_rows {
             date_entry.insert(
                 row.account,
@@ -257,24 +208,27 @@ pub async fn get_statistic_data(ledger: Data<Arc<RwLock<Ledger>>>, params: Query
         detail_ret.insert(target_day, target_day_ret);
     }
 
-    ResponseWrapper::json(StatisticResponse {
-        changes: ret,
-        details: detail_ret,
-    })
+    ResponseWrapper::tabular(
+        StatisticResponse {
+            changes: ret,
+            details: detail_ret,
+        },
+        export.format,
+        "statistic",
+    )
 }
 
-#[get("/api/statistic/current")]
+#[utoipa::path(tag = "statistics", responses((status = 200, body = SuccessWrapper<CurrentStatisticResponse>)))]
+#[get("/statistic/current")]
 pub async fn current_statistic(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<CurrentStatisticResponse> {
     let ledger = ledger.read().await;
 
-    let mut connection = ledger.connection().await;
-
     let month_beginning = Local.beginning_of_month().naive_local();
     let month_end = Local.end_of_month().naive_local();
 
     let mut operations = ledger.operations().await;
 
-    let latest_account_balances = operations.accounts_latest_balance().await?;
+    let latest_account_balances = operations.accounts_latest_balance(Bound::Unbounded).await?;
 
     let balances = group_and_calculate(
         &mut operations,
@@ -296,31 +250,7 @@ pub async fn current_statistic(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<C
     )
     .await?;
 
-    #[derive(FromRow)]
-    struct CurrentMonthBalance {
-        account_type: String,
-        amount: ZhangBigDecimal,
-        commodity: String,
-    }
-
-    let current_month_balance = sqlx::query_as::<_, CurrentMonthBalance>(
-        r#"
-    SELECT accounts.type             AS account_type,
-           sum(inferred_unit_number) AS amount,
-           inferred_unit_commodity   AS commodity
-    FROM transaction_postings
-             JOIN transactions ON transactions.id = transaction_postings.trx_id
-             JOIN accounts ON accounts.name = transaction_postings.account
-    WHERE transactions.datetime >= $1 and transactions.datetime <= $2
-    GROUP BY
-        accounts.type,
-        inferred_unit_commodity
-    "#,
-    )
-    .bind(month_beginning)
-    .bind(month_end)
-    .fetch_all(&mut connection)
-    .await?;
+    let current_month_balance = operations.account_type_totals(month_beginning, month_end).await?;
 
     let income = current_month_balance
         .iter()
@@ -353,7 +283,7 @@ pub async fn current_statistic(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<C
     })
 }
 
-async fn group_and_calculate<T: AmountLike>(operations: &mut Operations, latest_account_balances: Vec<T>) -> ZhangResult<CalculatedAmount> {
+async fn group_and_calculate<T: AmountLike>(operations: &mut Operations<'_>, latest_account_balances: Vec<T>) -> ZhangResult<CalculatedAmount> {
     let operating_currency = operations
         .option(KEY_OPERATING_CURRENCY)
         .await?
@@ -385,262 +315,230 @@ async fn group_and_calculate<T: AmountLike>(operations: &mut Operations, latest_
     })
 }
 
-#[get("/api/journals")]
-pub async fn get_journals(ledger: Data<Arc<RwLock<Ledger>>>, params: Query<JournalRequest>) -> ApiResult<Pageable<JournalItemResponse>> {
+#[utoipa::path(tag = "journals", params(JournalRequest, ExportRequest), responses((status = 200, body = SuccessWrapper<Pageable<JournalItemResponse>>, description = "json, or spreadsheet file when `format` is given")))]
+#[get("/journals")]
+pub async fn get_journals(
+    ledger: Data<Arc<RwLock<Ledger>>>, params: Query<JournalRequest>, export: Query<ExportRequest>,
+) -> ApiResult<Pageable<JournalItemResponse>> {
     let ledger = ledger.read().await;
     let mut operations = ledger.operations().await;
-    let mut connection = ledger.connection().await;
     let params = params.into_inner();
 
-    let total_count = operations.transaction_counts().await?;
-
-    #[derive(Debug, FromRow)]
-    struct JournalHeader {
-        id: String,
-        sequence: i64,
-        datetime: NaiveDateTime,
-        journal_type: String,
-        payee: String,
-        narration: Option<String>,
-    }
-    let journal_headers = sqlx::query_as::<_, JournalHeader>(
-        r#"
-        SELECT id, sequence, datetime, type as journal_type, payee, narration FROM transactions ORDER BY "sequence" DESC LIMIT $1 OFFSET $2
-        "#,
-    )
-    .bind(params.limit())
-    .bind(params.offset())
-    .fetch_all(&mut connection)
-    .await?;
-
-    #[derive(Debug, FromRow)]
-    struct JournalArm {
-        trx_id: String,
-        account: String,
-        unit_number: Option<ZhangBigDecimal>,
-        unit_commodity: Option<String>,
-        cost_number: Option<ZhangBigDecimal>,
-        cost_commodity: Option<String>,
-        price_number: Option<ZhangBigDecimal>,
-        price_commodity: Option<String>,
-        inferred_unit_number: ZhangBigDecimal,
-        inferred_unit_commodity: String,
-        account_before_number: ZhangBigDecimal,
-        account_before_commodity: String,
-        account_after_number: ZhangBigDecimal,
-        account_after_commodity: String,
-    }
-    let journal_arms = sqlx::query_as::<_, JournalArm>(
-        r#"
-        select * from transaction_postings where trx_id in ( SELECT id FROM transactions ORDER BY "sequence" DESC LIMIT $1 OFFSET $2 )
-        "#,
-    )
-    .bind(params.limit())
-    .bind(params.offset())
-    .fetch_all(&mut connection)
-    .await?;
+    // spreadsheets hold every matched journal in one page, otherwise the rest would be dropped silently
+    let (page, limit, offset) = match export.format {
+        Some(_) => (1, u32::MAX, 0),
+        None => (params.page(), params.limit(), params.offset()),
+    };
+    let (total_count, journals) = operations.journals(&params.filter(), limit, offset).await?;
 
-    let mut header_map: HashMap<String, JournalHeader> = journal_headers.into_iter().map(|it| (it.id.to_owned(), it)).collect();
-    let mut ret = vec![];
-    for (trx_id, arms) in &journal_arms.into_iter().group_by(|it| it.trx_id.to_owned()) {
-        let header = header_map.remove(&trx_id);
-        if let Some(header) = header {
-            let item = match header.journal_type.as_str() {
-                "BalancePad" => {
-                    let postings = arms
-                        .map(|arm| JournalTransactionPostingResponse {
-                            account: arm.account,
-                            unit_number: arm.unit_number,
-                            unit_commodity: arm.unit_commodity,
-                            cost_number: arm.cost_number,
-                            cost_commodity: arm.cost_commodity,
-                            price_number: arm.price_number,
-                            price_commodity: arm.price_commodity,
-                            inferred_unit_number: arm.inferred_unit_number,
-                            inferred_unit_commodity: arm.inferred_unit_commodity,
-                            account_before_number: arm.account_before_number,
-                            account_before_commodity: arm.account_before_commodity,
-                            account_after_number: arm.account_after_number,
-                            account_after_commodity: arm.account_after_commodity,
-                        })
-                        .collect_vec();
-                    JournalItemResponse::BalancePad(JournalBalancePadItemResponse {
-                        id: trx_id,
-                        sequence: header.sequence,
-                        datetime: header.datetime,
-                        payee: header.payee,
-                        narration: header.narration,
-                        type_: header.journal_type,
-                        postings,
-                    })
-                }
-                "BalanceCheck" => {
-                    let postings = arms
-                        .map(|arm| JournalTransactionPostingResponse {
-                            account: arm.account,
-                            unit_number: arm.unit_number,
-                            unit_commodity: arm.unit_commodity,
-                            cost_number: arm.cost_number,
-                            cost_commodity: arm.cost_commodity,
-                            price_number: arm.price_number,
-                            price_commodity: arm.price_commodity,
-                            inferred_unit_number: arm.inferred_unit_number,
-                            inferred_unit_commodity: arm.inferred_unit_commodity,
-                            account_before_number: arm.account_before_number,
-                            account_before_commodity: arm.account_before_commodity,
-                            account_after_number: arm.account_after_number,
-                            account_after_commodity: arm.account_after_commodity,
-                        })
-                        .collect_vec();
-                    JournalItemResponse::BalanceCheck(JournalBalanceCheckItemResponse {
-                        id: trx_id,
-                        sequence: header.sequence,
-                        datetime: header.datetime,
-                        payee: header.payee,
-                        narration: header.narration,
-                        type_: header.journal_type,
-                        postings,
-                    })
-                }
-                _ => {
-                    let postings = arms
-                        .map(|arm| JournalTransactionPostingResponse {
-                            account: arm.account,
-                            unit_number: arm.unit_number,
-                            unit_commodity: arm.unit_commodity,
-                            cost_number: arm.cost_number,
-                            cost_commodity: arm.cost_commodity,
-                            price_number: arm.price_number,
-                            price_commodity: arm.price_commodity,
-                            inferred_unit_number: arm.inferred_unit_number,
-                            inferred_unit_commodity: arm.inferred_unit_commodity,
-                            account_before_number: arm.account_before_number,
-                            account_before_commodity: arm.account_before_commodity,
-                            account_after_number: arm.account_after_number,
-                            account_after_commodity: arm.account_after_commodity,
-                        })
-                        .collect_vec();
-                    let tags = operations.trx_tags(&trx_id).await?;
-                    let links = operations.trx_links(&trx_id).await?;
-                    let metas = operations
-                        .metas(MetaType::TransactionMeta, &trx_id)
-                        .await
-                        .unwrap()
-                        .into_iter()
-                        .map(|it| it.into())
-                        .collect();
-                    JournalItemResponse::Transaction(JournalTransactionItemResponse {
-                        id: trx_id,
-                        sequence: header.sequence,
-                        datetime: header.datetime,
-                        payee: header.payee,
-                        narration: header.narration,
-                        tags,
-                        links,
-                        flag: header.journal_type,
-                        is_balanced: true,
-                        postings,
-                        metas,
-                    })
-                }
-            };
-            ret.push(item);
-        }
-    }
-    ret.sort_by_key(|item| item.sequence());
-    ret.reverse();
-    ResponseWrapper::json(Pageable::new(total_count as u32, params.page(), params.limit(), ret))
+    let ret = journals.into_iter().map(JournalItemResponse::from).collect_vec();
+    let size = match export.format {
+        Some(_) => max(total_count as u32, 1),
+        None => limit,
+    };
+    ResponseWrapper::tabular(Pageable::new(total_count as u32, page, size, ret), export.format, "journals")
 }
 
-#[post("/api/transactions")]
+#[utoipa::path(tag = "journals", request_body = CreateTransactionRequest, responses((status = 200, body = SuccessWrapper<String>), (status = 400, body = ErrorWrapper)))]
+#[post("/transactions")]
 pub async fn create_new_transaction(
     ledger: Data<Arc<RwLock<Ledger>>>, Json(payload): Json<CreateTransactionRequest>, exporter: Data<dyn AppendableExporter>,
 ) -> ApiResult<String> {
     let ledger = ledger.read().await;
+    let trx = build_transaction(&ledger, payload, None)?;
+    exporter.as_ref().append_directives(&ledger, vec![Directive::Transaction(trx)])?;
 
+    ResponseWrapper::json("Ok".to_string())
+}
+
+/// transaction ids are derived from their location in source file,
+/// so the ids of transactions placed after the updated one in the same file may change after reloading
+#[utoipa::path(
+    tag = "journals",
+    params(("transaction_id" = String, Path)),
+    request_body = CreateTransactionRequest,
+    responses(
+        (status = 200, body = SuccessWrapper<String>, description = "id of the updated transaction, ids of the later transactions in the same file may change"),
+        (status = 400, body = ErrorWrapper),
+        (status = 404, body = ErrorWrapper),
+        (status = 409, description = "source file is modified", body = ErrorWrapper)
+    )
+)]
+#[put("/transactions/{transaction_id}")]
+pub async fn update_transaction(
+    ledger: Data<Arc<RwLock<Ledger>>>, path: web::Path<(String,)>, Json(payload): Json<CreateTransactionRequest>, exporter: Data<dyn AppendableExporter>,
+) -> ApiResult<String> {
+    let transaction_id = path.into_inner().0;
+    // hold the write lock so that the staleness check and the write of source file are not interleaved with other writes
+    let ledger = ledger.write().await;
+    let (span, origin) = transaction_source(&ledger, &transaction_id)?;
+    let trx = build_transaction(&ledger, payload, Some(origin))?;
+    replace_transaction(&ledger, exporter.as_ref(), &span, Some(Directive::Transaction(trx)))?;
+
+    // the updated transaction keeps its start position, hence its id
+    ResponseWrapper::json(transaction_id)
+}
+
+/// like updating, the ids of transactions placed after the deleted one in the same file may change after reloading
+#[utoipa::path(tag = "journals", params(("transaction_id" = String, Path)), responses((status = 200, body = SuccessWrapper<String>), (status = 404, body = ErrorWrapper), (status = 409, description = "source file is modified", body = ErrorWrapper)))]
+#[delete("/transactions/{transaction_id}")]
+pub async fn delete_transaction(ledger: Data<Arc<RwLock<Ledger>>>, path: web::Path<(String,)>, exporter: Data<dyn AppendableExporter>) -> ApiResult<String> {
+    let transaction_id = path.into_inner().0;
+    let ledger = ledger.write().await;
+    let (span, _) = transaction_source(&ledger, &transaction_id)?;
+    replace_transaction(&ledger, exporter.as_ref(), &span, None)?;
+
+    ResponseWrapper::json("Ok".to_string())
+}
+
+/// build transaction from payload, the date form, flag and posting details of the origin transaction are kept if the payload does not change them
+fn build_transaction(ledger: &Ledger, payload: CreateTransactionRequest, origin: Option<&Transaction>) -> ServerResult<Transaction> {
+    if payload.postings.is_empty() {
+        return Err(ServerError::BadRequest("transaction should have at least one posting".to_string()));
+    }
+    let parse_flag = |flag: String| Flag::from_str(&flag).map_err(|_| ServerError::BadRequest(format!("flag {} is invalid", flag)));
+    // postings of the origin transaction are paired by account, so that the parts not given by payload are kept
+    let mut origin_postings = origin.map(|it| it.postings.iter().collect_vec()).unwrap_or_default();
     let mut postings = vec![];
     for posting in payload.postings.into_iter() {
-        postings.push(Posting {
-            flag: None,
-            account: Account::from_str(&posting.account)?,
+        let account = Account::from_str(&posting.account)?;
+        let origin_posting = origin_postings
+            .iter()
+            .position(|it| it.account == account)
+            .map(|idx| origin_postings.remove(idx));
+        let mut built = Posting {
+            flag: origin_posting.and_then(|it| it.flag.clone()),
+            account,
             units: posting.unit.map(|unit| Amount::new(unit.number, unit.commodity)),
-            cost: None,
-            cost_date: None,
-            price: None,
-            meta: Default::default(),
-        });
+            cost: origin_posting.and_then(|it| it.cost.clone()),
+            cost_date: origin_posting.and_then(|it| it.cost_date.clone()),
+            price: origin_posting.and_then(|it| it.price.clone()),
+            meta: origin_posting.map(|it| it.meta.clone()).unwrap_or_default(),
+        };
+        if let Some(cost) = posting.cost {
+            built.cost = Some(Amount::new(cost.number, cost.commodity));
+        }
+        if let Some(price) = posting.price {
+            built.price = Some(SingleTotalPrice::Single(Amount::new(price.number, price.commodity)));
+        }
+        if let Some(flag) = posting.flag {
+            built.flag = Some(parse_flag(flag)?);
+        }
+        if let Some(metas) = posting.metas {
+            built.meta = Meta::default();
+            for meta in metas {
+                built.meta.insert(meta.key, meta.value.to_quote());
+            }
+        }
+        postings.push(built);
     }
 
     let mut metas = Meta::default();
     for meta in payload.metas {
         metas.insert(meta.key, meta.value.to_quote());
     }
+    let flag = match payload.flag {
+        Some(flag) => Some(parse_flag(flag)?),
+        None => origin.map(|it| it.flag.clone()).unwrap_or(Some(Flag::Okay)),
+    };
     let time = payload.datetime.with_timezone(&ledger.options.timezone).naive_local();
-    let trx = Directive::Transaction(Transaction {
-        date: Date::Datetime(time),
-        flag: Some(Flag::Okay),
+    let date = match origin.map(|it| &it.date) {
+        Some(Date::Date(_)) if time.time() == NaiveTime::MIN => Date::Date(time.date()),
+        Some(Date::DateHour(_)) if time.second() == 0 && time.nanosecond() == 0 => Date::DateHour(time),
+        _ => Date::Datetime(time),
+    };
+    Ok(Transaction {
+        date,
+        flag,
         payee: Some(payload.payee.to_quote()),
         narration: payload.narration.map(|it| it.to_quote()),
-        tags: IndexSet::from_iter(payload.tags.into_iter()),
-        links: IndexSet::from_iter(payload.links.into_iter()),
+        tags: IndexSet::from_iter(payload.tags),
+        links: IndexSet::from_iter(payload.links),
         postings,
         meta: metas,
-    });
-    exporter.as_ref().append_directives(&ledger, vec![trx])?;
+    })
+}
 
-    ResponseWrapper::json("Ok".to_string())
+/// the span is reported back if the source file is changed after loading, so that clients can reload and retry
+fn replace_transaction(ledger: &Ledger, exporter: &dyn AppendableExporter, span: &SpanInfo, directive: Option<Directive>) -> ServerResult<()> {
+    exporter.replace_directive(ledger, span, directive).map_err(|e| match e {
+        ZhangError::StaleSpan(_) => ServerError::StaleDirective(span.clone()),
+        ZhangError::InvalidContent { path, error } => ServerError::InvalidFileContent {
+            path: path.display().to_string(),
+            error: *error,
+        },
+        e => e.into(),
+    })
+}
+
+/// span and content of transaction when the ledger was loaded, used to detect the changes made to source file after that
+fn transaction_source<'a>(ledger: &'a Ledger, transaction_id: &str) -> ServerResult<(SpanInfo, &'a Transaction)> {
+    ledger
+        .directives
+        .iter()
+        .find_map(|it| match &it.data {
+            Directive::Transaction(trx) if Uuid::from_span(&it.span).to_string() == transaction_id => Some((it.span.clone(), trx)),
+            _ => None,
+        })
+        .ok_or_else(|| ServerError::NotFound(format!("transaction {}", transaction_id)))
 }
 
 // todo(refact): use exporter to update transaction
-#[post("/api/transactions/{transaction_id}/documents")]
+#[utoipa::path(tag = "documents", params(("transaction_id" = String, Path)), request_body(content_type = "multipart/form-data"), responses((status = 200, body = SuccessWrapper<String>)))]
+#[post("/transactions/{transaction_id}/documents")]
 pub async fn upload_transaction_document(ledger: Data<Arc<RwLock<Ledger>>>, mut multipart: Multipart, path: web::Path<(String,)>) -> ApiResult<String> {
     let transaction_id = path.into_inner().0;
     let ledger_stage = ledger.read().await;
     let mut operations = ledger_stage.operations().await;
     let entry = &ledger_stage.entry.0;
-    let mut documents = vec![];
-
-    while let Some(item) = multipart.next().await {
-        let mut field = item.unwrap();
-        let _name = field.name().to_string();
-        let file_name = field.content_disposition().get_filename().unwrap().to_string();
-        let _content_type = field.content_type().type_().as_str().to_string();
-
-        let v4 = Uuid::new_v4();
-        let buf = entry.join("attachments").join(v4.to_string()).join(&file_name);
-        info!("uploading document `{}`(id={}) to transaction {}", file_name, &v4.to_string(), &transaction_id);
-        create_folder_if_not_exist(&buf);
-        let mut f = File::create(&buf).expect("Unable to create file");
-        while let Some(chunk) = field.next().await {
-            let data = chunk.unwrap();
-            f.write_all(&data).expect("cannot wirte content");
-        }
-        let path = match buf.strip_prefix(entry) {
-            Ok(relative_path) => relative_path.to_str().unwrap(),
-            Err(_) => buf.to_str().unwrap(),
-        };
-
-        documents.push(ZhangString::QuoteString(path.to_string()));
-    }
-    let span_info = operations.transaction_span(&transaction_id).await?;
+    let documents = save_uploaded_documents(entry, &mut multipart, &format!("transaction {}", transaction_id)).await?;
+    let span_info = operations
+        .transaction_span(&transaction_id)
+        .await?
+        .ok_or_else(|| ServerError::NotFound(format!("transaction {}", transaction_id)))?;
     let metas_content = documents
         .into_iter()
-        .map(|document| format!("  document: {}", document.to_plain_string()))
+        .map(|document| format!("  document: {}", ZhangString::QuoteString(document).to_plain_string()))
         .join("\n");
     insert_line(PathBuf::from(span_info.source_file), &metas_content, span_info.span_end as usize)?;
     // todo add update method in exporter
     ResponseWrapper::json("Ok".to_string())
 }
 
-#[get("/api/documents/{file_path}")]
-pub async fn download_document(ledger: Data<Arc<RwLock<Ledger>>>, path: Path<(String,)>) -> impl Responder {
-    let encoded_file_path = path.into_inner().0;
-    let filename = String::from_utf8(base64::decode(encoded_file_path).unwrap()).unwrap();
+#[utoipa::path(tag = "documents", params(("file_path" = String, Path, description = "base64 encoded path relative to ledger root")), responses((status = 200, description = "content of the document", content_type = "application/octet-stream")))]
+#[get("/documents/{file_path}")]
+pub async fn download_document(ledger: Data<Arc<RwLock<Ledger>>>, path: Path<(String,)>) -> ServerResult<NamedFile> {
     let ledger = ledger.read().await;
-    let entry = &ledger.entry.0;
-    let full_path = entry.join(filename);
+    let (_, full_path) = ledger_file_path(&ledger.entry.0, &path.into_inner().0)?;
 
-    NamedFile::open_async(full_path).await
+    Ok(NamedFile::open_async(full_path).await?)
+}
+
+/// save uploaded files into `attachments` folder, and return their paths relative to ledger root
+async fn save_uploaded_documents(entry: &std::path::Path, multipart: &mut Multipart, target: &str) -> ServerResult<Vec<String>> {
+    let mut documents = vec![];
+    while let Some(item) = multipart.next().await {
+        let mut field = item.map_err(|e| ServerError::BadRequest(e.to_string()))?;
+        // folders in the given file name are dropped, files are always placed in their own attachment folder
+        let file_name = field
+            .content_disposition()
+            .get_filename()
+            .and_then(|it| std::path::Path::new(it).file_name())
+            .map(|it| it.to_string_lossy().to_string())
+            .ok_or_else(|| ServerError::BadRequest("uploaded file has no file name".to_string()))?;
+
+        let v4 = Uuid::new_v4();
+        let buf = entry.join("attachments").join(v4.to_string()).join(&file_name);
+        info!("uploading document `{}`(id={}) to {}", file_name, &v4.to_string(), target);
+        create_folder_if_not_exist(&buf);
+        let mut f = File::create(&buf)?;
+        while let Some(chunk) = field.next().await {
+            let data = chunk.map_err(|e| ServerError::BadRequest(e.to_string()))?;
+            f.write_all(&data)?;
+        }
+        documents.push(buf.strip_prefix(entry).unwrap_or(&buf).to_string_lossy().to_string());
+    }
+    Ok(documents)
 }
 
 #[cfg(feature = "frontend")]
@@ -654,7 +552,8 @@ pub async fn serve_frontend(uri: actix_web::http::Uri) -> impl Responder {
     }
 }
 
-#[get("/api/accounts")]
+#[utoipa::path(tag = "accounts", responses((status = 200, body = SuccessWrapper<Vec<AccountResponse>>)))]
+#[get("/accounts")]
 pub async fn get_account_list(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<Vec<AccountResponse>> {
     let ledger = ledger.read().await;
     let mut operations = ledger.operations().await;
@@ -676,7 +575,140 @@ pub async fn get_account_list(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<Ve
     ResponseWrapper::json(ret)
 }
 
-#[get("/api/accounts/{account_name}")]
+#[utoipa::path(tag = "accounts", responses((status = 200, body = SuccessWrapper<Vec<AccountTreeNodeResponse>>)))]
+#[get("/accounts/tree")]
+pub async fn get_account_tree(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<Vec<AccountTreeNodeResponse>> {
+    let ledger = ledger.read().await;
+    let mut operations = ledger.operations().await;
+    let operating_currency = ledger.options.operating_currency.clone();
+
+    let accounts = operations.accounts().await?;
+    let balances = operations.account_balances().await?;
+
+    // prices are taken at the current time of ledger's timezone, in which prices are recorded
+    let now = ledger.options.timezone.now().naive_local();
+    let mut prices = HashMap::new();
+    for commodity in balances.iter().map(|it| &it.balance_commodity).unique() {
+        if commodity.ne(&operating_currency) {
+            if let Some(price) = operations.get_price(now, commodity, &operating_currency).await? {
+                prices.insert(commodity.clone(), price.amount.0);
+            }
+        }
+    }
+    ResponseWrapper::json(AccountTreeBuilder::new(accounts, balances, operating_currency, prices).build())
+}
+
+/// nests accounts by their parents, and creates synthetic nodes for parents which are never opened
+struct AccountTreeBuilder {
+    opened: HashMap<String, AccountDomain>,
+    children: HashMap<String, BTreeSet<String>>,
+    balances: HashMap<String, HashMap<String, BigDecimal>>,
+    operating_currency: String,
+    prices: HashMap<String, BigDecimal>,
+}
+
+impl AccountTreeBuilder {
+    fn new(accounts: Vec<AccountDomain>, balances: Vec<AccountBalanceDomain>, operating_currency: String, prices: HashMap<String, BigDecimal>) -> Self {
+        let mut children: HashMap<String, BTreeSet<String>> = HashMap::new();
+        for mut account in accounts.iter().filter_map(|it| Account::from_str(&it.name).ok()) {
+            loop {
+                let parent = if account.is_root_account() {
+                    account.account_type.to_string()
+                } else {
+                    account.parent().content
+                };
+                let is_new_child = children.entry(parent).or_default().insert(account.content.clone());
+                if !is_new_child || account.is_root_account() {
+                    break;
+                }
+                account = account.parent();
+            }
+        }
+
+        let mut own_balances: HashMap<String, HashMap<String, BigDecimal>> = HashMap::new();
+        for balance in balances {
+            own_balances
+                .entry(balance.account)
+                .or_default()
+                .entry(balance.balance_commodity)
+                .or_insert_with(BigDecimal::zero)
+                .add_assign(balance.balance_number.0);
+        }
+        Self {
+            opened: accounts.into_iter().map(|it| (it.name.clone(), it)).collect(),
+            children,
+            balances: own_balances,
+            operating_currency,
+            prices,
+        }
+    }
+
+    fn build(mut self) -> Vec<AccountTreeNodeResponse> {
+        let roots = [
+            AccountType::Assets,
+            AccountType::Liabilities,
+            AccountType::Equity,
+            AccountType::Income,
+            AccountType::Expenses,
+        ]
+        .iter()
+        .map(|it| it.to_string())
+        .filter(|it| self.children.contains_key(it))
+        .collect_vec();
+        roots.into_iter().map(|it| self.node(it)).collect()
+    }
+
+    fn node(&mut self, name: String) -> AccountTreeNodeResponse {
+        let children = self
+            .children
+            .get(&name)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|child| self.node(child))
+            .collect_vec();
+
+        let own = self.balances.remove(&name).unwrap_or_default();
+        let mut subtree = own.clone();
+        for child in &children {
+            for (commodity, number) in &child.subtree_amount.detail {
+                subtree.entry(commodity.clone()).or_insert_with(BigDecimal::zero).add_assign(&number.0);
+            }
+        }
+        let account = self.opened.remove(&name);
+        AccountTreeNodeResponse {
+            leaf: name.rsplit(':').next().unwrap_or_default().to_string(),
+            synthetic: account.is_none(),
+            status: account.as_ref().map(|it| it.status),
+            alias: account.and_then(|it| it.alias),
+            amount: self.calculate(own),
+            subtree_amount: self.calculate(subtree),
+            children,
+            name,
+        }
+    }
+
+    fn calculate(&self, amounts: HashMap<String, BigDecimal>) -> CalculatedAmount {
+        let mut total_sum = BigDecimal::zero();
+        for (commodity, number) in &amounts {
+            if commodity.eq(&self.operating_currency) {
+                total_sum.add_assign(number);
+            } else if let Some(price) = self.prices.get(commodity) {
+                total_sum.add_assign(number.mul(price));
+            }
+        }
+        CalculatedAmount {
+            calculated: AmountResponse {
+                number: ZhangBigDecimal(total_sum),
+                commodity: self.operating_currency.clone(),
+            },
+            detail: amounts.into_iter().map(|(commodity, number)| (commodity, ZhangBigDecimal(number))).collect(),
+        }
+    }
+}
+
+#[utoipa::path(tag = "accounts", params(("account_name" = String, Path)), responses((status = 200, body = SuccessWrapper<AccountInfoResponse>), (status = 404, body = ErrorWrapper)))]
+#[get("/accounts/{account_name}")]
 pub async fn get_account_info(ledger: Data<Arc<RwLock<Ledger>>>, path: Path<(String,)>) -> ApiResult<AccountInfoResponse> {
     let account_name = path.into_inner().0;
     let ledger = ledger.read().await;
@@ -685,7 +717,7 @@ pub async fn get_account_info(ledger: Data<Arc<RwLock<Ledger>>>, path: Path<(Str
 
     let account_info = match account_domain {
         Some(info) => info,
-        None => return ResponseWrapper::not_found(),
+        None => return Err(ServerError::NotFound(format!("account {}", account_name))),
     };
     let vec = operations.single_account_balances(&account_info.name).await?;
     let amount = group_and_calculate(&mut operations, vec).await?;
@@ -700,108 +732,181 @@ pub async fn get_account_info(ledger: Data<Arc<RwLock<Ledger>>>, path: Path<(Str
     })
 }
 
-#[get("/api/documents")]
+#[utoipa::path(tag = "documents", responses((status = 200, body = SuccessWrapper<Vec<DocumentResponse>>)))]
+#[get("/documents")]
 pub async fn get_documents(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<Vec<DocumentResponse>> {
     let ledger = ledger.read().await;
-    let mut connection = ledger.connection().await;
-
-    let rows = sqlx::query_as::<_, DocumentResponse>(
-        r#"
-    select documents.*
-    from documents
-             left join transaction_postings tp on documents.trx_id = tp.trx_id
-    group by path, tp.trx_id
-    "#,
-    )
-    .fetch_all(&mut connection)
-    .await?;
+    let mut operations = ledger.operations().await;
+
+    let rows = operations.documents().await?.into_iter().map(DocumentResponse::from).collect_vec();
     ResponseWrapper::json(rows)
 }
 
-#[post("/api/accounts/{account_name}/documents")]
+#[utoipa::path(tag = "documents", params(("account_name" = String, Path)), request_body(content_type = "multipart/form-data"), responses((status = 201)))]
+#[post("/accounts/{account_name}/documents")]
 pub async fn upload_account_document(
     ledger: Data<Arc<RwLock<Ledger>>>, mut multipart: Multipart, path: web::Path<(String,)>, exporter: Data<dyn AppendableExporter>,
 ) -> ApiResult<()> {
     let account_name = path.into_inner().0;
     let ledger_stage = ledger.read().await;
     let entry = &ledger_stage.entry.0;
-    let mut documents = vec![];
-
-    while let Some(item) = multipart.next().await {
-        let mut field = item.unwrap();
-        let _name = field.name().to_string();
-        let file_name = field.content_disposition().get_filename().unwrap().to_string();
-        let _content_type = field.content_type().type_().as_str().to_string();
-
-        let v4 = Uuid::new_v4();
-        let buf = entry.join("attachments").join(v4.to_string()).join(&file_name);
-        info!("uploading document `{}`(id={}) to account {}", file_name, &v4.to_string(), &account_name);
-        create_folder_if_not_exist(&buf);
-        let mut f = File::create(&buf).expect("Unable to create file");
-        while let Some(chunk) = field.next().await {
-            let data = chunk.unwrap();
-            f.write_all(&data).expect("cannot wirte content");
-        }
-        let path = match buf.strip_prefix(entry) {
-            Ok(relative_path) => relative_path.to_str().unwrap(),
-            Err(_) => buf.to_str().unwrap(),
-        };
-
-        documents.push(Directive::Document(Document {
-            date: Date::now(&ledger_stage.options.timezone),
-            account: Account::from_str(&account_name)?,
-            filename: ZhangString::QuoteString(path.to_string()),
-            tags: None,
-            links: None,
-            meta: Default::default(),
-        }));
-    }
+    let account = Account::from_str(&account_name)?;
+    let documents = save_uploaded_documents(entry, &mut multipart, &format!("account {}", account_name))
+        .await?
+        .into_iter()
+        .map(|path| {
+            Directive::Document(Document {
+                date: Date::now(&ledger_stage.options.timezone),
+                account: account.clone(),
+                filename: ZhangString::QuoteString(path),
+                tags: None,
+                links: None,
+                meta: Default::default(),
+            })
+        })
+        .collect();
 
     exporter.as_ref().append_directives(&ledger_stage, documents)?;
 
     ResponseWrapper::<()>::created()
 }
 
-#[get("/api/accounts/{account_name}/documents")]
+#[utoipa::path(tag = "documents", params(("account_name" = String, Path)), responses((status = 200, body = SuccessWrapper<Vec<DocumentResponse>>)))]
+#[get("/accounts/{account_name}/documents")]
 pub async fn get_account_documents(ledger: Data<Arc<RwLock<Ledger>>>, params: Path<(String,)>) -> ApiResult<Vec<DocumentResponse>> {
     let account_name = params.into_inner().0;
     let ledger = ledger.read().await;
-    let mut connection = ledger.connection().await;
-
-    let rows = sqlx::query_as::<_, DocumentResponse>(
-        r#"
-    select documents.*
-    from documents
-             left join transaction_postings tp on documents.trx_id = tp.trx_id
-    where documents.account = $1
-       or tp.account = $1
-    "#,
-    )
-    .bind(account_name)
-    .fetch_all(&mut connection)
-    .await?;
+    let mut operations = ledger.operations().await;
+
+    let rows = operations
+        .account_documents(&account_name)
+        .await?
+        .into_iter()
+        .map(DocumentResponse::from)
+        .collect_vec();
 
     ResponseWrapper::json(rows)
 }
 
-#[get("/api/accounts/{account_name}/journals")]
-pub async fn get_account_journals(ledger: Data<Arc<RwLock<Ledger>>>, params: Path<(String,)>) -> ApiResult<Vec<AccountJournalDomain>> {
+#[utoipa::path(tag = "accounts", params(("account_name" = String, Path), ExportRequest), responses((status = 200, body = SuccessWrapper<Vec<AccountJournalDomain>>, description = "json, or spreadsheet file when `format` is given")))]
+#[get("/accounts/{account_name}/journals")]
+pub async fn get_account_journals(
+    ledger: Data<Arc<RwLock<Ledger>>>, params: Path<(String,)>, export: Query<ExportRequest>,
+) -> ApiResult<Vec<AccountJournalDomain>> {
     let account_name = params.into_inner().0;
     let ledger = ledger.read().await;
     let mut operations = ledger.operations().await;
 
     let journals = operations.account_journals(&account_name).await?;
 
-    ResponseWrapper::json(journals)
+    ResponseWrapper::tabular(journals, export.format, &format!("{}-journals", account_name))
 }
 
-#[post("/api/accounts/{account_name}/balances")]
+/// about ten years of daily points
+const MAX_BALANCE_HISTORY_DAYS: i64 = 3660;
+
+#[utoipa::path(tag = "accounts", params(("account_name" = String, Path), AccountBalanceHistoryRequest), responses((status = 200, body = SuccessWrapper<Vec<AccountBalancePointResponse>>), (status = 400, body = ErrorWrapper), (status = 404, body = ErrorWrapper)))]
+#[get("/accounts/{account_name}/balances")]
+pub async fn get_account_balance_history(
+    ledger: Data<Arc<RwLock<Ledger>>>, path: Path<(String,)>, params: Query<AccountBalanceHistoryRequest>,
+) -> ApiResult<Vec<AccountBalancePointResponse>> {
+    let account_name = path.into_inner().0;
+    let params = params.into_inner();
+    let ledger = ledger.read().await;
+    let mut operations = ledger.operations().await;
+    let operating_currency = ledger.options.operating_currency.clone();
+
+    let (from, to) = (params.from.date_naive(), params.to.date_naive());
+    if from > to {
+        return Err(ServerError::BadRequest("`from` should not be later than `to`".to_string()));
+    }
+    if (to - from).num_days() >= MAX_BALANCE_HISTORY_DAYS {
+        return Err(ServerError::BadRequest(format!(
+            "balance history is limited to {} days",
+            MAX_BALANCE_HISTORY_DAYS
+        )));
+    }
+    if operations.account(&account_name).await?.is_none() {
+        return Err(ServerError::NotFound(format!("account {}", account_name)));
+    }
+    let balances = operations
+        .account_daily_balances(&account_name, params.from.naive_local(), params.to.naive_local())
+        .await?;
+    let interval = params.interval.unwrap_or(StatisticInterval::Day);
+
+    // prices of each commodity are loaded once, instead of being queried for every point
+    let mut prices: HashMap<String, Vec<PriceDomain>> = HashMap::new();
+    for commodity in balances.iter().map(|it| &it.balance_commodity).unique() {
+        if commodity.ne(&operating_currency) {
+            let series = operations
+                .prices(Some(commodity))
+                .await?
+                .into_iter()
+                .filter(|it| it.target_commodity.eq(&operating_currency))
+                .collect_vec();
+            prices.insert(commodity.clone(), series);
+        }
+    }
+
+    let mut ret = vec![];
+    for (date, amounts) in balance_history(balances, from, to, &interval) {
+        let mut total_sum = BigDecimal::zero();
+        for (commodity, number) in &amounts {
+            if commodity.eq(&operating_currency) {
+                total_sum.add_assign(number);
+            } else if let Some(price) = prices
+                .get(commodity)
+                .and_then(|series| latest_price(series, date.and_hms_opt(23, 59, 59).unwrap_or_default()))
+            {
+                total_sum.add_assign(number.mul(price));
+            }
+        }
+        ret.push(AccountBalancePointResponse {
+            date,
+            balance: CalculatedAmount {
+                calculated: AmountResponse {
+                    number: ZhangBigDecimal(total_sum),
+                    commodity: operating_currency.clone(),
+                },
+                detail: amounts.into_iter().map(|(commodity, number)| (commodity, ZhangBigDecimal(number))).collect(),
+            },
+        });
+    }
+    ResponseWrapper::json(ret)
+}
+
+/// amount of the latest price at or before `datetime`, given prices sorted by datetime
+fn latest_price(prices: &[PriceDomain], datetime: NaiveDateTime) -> Option<&BigDecimal> {
+    let position = prices.partition_point(|it| it.datetime <= datetime);
+    position.checked_sub(1).map(|idx| &prices[idx].amount.0)
+}
+
+/// balances per commodity at the end of each interval between `from` and `to`,
+/// days without any change carry the latest balance before them
+fn balance_history(
+    balances: Vec<AccountDailyBalanceDomain>, from: NaiveDate, to: NaiveDate, interval: &StatisticInterval,
+) -> Vec<(NaiveDate, HashMap<String, BigDecimal>)> {
+    let mut balances = balances.into_iter().peekable();
+    let mut current: HashMap<String, BigDecimal> = HashMap::new();
+    let mut ret = vec![];
+    for date in NaiveDateRange::new(from, to) {
+        while let Some(balance) = balances.next_if(|it| it.date <= date) {
+            current.insert(balance.balance_commodity, balance.balance_number.0);
+        }
+        if date == to || interval.is_period_end(date) {
+            ret.push((date, current.clone()));
+        }
+    }
+    ret
+}
+
+#[utoipa::path(tag = "accounts", params(("account_name" = String, Path)), request_body = AccountBalanceRequest, responses((status = 201)))]
+#[post("/accounts/{account_name}/balances")]
 pub async fn create_account_balance(
     ledger: Data<Arc<RwLock<Ledger>>>, params: web::Path<(String,)>, Json(payload): Json<AccountBalanceRequest>, exporter: Data<dyn AppendableExporter>,
 ) -> ApiResult<()> {
     let target_account = params.into_inner().0;
     let ledger = ledger.read().await;
-    let _connection = ledger.connection().await;
 
     let balance = match payload {
         AccountBalanceRequest::Check { amount, .. } => Balance::BalanceCheck(BalanceCheck {
@@ -828,7 +933,8 @@ pub async fn create_account_balance(
     exporter.as_ref().append_directives(&ledger, vec![Directive::Balance(balance)])?;
     ResponseWrapper::<()>::created()
 }
-#[post("/api/accounts/batch-balances")]
+#[utoipa::path(tag = "accounts", request_body = Vec<AccountBalanceRequest>, responses((status = 201)))]
+#[post("/accounts/batch-balances")]
 pub async fn create_batch_account_balances(
     ledger: Data<Arc<RwLock<Ledger>>>, Json(payload): Json<Vec<AccountBalanceRequest>>, exporter: Data<dyn AppendableExporter>,
 ) -> ApiResult<()> {
@@ -863,95 +969,176 @@ pub async fn create_batch_account_balances(
     ResponseWrapper::<()>::created()
 }
 
-#[get("/api/commodities")]
-pub async fn get_all_commodities(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<Vec<CommodityListItemResponse>> {
+#[utoipa::path(tag = "commodities", params(ExportRequest), responses((status = 200, body = SuccessWrapper<Vec<CommodityListItemResponse>>, description = "json, or spreadsheet file when `format` is given")))]
+#[get("/commodities")]
+pub async fn get_all_commodities(ledger: Data<Arc<RwLock<Ledger>>>, export: Query<ExportRequest>) -> ApiResult<Vec<CommodityListItemResponse>> {
     let ledger = ledger.read().await;
-    let mut connection = ledger.connection().await;
-
-    let vec = sqlx::query_as::<_, CommodityListItemResponse>(
-        r#"
-            select commodities.*,
-                   IFNULL(commodity_total_amount.total_amount, 0.00) as total_amount,
-                   latest_price.datetime         latest_price_date,
-                   latest_price.amount           latest_price_amount,
-                   latest_price.target_commodity latest_price_commodity
-            from commodities
-                     left join (select commodity, datetime, amount, target_commodity
-                                from prices
-                                group by commodity
-                                having min(datetime)) latest_price on commodities.name = latest_price.commodity
-                     left join (select commodity, total(amount) as total_amount
-                                from commodity_lots
-                                         join accounts on commodity_lots.account = accounts.name
-                                where accounts.type in ('Assets', 'Liabilities')
-                                group by commodity) commodity_total_amount on commodities.name = commodity_total_amount.commodity
-    "#,
-    )
-    .fetch_all(&mut connection)
-    .await?;
-    ResponseWrapper::json(vec)
+    let mut operations = ledger.operations().await;
+
+    let vec = operations
+        .commodity_summaries()
+        .await?
+        .into_iter()
+        .map(CommodityListItemResponse::from)
+        .collect_vec();
+    ResponseWrapper::tabular(vec, export.format, "commodities")
 }
 
-#[get("/api/commodities/{commodity_name}")]
+#[utoipa::path(tag = "commodities", params(("commodity_name" = String, Path)), responses((status = 200, body = SuccessWrapper<CommodityDetailResponse>)))]
+#[get("/commodities/{commodity_name}")]
 pub async fn get_single_commodity(ledger: Data<Arc<RwLock<Ledger>>>, params: Path<(String,)>) -> ApiResult<CommodityDetailResponse> {
     let commodity_name = params.into_inner().0;
     let ledger = ledger.read().await;
-    let mut connection = ledger.connection().await;
-
-    let basic_info = sqlx::query_as::<_, CommodityListItemResponse>(
-        r#"
-            select commodities.*,
-                   IFNULL(commodity_total_amount.total_amount, 0.00) as total_amount,
-                   latest_price.datetime         latest_price_date,
-                   latest_price.amount           latest_price_amount,
-                   latest_price.target_commodity latest_price_commodity
-            from commodities
-                     left join (select commodity, datetime, amount, target_commodity
-                                from prices
-                                group by commodity
-                                having min(datetime)) latest_price on commodities.name = latest_price.commodity
-                     left join (select commodity, total(amount) as total_amount
-                                from commodity_lots
-                                         join accounts on commodity_lots.account = accounts.name
-                                where accounts.type in ('Assets', 'Liabilities')
-                                group by commodity) commodity_total_amount on commodities.name = commodity_total_amount.commodity
-            where commodities.name = $1
-    "#,
-    )
-    .bind(&commodity_name)
-    .fetch_one(&mut connection)
-    .await?;
-
-    let lots = sqlx::query_as::<_, CommodityLot>(
-        r#"
-            select datetime, amount, price_amount, price_commodity, account
-            from commodity_lots
-            where commodity = $1
-    "#,
-    )
-    .bind(&commodity_name)
-    .fetch_all(&mut connection)
-    .await?;
+    let mut operations = ledger.operations().await;
 
-    let prices = sqlx::query_as::<_, CommodityPrice>(
-        r#"
-            select datetime, amount, target_commodity
-            from prices
-            where commodity = $1
-    "#,
-    )
-    .bind(&commodity_name)
-    .fetch_all(&mut connection)
-    .await?;
+    let basic_info = operations
+        .commodity_summary(&commodity_name)
+        .await?
+        .ok_or_else(|| ServerError::NotFound(format!("commodity {}", commodity_name)))?;
+    let lots = operations
+        .commodity_lots(&commodity_name)
+        .await?
+        .into_iter()
+        .map(CommodityLot::from)
+        .collect_vec();
+    let prices = operations
+        .prices(Some(&commodity_name))
+        .await?
+        .into_iter()
+        .map(CommodityPrice::from)
+        .collect_vec();
 
     ResponseWrapper::json(CommodityDetailResponse {
-        info: basic_info,
+        info: basic_info.into(),
         lots,
         prices,
     })
 }
 
-#[get("/api/files")]
+#[utoipa::path(tag = "labels", params(LabelRequest), responses((status = 200, body = SuccessWrapper<Vec<LabelResponse>>)))]
+#[get("/tags")]
+pub async fn get_tags(ledger: Data<Arc<RwLock<Ledger>>>, params: Query<LabelRequest>) -> ApiResult<Vec<LabelResponse>> {
+    let ledger = ledger.read().await;
+    let mut operations = ledger.operations().await;
+    let period_format = params.interval.as_ref().unwrap_or(&StatisticInterval::Month).period_format();
+
+    let usages = operations.tag_usages().await?;
+    let totals = operations.tag_period_totals(period_format).await?;
+    ResponseWrapper::json(label_responses(usages, totals))
+}
+
+#[utoipa::path(tag = "labels", params(("tag" = String, Path)), responses((status = 200, body = SuccessWrapper<LabelDetailResponse>), (status = 404, body = ErrorWrapper)))]
+#[get("/tags/{tag}")]
+pub async fn get_tag(ledger: Data<Arc<RwLock<Ledger>>>, path: Path<(String,)>) -> ApiResult<LabelDetailResponse> {
+    let tag = path.into_inner().0;
+    let ledger = ledger.read().await;
+    let mut operations = ledger.operations().await;
+
+    let filter = JournalFilter {
+        tag: Some(tag.clone()),
+        ..Default::default()
+    };
+    let (_, journals) = operations.journals(&filter, u32::MAX, 0).await?;
+    if journals.is_empty() {
+        return Err(ServerError::NotFound(format!("tag {}", tag)));
+    }
+    let effects = operations.tag_account_effects(&tag).await?;
+    ResponseWrapper::json(label_detail(tag, effects, journals))
+}
+
+#[utoipa::path(tag = "labels", params(LabelRequest), responses((status = 200, body = SuccessWrapper<Vec<LabelResponse>>)))]
+#[get("/links")]
+pub async fn get_links(ledger: Data<Arc<RwLock<Ledger>>>, params: Query<LabelRequest>) -> ApiResult<Vec<LabelResponse>> {
+    let ledger = ledger.read().await;
+    let mut operations = ledger.operations().await;
+    let period_format = params.interval.as_ref().unwrap_or(&StatisticInterval::Month).period_format();
+
+    let usages = operations.link_usages().await?;
+    let totals = operations.link_period_totals(period_format).await?;
+    ResponseWrapper::json(label_responses(usages, totals))
+}
+
+#[utoipa::path(tag = "labels", params(("link" = String, Path)), responses((status = 200, body = SuccessWrapper<LabelDetailResponse>), (status = 404, body = ErrorWrapper)))]
+#[get("/links/{link}")]
+pub async fn get_link(ledger: Data<Arc<RwLock<Ledger>>>, path: Path<(String,)>) -> ApiResult<LabelDetailResponse> {
+    let link = path.into_inner().0;
+    let ledger = ledger.read().await;
+    let mut operations = ledger.operations().await;
+
+    let filter = JournalFilter {
+        link: Some(link.clone()),
+        ..Default::default()
+    };
+    let (_, journals) = operations.journals(&filter, u32::MAX, 0).await?;
+    if journals.is_empty() {
+        return Err(ServerError::NotFound(format!("link {}", link)));
+    }
+    let effects = operations.link_account_effects(&link).await?;
+    ResponseWrapper::json(label_detail(link, effects, journals))
+}
+
+fn label_responses(usages: Vec<LabelUsageDomain>, totals: Vec<LabelPeriodTotalDomain>) -> Vec<LabelResponse> {
+    let mut totals = totals.into_iter().map(|it| (it.name.clone(), it)).into_group_map();
+    usages
+        .into_iter()
+        .map(|usage| {
+            let periods = totals
+                .remove(&usage.name)
+                .unwrap_or_default()
+                .into_iter()
+                .group_by(|it| it.period.clone())
+                .into_iter()
+                .map(|(period, totals)| LabelPeriodResponse {
+                    period,
+                    amounts: totals
+                        .map(|it| AmountResponse {
+                            number: it.amount,
+                            commodity: it.commodity,
+                        })
+                        .collect(),
+                })
+                .collect();
+            LabelResponse {
+                name: usage.name,
+                count: usage.count,
+                first_datetime: usage.first_datetime,
+                last_datetime: usage.last_datetime,
+                periods,
+            }
+        })
+        .collect()
+}
+
+/// the group is balanced when every account posted by more than one transaction nets to zero,
+/// e.g. the receivable account of an invoice and its payment
+fn label_detail(name: String, effects: Vec<LabelAccountEffectDomain>, journals: Vec<JournalDomain>) -> LabelDetailResponse {
+    let shared_accounts = effects.iter().filter(|it| it.transactions > 1).collect_vec();
+    let balanced = !shared_accounts.is_empty() && shared_accounts.iter().all(|it| it.amount.0.round(8).is_zero());
+
+    let accounts = effects
+        .into_iter()
+        .group_by(|it| it.account.clone())
+        .into_iter()
+        .map(|(account, effects)| LabelAccountEffectResponse {
+            account,
+            amounts: effects
+                .map(|it| AmountResponse {
+                    number: it.amount,
+                    commodity: it.commodity,
+                })
+                .collect(),
+        })
+        .collect();
+    LabelDetailResponse {
+        name,
+        balanced,
+        accounts,
+        journals: journals.into_iter().map(JournalItemResponse::from).collect(),
+    }
+}
+
+#[utoipa::path(tag = "files", responses((status = 200, body = SuccessWrapper<Vec<Option<String>>>)))]
+#[get("/files")]
 pub async fn get_files(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<Vec<Option<String>>> {
     let ledger = ledger.read().await;
     let entry_path = &ledger.entry.0;
@@ -971,35 +1158,33 @@ pub async fn get_files(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<Vec<Optio
     ResponseWrapper::json(ret)
 }
 
-#[get("/api/files/{file_path}")]
+#[utoipa::path(tag = "files", params(("file_path" = String, Path, description = "base64 encoded path relative to ledger root")), responses((status = 200, body = SuccessWrapper<FileDetailResponse>), (status = 400, body = ErrorWrapper)))]
+#[get("/files/{file_path}")]
 pub async fn get_file_content(ledger: Data<Arc<RwLock<Ledger>>>, path: web::Path<(String,)>) -> ApiResult<FileDetailResponse> {
-    let encoded_file_path = path.into_inner().0;
-    let filename = String::from_utf8(base64::decode(encoded_file_path).unwrap()).unwrap();
     let ledger = ledger.read().await;
-    let entry = &ledger.entry.0;
-    let full_path = entry.join(&filename);
+    let (filename, full_path) = ledger_file_path(&ledger.entry.0, &path.into_inner().0)?;
 
     ResponseWrapper::json(FileDetailResponse {
         path: filename,
         content: std::fs::read_to_string(full_path)?,
     })
 }
-#[put("/api/files/{file_path}")]
+#[utoipa::path(tag = "files", params(("file_path" = String, Path, description = "base64 encoded path relative to ledger root")), request_body = FileUpdateRequest, responses((status = 201), (status = 400, body = ErrorWrapper)))]
+#[put("/files/{file_path}")]
 pub async fn update_file_content(ledger: Data<Arc<RwLock<Ledger>>>, path: web::Path<(String,)>, Json(payload): Json<FileUpdateRequest>) -> ApiResult<()> {
-    let encoded_file_path = path.into_inner().0;
-    let filename = String::from_utf8(base64::decode(encoded_file_path).unwrap()).unwrap();
-    let ledger = ledger.read().await;
-    let entry = &ledger.entry.0;
-    let full_path = entry.join(&filename);
-
-    // todo(refact) test the syntax valid
-    // if parse_zhang(&payload.content, None).is_ok() {
-    std::fs::write(full_path, payload.content)?;
-    // }
+    // hold the write lock so that the file is not written while other writes or reloads are in progress
+    let ledger = ledger.write().await;
+    let (filename, full_path) = ledger_file_path(&ledger.entry.0, &path.into_inner().0)?;
+
+    ledger
+        .validate_file_content(&payload.content, full_path.clone())
+        .map_err(|error| ServerError::InvalidFileContent { path: filename, error })?;
+    write_file_atomically(&full_path, &payload.content)?;
     ResponseWrapper::<()>::created()
 }
 
-#[get("api/errors")]
+#[utoipa::path(tag = "system", params(JournalRequest), responses((status = 200, body = SuccessWrapper<Pageable<ErrorDomain>>)))]
+#[get("/errors")]
 pub async fn get_errors(ledger: Data<Arc<RwLock<Ledger>>>, params: Query<JournalRequest>) -> ApiResult<Pageable<ErrorDomain>> {
     let ledger = ledger.read().await;
     let mut operations = ledger.operations().await;
@@ -1014,31 +1199,13 @@ pub async fn get_errors(ledger: Data<Arc<RwLock<Ledger>>>, params: Query<Journal
     ResponseWrapper::json(Pageable::new(total_count as u32, params.page(), params.limit(), ret))
 }
 
-#[get("/api/report")]
-pub async fn get_report(ledger: Data<Arc<RwLock<Ledger>>>, params: Query<ReportRequest>) -> ApiResult<ReportResponse> {
+#[utoipa::path(tag = "statistics", params(ReportRequest, ExportRequest), responses((status = 200, body = SuccessWrapper<ReportResponse>, description = "json, or spreadsheet file when `format` is given")))]
+#[get("/report")]
+pub async fn get_report(ledger: Data<Arc<RwLock<Ledger>>>, params: Query<ReportRequest>, export: Query<ExportRequest>) -> ApiResult<ReportResponse> {
     let ledger = ledger.read().await;
-    let mut connection = ledger.connection().await;
     let mut operations = ledger.operations().await;
 
-    let latest_account_balances = sqlx::query_as::<_, DetailRow>(
-        r#"
-        SELECT
-            date(datetime) AS date,
-            account,
-            balance_number,
-            balance_commodity
-        FROM
-            account_daily_balance
-        WHERE datetime <= $1
-        GROUP BY
-            account
-        HAVING
-            max(datetime)
-    "#,
-    )
-    .bind(params.to.naive_local())
-    .fetch_all(&mut connection)
-    .await?;
+    let latest_account_balances = operations.accounts_latest_balance(Bound::Included(params.to.naive_local())).await?;
     let balance = latest_account_balances
         .iter()
         .filter(|it| it.account.starts_with("Assets") || it.account.starts_with("Liabilities"))
@@ -1049,31 +1216,7 @@ pub async fn get_report(ledger: Data<Arc<RwLock<Ledger>>>, params: Query<ReportR
         .filter(|it| it.account.starts_with("Liabilities"))
         .fold(BigDecimal::zero(), |acc, item| acc.add(&*item.balance_number));
 
-    #[derive(FromRow)]
-    struct DurationBalance {
-        account_type: String,
-        amount: ZhangBigDecimal,
-        commodity: String,
-    }
-
-    let duration_balances = sqlx::query_as::<_, DurationBalance>(
-        r#"
-    SELECT accounts.type             AS account_type,
-           sum(inferred_unit_number) AS amount,
-           inferred_unit_commodity   AS commodity
-    FROM transaction_postings
-             JOIN transactions ON transactions.id = transaction_postings.trx_id
-             JOIN accounts ON accounts.name = transaction_postings.account
-    WHERE transactions.datetime >= $1 and transactions.datetime <= $2
-    GROUP BY
-        accounts.type,
-        inferred_unit_commodity
-    "#,
-    )
-    .bind(params.from.naive_local())
-    .bind(params.to.naive_local())
-    .fetch_all(&mut connection)
-    .await?;
+    let duration_balances = operations.account_type_totals(params.from.naive_local(), params.to.naive_local()).await?;
 
     let income = duration_balances
         .iter()
@@ -1098,21 +1241,7 @@ pub async fn get_report(ledger: Data<Arc<RwLock<Ledger>>>, params: Query<ReportR
             commodity: ledger.options.operating_currency.to_owned(),
         });
 
-    let transaction_total = sqlx::query_as::<_, (i64,)>(
-        r#"
-        select count(1) as total
-        from transactions
-        where transactions."type" != 'BalancePad'
-          and transactions."type" != 'BalanceCheck'
-          and datetime >= ?
-          and datetime <= ?
-    "#,
-    )
-    .bind(params.from.naive_local())
-    .bind(params.to.naive_local())
-    .fetch_one(&mut connection)
-    .await?
-    .0;
+    let transaction_total = operations.dated_transaction_counts(params.from.naive_local(), params.to.naive_local()).await?;
 
     let income_transactions = operations
         .account_dated_journals("Income", params.from.naive_local(), params.to.naive_local())
@@ -1176,28 +1305,33 @@ pub async fn get_report(ledger: Data<Arc<RwLock<Ledger>>>, params: Query<ReportR
         .take(10)
         .collect_vec();
 
-    ResponseWrapper::json(ReportResponse {
-        from: params.from.naive_local(),
-        to: params.to.naive_local(),
-        balance: AmountResponse {
-            number: ZhangBigDecimal(balance),
-            commodity: ledger.options.operating_currency.to_owned(),
-        },
-        liability: AmountResponse {
-            number: ZhangBigDecimal(liability),
-            commodity: ledger.options.operating_currency.to_owned(),
+    ResponseWrapper::tabular(
+        ReportResponse {
+            from: params.from.naive_local(),
+            to: params.to.naive_local(),
+            balance: AmountResponse {
+                number: ZhangBigDecimal(balance),
+                commodity: ledger.options.operating_currency.to_owned(),
+            },
+            liability: AmountResponse {
+                number: ZhangBigDecimal(liability),
+                commodity: ledger.options.operating_currency.to_owned(),
+            },
+            income,
+            expense,
+            transaction_number: transaction_total,
+            income_rank,
+            income_top_transactions,
+            expense_rank,
+            expense_top_transactions,
         },
-        income,
-        expense,
-        transaction_number: transaction_total,
-        income_rank,
-        income_top_transactions,
-        expense_rank,
-        expense_top_transactions,
-    })
+        export.format,
+        "report",
+    )
 }
 
-#[get("/api/options")]
+#[utoipa::path(tag = "system", responses((status = 200, body = SuccessWrapper<Vec<OptionDomain>>)))]
+#[get("/options")]
 pub async fn get_all_options(ledger: Data<Arc<RwLock<Ledger>>>) -> ApiResult<Vec<OptionDomain>> {
     let ledger = ledger.read().await;
     let mut operations = ledger.operations().await;
@@ -1217,9 +1351,12 @@ use crate::util::AmountLike;
 #[cfg(feature = "frontend")]
 use actix_web::{HttpRequest, HttpResponse};
 use zhang_core::constants::KEY_OPERATING_CURRENCY;
-use zhang_core::domains::schemas::{AccountJournalDomain, ErrorDomain, MetaType, OptionDomain};
+use zhang_core::domains::schemas::{
+    AccountBalanceDomain, AccountDailyBalanceDomain, AccountDomain, AccountJournalDomain, AccountStatus, ErrorDomain, JournalDomain, JournalFilter,
+    LabelAccountEffectDomain, LabelPeriodTotalDomain, LabelUsageDomain, OptionDomain, PriceDomain,
+};
 use zhang_core::domains::Operations;
-use zhang_core::exporter::AppendableExporter;
+use zhang_core::exporter::{write_file_atomically, AppendableExporter};
 use zhang_core::{ZhangError, ZhangResult};
 
 #[cfg(feature = "frontend")]
@@ -1243,9 +1380,385 @@ where
     }
 }
 
+/// decode the base64 encoded file path and resolve it against ledger root, paths escaping the root are refused
+pub(crate) fn ledger_file_path(entry: &std::path::Path, encoded_file_path: &str) -> ServerResult<(String, PathBuf)> {
+    let filename = base64::decode(encoded_file_path)
+        .ok()
+        .and_then(|it| String::from_utf8(it).ok())
+        .ok_or_else(|| ServerError::BadRequest("file path is not valid base64 encoded utf-8".to_string()))?;
+    let relative_path = PathBuf::from(&filename);
+    if !relative_path.components().all(|it| matches!(it, Component::Normal(_) | Component::CurDir)) {
+        return Err(ServerError::BadRequest(format!("file path {} is outside of ledger", filename)));
+    }
+    let full_path = entry.join(&relative_path);
+    // resolve symlinks, the file itself may not exist yet
+    let resolved = match (full_path.canonicalize(), full_path.parent(), full_path.file_name()) {
+        (Ok(resolved), _, _) => resolved,
+        (Err(_), Some(parent), Some(name)) => parent.canonicalize().map(|it| it.join(name)).unwrap_or_else(|_| full_path.clone()),
+        _ => full_path.clone(),
+    };
+    if !resolved.starts_with(entry) {
+        return Err(ServerError::BadRequest(format!("file path {} is outside of ledger", filename)));
+    }
+    Ok((filename, full_path))
+}
+
 pub(crate) fn insert_line(file: PathBuf, content: &str, at: usize) -> ServerResult<()> {
     let mut file_content = std::fs::read_to_string(&file).with_path(&file)?;
     file_content.insert(at, '\n');
     file_content.insert_str(at + 1, content);
-    Ok(std::fs::write(&file, file_content).with_path(&file)?)
+    Ok(write_file_atomically(&file, &file_content)?)
+}
+
+#[cfg(test)]
+mod test {
+    use tempfile::tempdir;
+
+    use std::collections::HashMap;
+
+    use bigdecimal::{BigDecimal, Zero};
+    use chrono::{NaiveDate, NaiveDateTime, Timelike};
+    use zhang_core::database::type_ext::big_decimal::ZhangBigDecimal;
+    use zhang_core::domains::schemas::{AccountBalanceDomain, AccountDailyBalanceDomain, AccountDomain, AccountStatus, LabelAccountEffectDomain, PriceDomain};
+
+    use crate::request::{CreateTransactionPostingRequest, CreateTransactionRequest, StatisticInterval};
+    use crate::route::{balance_history, build_transaction, label_detail, latest_price, ledger_file_path, AccountTreeBuilder};
+
+    async fn load_ledger(content: &str) -> (tempfile::TempDir, zhang_core::ledger::Ledger) {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("main.zhang"), content).unwrap();
+        let ledger = zhang_core::ledger::Ledger::load::<zhang_core::transform::TextTransformer>(dir.path().to_path_buf(), "main.zhang".to_string())
+            .await
+            .unwrap();
+        (dir, ledger)
+    }
+
+    fn transaction_request(ledger: &zhang_core::ledger::Ledger, datetime: NaiveDateTime, accounts: &[&str]) -> CreateTransactionRequest {
+        use chrono::TimeZone;
+        CreateTransactionRequest {
+            datetime: ledger.options.timezone.from_local_datetime(&datetime).unwrap().with_timezone(&chrono::Utc),
+            payee: "KFC".to_string(),
+            narration: None,
+            postings: accounts
+                .iter()
+                .map(|account| CreateTransactionPostingRequest {
+                    account: account.to_string(),
+                    unit: None,
+                    cost: None,
+                    price: None,
+                    flag: None,
+                    metas: None,
+                })
+                .collect(),
+            metas: vec![],
+            tags: vec![],
+            links: vec![],
+            flag: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn should_keep_date_form_and_flag_of_origin_transaction() {
+        let (_dir, ledger) = load_ledger("1970-01-02 ! \"KFC\"\n  Assets:Bank -10 CNY\n  Expenses:Food\n").await;
+        let origin = ledger
+            .directives
+            .iter()
+            .find_map(|it| match &it.data {
+                zhang_ast::Directive::Transaction(trx) => Some(trx.clone()),
+                _ => None,
+            })
+            .unwrap();
+        let midnight = NaiveDate::from_ymd_opt(1970, 1, 3).unwrap().and_hms_opt(0, 0, 0).unwrap();
+
+        let trx = build_transaction(
+            &ledger,
+            transaction_request(&ledger, midnight, &["Assets:Bank", "Expenses:Food"]),
+            Some(&origin),
+        )
+        .unwrap();
+        assert_eq!(zhang_ast::Date::Date(midnight.date()), trx.date);
+        assert_eq!(Some(zhang_ast::Flag::Warning), trx.flag);
+
+        let mut request = transaction_request(&ledger, midnight.with_hour(12).unwrap(), &["Assets:Bank", "Expenses:Food"]);
+        request.flag = Some("*".to_string());
+        let trx = build_transaction(&ledger, request, Some(&origin)).unwrap();
+        assert_eq!(zhang_ast::Date::Datetime(midnight.with_hour(12).unwrap()), trx.date);
+        assert_eq!(Some(zhang_ast::Flag::Okay), trx.flag);
+
+        let mut flagged = origin.clone();
+        flagged.postings[1].flag = Some(zhang_ast::Flag::Warning);
+        let trx = build_transaction(
+            &ledger,
+            transaction_request(&ledger, midnight, &["Expenses:Food", "Assets:Bank"]),
+            Some(&flagged),
+        )
+        .unwrap();
+        assert_eq!(Some(zhang_ast::Flag::Warning), trx.postings[0].flag);
+        assert_eq!(None, trx.postings[1].flag);
+    }
+
+    #[tokio::test]
+    async fn should_refuse_transaction_without_posting() {
+        let (_dir, ledger) = load_ledger("").await;
+        let midnight = NaiveDate::from_ymd_opt(1970, 1, 3).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert!(build_transaction(&ledger, transaction_request(&ledger, midnight, &[]), None).is_err());
+
+        let mut request = transaction_request(&ledger, midnight, &["Assets:Bank"]);
+        request.flag = Some("?".to_string());
+        assert!(build_transaction(&ledger, request, None).is_err());
+    }
+
+    #[actix_web::test]
+    async fn should_keep_cost_price_and_meta_of_postings_when_updating_transaction() {
+        use actix_web::test::{call_service, init_service, TestRequest};
+        use zhang_core::exporter::AppendableExporter;
+        use zhang_core::utils::id::FromSpan;
+
+        let (dir, ledger) = load_ledger(indoc::indoc! {r#"
+            1970-01-01 open Assets:Bank
+            1970-01-01 open Assets:Stock
+            1970-01-02 "Broker" "buy"
+              Assets:Stock 10 AAPL {15 USD} @ 16 USD
+                lot: "first"
+              Assets:Bank -150 USD
+        "#})
+        .await;
+        let transaction_id = ledger
+            .directives
+            .iter()
+            .find_map(|it| match &it.data {
+                zhang_ast::Directive::Transaction(_) => Some(uuid::Uuid::from_span(&it.span).to_string()),
+                _ => None,
+            })
+            .unwrap();
+        let request = serde_json::json!({
+            "datetime": "1970-01-02T00:00:00Z",
+            "payee": "Broker",
+            "narration": "buy more",
+            "postings": [
+                {"account": "Assets:Stock", "unit": {"number": "10", "commodity": "AAPL"}},
+                {"account": "Assets:Bank", "unit": null}
+            ],
+            "metas": [],
+            "tags": [],
+            "links": []
+        });
+
+        let exporter: std::sync::Arc<dyn AppendableExporter> = std::sync::Arc::new(zhang_core::exporter::TextExporter {});
+        let app = init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(std::sync::Arc::new(tokio::sync::RwLock::new(ledger))))
+                .app_data(actix_web::web::Data::from(exporter))
+                .service(crate::route::update_transaction),
+        )
+        .await;
+        let response = call_service(
+            &app,
+            TestRequest::put()
+                .uri(&format!("/transactions/{}", transaction_id))
+                .set_json(request)
+                .to_request(),
+        )
+        .await;
+        assert!(response.status().is_success());
+
+        let content = std::fs::read_to_string(dir.path().join("main.zhang")).unwrap();
+        assert!(content.contains("buy more"), "{}", content);
+        assert!(content.contains("Assets:Stock 10 AAPL { 15 USD } @ 16 USD"), "{}", content);
+        assert!(content.contains("lot: \"first\""), "{}", content);
+    }
+
+    #[actix_web::test]
+    async fn should_export_every_journal_regardless_of_page() {
+        use actix_web::test::{call_service, init_service, read_body, TestRequest};
+
+        let content = (1..=3)
+            .map(|day| format!("1970-01-0{} \"KFC\" \"lunch {}\"\n  Assets:Bank -10 CNY\n  Expenses:Food\n", day, day))
+            .collect::<String>();
+        let (_dir, ledger) = load_ledger(&content).await;
+        let app = init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new(std::sync::Arc::new(tokio::sync::RwLock::new(ledger))))
+                .service(crate::route::get_journals)
+                .service(crate::route::get_account_journals),
+        )
+        .await;
+
+        for uri in ["/journals?size=2&page=2&format=csv", "/accounts/Assets:Bank/journals?format=csv"] {
+            let response = call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+            assert!(response.status().is_success(), "{} should be exported", uri);
+            let body = String::from_utf8(read_body(response).await.to_vec()).unwrap();
+            for day in 1..=3 {
+                assert!(body.contains(&format!("lunch {}", day)), "{} should contain every journal, got {}", uri, body);
+            }
+        }
+
+        let response = call_service(&app, TestRequest::get().uri("/journals?size=2").to_request()).await;
+        let body: serde_json::Value = serde_json::from_slice(&read_body(response).await).unwrap();
+        assert_eq!(2, body["data"]["records"].as_array().unwrap().len());
+        assert_eq!(3, body["data"]["total_count"]);
+    }
+
+    #[test]
+    fn should_resolve_file_path_inside_ledger() {
+        let dir = tempdir().unwrap();
+        let entry = dir.path().canonicalize().unwrap();
+        let (filename, path) = ledger_file_path(&entry, &base64::encode("data/2023/1.zhang")).unwrap();
+        assert_eq!("data/2023/1.zhang", filename);
+        assert_eq!(entry.join("data/2023/1.zhang"), path);
+    }
+
+    #[test]
+    fn should_refuse_file_path_outside_ledger() {
+        let dir = tempdir().unwrap();
+        let entry = dir.path().canonicalize().unwrap();
+        assert!(ledger_file_path(&entry, &base64::encode("../outside.zhang")).is_err());
+        assert!(ledger_file_path(&entry, &base64::encode("data/../../outside.zhang")).is_err());
+        assert!(ledger_file_path(&entry, &base64::encode("/etc/passwd")).is_err());
+        assert!(ledger_file_path(&entry, "not base64!").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_refuse_symlink_escaping_ledger() {
+        let dir = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        let entry = dir.path().canonicalize().unwrap();
+        std::os::unix::fs::symlink(outside.path(), entry.join("link")).unwrap();
+        assert!(ledger_file_path(&entry, &base64::encode("link/main.zhang")).is_err());
+    }
+
+    fn effect(account: &str, amount: i32, transactions: i64) -> LabelAccountEffectDomain {
+        LabelAccountEffectDomain {
+            account: account.to_string(),
+            commodity: "CNY".to_string(),
+            amount: ZhangBigDecimal(BigDecimal::from(amount)),
+            transactions,
+        }
+    }
+
+    #[test]
+    fn should_balance_label_group_when_shared_accounts_are_cleared() {
+        let invoice = vec![
+            effect("Assets:Bank", 100, 1),
+            effect("Assets:Receivable", 0, 2),
+            effect("Income:Sales", -100, 1),
+        ];
+        let detail = label_detail("invoice-123".to_string(), invoice, vec![]);
+        assert!(detail.balanced);
+        assert_eq!(3, detail.accounts.len());
+
+        let partially_paid = vec![
+            effect("Assets:Bank", 60, 1),
+            effect("Assets:Receivable", 40, 2),
+            effect("Income:Sales", -100, 1),
+        ];
+        assert!(!label_detail("invoice-123".to_string(), partially_paid, vec![]).balanced);
+
+        let unpaid = vec![effect("Assets:Receivable", 100, 1), effect("Income:Sales", -100, 1)];
+        assert!(!label_detail("invoice-123".to_string(), unpaid, vec![]).balanced);
+    }
+
+    fn account(name: &str) -> AccountDomain {
+        AccountDomain {
+            date: NaiveDateTime::default(),
+            r#type: name.split(':').next().unwrap().to_string(),
+            name: name.to_string(),
+            status: AccountStatus::Open,
+            alias: None,
+        }
+    }
+
+    fn balance(account: &str, number: i32, commodity: &str) -> AccountBalanceDomain {
+        AccountBalanceDomain {
+            datetime: NaiveDateTime::default(),
+            account: account.to_string(),
+            account_status: AccountStatus::Open,
+            balance_number: ZhangBigDecimal(BigDecimal::from(number)),
+            balance_commodity: commodity.to_string(),
+        }
+    }
+
+    #[test]
+    fn should_build_account_tree_with_synthetic_parents_and_rolled_up_balances() {
+        let accounts = vec![
+            account("Assets:Bank:Checking"),
+            account("Assets:Bank:Saving"),
+            account("Assets:Cash"),
+            account("Expenses:Food"),
+        ];
+        let balances = vec![
+            balance("Assets:Bank:Checking", 100, "CNY"),
+            balance("Assets:Bank:Saving", 10, "USD"),
+            balance("Assets:Cash", 20, "CNY"),
+        ];
+        let prices = HashMap::from([("USD".to_string(), BigDecimal::from(7))]);
+        let tree = AccountTreeBuilder::new(accounts, balances, "CNY".to_string(), prices).build();
+
+        assert_eq!(vec!["Assets", "Expenses"], tree.iter().map(|it| it.name.as_str()).collect::<Vec<_>>());
+        let assets = &tree[0];
+        assert!(assets.synthetic);
+        assert_eq!(BigDecimal::from(190), assets.subtree_amount.calculated.number.0);
+        assert_eq!(
+            vec!["Assets:Bank", "Assets:Cash"],
+            assets.children.iter().map(|it| it.name.as_str()).collect::<Vec<_>>()
+        );
+
+        let bank = &assets.children[0];
+        assert!(bank.synthetic);
+        assert_eq!("Bank", bank.leaf);
+        assert_eq!(BigDecimal::zero(), bank.amount.calculated.number.0);
+        assert_eq!(BigDecimal::from(170), bank.subtree_amount.calculated.number.0);
+        assert_eq!(BigDecimal::from(10), bank.subtree_amount.detail["USD"].0);
+
+        let checking = &bank.children[0];
+        assert!(!checking.synthetic);
+        assert!(checking.children.is_empty());
+        assert_eq!(BigDecimal::from(100), checking.amount.calculated.number.0);
+    }
+
+    fn daily_balance(date: NaiveDate, number: i32, commodity: &str) -> AccountDailyBalanceDomain {
+        AccountDailyBalanceDomain {
+            date,
+            account: "Assets:Bank".to_string(),
+            balance_number: ZhangBigDecimal(BigDecimal::from(number)),
+            balance_commodity: commodity.to_string(),
+        }
+    }
+
+    #[test]
+    fn should_gap_fill_balance_history_per_interval() {
+        let date = |month, day| NaiveDate::from_ymd_opt(2023, month, day).unwrap();
+        let balances = vec![
+            daily_balance(date(1, 1), 100, "CNY"),
+            daily_balance(date(2, 10), 80, "CNY"),
+            daily_balance(date(3, 5), 5, "USD"),
+        ];
+        let history = balance_history(balances, date(1, 15), date(3, 20), &StatisticInterval::Month);
+        assert_eq!(vec![date(1, 31), date(2, 28), date(3, 20)], history.iter().map(|it| it.0).collect::<Vec<_>>());
+        assert_eq!(BigDecimal::from(100), history[0].1["CNY"]);
+        assert_eq!(BigDecimal::from(80), history[1].1["CNY"]);
+        assert!(!history[1].1.contains_key("USD"));
+        assert_eq!(BigDecimal::from(80), history[2].1["CNY"]);
+        assert_eq!(BigDecimal::from(5), history[2].1["USD"]);
+
+        let daily = balance_history(vec![], date(1, 1), date(1, 7), &StatisticInterval::Day);
+        assert_eq!(7, daily.len());
+        assert!(daily.iter().all(|it| it.1.is_empty()));
+    }
+
+    #[test]
+    fn should_find_latest_price_in_series() {
+        let price = |day, amount| PriceDomain {
+            datetime: NaiveDate::from_ymd_opt(2023, 1, day).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            commodity: "USD".to_string(),
+            amount: ZhangBigDecimal(BigDecimal::from(amount)),
+            target_commodity: "CNY".to_string(),
+        };
+        let prices = vec![price(2, 7), price(5, 8)];
+        let at = |day| NaiveDate::from_ymd_opt(2023, 1, day).unwrap().and_hms_opt(23, 59, 59).unwrap();
+        assert_eq!(None, latest_price(&prices, at(1)));
+        assert_eq!(Some(&BigDecimal::from(7)), latest_price(&prices, at(4)));
+        assert_eq!(Some(&BigDecimal::from(8)), latest_price(&prices, at(5)));
+    }
 }
@@ -0,0 +1,488 @@
+use bigdecimal::{BigDecimal, ToPrimitive};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook, XlsxError};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use zhang_core::database::type_ext::big_decimal::ZhangBigDecimal;
+use zhang_core::domains::schemas::AccountJournalDomain;
+
+use crate::response::{
+    AmountResponse, CommodityListItemResponse, JournalItemResponse, JournalTransactionPostingResponse, Pageable, ReportRankItemResponse, ReportResponse,
+    StatisticResponse,
+};
+use crate::ServerResult;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        }
+    }
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Cell {
+    Empty,
+    Text(String),
+    Number(BigDecimal),
+    Integer(i64),
+    Boolean(bool),
+    Date(NaiveDate),
+    Datetime(NaiveDateTime),
+}
+
+impl Cell {
+    fn to_text(&self) -> String {
+        match self {
+            Cell::Empty => String::new(),
+            Cell::Text(text) => text.clone(),
+            Cell::Number(number) => number.to_string(),
+            Cell::Integer(number) => number.to_string(),
+            Cell::Boolean(boolean) => boolean.to_string(),
+            Cell::Date(date) => date.format("%Y-%m-%d").to_string(),
+            Cell::Datetime(datetime) => datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+        }
+    }
+}
+
+impl From<String> for Cell {
+    fn from(value: String) -> Self {
+        Cell::Text(value)
+    }
+}
+impl From<&str> for Cell {
+    fn from(value: &str) -> Self {
+        Cell::Text(value.to_owned())
+    }
+}
+impl From<ZhangBigDecimal> for Cell {
+    fn from(value: ZhangBigDecimal) -> Self {
+        Cell::Number(value.0)
+    }
+}
+impl From<i64> for Cell {
+    fn from(value: i64) -> Self {
+        Cell::Integer(value)
+    }
+}
+impl From<i32> for Cell {
+    fn from(value: i32) -> Self {
+        Cell::Integer(value as i64)
+    }
+}
+impl From<bool> for Cell {
+    fn from(value: bool) -> Self {
+        Cell::Boolean(value)
+    }
+}
+impl From<NaiveDate> for Cell {
+    fn from(value: NaiveDate) -> Self {
+        Cell::Date(value)
+    }
+}
+impl From<NaiveDateTime> for Cell {
+    fn from(value: NaiveDateTime) -> Self {
+        Cell::Datetime(value)
+    }
+}
+impl<T: Into<Cell>> From<Option<T>> for Cell {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Cell::Empty)
+    }
+}
+
+macro_rules! row {
+    ($($cell:expr),* $(,)?) => {
+        vec![$(Cell::from($cell)),*]
+    };
+}
+
+pub struct Sheet {
+    pub name: &'static str,
+    pub headers: Vec<&'static str>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+/// response which can be flattened into spreadsheet tables
+pub trait Tabular {
+    fn sheets(&self) -> Vec<Sheet>;
+}
+
+/// a single record which would be flattened into one or more rows, e.g. a journal with its postings
+pub trait TabularRow {
+    const NAME: &'static str;
+    fn headers() -> Vec<&'static str>;
+    fn rows(&self) -> Vec<Vec<Cell>>;
+}
+
+impl<T: TabularRow> Tabular for Vec<T> {
+    fn sheets(&self) -> Vec<Sheet> {
+        vec![Sheet {
+            name: T::NAME,
+            headers: T::headers(),
+            rows: self.iter().flat_map(|it| it.rows()).collect(),
+        }]
+    }
+}
+
+impl<T: TabularRow + serde::Serialize> Tabular for Pageable<T> {
+    fn sheets(&self) -> Vec<Sheet> {
+        self.records.sheets()
+    }
+}
+
+pub fn render(format: ExportFormat, sheets: Vec<Sheet>) -> ServerResult<Vec<u8>> {
+    match format {
+        ExportFormat::Csv => render_csv(sheets),
+        ExportFormat::Xlsx => render_xlsx(sheets),
+    }
+}
+
+/// csv has no concept of sheets, so multiple sheets are written one after another, each leading by its name
+fn render_csv(sheets: Vec<Sheet>) -> ServerResult<Vec<u8>> {
+    let with_title = sheets.len() > 1;
+    let mut content = vec![];
+    for (idx, sheet) in sheets.into_iter().enumerate() {
+        if with_title && idx > 0 {
+            content.push(b'\n');
+        }
+        let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(vec![]);
+        if with_title {
+            writer.write_record([sheet.name])?;
+        }
+        writer.write_record(&sheet.headers)?;
+        for row in sheet.rows {
+            writer.write_record(row.iter().map(Cell::to_text))?;
+        }
+        content.extend(writer.into_inner().map_err(|e| e.into_error())?);
+    }
+    Ok(content)
+}
+
+fn render_xlsx(sheets: Vec<Sheet>) -> ServerResult<Vec<u8>> {
+    let header_format = Format::new().set_bold();
+    let date_format = Format::new().set_num_format("yyyy-mm-dd");
+    let datetime_format = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+
+    let mut workbook = Workbook::new();
+    for sheet in sheets {
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name(sheet.name)?;
+        for (col, header) in sheet.headers.iter().enumerate() {
+            worksheet.write_string_with_format(0, col as u16, *header, &header_format)?;
+        }
+        for (row_idx, row) in sheet.rows.into_iter().enumerate() {
+            let row_idx = row_idx as u32 + 1;
+            for (col, cell) in row.into_iter().enumerate() {
+                let col = col as u16;
+                match cell {
+                    Cell::Empty => continue,
+                    Cell::Text(text) => worksheet.write_string(row_idx, col, text)?,
+                    Cell::Number(number) => match number.to_f64() {
+                        Some(number) => worksheet.write_number(row_idx, col, number)?,
+                        None => worksheet.write_string(row_idx, col, number.to_string())?,
+                    },
+                    Cell::Integer(number) => worksheet.write_number(row_idx, col, number as f64)?,
+                    Cell::Boolean(boolean) => worksheet.write_boolean(row_idx, col, boolean)?,
+                    Cell::Date(date) => worksheet.write_datetime_with_format(row_idx, col, excel_date(&date)?, &date_format)?,
+                    Cell::Datetime(datetime) => {
+                        let excel_datetime = excel_date(&datetime.date())?.and_hms(datetime.hour() as u16, datetime.minute() as u8, datetime.second())?;
+                        worksheet.write_datetime_with_format(row_idx, col, excel_datetime, &datetime_format)?
+                    }
+                };
+            }
+        }
+    }
+    Ok(workbook.save_to_buffer()?)
+}
+
+fn excel_date(date: &NaiveDate) -> Result<ExcelDateTime, XlsxError> {
+    ExcelDateTime::from_ymd(date.year() as u16, date.month() as u8, date.day() as u8)
+}
+
+fn posting_row(posting: &JournalTransactionPostingResponse) -> Vec<Cell> {
+    row![
+        posting.account.as_str(),
+        posting.unit_number.clone(),
+        posting.unit_commodity.clone(),
+        posting.cost_number.clone(),
+        posting.cost_commodity.clone(),
+        posting.price_number.clone(),
+        posting.price_commodity.clone(),
+        posting.inferred_unit_number.clone(),
+        posting.inferred_unit_commodity.as_str(),
+        posting.account_before_number.clone(),
+        posting.account_before_commodity.as_str(),
+        posting.account_after_number.clone(),
+        posting.account_after_commodity.as_str(),
+    ]
+}
+
+impl TabularRow for JournalItemResponse {
+    const NAME: &'static str = "journals";
+
+    fn headers() -> Vec<&'static str> {
+        vec![
+            "id",
+            "datetime",
+            "type",
+            "flag",
+            "payee",
+            "narration",
+            "tags",
+            "links",
+            "account",
+            "unit_number",
+            "unit_commodity",
+            "cost_number",
+            "cost_commodity",
+            "price_number",
+            "price_commodity",
+            "inferred_unit_number",
+            "inferred_unit_commodity",
+            "account_before_number",
+            "account_before_commodity",
+            "account_after_number",
+            "account_after_commodity",
+        ]
+    }
+
+    fn rows(&self) -> Vec<Vec<Cell>> {
+        let (header, postings) = match self {
+            JournalItemResponse::Transaction(trx) => (
+                row![
+                    trx.id.as_str(),
+                    trx.datetime,
+                    "Transaction",
+                    trx.flag.as_str(),
+                    trx.payee.as_str(),
+                    trx.narration.clone(),
+                    trx.tags.join(","),
+                    trx.links.join(","),
+                ],
+                &trx.postings,
+            ),
+            JournalItemResponse::BalanceCheck(check) => (
+                row![
+                    check.id.as_str(),
+                    check.datetime,
+                    "BalanceCheck",
+                    None::<String>,
+                    check.payee.as_str(),
+                    check.narration.clone(),
+                    None::<String>,
+                    None::<String>,
+                ],
+                &check.postings,
+            ),
+            JournalItemResponse::BalancePad(pad) => (
+                row![
+                    pad.id.as_str(),
+                    pad.datetime,
+                    "BalancePad",
+                    None::<String>,
+                    pad.payee.as_str(),
+                    pad.narration.clone(),
+                    None::<String>,
+                    None::<String>,
+                ],
+                &pad.postings,
+            ),
+        };
+        postings
+            .iter()
+            .map(|posting| header.iter().cloned().chain(posting_row(posting)).collect())
+            .collect()
+    }
+}
+
+impl TabularRow for AccountJournalDomain {
+    const NAME: &'static str = "account_journals";
+
+    fn headers() -> Vec<&'static str> {
+        vec![
+            "datetime",
+            "account",
+            "trx_id",
+            "payee",
+            "narration",
+            "inferred_unit_number",
+            "inferred_unit_commodity",
+            "account_after_number",
+            "account_after_commodity",
+        ]
+    }
+
+    fn rows(&self) -> Vec<Vec<Cell>> {
+        vec![row![
+            self.datetime,
+            self.account.as_str(),
+            self.trx_id.as_str(),
+            self.payee.as_str(),
+            self.narration.clone(),
+            self.inferred_unit_number.clone(),
+            self.inferred_unit_commodity.as_str(),
+            self.account_after_number.clone(),
+            self.account_after_commodity.as_str(),
+        ]]
+    }
+}
+
+impl TabularRow for CommodityListItemResponse {
+    const NAME: &'static str = "commodities";
+
+    fn headers() -> Vec<&'static str> {
+        vec![
+            "name",
+            "precision",
+            "prefix",
+            "suffix",
+            "rounding",
+            "total_amount",
+            "latest_price_date",
+            "latest_price_amount",
+            "latest_price_commodity",
+        ]
+    }
+
+    fn rows(&self) -> Vec<Vec<Cell>> {
+        vec![row![
+            self.name.as_str(),
+            self.precision,
+            self.prefix.clone(),
+            self.suffix.clone(),
+            self.rounding.clone(),
+            self.total_amount.clone(),
+            self.latest_price_date,
+            self.latest_price_amount.clone(),
+            self.latest_price_commodity.clone(),
+        ]]
+    }
+}
+
+impl Tabular for StatisticResponse {
+    fn sheets(&self) -> Vec<Sheet> {
+        let flatten = |data: &std::collections::HashMap<NaiveDate, std::collections::HashMap<String, AmountResponse>>| {
+            let mut rows = data
+                .iter()
+                .flat_map(|(date, amounts)| amounts.iter().map(move |(key, amount)| (*date, key.clone(), amount.clone())))
+                .collect::<Vec<_>>();
+            rows.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+            rows.into_iter()
+                .map(|(date, key, amount)| row![date, key, amount.number, amount.commodity])
+                .collect::<Vec<_>>()
+        };
+        vec![
+            Sheet {
+                name: "changes",
+                headers: vec!["date", "account_type", "number", "commodity"],
+                rows: flatten(&self.changes),
+            },
+            Sheet {
+                name: "details",
+                headers: vec!["date", "account", "number", "commodity"],
+                rows: flatten(&self.details),
+            },
+        ]
+    }
+}
+
+impl Tabular for ReportResponse {
+    fn sheets(&self) -> Vec<Sheet> {
+        let amount_row = |item: &str, amount: &AmountResponse| row![item, amount.number.clone(), amount.commodity.as_str()];
+        let rank_rows = |rank: &Vec<ReportRankItemResponse>| rank.iter().map(|it| row![it.account.as_str(), it.percent.clone()]).collect();
+        vec![
+            Sheet {
+                name: "summary",
+                headers: vec!["item", "number", "commodity"],
+                rows: vec![
+                    row!["from", self.from],
+                    row!["to", self.to],
+                    amount_row("balance", &self.balance),
+                    amount_row("liability", &self.liability),
+                    amount_row("income", &self.income),
+                    amount_row("expense", &self.expense),
+                    row!["transaction_number", self.transaction_number],
+                ],
+            },
+            Sheet {
+                name: "income_rank",
+                headers: vec!["account", "percent"],
+                rows: rank_rows(&self.income_rank),
+            },
+            Sheet {
+                name: "income_top_transactions",
+                headers: AccountJournalDomain::headers(),
+                rows: self.income_top_transactions.iter().flat_map(|it| it.rows()).collect(),
+            },
+            Sheet {
+                name: "expense_rank",
+                headers: vec!["account", "percent"],
+                rows: rank_rows(&self.expense_rank),
+            },
+            Sheet {
+                name: "expense_top_transactions",
+                headers: AccountJournalDomain::headers(),
+                rows: self.expense_top_transactions.iter().flat_map(|it| it.rows()).collect(),
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bigdecimal::BigDecimal;
+    use chrono::NaiveDate;
+
+    use crate::tabular::{render, Cell, ExportFormat, Sheet};
+
+    fn sheet(name: &'static str) -> Sheet {
+        Sheet {
+            name,
+            headers: vec!["date", "account", "number"],
+            rows: vec![vec![
+                Cell::Date(NaiveDate::from_ymd_opt(2023, 1, 1).unwrap()),
+                Cell::Text("Assets:Bank, Main".to_string()),
+                Cell::Number(BigDecimal::from(-10)),
+            ]],
+        }
+    }
+
+    #[test]
+    fn should_render_single_sheet_csv() {
+        let content = render(ExportFormat::Csv, vec![sheet("data")]).unwrap();
+        assert_eq!(
+            "date,account,number\n2023-01-01,\"Assets:Bank, Main\",-10\n",
+            String::from_utf8(content).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_render_multiple_sheets_csv_with_title() {
+        let content = render(ExportFormat::Csv, vec![sheet("a"), sheet("b")]).unwrap();
+        assert_eq!(
+            "a\ndate,account,number\n2023-01-01,\"Assets:Bank, Main\",-10\n\nb\ndate,account,number\n2023-01-01,\"Assets:Bank, Main\",-10\n",
+            String::from_utf8(content).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_render_xlsx_as_zip() {
+        let content = render(ExportFormat::Xlsx, vec![sheet("a"), sheet("b")]).unwrap();
+        assert_eq!(b"PK", &content[0..2]);
+    }
+}
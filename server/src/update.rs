@@ -0,0 +1,212 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{Local, NaiveDateTime};
+use log::{debug, info};
+use self_update::version::bump_is_greater;
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+use crate::response::UpdateStatusResponse;
+use crate::{ServerError, ServerResult};
+
+/// upstream of zhang releases, only contacted when update checking or version reporting is enabled
+#[async_trait]
+pub trait ReleaseSource: Send + Sync {
+    /// version of the latest release
+    async fn latest_version(&self) -> ServerResult<String>;
+
+    /// report the running version for anonymous statistics
+    async fn report_version(&self, version: &str, build_date: &str) -> ServerResult<()>;
+}
+
+/// releases on github, versions are reported to zhang.resource.rs
+pub struct GithubReleaseSource;
+
+#[async_trait]
+impl ReleaseSource for GithubReleaseSource {
+    async fn latest_version(&self) -> ServerResult<String> {
+        let release = tokio::task::spawn_blocking(|| {
+            self_update::backends::github::Update::configure()
+                .repo_owner("zhang-accounting")
+                .repo_name("zhang")
+                .bin_name("zhang")
+                .current_version(env!("CARGO_PKG_VERSION"))
+                .build()?
+                .get_latest_release()
+        })
+        .await
+        .map_err(|e| ServerError::ReleaseSourceError(e.to_string()))?
+        .map_err(|e| ServerError::ReleaseSourceError(e.to_string()))?;
+        Ok(release.version)
+    }
+
+    async fn report_version(&self, version: &str, build_date: &str) -> ServerResult<()> {
+        #[derive(Serialize)]
+        struct VersionReport<'a> {
+            version: &'a str,
+            build_date: &'a str,
+        }
+        reqwest::Client::new()
+            .post("https://zhang.resource.rs")
+            .json(&VersionReport { version, build_date })
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct CheckResult {
+    latest_version: Option<String>,
+    checked_at: Option<NaiveDateTime>,
+}
+
+/// keeps the result of the latest successful check, failures are only logged in debug level
+/// since the server is expected to run without network access
+pub struct UpdateChecker {
+    source: Arc<dyn ReleaseSource>,
+    enabled: bool,
+    current_version: String,
+    result: RwLock<CheckResult>,
+}
+
+impl UpdateChecker {
+    pub fn new(source: Arc<dyn ReleaseSource>, enabled: bool) -> UpdateChecker {
+        UpdateChecker {
+            source,
+            enabled,
+            current_version: env!("CARGO_PKG_VERSION").to_owned(),
+            result: RwLock::new(CheckResult::default()),
+        }
+    }
+
+    /// fetch the latest release, return its version if it is newer than the running one
+    pub async fn check(&self) -> Option<String> {
+        let latest_version = match self.source.latest_version().await {
+            Ok(version) => version,
+            Err(e) => {
+                debug!("fail to check the latest release: {}", e);
+                return None;
+            }
+        };
+        let mut result = self.result.write().await;
+        result.latest_version = Some(latest_version.clone());
+        result.checked_at = Some(Local::now().naive_local());
+        if self.is_newer(&latest_version) {
+            info!("new version {} of zhang is found", latest_version);
+            Some(latest_version)
+        } else {
+            None
+        }
+    }
+
+    /// report the running version, failures are only logged like checking
+    pub async fn report(&self) {
+        match self.source.report_version(&self.current_version, env!("ZHANG_BUILD_DATE")).await {
+            Ok(_) => debug!("report zhang's version successfully"),
+            Err(e) => debug!("fail to report zhang's version: {}", e),
+        }
+    }
+
+    pub async fn status(&self) -> UpdateStatusResponse {
+        let result = self.result.read().await;
+        UpdateStatusResponse {
+            enabled: self.enabled,
+            current_version: self.current_version.clone(),
+            latest_version: result.latest_version.clone(),
+            has_update: result.latest_version.as_deref().map(|it| self.is_newer(it)).unwrap_or(false),
+            checked_at: result.checked_at,
+        }
+    }
+
+    fn is_newer(&self, version: &str) -> bool {
+        bump_is_greater(&self.current_version, version).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+
+    use crate::update::{ReleaseSource, UpdateChecker};
+    use crate::{ServerError, ServerResult};
+
+    /// local stub instead of github, `None` acts like an air-gapped environment
+    struct StubReleaseSource {
+        version: Option<&'static str>,
+        reported: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl ReleaseSource for StubReleaseSource {
+        async fn latest_version(&self) -> ServerResult<String> {
+            self.version
+                .map(str::to_owned)
+                .ok_or_else(|| ServerError::ReleaseSourceError("network is unreachable".to_owned()))
+        }
+
+        async fn report_version(&self, _version: &str, _build_date: &str) -> ServerResult<()> {
+            self.reported.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn checker(version: Option<&'static str>) -> UpdateChecker {
+        let source = StubReleaseSource {
+            version,
+            reported: Arc::new(AtomicUsize::new(0)),
+        };
+        let mut checker = UpdateChecker::new(Arc::new(source), true);
+        checker.current_version = "0.1.0".to_owned();
+        checker
+    }
+
+    #[tokio::test]
+    async fn should_find_newer_version() {
+        let checker = checker(Some("0.2.0"));
+        assert_eq!(Some("0.2.0".to_owned()), checker.check().await);
+
+        let status = checker.status().await;
+        assert!(status.has_update);
+        assert_eq!(Some("0.2.0".to_owned()), status.latest_version);
+        assert!(status.checked_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn should_not_report_same_or_older_version() {
+        assert_eq!(None, checker(Some("0.1.0")).check().await);
+
+        let checker = checker(Some("0.0.9"));
+        assert_eq!(None, checker.check().await);
+        assert!(!checker.status().await.has_update);
+    }
+
+    #[tokio::test]
+    async fn should_keep_silent_if_source_is_unreachable() {
+        let checker = checker(None);
+        assert_eq!(None, checker.check().await);
+
+        let status = checker.status().await;
+        assert!(status.enabled);
+        assert!(!status.has_update);
+        assert_eq!(None, status.latest_version);
+        assert_eq!(None, status.checked_at);
+    }
+
+    #[tokio::test]
+    async fn should_report_version_to_source() {
+        let reported = Arc::new(AtomicUsize::new(0));
+        let source = StubReleaseSource {
+            version: None,
+            reported: reported.clone(),
+        };
+        UpdateChecker::new(Arc::new(source), false).report().await;
+        assert_eq!(1, reported.load(Ordering::SeqCst));
+    }
+}
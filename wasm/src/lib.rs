@@ -1,28 +1,257 @@
-mod utils;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
+use glob::Pattern;
+use itertools::Itertools;
+use serde::Serialize;
 use wasm_bindgen::prelude::*;
-// use console_error_panic_hook::hook;
+use zhang_core::database::type_ext::big_decimal::ZhangBigDecimal;
+use zhang_core::exporter::TextExportable;
+use zhang_core::ledger::Ledger;
+use zhang_core::parser::parse as parse_zhang;
+use zhang_core::transform::{TransformResult, Transformer};
+use zhang_core::{ZhangError, ZhangResult};
+
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
 #[cfg(feature = "wee_alloc")]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+/// endpoint of the single file ledger, only shown in spans
+const ENDPOINT: &str = "main.zhang";
+
+/// problem of the content, located by byte offsets of the span and the line and column of its beginning, both starting from 1
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    pub error_type: String,
+    pub message: String,
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub metas: HashMap<String, String>,
+}
+
+impl Diagnostic {
+    fn from_error(error: ZhangError) -> Diagnostic {
+        let (line, column) = match &error {
+            ZhangError::SyntaxError { line, column, .. } => (Some(*line), Some(*column)),
+            _ => (None, None),
+        };
+        Diagnostic {
+            error_type: match error {
+                ZhangError::SyntaxError { .. } => "SyntaxError".to_owned(),
+                _ => "LedgerError".to_owned(),
+            },
+            message: error.to_string(),
+            start: None,
+            end: None,
+            line,
+            column,
+            metas: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Balance {
+    pub account: String,
+    pub commodity: String,
+    pub number: ZhangBigDecimal,
+}
+
+/// transformer of the single file ledger held in memory
+struct ContentTransformer {
+    content: String,
+}
+
+impl Transformer for ContentTransformer {
+    fn load(&self, _entry: PathBuf, endpoint: String) -> ZhangResult<TransformResult> {
+        Ok(TransformResult {
+            directives: parse_zhang(&self.content, PathBuf::from(endpoint)).map_err(ZhangError::syntax_error)?,
+            visited_files: vec![Pattern::new(ENDPOINT).expect("endpoint should be a valid pattern")],
+        })
+    }
+
+    fn validate(&self, content: &str, path: PathBuf) -> ZhangResult<()> {
+        parse_zhang(content, path).map(|_| ()).map_err(ZhangError::syntax_error)
+    }
+}
+
+fn load(content: &str) -> ZhangResult<Ledger> {
+    let transformer = Arc::new(ContentTransformer { content: content.to_owned() });
+    futures_executor::block_on(Ledger::load_in_memory(PathBuf::from("/"), ENDPOINT.to_owned(), transformer))
+}
+
+/// line and column of the byte offset, both starting from 1
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map(|it| it.chars().count()).unwrap_or_default() + 1;
+    (line, column)
+}
+
+pub fn parse_content(content: &str) -> Result<String, Diagnostic> {
+    let directives = parse_zhang(content, PathBuf::from(ENDPOINT))
+        .map_err(ZhangError::syntax_error)
+        .map_err(Diagnostic::from_error)?;
+    Ok(json::JsonExporter::default().export_directives(directives))
+}
+
+pub fn validate_content(content: &str) -> Vec<Diagnostic> {
+    let ledger = match load(content) {
+        Ok(ledger) => ledger,
+        Err(e) => return vec![Diagnostic::from_error(e)],
+    };
+    let errors = match futures_executor::block_on(async { ledger.operations().await.errors().await }) {
+        Ok(errors) => errors,
+        Err(e) => return vec![Diagnostic::from_error(e)],
+    };
+    errors
+        .into_iter()
+        .map(|error| {
+            let (line, column) = match &error.span {
+                Some(span) => {
+                    let (line, column) = line_column(content, span.start);
+                    (Some(line), Some(column))
+                }
+                None => (None, None),
+            };
+            Diagnostic {
+                error_type: error.error_type.as_ref().to_owned(),
+                message: error.error_type.as_ref().to_owned(),
+                start: error.span.as_ref().map(|it| it.start),
+                end: error.span.as_ref().map(|it| it.end),
+                line,
+                column,
+                metas: error.metas,
+            }
+        })
+        .collect_vec()
+}
+
+pub fn balances_of_content(content: &str) -> Result<Vec<Balance>, Diagnostic> {
+    let ledger = load(content).map_err(Diagnostic::from_error)?;
+    let balances = futures_executor::block_on(async { ledger.operations().await.account_balances().await }).map_err(Diagnostic::from_error)?;
+    Ok(balances
+        .into_iter()
+        .map(|it| Balance {
+            account: it.account,
+            commodity: it.balance_commodity,
+            number: it.balance_number,
+        })
+        .collect_vec())
+}
+
+pub fn format_content(content: &str) -> Result<String, Diagnostic> {
+    let directives = parse_zhang(content, None).map_err(ZhangError::syntax_error).map_err(Diagnostic::from_error)?;
+    let mut formatted = directives.into_iter().map(|it| it.data.export()).join("\n\n");
+    formatted.push('\n');
+    Ok(formatted)
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("response should always be serializable")
+}
+
+fn js_error(diagnostic: Diagnostic) -> JsValue {
+    JsValue::from_str(&to_json(&diagnostic))
+}
+
+/// directives of the content in the layout of json exporter, throw the syntax error as json
 #[wasm_bindgen]
-extern "C" {
-    fn alert(s: &str);
+pub fn parse(content: &str) -> Result<String, JsValue> {
+    parse_content(content).map_err(js_error)
 }
 
+/// all syntax and ledger errors of the content as a json array, which is empty if the content is valid
 #[wasm_bindgen]
-pub fn greet() {
-    alert("Hello, zhang-js!");
+pub fn validate(content: &str) -> String {
+    to_json(&validate_content(content))
 }
 
+/// balances of every account and commodity as a json array, throw the error as json
 #[wasm_bindgen]
-pub fn parse(content: &str) -> String {
-    let parser = zhang::EntryParser::new();
-    match parser.parse(content) {
-        Ok(entry) => serde_json::to_string(&entry).unwrap(),
-        Err(e) => format!("{}", e.to_string()),
+pub fn balances(content: &str) -> Result<String, JsValue> {
+    balances_of_content(content).map(|it| to_json(&it)).map_err(js_error)
+}
+
+/// content formatted by the text exporter, throw the syntax error as json
+#[wasm_bindgen]
+pub fn format(content: &str) -> Result<String, JsValue> {
+    format_content(content).map_err(js_error)
+}
+
+#[wasm_bindgen(start)]
+pub fn set_panic_hook() {
+    // When the `console_error_panic_hook` feature is enabled, we can call the
+    // `set_panic_hook` function at least once during initialization, and then
+    // we will get better error messages if our code ever panics.
+    //
+    // For more details see
+    // https://github.com/rustwasm/console_error_panic_hook#readme
+    #[cfg(feature = "console_error_panic_hook")]
+    console_error_panic_hook::set_once();
+}
+
+#[cfg(test)]
+mod test {
+    use bigdecimal::BigDecimal;
+    use indoc::indoc;
+
+    use crate::{balances_of_content, format_content, parse_content, validate_content};
+
+    const CONTENT: &str = indoc! {r#"
+        option "operating_currency" "CNY"
+        1970-01-01 open Assets:Bank
+        1970-01-01 open Expenses:Food
+        2023-01-02 "KFC" "lunch"
+          Expenses:Food 25 CNY
+          Assets:Bank
+    "#};
+
+    #[test]
+    fn should_parse_content_into_json() {
+        let json = parse_content(CONTENT).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(4, value["directives"].as_array().unwrap().len());
+    }
+
+    #[test]
+    fn should_locate_syntax_error() {
+        let diagnostics = validate_content("1970-01-01 open Assets:Bank\n1970-01-01 opne Assets:Cash\n");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("SyntaxError", diagnostics[0].error_type);
+        assert_eq!(Some(2), diagnostics[0].line);
+    }
+
+    #[test]
+    fn should_locate_ledger_error() {
+        assert!(validate_content(CONTENT).is_empty());
+
+        let content = format!("{}2023-01-03 balance Assets:Bank 10 CNY\n", CONTENT);
+        let diagnostics = validate_content(&content);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("AccountBalanceCheckError", diagnostics[0].error_type);
+        assert_eq!(Some(7), diagnostics[0].line);
+        assert_eq!(Some(1), diagnostics[0].column);
+        assert_eq!("Assets:Bank", diagnostics[0].metas["account_name"]);
+    }
+
+    #[test]
+    fn should_calculate_balances() {
+        let balances = balances_of_content(CONTENT).unwrap();
+        let bank = balances.iter().find(|it| it.account == "Assets:Bank").unwrap();
+        assert_eq!("CNY", bank.commodity);
+        assert_eq!(BigDecimal::from(-25), bank.number.0);
+    }
+
+    #[test]
+    fn should_format_content() {
+        let formatted = format_content(CONTENT).unwrap();
+        assert!(formatted.contains("1970-01-01 open Assets:Bank"));
+        assert_eq!(formatted, format_content(&formatted).unwrap());
     }
 }
@@ -11,3 +11,12 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+#[wasm_bindgen_test]
+fn should_validate_content_in_browser() {
+    let content = "option \"operating_currency\" \"CNY\"\n1970-01-01 open Assets:Bank\n1970-01-01 open Expenses:Food\n2023-01-02 \"KFC\" \"lunch\"\n  Expenses:Food 25 CNY\n  Assets:Bank\n";
+    assert_eq!("[]", zhang_wasm::validate(content));
+
+    let diagnostics = zhang_wasm::validate(&format!("{}2023-01-03 balance Assets:Bank 10 CNY\n", content));
+    assert!(diagnostics.contains("AccountBalanceCheckError"), "{}", diagnostics);
+}